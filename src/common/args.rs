@@ -4,22 +4,264 @@ use std::str::FromStr;
 
 use getrandom::getrandom;
 
+use super::color::{Color, HighlightMode, Primaries};
+use super::config;
+use super::raytracer::{Caustics, Fog, RngEngine};
+use crate::output::png::IDAT_SIZE;
+use crate::output::{self, Format, PngCompression, EXR, PNG, PPM, RAWVIDEO};
+
+/// the deepest sphereflake has about 5.4 million spheres, and each level takes 9 times as much
+/// memory as the last
+pub const MAX_FLAKE_DEPTH: u32 = 7;
+/// the most detailed Menger sponge has 160,000 blocks of 6 rectangles each
+pub const MAX_MENGER_LEVEL: u32 = 4;
+/// PNG chunk lengths are 31 bits
+pub const MAX_PNG_CHUNK_SIZE: usize = (1 << 31) - 1;
+
 #[derive(Debug)]
 pub struct Args {
 	pub threads: usize,
 	pub width: usize,
 	pub samples: usize,
 	pub depth: usize,
+	/// bounces through glass that don't count towards depth, for glass without its own
+	pub glass_depth_bonus: u32,
 	pub world_seed: u64,
 	pub sample_seed: u64,
 	pub output: Option<String>,
 	pub scene: WhichScene,
+	/// preset that the defaults for width, samples, and depth came from
+	pub quality: Option<Quality>,
+	/// levels of children in the sphereflake scene
+	pub flake_depth: u32,
+	/// how many times the menger scene's sponge is subdivided
+	pub menger_level: u32,
 	pub verbose: bool,
-	pub format: FileFormat,
+	pub format: &'static Format,
 	pub bit_depth: u8,
+	/// bit depth for the OpenEXR depth layer, which needs more precision than the color layers
+	pub z_bit_depth: u8,
+	pub aovs: bool,
+	/// add a world space position layer to OpenEXR output
+	pub position: bool,
+	/// add Cryptomatte object id layers to OpenEXR output
+	pub cryptomatte: bool,
+	/// also write the direct and indirect light to OpenEXR files next to the output
+	pub split_lighting: bool,
+	pub alpha: bool,
 	pub debug_mode: Option<DebugMode>,
+	/// material to show everything but the lights with, for looking at the shapes in a scene
+	pub override_material: Option<OverrideMaterial>,
+	/// most samples per pixel to take in a debug mode
+	pub debug_samples: usize,
+	/// render a debug mode at 1/debug_scale of the width and height, and scale it back up
+	pub debug_scale: usize,
+	pub denoise: Option<f64>,
+	pub denoise_raw: bool,
+	pub aberration: Option<f64>,
+	pub vignette: Option<f64>,
+	pub burn_in: bool,
+	pub contact_sheet: bool,
+	pub bake_texture: Option<WhichTexture>,
+	pub texture_scale: f64,
+	/// directory to look for textures and light profiles in when they aren't where scenes expect
+	pub texture_dir: Option<String>,
+	pub auto_expose: bool,
+	pub bake_exposure: bool,
+	pub output_primaries: Primaries,
+	pub highlights: HighlightMode,
+	pub fog: Option<Fog>,
+	pub caustics: Option<Caustics>,
+	pub shutter: Option<Shutter>,
+	pub scene_stats: bool,
+	pub pick: Option<Pick>,
+	/// pixel to focus the camera on, overriding the scene's focus distance
+	pub focus_at: Option<Pick>,
+	pub focus_distance: Option<f64>,
+	/// f-number of the lens, which turns on physical exposure
+	pub f_stop: Option<f64>,
+	/// sensitivity with physical exposure, or None for 100
+	pub iso: Option<f64>,
+	/// seconds the shutter is open for with physical exposure, or None for 1/100
+	pub shutter_speed: Option<f64>,
+	/// multiplies the scene's background color, which is the light coming from everywhere that
+	/// rays escape to
+	pub background_intensity: f64,
+	pub stereo: Option<Stereo>,
+	/// file to write the depth of each pixel to, and its format, which is PNG or OpenEXR
+	pub output_depth: Option<(String, &'static Format)>,
+	/// depths to map to black and white in a PNG depth map, instead of percentiles of the image
+	pub depth_range: Option<DepthRange>,
+	/// distance between the eyes for --stereo, in scene units
+	pub ipd: f64,
+	/// file to write a JSON summary of the run to
+	pub stats_out: Option<String>,
+	/// file to write the boxes of the BVH to, as JSON or OBJ
+	pub dump_bvh: Option<String>,
+	/// deepest level of the BVH to write with --dump-bvh
+	pub dump_bvh_depth: Option<usize>,
+	/// seconds a render thread can go without finishing a scanline before it's reported as stalled
+	pub stall_timeout: u64,
+	/// exit with an error when a thread stalls, instead of just warning
+	pub abort_on_stall: bool,
+	/// samples per pixel for a quick first pass that is written to the output before the real render
+	pub preview_samples: Option<usize>,
+	pub tile_order: TileOrder,
+	pub rng_engine: RngEngine,
+	/// bytes of image data in each IDAT chunk of PNG output
+	pub png_chunk_size: usize,
+	pub png_compression: PngCompression,
+	/// 1 to 100
+	pub jpeg_quality: u8,
+	/// render this many frames across the shutter interval into an animated PNG
+	pub frames: Option<u32>,
+	/// frames per second of the animation
+	pub fps: u16,
+	/// JSON file of keyframes for the camera to follow across the frames
+	pub camera_path: Option<String>,
+	pub temporal_seed: TemporalSeed,
+	/// render every frame over the whole shutter interval and write the average of the frames so
+	/// far, instead of each frame on its own
+	pub accumulate_frames: bool,
+	/// load the scene and check the output paths, but don't render anything
+	pub dry_run: bool,
+	/// check that PNG output is well formed and holds the image after writing it
+	pub verify_output: bool,
 }
 
+impl Args {
+	/// A command line that renders the same image again, with the seeds filled in even if they
+	/// came from the OS. Only options that change the image are included, so it writes to stdout
+	pub fn reproduce_command(&self) -> String {
+		let mut parts = vec![program_name()];
+		if self.contact_sheet {
+			parts.push("--contact-sheet".to_string());
+		} else if let Some(texture) = self.bake_texture {
+			parts.extend([
+				"--bake-texture".to_string(),
+				texture.name().to_string(),
+				"--texture-scale".to_string(),
+				self.texture_scale.to_string(),
+			]);
+		} else {
+			parts.extend(["-S".to_string(), self.scene.name().to_string()]);
+		}
+		parts.extend([
+			format!("-w {}", self.width),
+			format!("-s {}", self.samples),
+			format!("-d {}", self.depth),
+			format!("-r {}", self.world_seed),
+			format!("-R {}", self.sample_seed),
+			format!("-f {}", self.format.name()),
+			format!("-b {}", self.bit_depth),
+		]);
+		if let Some(f_stop) = self.f_stop {
+			parts.extend([
+				format!("--f-stop {}", f_stop),
+				format!("--iso {}", self.iso.unwrap_or(100.0)),
+				format!("--shutter-speed {}", self.shutter_speed.unwrap_or(0.01)),
+			]);
+		}
+		if self.glass_depth_bonus != 0 {
+			parts.push(format!("--glass-depth-bonus {}", self.glass_depth_bonus));
+		}
+		if self.background_intensity != 1.0 {
+			parts.push(format!(
+				"--background-intensity {}",
+				self.background_intensity
+			));
+		}
+		if let Some(caustics) = self.caustics {
+			parts.push(format!("--caustics {}", caustics));
+		}
+		if self.rng_engine != RngEngine::Xoshiro {
+			parts.push(format!("--rng-engine {}", self.rng_engine.name()));
+		}
+		if let Some(material) = self.override_material {
+			parts.push(format!("--override-material {}", material.name()));
+		}
+		if let WhichScene::Sphereflake = self.scene {
+			parts.push(format!("--flake-depth {}", self.flake_depth));
+		}
+		if let WhichScene::Menger = self.scene {
+			parts.push(format!("--menger-level {}", self.menger_level));
+		}
+		if let Some(mode) = self.debug_mode {
+			parts.extend([
+				format!("-D {}", mode.name()),
+				format!("--debug-samples {}", self.debug_samples),
+				format!("--debug-scale {}", self.debug_scale),
+			]);
+		}
+		if let Some(fog) = self.fog {
+			parts.push(format!("--fog {}", fog));
+		}
+		if let Some(shutter) = self.shutter {
+			parts.push(format!("--shutter {}", shutter));
+		}
+		if let Some(pixel) = self.focus_at {
+			parts.push(format!("--focus-at {}", pixel));
+		}
+		if let Some(distance) = self.focus_distance {
+			parts.push(format!("--focus-distance {}", distance));
+		}
+		if let Some(stereo) = self.stereo {
+			parts.extend([
+				format!("--stereo {}", stereo.name()),
+				format!("--ipd {}", self.ipd),
+			]);
+		}
+		if self.alpha {
+			parts.push("--alpha".to_string());
+		}
+		if self.auto_expose {
+			parts.push("--auto-expose".to_string());
+			if self.bake_exposure {
+				parts.push("--bake-exposure".to_string());
+			}
+		}
+		if self.output_primaries != Primaries::Srgb {
+			parts.push(format!(
+				"--output-primaries {}",
+				self.output_primaries.name()
+			));
+		}
+		if self.highlights != HighlightMode::Clip {
+			parts.push(format!("--highlights {}", self.highlights.name()));
+		}
+		if let Some(strength) = self.denoise {
+			parts.push(format!("--denoise {}", strength));
+		}
+		if let Some(strength) = self.aberration {
+			parts.push(format!("--aberration {}", strength));
+		}
+		if let Some(strength) = self.vignette {
+			parts.push(format!("--vignette {}", strength));
+		}
+		if self.denoise_raw {
+			parts.push("--denoise-raw".to_string());
+		}
+		if self.burn_in {
+			parts.push("--burn-in".to_string());
+		}
+		if let Some(frames) = self.frames {
+			parts.extend([
+				format!("--frames {}", frames),
+				format!("--fps {}", self.fps),
+			]);
+			if let Some(ref path) = self.camera_path {
+				parts.push(format!("--camera-path {}", path));
+			}
+			parts.push(format!("--temporal-seed {}", self.temporal_seed.name()));
+			if self.accumulate_frames {
+				parts.push("--accumulate-frames".to_string());
+			}
+		}
+		parts.join(" ")
+	}
+}
+
+#[derive(Clone, Copy)]
 pub struct ParseEnumError(pub &'static str);
 
 impl Display for ParseEnumError {
@@ -28,7 +270,7 @@ impl Display for ParseEnumError {
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum WhichScene {
 	Weekend,
 	Gay,
@@ -38,6 +280,214 @@ pub enum WhichScene {
 	Cornell,
 	Bisexual,
 	Week,
+	Prism,
+	Sunny,
+	Spot,
+	Softbox,
+	Glass,
+	Spin,
+	Catcher,
+	Water,
+	Sphereflake,
+	Menger,
+	Figure19,
+	Refraction,
+	Golf,
+}
+
+impl WhichScene {
+	pub const ALL: [WhichScene; 21] = [
+		Self::Weekend,
+		Self::Gay,
+		Self::Tuesday,
+		Self::Perlin,
+		Self::Earth,
+		Self::Cornell,
+		Self::Bisexual,
+		Self::Week,
+		Self::Prism,
+		Self::Sunny,
+		Self::Spot,
+		Self::Softbox,
+		Self::Glass,
+		Self::Spin,
+		Self::Catcher,
+		Self::Water,
+		Self::Sphereflake,
+		Self::Menger,
+		Self::Figure19,
+		Self::Refraction,
+		Self::Golf,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Weekend => "weekend",
+			Self::Gay => "gay",
+			Self::Tuesday => "tuesday",
+			Self::Perlin => "perlin",
+			Self::Earth => "earth",
+			Self::Cornell => "cornell",
+			Self::Bisexual => "bisexual",
+			Self::Week => "week",
+			Self::Prism => "prism",
+			Self::Sunny => "sunny",
+			Self::Spot => "spot",
+			Self::Softbox => "softbox",
+			Self::Glass => "glass",
+			Self::Spin => "spin",
+			Self::Catcher => "catcher",
+			Self::Water => "water",
+			Self::Sphereflake => "sphereflake",
+			Self::Menger => "menger",
+			Self::Figure19 => "figure19",
+			Self::Refraction => "refraction",
+			Self::Golf => "golf",
+		}
+	}
+
+	/// what the scene looks like, for --help. may be more than one line
+	pub fn description(&self) -> &'static str {
+		match self {
+			Self::Weekend => "random spheres; final render from Ray Tracing in One Weekend",
+			Self::Gay => {
+				"the random spheres scene, but with pride flag textures on the small spheres"
+			},
+			Self::Tuesday => concat!(
+				"the random spheres scene, but upgraded with features from The Next Week:\n",
+				"  - moving spheres\n",
+				"  - checkered ground texture",
+			),
+			Self::Perlin => "two spheres with Perlin noise",
+			Self::Earth => "a globe with the texture of the Earth",
+			Self::Cornell => "the Cornell box",
+			Self::Bisexual => "the Cornell box but with bisexual lighting",
+			Self::Week => "final scene from Ray Tracing: The Next Week",
+			Self::Prism => "a slit of light dispersed into a rainbow by a glass block",
+			Self::Sunny => "the random spheres scene lit by the sun and a lamp",
+			Self::Spot => "the Cornell box lit by spotlights, one using lights/spot.ies",
+			Self::Softbox => concat!(
+				"the Cornell box with its light sampled directly, which is much less noisy\n",
+				"but leaves the light itself invisible",
+			),
+			Self::Glass => concat!(
+				"the softbox scene with a glass ball, whose caustic on the floor only shows\n",
+				"up with --caustics",
+			),
+			Self::Spin => "the Cornell box with the tall block spinning while the shutter is open",
+			Self::Catcher => "two spheres on a shadow catcher, for use with --alpha",
+			Self::Water => "a glass of water with air bubbles in it",
+			Self::Sphereflake => concat!(
+				"a fractal of spheres with nine smaller spheres around each one, for\n",
+				"benchmarking. see --flake-depth",
+			),
+			Self::Menger => concat!(
+				"a Menger sponge made of blocks in the Cornell box, for benchmarking. see\n",
+				"--menger-level",
+			),
+			Self::Figure19 => {
+				"figure 19 from Ray Tracing in One Weekend; three spheres with different materials"
+			},
+			Self::Refraction => "a row of spheres sinking into a slab of tinted glass",
+			Self::Golf => "a golf ball whose dimples are displaced into its surface by a texture",
+		}
+	}
+
+	/// Settings that give a clean image of the scene in a reasonable time, which --quality is
+	/// based on. None for the benchmarking scenes, whose cost depends on their size
+	pub fn recommended(&self) -> Option<Recommended> {
+		let (width, samples, depth) = match self {
+			Self::Weekend | Self::Gay | Self::Perlin | Self::Earth | Self::Sunny => (600, 100, 50),
+			Self::Tuesday
+			| Self::Figure19
+			| Self::Catcher
+			| Self::Softbox
+			| Self::Glass
+			| Self::Golf => (600, 200, 50),
+			Self::Cornell | Self::Bisexual | Self::Spot | Self::Spin => (600, 1000, 50),
+			Self::Week => (800, 2000, 100),
+			Self::Prism => (600, 2000, 50),
+			Self::Water | Self::Refraction => (600, 500, 100),
+			Self::Sphereflake | Self::Menger => return None,
+		};
+		Some(Recommended {
+			width,
+			samples,
+			depth,
+		})
+	}
+}
+
+/// image width, samples per pixel, and maximum depth to render a scene with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recommended {
+	pub width: usize,
+	pub samples: usize,
+	pub depth: usize,
+}
+
+impl Recommended {
+	/// the defaults for scenes without recommendations
+	pub const DEFAULT: Recommended = Recommended {
+		width: 600,
+		samples: 100,
+		depth: 50,
+	};
+}
+
+impl Display for Recommended {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "-w {} -s {} -d {}", self.width, self.samples, self.depth)
+	}
+}
+
+/// a preset for how long to spend on a render, relative to the scene's recommended settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+	Draft,
+	Medium,
+	Final,
+}
+
+impl Quality {
+	pub const ALL: [Quality; 3] = [Self::Draft, Self::Medium, Self::Final];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Draft => "draft",
+			Self::Medium => "medium",
+			Self::Final => "final",
+		}
+	}
+
+	/// how many times fewer samples per pixel this takes than recommended. width and depth are
+	/// left alone, since they change what the image looks like and not just how noisy it is
+	pub fn sample_divisor(&self) -> usize {
+		match self {
+			Self::Draft => 8,
+			Self::Medium => 3,
+			Self::Final => 1,
+		}
+	}
+
+	pub fn apply(&self, recommended: Recommended) -> Recommended {
+		Recommended {
+			samples: (recommended.samples / self.sample_divisor()).max(1),
+			..recommended
+		}
+	}
+}
+
+impl FromStr for Quality {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"draft" => Ok(Self::Draft),
+			"medium" => Ok(Self::Medium),
+			"final" => Ok(Self::Final),
+			_ => Err(ParseEnumError("quality")),
+		}
+	}
 }
 
 impl FromStr for WhichScene {
@@ -52,48 +502,314 @@ impl FromStr for WhichScene {
 			"cornell" => Ok(Self::Cornell),
 			"bisexual" => Ok(Self::Bisexual),
 			"week" => Ok(Self::Week),
+			"prism" => Ok(Self::Prism),
+			"sunny" => Ok(Self::Sunny),
+			"spot" => Ok(Self::Spot),
+			"softbox" => Ok(Self::Softbox),
+			"glass" => Ok(Self::Glass),
+			"spin" => Ok(Self::Spin),
+			"catcher" => Ok(Self::Catcher),
+			"water" => Ok(Self::Water),
+			"sphereflake" => Ok(Self::Sphereflake),
+			"menger" => Ok(Self::Menger),
+			"figure19" => Ok(Self::Figure19),
+			"refraction" => Ok(Self::Refraction),
+			"golf" => Ok(Self::Golf),
 			_ => Err(ParseEnumError("scene")),
 		}
 	}
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum FileFormat {
-	Png,
-	Ppm,
-	Exr,
+#[derive(Debug, Clone, Copy)]
+pub enum WhichTexture {
+	Checker,
+	Trans,
+	Rainbow,
+	Enby,
+	Bi,
+	Noise,
+	Marble,
+	Earth,
 }
 
-impl FileFormat {
-	pub fn from_extension(filename: &str) -> Result<FileFormat, ParseEnumError> {
-		if filename.ends_with(".png") {
-			Ok(FileFormat::Png)
-		} else if filename.ends_with(".ppm") {
-			Ok(FileFormat::Ppm)
-		} else if filename.ends_with(".exr") {
-			Ok(FileFormat::Exr)
-		} else {
-			Err(ParseEnumError("format"))
+impl WhichTexture {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Checker => "checker",
+			Self::Trans => "trans",
+			Self::Rainbow => "rainbow",
+			Self::Enby => "enby",
+			Self::Bi => "bi",
+			Self::Noise => "noise",
+			Self::Marble => "marble",
+			Self::Earth => "earth",
+		}
+	}
+}
+
+impl FromStr for WhichTexture {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"checker" => Ok(Self::Checker),
+			"trans" => Ok(Self::Trans),
+			"rainbow" => Ok(Self::Rainbow),
+			"enby" => Ok(Self::Enby),
+			"bi" => Ok(Self::Bi),
+			"noise" => Ok(Self::Noise),
+			"marble" => Ok(Self::Marble),
+			"earth" => Ok(Self::Earth),
+			_ => Err(ParseEnumError("texture")),
+		}
+	}
+}
+
+impl FromStr for Primaries {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"srgb" => Ok(Self::Srgb),
+			"p3" => Ok(Self::DisplayP3),
+			"rec2020" => Ok(Self::Rec2020),
+			_ => Err(ParseEnumError("primaries")),
+		}
+	}
+}
+
+impl Primaries {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Srgb => "srgb",
+			Self::DisplayP3 => "p3",
+			Self::Rec2020 => "rec2020",
+		}
+	}
+}
+
+impl FromStr for HighlightMode {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"clip" => Ok(Self::Clip),
+			"preserve-hue" => Ok(Self::PreserveHue),
+			_ => Err(ParseEnumError("highlight mode")),
+		}
+	}
+}
+
+impl HighlightMode {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Clip => "clip",
+			Self::PreserveHue => "preserve-hue",
+		}
+	}
+}
+
+impl FromStr for Fog {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let error = ParseEnumError("fog, expected density,#rrggbb");
+		let (density, color) = s.split_once(',').ok_or(error)?;
+		let density: f64 = density.parse().map_err(|_| error)?;
+		let color = color.strip_prefix('#').ok_or(error)?;
+		if density < 0.0 || color.len() != 6 {
+			return Err(error);
+		}
+		Ok(Fog {
+			density,
+			color: Color::from_srgb_hex(u32::from_str_radix(color, 16).map_err(|_| error)?),
+		})
+	}
+}
+
+impl Display for Fog {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{},#{:06x}", self.density, self.color.to_srgb_hex())
+	}
+}
+
+impl FromStr for RngEngine {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"xoshiro" => Ok(Self::Xoshiro),
+			"philox" => Ok(Self::Philox),
+			_ => Err(ParseEnumError("random number generator")),
+		}
+	}
+}
+
+impl FromStr for Caustics {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let error = ParseEnumError("caustics, expected photons=n,radius=r");
+		let mut caustics = Caustics {
+			photons: 100_000,
+			radius: 0.01,
+		};
+		for setting in s.split(',').filter(|s| !s.is_empty()) {
+			match setting.split_once('=').ok_or(error)? {
+				("photons", n) => caustics.photons = n.parse().map_err(|_| error)?,
+				("radius", r) => caustics.radius = r.parse().map_err(|_| error)?,
+				_ => return Err(error),
+			}
+		}
+		if caustics.photons == 0 || !(caustics.radius > 0.0 && caustics.radius.is_finite()) {
+			return Err(error);
+		}
+		Ok(caustics)
+	}
+}
+
+impl Display for Caustics {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "photons={},radius={}", self.photons, self.radius)
+	}
+}
+
+/// when the camera's shutter is open, overriding the scene's
+#[derive(Debug, Clone, Copy)]
+pub enum Shutter {
+	Open(f64, f64),
+	/// a single instant, with no motion blur
+	Static(f64),
+}
+
+impl FromStr for Shutter {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let error = ParseEnumError("shutter, expected open,close or a single time");
+		match s.split_once(',') {
+			Some((open, close)) => {
+				let open: f64 = open.parse().map_err(|_| error)?;
+				let close: f64 = close.parse().map_err(|_| error)?;
+				if close < open {
+					return Err(error);
+				}
+				Ok(Self::Open(open, close))
+			},
+			None => Ok(Self::Static(s.parse().map_err(|_| error)?)),
+		}
+	}
+}
+
+impl Display for Shutter {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Open(open, close) => write!(f, "{},{}", open, close),
+			Self::Static(time) => write!(f, "{}", time),
+		}
+	}
+}
+
+/// distances that are black and white in a depth map
+#[derive(Debug, Clone, Copy)]
+pub struct DepthRange {
+	pub near: f64,
+	pub far: f64,
+}
+
+impl FromStr for DepthRange {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let error = ParseEnumError("depth range, expected near,far");
+		let (near, far) = s.split_once(',').ok_or(error)?;
+		let near: f64 = near.parse().map_err(|_| error)?;
+		let far: f64 = far.parse().map_err(|_| error)?;
+		if !near.is_finite() || !far.is_finite() || far <= near {
+			return Err(error);
+		}
+		Ok(Self { near, far })
+	}
+}
+
+/// how to put the two views of a stereo render into one image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stereo {
+	SideBySide,
+	Anaglyph,
+}
+
+impl FromStr for Stereo {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"sbs" => Ok(Self::SideBySide),
+			"anaglyph" => Ok(Self::Anaglyph),
+			_ => Err(ParseEnumError("stereo mode")),
+		}
+	}
+}
+
+impl Stereo {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::SideBySide => "sbs",
+			Self::Anaglyph => "anaglyph",
 		}
 	}
 }
 
-impl FromStr for FileFormat {
+/// how the sample seed changes from one frame of an animation to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemporalSeed {
+	/// the same noise in every frame, so it stays still while the scene moves under it
+	Fixed,
+	/// different noise in every frame
+	Varying,
+}
+
+impl TemporalSeed {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Fixed => "fixed",
+			Self::Varying => "varying",
+		}
+	}
+}
+
+impl FromStr for TemporalSeed {
 	type Err = ParseEnumError;
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		match s {
-			"png" => Ok(Self::Png),
-			"ppm" => Ok(Self::Ppm),
-			"exr" => Ok(Self::Exr),
-			_ => Err(ParseEnumError("format")),
+			"fixed" => Ok(Self::Fixed),
+			"varying" => Ok(Self::Varying),
+			_ => Err(ParseEnumError("temporal seed")),
 		}
 	}
 }
 
+/// a pixel to trace a single ray through, counted from the top left of the image
+#[derive(Debug, Clone, Copy)]
+pub struct Pick {
+	pub x: usize,
+	pub y: usize,
+}
+
+impl FromStr for Pick {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let error = ParseEnumError("pixel, expected x,y");
+		let (x, y) = s.split_once(',').ok_or(error)?;
+		Ok(Pick {
+			x: x.trim().parse().map_err(|_| error)?,
+			y: y.trim().parse().map_err(|_| error)?,
+		})
+	}
+}
+
+impl Display for Pick {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{},{}", self.x, self.y)
+	}
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DebugMode {
 	Depth,
 	Bvh,
+	Id,
 }
 
 impl FromStr for DebugMode {
@@ -102,15 +818,88 @@ impl FromStr for DebugMode {
 		match s {
 			"depth" => Ok(Self::Depth),
 			"bvh" => Ok(Self::Bvh),
+			"id" => Ok(Self::Id),
 			_ => Err(ParseEnumError("debug mode")),
 		}
 	}
 }
 
+impl DebugMode {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Depth => "depth",
+			Self::Bvh => "bvh",
+			Self::Id => "id",
+		}
+	}
+}
+
+/// material that --override-material puts in place of every other one except for lights
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverrideMaterial {
+	/// plain gray
+	Clay,
+	/// plain gray, with the edges of rectangles and blocks drawn in
+	Wireframe,
+}
+
+impl OverrideMaterial {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Clay => "clay",
+			Self::Wireframe => "wireframe",
+		}
+	}
+}
+
+/// an --override-material, or None for "none"
+fn parse_override_material(s: &str) -> Result<Option<OverrideMaterial>, ParseEnumError> {
+	match s {
+		"clay" => Ok(Some(OverrideMaterial::Clay)),
+		"wireframe" => Ok(Some(OverrideMaterial::Wireframe)),
+		"none" => Ok(None),
+		_ => Err(ParseEnumError("material override")),
+	}
+}
+
+/// order that tiles are handed out to the render threads in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+	/// left to right, from the bottom row up
+	Scanline,
+	/// most expensive first, estimated by tracing a few rays through each tile
+	Cost,
+}
+
+impl FromStr for PngCompression {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"default" => Ok(Self::Default),
+			"fast" => Ok(Self::Fast),
+			"parallel" => Ok(Self::Parallel),
+			_ => Err(ParseEnumError("PNG compression")),
+		}
+	}
+}
+
+impl FromStr for TileOrder {
+	type Err = ParseEnumError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"scanline" => Ok(Self::Scanline),
+			"cost" => Ok(Self::Cost),
+			_ => Err(ParseEnumError("tile order")),
+		}
+	}
+}
+
 pub enum Error {
 	PicoError(pico_args::Error),
 	UnrecognizedArguments(Vec<OsString>),
 	GetrandomError(getrandom::Error),
+	/// a malformed config file or environment variable
+	Config(String),
 }
 
 impl Display for Error {
@@ -119,6 +908,7 @@ impl Display for Error {
 			Self::PicoError(e) => e.fmt(f)?,
 			Self::UnrecognizedArguments(v) => write!(f, "unrecognized argument(s): {:?}", v)?,
 			Self::GetrandomError(e) => write!(f, "error generating entropy: {}", e)?,
+			Self::Config(e) => e.fmt(f)?,
 		}
 		Ok(())
 	}
@@ -136,6 +926,19 @@ impl From<getrandom::Error> for Error {
 	}
 }
 
+/// a number of seconds, either as a decimal or a fraction like 1/250
+fn parse_seconds(s: &str) -> Result<f64, ParseEnumError> {
+	let error = ParseEnumError("time, expected seconds like 0.004 or 1/250");
+	match s.split_once('/') {
+		Some((numerator, denominator)) => {
+			let numerator: f64 = numerator.parse().map_err(|_| error)?;
+			let denominator: f64 = denominator.parse().map_err(|_| error)?;
+			Ok(numerator / denominator)
+		},
+		None => s.parse().map_err(|_| error),
+	}
+}
+
 fn system_threads() -> usize {
 	std::thread::available_parallelism()
 		.unwrap_or(1.try_into().unwrap())
@@ -153,62 +956,291 @@ pub fn show_help() {
 		concat!(
 			"usage: {} [-t|--threads n] [-w|--width w] [-s|--samples s] [-r|--seed r] \n",
 			"         [-d|--depth d] [-o|--output filename] [-S|--scene scene]\n",
+			"         [--quality draft|medium|final]\n",
 			"\n",
 			"  -t, --threads n:           number of threads. default: number of logical processors\n",
 			"                             ({})\n",
-			"  -w, --width w:             width of image in pixels. default: 600\n",
-			"  -s, --samples s:           number of samples per pixel. default: 100\n",
-			"  -d, --depth d:             maximum bounces per ray. default: 50\n",
+			"  -w, --width w:             width of image in pixels. default: 600, or set by --quality\n",
+			"  -s, --samples s:           number of samples per pixel. default: 100, or set by\n",
+			"                             --quality\n",
+			"  -d, --depth d:             maximum bounces per ray. default: 50, or set by --quality\n",
+			"  --glass-depth-bonus n:     let rays go through glass n times without counting\n",
+			"                             towards --depth, so panes and hollow glass don't go black\n",
+			"                             at low depths. no path goes past 256 bounces. default: 0\n",
 			"  -r, --world-seed n:        random number seed for generating the world.\n",
 			"                             default: entropy from the OS\n",
 			"  -R, --sample-seed n:       random number seed for shooting rays.\n",
 			"                             default: entropy from the OS\n",
 			"  -o, --output filename:     file to output image to. default: stdout\n",
-			"  -f, --format png|ppm|exr|: which format to output. default: guess from file\n",
-			"                             extension, or PPM for stdout\n",
+			"  -f, --format format:       which format to output: png, ppm, ppm-ascii (PPM with\n",
+			"                             the samples as text), bmp, jpeg, rawvideo (bare RGB\n",
+			"                             frames for ffmpeg, with --frames), or exr. default:\n",
+			"                             guess from file extension, or PPM for stdout\n",
 			"  -b, --bit-depth n:         number of bits per channel in the output image.\n",
-			"                             default: 8 for PNG or PPM, 32 for OpenEXR.\n",
-			"                             range: 1-8 for PPM, 1-16 for PNG.\n",
+			"                             default: 8 for PNG or PPM, 16 for OpenEXR (32 for\n",
+			"                             its depth layer).\n",
+			"                             range: 1-16 for PNG or PPM. BMP and JPEG must be 8.\n",
+			"                             rawvideo must be 8 or 16.\n",
 			"                             for OpenEXR must be 16 or 32 (floating point).\n",
+			"  --aovs:                    add normal, depth, albedo, and sample count layers to\n",
+			"                             OpenEXR output\n",
+			"  --position:                add a layer with the world space position of the first\n",
+			"                             thing hit in each pixel to OpenEXR output, for\n",
+			"                             relighting. pixels that hit nothing are NaN\n",
+			"  --cryptomatte:             add Cryptomatte layers to OpenEXR output, with the ids\n",
+			"                             and coverage of the objects in each pixel, for making\n",
+			"                             mattes in compositing software\n",
+			"  --split-lighting:          also write the direct light, from the first thing hit in\n",
+			"                             each pixel and the lights on it, and the indirect light\n",
+			"                             that bounced there, to name.direct.exr and\n",
+			"                             name.indirect.exr next to the output, for denoising\n",
+			"                             separately. they add up to the OpenEXR output. needs -o\n",
+			"  --alpha:                   add an alpha channel where the background is transparent\n",
+			"                             and shadow catchers only show their shadows. PNG and\n",
+			"                             OpenEXR only\n",
+			"  --output-primaries p:      color primaries of the output image: srgb, p3 (Display P3),\n",
+			"                             or rec2020. default: srgb\n",
+			"  --highlights mode:         how to display colors brighter than white. values of mode:\n",
+			"    clip:\n",
+			"      clip each channel separately, which can shift the hue of bright colors (default)\n",
+			"    preserve-hue:\n",
+			"      desaturate bright colors until they fit, keeping their hue and luminance\n",
+			"  --fog density,#rrggbb:     fade objects into a colored fog with distance. density is\n",
+			"                             the fraction of light replaced by fog per unit, e.g.\n",
+			"                             --fog 0.0005,#b0b8c8 for the week scene\n",
+			"  --caustics settings:       trace photons from the lights through mirrors and glass\n",
+			"                             first, to show the light they focus onto diffuse\n",
+			"                             surfaces. settings is a list like\n",
+			"                             photons=100000,radius=0.01, where radius is how far\n",
+			"                             photons are spread, as a fraction of the scene's size.\n",
+			"                             a larger radius is less noisy but blurrier, and makes\n",
+			"                             caustics bleed past their edges. only lights given to\n",
+			"                             the scene separately, like softbox's, send out photons.\n",
+			"                             default: photons=100000,radius=0.01\n",
+			"  --shutter open,close|time: when the camera's shutter opens and closes, for motion\n",
+			"                             blur. a single time renders that instant without blur.\n",
+			"                             default: depends on the scene, usually 0,1\n",
+			"  --focus-at x,y:            focus the camera on whatever is at pixel x,y (from the top\n",
+			"                             left) instead of the scene's focus distance\n",
+			"  --focus-distance d:        focus the camera at distance d\n",
+			"  --f-stop n:                size the lens for f/n, where n is the focus distance over\n",
+			"                             the lens's diameter, and make the image as bright as a\n",
+			"                             real camera's would be with --iso and --shutter-speed.\n",
+			"                             f/16 at ISO 100 and 1/100 second is as bright as without\n",
+			"                             --f-stop\n",
+			"  --iso n:                   sensitivity with --f-stop. default: 100\n",
+			"  --shutter-speed secs:      how long the shutter is open with --f-stop, like 0.004 or\n",
+			"                             1/250. only changes brightness; motion blur comes from\n",
+			"                             --shutter. default: 1/100\n",
+			"  --background-intensity n:  multiply the light from the scene's background by n, like\n",
+			"                             0.5 to dim it. debug views are left alone. default: 1\n",
+			"  --stereo mode:             render the scene from two eyes, converged on the focus\n",
+			"                             distance, and combine them into one image. values of\n",
+			"                             mode:\n",
+			"    sbs:\n",
+			"      side by side in an image twice as wide, with the left eye on the left\n",
+			"    anaglyph:\n",
+			"      red from the left eye and cyan from the right, for red/cyan glasses\n",
+			"  --ipd d:                   distance between the eyes for --stereo, in the units of\n",
+			"                             the scene. default: 0.065\n",
+			"  --override-material m:     show everything except the lights with one material, to\n",
+			"                             look at the shapes in a scene. values of m:\n",
+			"    clay:\n",
+			"      plain gray\n",
+			"    wireframe:\n",
+			"      plain gray, with dark lines along the edges of rectangles and blocks\n",
+			"    none:\n",
+			"      each object's own material (default)\n",
 			"  -D, --debug-mode mode:     render a debug view instead of the actual scene. values of\n",
 			"                             mode:\n",
 			"    depth:\n",
 			"      draw a heatmap of how many ray bounces occurred at each pixel\n",
 			"    bvh:\n",
 			"      indicate which BVH nodes were first hit using a random color\n",
-			"  -v, --verbose:             log performance data to stderr\n",
-			"  -S, --scene scene:         which scene to render. options:\n",
-			"    weekend:\n",
-			"      random spheres; final render from Ray Tracing in One Weekend\n",
-			"    gay:\n",
-			"      the random spheres scene, but with pride flag textures on the small spheres\n",
-			"    tuesday:\n",
-			"      the random spheres scene, but upgraded with features from The Next Week:\n",
-			"        - moving spheres\n",
-			"        - checkered ground texture\n",
-			"    perlin:\n",
-			"      two spheres with Perlin noise\n",
-			"    earth:\n",
-			"      a globe with the texture of the Earth\n",
-			"    cornell:\n",
-			"      the Cornell box\n",
-			"    bisexual:\n",
-			"      the Cornell box but with bisexual lighting\n",
-			"    week:\n",
-			"      final scene from Ray Tracing: The Next Week\n",
-			"    default: weekend\n",
+			"    id:\n",
+			"      give each object a different flat color. see --pick\n",
+			"  --debug-samples n:         most samples per pixel to take with --debug-mode.\n",
+			"                             default: 4\n",
+			"  --debug-scale n:           render --debug-mode at 1/n of the width and height and\n",
+			"                             scale it up to the full size afterwards. default: 1\n",
+			"  --pick x,y:                instead of rendering, print the object, material, and hit\n",
+			"                             details of the ray through pixel x,y (from the top left)\n",
+			"  --denoise strength:        run an edge-aware denoising filter over the image before\n",
+			"                             tonemapping. range: 0.0-1.0\n",
+			"  --denoise-raw:             also apply --denoise, --aberration, and --vignette to\n",
+			"                             OpenEXR output, which is otherwise left untouched\n",
+			"  --aberration strength:     add colored fringes towards the edges of the image, like a\n",
+			"                             lens that magnifies each color differently. range: 0.0-1.0,\n",
+			"                             e.g. 0.005\n",
+			"  --vignette strength:       darken the corners of the image like a real lens.\n",
+			"                             range: 0.0-1.0\n",
+			"  --output-depth filename:   also write the distance to the first thing hit in each\n",
+			"                             pixel to a .png or .exr file. OpenEXR gets the distance\n",
+			"                             itself, with infinity where nothing was hit. PNG gets a\n",
+			"                             16-bit gray map from black at the near end of\n",
+			"                             --depth-range to white at the far end and beyond\n",
+			"  --depth-range near,far:    depths that are black and white in a PNG depth map.\n",
+			"                             default: the 1st and 99th percentiles of the image\n",
+			"  --burn-in:                 draw the scene, seeds, samples per pixel, render time, and\n",
+			"                             a luminance histogram into the top left corner of PNG and\n",
+			"                             PPM output\n",
+			"  --auto-expose:             scale brightness so that the 99th percentile luminance\n",
+			"                             maps to 1.0 before tonemapping\n",
+			"  --bake-exposure:           also apply --auto-expose to OpenEXR output\n",
+			"  -v, --verbose:             log performance data and image statistics to stderr\n",
+			"  --dry-run:                 load the scene and its textures, build the BVH, and check\n",
+			"                             that the output files can be written, then print the\n",
+			"                             image size, seeds, and estimated memory use and exit\n",
+			"                             without rendering\n",
+			"  --verify-output:           after writing PNG output, read it back and check its\n",
+			"                             chunks, header, and compressed data, and that some of\n",
+			"                             its rows hold the rendered image\n",
+			"  --no-config:               ignore the config file and environment. otherwise, any\n",
+			"                             option without a value on the command line is read from\n",
+			"                             a RAYTRACING_* environment variable like\n",
+			"                             RAYTRACING_WORLD_SEED=1, or else from raytracing.toml in\n",
+			"                             the current directory or $XDG_CONFIG_HOME (~/.config),\n",
+			"                             which has lines like `format = \"exr\"` or `aovs = true`\n",
+			"  --stats-out filename:      write a JSON summary of the run: timings, rays traced per\n",
+			"                             thread and in total, peak memory use, and the arguments\n",
+			"                             and seeds used\n",
+			"  --stall-timeout secs:      warn when a render thread goes this long without finishing\n",
+			"                             a row of its tile. default: 30\n",
+			"  --abort-on-stall:          exit with an error instead of warning when a thread stalls\n",
+			"  --preview-samples n:       first render and write the image with only n samples per\n",
+			"                             pixel and the same seeds, then overwrite it with the full\n",
+			"                             render. needs -o\n",
+			"  --tile-order order:        order to render tiles in, which doesn't change the image.\n",
+			"                             values of order:\n",
+			"    cost:\n",
+			"      most expensive first, estimated by tracing 4 rays per tile, so that threads\n",
+			"      don't sit idle while one finishes a slow tile at the end (default)\n",
+			"    scanline:\n",
+			"      left to right, from the bottom row up\n",
+			"  --rng-engine engine:       random number generator for each sample of each pixel.\n",
+			"                             values of engine:\n",
+			"    xoshiro:\n",
+			"      xoshiro256++, seeded from the sample seed, pixel, and sample (default)\n",
+			"    philox:\n",
+			"      Philox4x32-10, a counter-based generator keyed with the sample seed\n",
+			"  --png-chunk-size bytes:    how much compressed image data to put in each IDAT chunk\n",
+			"                             of PNG output. default: 8192\n",
+			"  --frames n:                render n frames into an animated PNG or raw video, each\n",
+			"                             covering the next 1/n of the time the shutter is open,\n",
+			"                             so moving objects move across the animation\n",
+			"  --fps n:                   frames per second of the animation. default: 24\n",
+			"  --camera-path file:        with --frames, move the camera smoothly through the\n",
+			"                             keyframes in file, a JSON list of objects like\n",
+			"                             {{\"time\": 0, \"from\": [x, y, z], \"at\": [x, y, z],\n",
+			"                             \"fov\": degrees}}, with the frames spread evenly from the\n",
+			"                             first keyframe's time to the last\n",
+			"  --temporal-seed mode:      whether the noise changes between frames. values of\n",
+			"                             mode:\n",
+			"    fixed:\n",
+			"      every frame uses the same sample seed, so the noise stays in place\n",
+			"    varying:\n",
+			"      every frame gets its own seed, so the noise flickers instead (default)\n",
+			"  --accumulate-frames:       for scenes where nothing moves, render every frame over\n",
+			"                             the whole shutter interval and write the average of the\n",
+			"                             frames so far, so each frame is cleaner than the last\n",
+			"  --jpeg-quality q:          quality of JPEG output, trading size for detail.\n",
+			"                             range: 1-100. default: 90\n",
+			"  --png-compression mode:    how to compress PNG output. values of mode:\n",
+			"    default:\n",
+			"      zlib's default level on one thread (default)\n",
+			"    fast:\n",
+			"      zlib's fastest level, for a bigger file written sooner\n",
+			"    parallel:\n",
+			"      the default level split across --threads threads, for a slightly bigger file\n",
+			"  --scene-stats:             print the number of objects of each type in the scene and\n",
+			"                             the volume of their bounding boxes before rendering\n",
+			"  --dump-bvh filename:       write every box in the BVH with its depth and number of\n",
+			"                             objects, along with histograms of depth and leaf size, as\n",
+			"                             JSON, or as box wireframes grouped by depth if filename\n",
+			"                             ends in .obj. the BVH is still built and written with\n",
+			"                             --dry-run\n",
+			"  --dump-bvh-depth n:        only write the boxes in the top n levels below the root\n",
+			"                             with --dump-bvh. the statistics still cover the whole tree\n",
+			"  --flake-depth n:           levels of smaller spheres in the sphereflake scene, which\n",
+			"                             has (9^(n+1) - 1) / 8 spheres. range: 0-{}. default: 4\n",
+			"  --menger-level n:          how many times to subdivide the sponge in the menger scene,\n",
+			"                             which has 20^n blocks. range: 0-{}. default: 3\n",
+			"  --contact-sheet:           render every scene at a small size with fixed seeds and\n",
+			"                             put them in a grid, instead of rendering one scene\n",
+			"  --bake-texture texture:    instead of rendering, draw a texture flat over its whole\n",
+			"                             u,v range into a 2:1 image, with u across and v up.\n",
+			"                             options: checker, trans, rainbow, enby, bi, noise, marble,\n",
+			"                             earth\n",
+			"  --texture-scale s:         size of the x,y plane at z=0 that --bake-texture maps the\n",
+			"                             image onto, for textures that depend on position like\n",
+			"                             checker, noise, and marble. default: 1\n",
+			"  --texture-dir dir:         where to look for image textures and light profiles, like\n",
+			"                             textures/earthmap.jpg, that aren't in the current\n",
+			"                             directory: either the directory holding them or the one\n",
+			"                             above it. the directory holding this program and the two\n",
+			"                             above it are tried after that\n",
 		),
-		std::env::args_os()
-			.nth(0)
-			.unwrap_or_else(|| "raytracing".into())
-			.into_string()
-			.unwrap_or_else(|_| "raytracing".into()),
-		system_threads()
+		program_name(),
+		system_threads(),
+		MAX_FLAKE_DEPTH,
+		MAX_MENGER_LEVEL
 	);
+	eprintln!("  --quality preset:          samples, width, and depth recommended for the scene,");
+	eprintln!("                             overridden by -s, -w, and -d. values of preset:");
+	for quality in Quality::ALL {
+		eprintln!("    {}:", quality.name());
+		match quality.sample_divisor() {
+			1 => eprintln!("      the recommended samples per pixel"),
+			n => eprintln!("      1/{} of the recommended samples per pixel", n),
+		}
+	}
+	eprintln!("  -S, --scene scene:         which scene to render. options:");
+	for scene in WhichScene::ALL {
+		eprintln!("    {}:", scene.name());
+		for line in scene.description().lines() {
+			eprintln!("      {}", line);
+		}
+		if let Some(recommended) = scene.recommended() {
+			eprintln!("      recommended: {}", recommended);
+		}
+	}
+	eprintln!("    default: weekend");
+	eprint!(concat!(
+		"\n",
+		"exit codes:\n",
+		"  0: success\n",
+		"  2: bad arguments\n",
+		"  3: the scene couldn't be loaded, usually because of a missing texture or light profile\n",
+		"  4: the render was abandoned because a thread panicked or stalled\n",
+		"  5: the output couldn't be written\n",
+	));
 }
 
+/// the name this program was run as
+fn program_name() -> String {
+	std::env::args_os()
+		.nth(0)
+		.unwrap_or_else(|| "raytracing".into())
+		.into_string()
+		.unwrap_or_else(|_| "raytracing".into())
+}
+
+/// parse the command line, with defaults from the config file and environment variables
 pub fn parse() -> Result<Args, Error> {
-	let mut pargs = pico_args::Arguments::from_env();
+	let mut arguments: Vec<OsString> = std::env::args_os().skip(1).collect();
+	if arguments.iter().any(|a| a == "--no-config") {
+		arguments.retain(|a| a != "--no-config");
+		return parse_from(arguments);
+	}
+	let file = config::load_file().map_err(Error::Config)?;
+	let arguments =
+		config::with_defaults(arguments, file, config::env_options()).map_err(Error::Config)?;
+	parse_from(arguments)
+}
+
+/// parse arguments that don't include the program name
+pub fn parse_from(arguments: Vec<OsString>) -> Result<Args, Error> {
+	let mut pargs = pico_args::Arguments::from_vec(arguments);
 	if pargs.contains(["-h", "--help"]) {
 		show_help();
 		std::process::exit(0);
@@ -218,15 +1250,32 @@ pub fn parse() -> Result<Args, Error> {
 	let mut guess_format = false;
 	let mut guess_bit_depth = false;
 
+	let scene = pargs
+		.opt_value_from_str(["-S", "--scene"])?
+		.unwrap_or(WhichScene::Weekend);
+	let quality: Option<Quality> = pargs.opt_value_from_str("--quality")?;
+	// -w, -s, and -d still win over the preset
+	let preset = match quality {
+		Some(quality) => quality.apply(scene.recommended().unwrap_or(Recommended::DEFAULT)),
+		None => Recommended::DEFAULT,
+	};
+
 	let mut args = Args {
 		threads: pargs
 			.opt_value_from_str(["-t", "--threads"])?
 			.unwrap_or(system_threads()),
-		width: pargs.opt_value_from_str(["-w", "--width"])?.unwrap_or(600),
+		width: pargs
+			.opt_value_from_str(["-w", "--width"])?
+			.unwrap_or(preset.width),
 		samples: pargs
 			.opt_value_from_str(["-s", "--samples"])?
-			.unwrap_or(100),
-		depth: pargs.opt_value_from_str(["-d", "--depth"])?.unwrap_or(50),
+			.unwrap_or(preset.samples),
+		depth: pargs
+			.opt_value_from_str(["-d", "--depth"])?
+			.unwrap_or(preset.depth),
+		glass_depth_bonus: pargs
+			.opt_value_from_str("--glass-depth-bonus")?
+			.unwrap_or(0),
 		world_seed: pargs
 			.opt_value_from_str(["-r", "--world-seed"])?
 			.map(|seed| Ok::<u64, getrandom::Error>(seed))
@@ -244,14 +1293,17 @@ pub fn parse() -> Result<Args, Error> {
 			})?,
 		output: pargs.opt_value_from_str(["-o", "--output"])?,
 		verbose: pargs.contains(["-v", "--verbose"]),
-		scene: pargs
-			.opt_value_from_str(["-S", "--scene"])?
-			.unwrap_or(WhichScene::Weekend),
+		scene,
+		quality,
+		flake_depth: pargs.opt_value_from_str("--flake-depth")?.unwrap_or(4),
+		menger_level: pargs.opt_value_from_str("--menger-level")?.unwrap_or(3),
 		format: pargs
-			.opt_value_from_str(["-f", "--format"])?
+			.opt_value_from_fn(["-f", "--format"], |name| {
+				output::format_by_name(name).ok_or(ParseEnumError("format"))
+			})?
 			.unwrap_or_else(|| {
 				guess_format = true;
-				FileFormat::Ppm
+				&PPM
 			}),
 		bit_depth: pargs
 			.opt_value_from_str(["-b", "--bit-depth"])?
@@ -259,7 +1311,84 @@ pub fn parse() -> Result<Args, Error> {
 				guess_bit_depth = true;
 				0
 			}),
-		debug_mode: pargs.opt_value_from_str(["-D", "--debug"])?,
+		z_bit_depth: 0,
+		aovs: pargs.contains("--aovs"),
+		position: pargs.contains("--position"),
+		cryptomatte: pargs.contains("--cryptomatte"),
+		split_lighting: pargs.contains("--split-lighting"),
+		alpha: pargs.contains("--alpha"),
+		// the help has always said --debug-mode, but only --debug used to work, so accept both
+		debug_mode: match pargs.opt_value_from_str(["-D", "--debug-mode"])? {
+			Some(mode) => Some(mode),
+			None => pargs.opt_value_from_str("--debug")?,
+		},
+		override_material: pargs
+			.opt_value_from_fn("--override-material", parse_override_material)?
+			.flatten(),
+		debug_samples: pargs.opt_value_from_str("--debug-samples")?.unwrap_or(4),
+		debug_scale: pargs.opt_value_from_str("--debug-scale")?.unwrap_or(1),
+		denoise: pargs.opt_value_from_str("--denoise")?,
+		denoise_raw: pargs.contains("--denoise-raw"),
+		aberration: pargs.opt_value_from_str("--aberration")?,
+		vignette: pargs.opt_value_from_str("--vignette")?,
+		burn_in: pargs.contains("--burn-in"),
+		contact_sheet: pargs.contains("--contact-sheet"),
+		bake_texture: pargs.opt_value_from_str("--bake-texture")?,
+		texture_scale: pargs.opt_value_from_str("--texture-scale")?.unwrap_or(1.0),
+		texture_dir: pargs.opt_value_from_str("--texture-dir")?,
+		auto_expose: pargs.contains("--auto-expose"),
+		bake_exposure: pargs.contains("--bake-exposure"),
+		output_primaries: pargs
+			.opt_value_from_str("--output-primaries")?
+			.unwrap_or(Primaries::Srgb),
+		highlights: pargs
+			.opt_value_from_str("--highlights")?
+			.unwrap_or(HighlightMode::Clip),
+		fog: pargs.opt_value_from_str("--fog")?,
+		caustics: pargs.opt_value_from_str("--caustics")?,
+		shutter: pargs.opt_value_from_str("--shutter")?,
+		scene_stats: pargs.contains("--scene-stats"),
+		pick: pargs.opt_value_from_str("--pick")?,
+		focus_at: pargs.opt_value_from_str("--focus-at")?,
+		focus_distance: pargs.opt_value_from_str("--focus-distance")?,
+		f_stop: pargs.opt_value_from_str("--f-stop")?,
+		iso: pargs.opt_value_from_str("--iso")?,
+		shutter_speed: pargs.opt_value_from_fn("--shutter-speed", parse_seconds)?,
+		background_intensity: pargs
+			.opt_value_from_str("--background-intensity")?
+			.unwrap_or(1.0),
+		stereo: pargs.opt_value_from_str("--stereo")?,
+		ipd: pargs.opt_value_from_str("--ipd")?.unwrap_or(0.065),
+		output_depth: None,
+		depth_range: pargs.opt_value_from_str("--depth-range")?,
+		stats_out: pargs.opt_value_from_str("--stats-out")?,
+		dump_bvh: pargs.opt_value_from_str("--dump-bvh")?,
+		dump_bvh_depth: pargs.opt_value_from_str("--dump-bvh-depth")?,
+		stall_timeout: pargs.opt_value_from_str("--stall-timeout")?.unwrap_or(30),
+		abort_on_stall: pargs.contains("--abort-on-stall"),
+		preview_samples: pargs.opt_value_from_str("--preview-samples")?,
+		rng_engine: pargs
+			.opt_value_from_str("--rng-engine")?
+			.unwrap_or(RngEngine::Xoshiro),
+		tile_order: pargs
+			.opt_value_from_str("--tile-order")?
+			.unwrap_or(TileOrder::Cost),
+		png_chunk_size: pargs
+			.opt_value_from_str("--png-chunk-size")?
+			.unwrap_or(IDAT_SIZE),
+		png_compression: pargs
+			.opt_value_from_str("--png-compression")?
+			.unwrap_or(PngCompression::Default),
+		jpeg_quality: pargs.opt_value_from_str("--jpeg-quality")?.unwrap_or(90),
+		frames: pargs.opt_value_from_str("--frames")?,
+		fps: pargs.opt_value_from_str("--fps")?.unwrap_or(24),
+		camera_path: pargs.opt_value_from_str("--camera-path")?,
+		temporal_seed: pargs
+			.opt_value_from_str("--temporal-seed")?
+			.unwrap_or(TemporalSeed::Varying),
+		accumulate_frames: pargs.contains("--accumulate-frames"),
+		dry_run: pargs.contains("--dry-run"),
+		verify_output: pargs.contains("--verify-output"),
 	};
 
 	if args.threads == 0 {
@@ -270,6 +1399,257 @@ pub fn parse() -> Result<Args, Error> {
 			},
 		));
 	}
+	if args.stall_timeout == 0 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "0".to_string(),
+				cause: "stall timeout must be nonzero".to_string(),
+			},
+		));
+	}
+	if args.png_chunk_size == 0 || args.png_chunk_size > MAX_PNG_CHUNK_SIZE {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.png_chunk_size.to_string(),
+				cause: format!(
+					"PNG chunk size must be between 1 and {}",
+					MAX_PNG_CHUNK_SIZE
+				),
+			},
+		));
+	}
+	if !(1..=100).contains(&args.jpeg_quality) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.jpeg_quality.to_string(),
+				cause: "JPEG quality must be between 1 and 100".to_string(),
+			},
+		));
+	}
+	if args.width == 0 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "0".to_string(),
+				cause: "image width must be nonzero".to_string(),
+			},
+		));
+	}
+	if let Some(strength) = args.denoise {
+		if !(0.0..=1.0).contains(&strength) {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: strength.to_string(),
+					cause: "denoise strength must be between 0.0 and 1.0".to_string(),
+				},
+			));
+		}
+	}
+	if args.f_stop.is_none() && (args.iso.is_some() || args.shutter_speed.is_some()) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--iso".to_string(),
+				cause: "--iso and --shutter-speed need --f-stop".to_string(),
+			},
+		));
+	}
+	for value in [args.f_stop, args.iso, args.shutter_speed]
+		.into_iter()
+		.flatten()
+	{
+		if !value.is_finite() || value <= 0.0 {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: value.to_string(),
+					cause: "--f-stop, --iso, and --shutter-speed must be positive".to_string(),
+				},
+			));
+		}
+	}
+	if !(args.background_intensity.is_finite() && args.background_intensity >= 0.0) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.background_intensity.to_string(),
+				cause: "--background-intensity can't be negative".to_string(),
+			},
+		));
+	}
+	if let Some(distance) = args.focus_distance {
+		if args.focus_at.is_some() {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--focus-distance".to_string(),
+					cause: "--focus-at and --focus-distance can't be used together".to_string(),
+				},
+			));
+		}
+		if !distance.is_finite() || distance <= 0.0 {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: distance.to_string(),
+					cause: "focus distance must be positive".to_string(),
+				},
+			));
+		}
+	}
+	if args.flake_depth > MAX_FLAKE_DEPTH {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.flake_depth.to_string(),
+				cause: format!("flake depth must be at most {}", MAX_FLAKE_DEPTH),
+			},
+		));
+	}
+	if args.menger_level > MAX_MENGER_LEVEL {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.menger_level.to_string(),
+				cause: format!("menger level must be at most {}", MAX_MENGER_LEVEL),
+			},
+		));
+	}
+	if args.debug_samples == 0 || args.debug_scale == 0 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "0".to_string(),
+				cause: "--debug-samples and --debug-scale must be nonzero".to_string(),
+			},
+		));
+	}
+	if args.debug_mode.is_some() {
+		// debug views are flat colors, which don't need many samples to converge
+		args.samples = args.samples.min(args.debug_samples);
+	} else if args.debug_scale > 1 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--debug-scale".to_string(),
+				cause: "--debug-scale needs --debug-mode".to_string(),
+			},
+		));
+	}
+	if args.dump_bvh_depth.is_some() && args.dump_bvh.is_none() {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--dump-bvh-depth".to_string(),
+				cause: "--dump-bvh-depth needs --dump-bvh".to_string(),
+			},
+		));
+	}
+	if args.dump_bvh.is_some() && (args.contact_sheet || args.bake_texture.is_some()) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--dump-bvh".to_string(),
+				cause: "--dump-bvh can't be used with --contact-sheet or --bake-texture"
+					.to_string(),
+			},
+		));
+	}
+	if args.split_lighting {
+		if args.contact_sheet || args.bake_texture.is_some() {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--split-lighting".to_string(),
+					cause: "--split-lighting can't be used with --contact-sheet or --bake-texture"
+						.to_string(),
+				},
+			));
+		}
+		if args.output.is_none() {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--split-lighting".to_string(),
+					cause: "--split-lighting needs an output file to write the layers next to"
+						.to_string(),
+				},
+			));
+		}
+	}
+	if let Some(preview) = args.preview_samples {
+		if args.contact_sheet || args.bake_texture.is_some() {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--preview-samples".to_string(),
+					cause: "--preview-samples can't be used with --contact-sheet or --bake-texture"
+						.to_string(),
+				},
+			));
+		}
+		if args.output.is_none() {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--preview-samples".to_string(),
+					cause: "--preview-samples needs an output file to overwrite".to_string(),
+				},
+			));
+		}
+		if preview == 0 || preview >= args.samples {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: preview.to_string(),
+					cause: "preview samples must be nonzero and fewer than --samples".to_string(),
+				},
+			));
+		}
+	}
+	if args.fps == 0 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "0".to_string(),
+				cause: "frames per second must be nonzero".to_string(),
+			},
+		));
+	}
+	if args.quality.is_some() && (args.contact_sheet || args.bake_texture.is_some()) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--quality".to_string(),
+				cause: "--quality can't be used with --contact-sheet or --bake-texture".to_string(),
+			},
+		));
+	}
+	if args.stereo.is_some() && (args.contact_sheet || args.bake_texture.is_some()) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--stereo".to_string(),
+				cause: "--stereo can't be used with --contact-sheet or --bake-texture".to_string(),
+			},
+		));
+	}
+	if !args.ipd.is_finite() || args.ipd <= 0.0 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.ipd.to_string(),
+				cause: "eye distance must be positive".to_string(),
+			},
+		));
+	}
+	if args.bake_texture.is_some() && args.contact_sheet {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--bake-texture".to_string(),
+				cause: "--bake-texture and --contact-sheet can't be used together".to_string(),
+			},
+		));
+	}
+	if !args.texture_scale.is_finite() || args.texture_scale <= 0.0 {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.texture_scale.to_string(),
+				cause: "texture scale must be positive".to_string(),
+			},
+		));
+	}
+	for (name, strength) in [("aberration", args.aberration), ("vignette", args.vignette)] {
+		if let Some(strength) = strength {
+			if !(0.0..=1.0).contains(&strength) {
+				return Err(Error::PicoError(
+					pico_args::Error::Utf8ArgumentParsingFailed {
+						value: strength.to_string(),
+						cause: format!("{} strength must be between 0.0 and 1.0", name),
+					},
+				));
+			}
+		}
+	}
 	if let Some(ref s) = args.output {
 		if s.is_empty() {
 			return Err(Error::PicoError(
@@ -281,9 +1661,25 @@ pub fn parse() -> Result<Args, Error> {
 		}
 	}
 
+	if let Some(path) = pargs.opt_value_from_str::<_, String>("--output-depth")? {
+		match output::format_from_extension(&path) {
+			Some(format) if *format == PNG || *format == EXR => {
+				args.output_depth = Some((path, format))
+			},
+			_ => {
+				return Err(Error::PicoError(
+					pico_args::Error::Utf8ArgumentParsingFailed {
+						value: path,
+						cause: "depth output must be a .png or .exr file".to_string(),
+					},
+				))
+			},
+		}
+	}
+
 	if guess_format {
 		if let Some(ref s) = args.output {
-			if let Ok(format) = FileFormat::from_extension(s) {
+			if let Some(format) = output::format_from_extension(s) {
 				args.format = format;
 			} else {
 				return Err(Error::PicoError(
@@ -297,44 +1693,129 @@ pub fn parse() -> Result<Args, Error> {
 	}
 
 	if guess_bit_depth {
-		if args.format == FileFormat::Exr {
-			args.bit_depth = 32;
+		args.bit_depth = args.format.default_bit_depth;
+		if *args.format == EXR {
+			args.z_bit_depth = 32;
+		}
+	} else {
+		args.z_bit_depth = args.bit_depth;
+	}
+
+	if !args.format.bit_depths.contains(&args.bit_depth) {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: args.bit_depth.to_string(),
+				cause: format!(
+					"{} image bit depth must be {}",
+					args.format.title,
+					args.format.describe_bit_depths()
+				),
+			},
+		));
+	}
+	if args.alpha && !args.format.alpha {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--alpha".to_string(),
+				cause: format!("{} images can't have an alpha channel", args.format.title),
+			},
+		));
+	}
+	// after the format is guessed from the output's extension
+	if args.verify_output && *args.format != PNG {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--verify-output".to_string(),
+				cause: "only PNG output can be verified".to_string(),
+			},
+		));
+	}
+
+	if args.position && *args.format != EXR {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--position".to_string(),
+				cause: "only OpenEXR images can have a position layer".to_string(),
+			},
+		));
+	}
+
+	if args.cryptomatte {
+		let cause = if *args.format != EXR {
+			Some("only OpenEXR images can have Cryptomatte layers")
+		} else if args.contact_sheet || args.bake_texture.is_some() {
+			Some("--cryptomatte needs a single scene")
 		} else {
-			args.bit_depth = 8;
+			None
+		};
+		if let Some(cause) = cause {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--cryptomatte".to_string(),
+					cause: cause.to_string(),
+				},
+			));
 		}
 	}
 
-	match args.format {
-		FileFormat::Png => {
-			if args.bit_depth < 1 || args.bit_depth > 16 {
-				return Err(Error::PicoError(
-					pico_args::Error::Utf8ArgumentParsingFailed {
-						value: args.bit_depth.to_string(),
-						cause: "PNG image bit depth must be between 1 and 16".to_string(),
-					},
-				));
-			}
-		},
-		FileFormat::Ppm => {
-			if args.bit_depth < 1 || args.bit_depth > 8 {
-				return Err(Error::PicoError(
-					pico_args::Error::Utf8ArgumentParsingFailed {
-						value: args.bit_depth.to_string(),
-						cause: "PPM image bit depth must be between 1 and 8".to_string(),
-					},
-				));
-			}
-		},
-		FileFormat::Exr => {
-			if args.bit_depth != 16 && args.bit_depth != 32 {
-				return Err(Error::PicoError(
-					pico_args::Error::Utf8ArgumentParsingFailed {
-						value: args.bit_depth.to_string(),
-						cause: "OpenEXR image bit depth must be 16 or 32".to_string(),
-					},
-				));
-			}
-		},
+	if let Some(frames) = args.frames {
+		if frames == 0 {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: frames.to_string(),
+					cause: "number of frames must be nonzero".to_string(),
+				},
+			));
+		}
+		if args.contact_sheet
+			|| args.bake_texture.is_some()
+			|| args.preview_samples.is_some()
+			|| args.output_depth.is_some()
+			|| args.split_lighting
+		{
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--frames".to_string(),
+					cause: "--frames can't be used with --contact-sheet, --bake-texture, \
+					        --preview-samples, --output-depth, or --split-lighting"
+						.to_string(),
+				},
+			));
+		}
+		if *args.format != PNG && *args.format != RAWVIDEO {
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--frames".to_string(),
+					cause: "animations can only be written as PNG or raw video".to_string(),
+				},
+			));
+		}
+		if args.accumulate_frames
+			&& (args.camera_path.is_some() || args.temporal_seed == TemporalSeed::Fixed)
+		{
+			return Err(Error::PicoError(
+				pico_args::Error::Utf8ArgumentParsingFailed {
+					value: "--accumulate-frames".to_string(),
+					cause: "--accumulate-frames needs a still camera and varying seeds, so it \
+					        can't be used with --camera-path or --temporal-seed fixed"
+						.to_string(),
+				},
+			));
+		}
+	} else if args.camera_path.is_some() || args.accumulate_frames {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "--frames".to_string(),
+				cause: "--camera-path and --accumulate-frames need --frames".to_string(),
+			},
+		));
+	} else if *args.format == RAWVIDEO {
+		return Err(Error::PicoError(
+			pico_args::Error::Utf8ArgumentParsingFailed {
+				value: "rawvideo".to_string(),
+				cause: "raw video needs --frames".to_string(),
+			},
+		));
 	}
 
 	let rest = pargs.finish();
@@ -351,3 +1832,41 @@ pub fn parse() -> Result<Args, Error> {
 
 	Ok(args)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_str(arguments: &str) -> Args {
+		let arguments = arguments.split(' ').map(|a| a.into()).collect();
+		match parse_from(arguments) {
+			Ok(args) => args,
+			Err(e) => panic!("{}", e),
+		}
+	}
+
+	#[test]
+	fn test_quality() {
+		let args = parse_str("-r 1 -R 2 -S week --quality draft");
+		assert_eq!((args.width, args.samples, args.depth), (800, 250, 100));
+		let args = parse_str("-r 1 -R 2 -S week --quality final -s 10 -w 100");
+		assert_eq!((args.width, args.samples, args.depth), (100, 10, 100));
+		// without a recommendation, the global defaults are scaled
+		let args = parse_str("-r 1 -R 2 -S menger --quality draft");
+		assert_eq!((args.width, args.samples, args.depth), (600, 12, 50));
+		let args = parse_str("-r 1 -R 2 -S week");
+		assert_eq!((args.width, args.samples, args.depth), (600, 100, 50));
+	}
+
+	#[test]
+	fn test_debug_samples() {
+		let args = parse_str("-r 1 -R 2 -D bvh");
+		assert_eq!((args.samples, args.debug_scale), (4, 1));
+		let args = parse_str("-r 1 -R 2 -D depth -s 2 --debug-scale 2");
+		assert_eq!((args.samples, args.debug_scale), (2, 2));
+		let args = parse_str("-r 1 -R 2 -D id -s 50 --debug-samples 20");
+		assert_eq!(args.samples, 20);
+		let arguments = ["--debug-scale", "2"].map(|a| a.into()).to_vec();
+		assert!(parse_from(arguments).is_err());
+	}
+}
@@ -0,0 +1,177 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// how many directories above the executable's to look in, which covers running
+/// target/release/raytracing from outside of the repository
+const EXE_ANCESTORS: usize = 3;
+
+/// Assets built into the program, by the path they're normally loaded from
+#[cfg(feature = "embedded-assets")]
+const EMBEDDED: &[(&str, &[u8])] = &[
+	// scaled down from 1024x512 to keep the program small
+	(
+		"textures/earthmap.jpg",
+		include_bytes!("../../textures/earthmap-512.jpg"),
+	),
+	("lights/spot.ies", include_bytes!("../../lights/spot.ies")),
+];
+#[cfg(not(feature = "embedded-assets"))]
+const EMBEDDED: &[(&str, &[u8])] = &[];
+
+/// An asset file that was found, or the copy built into the program
+#[derive(Debug, Clone)]
+pub enum Asset {
+	File(PathBuf),
+	Embedded(&'static [u8]),
+}
+
+/// Where to look for the textures and light profiles that scenes load, so that the program works
+/// when it's run from somewhere other than the repository
+#[derive(Debug, Clone, Default)]
+pub struct AssetDirs {
+	/// from --texture-dir
+	texture_dir: Option<PathBuf>,
+	/// the directory holding the executable, if the OS says
+	exe_dir: Option<PathBuf>,
+}
+
+impl AssetDirs {
+	/// texture_dir: a directory given by the user, either the one holding the assets or one above
+	///              it like the repository root
+	pub fn new(texture_dir: Option<&str>) -> AssetDirs {
+		AssetDirs {
+			texture_dir: texture_dir.map(PathBuf::from),
+			exe_dir: std::env::current_exe()
+				.ok()
+				.and_then(|exe| exe.parent().map(Path::to_path_buf)),
+		}
+	}
+
+	/// Every place that path could be, in the order they're tried: as given, in texture_dir, then
+	/// in the executable's directory and the ones above it
+	fn candidates(&self, path: &Path) -> Vec<PathBuf> {
+		let mut candidates = vec![path.to_path_buf()];
+		if path.is_absolute() {
+			return candidates;
+		}
+		if let Some(ref dir) = self.texture_dir {
+			candidates.push(dir.join(path));
+			if let Some(name) = path.file_name() {
+				candidates.push(dir.join(name));
+			}
+		}
+		if let Some(ref dir) = self.exe_dir {
+			candidates.extend(dir.ancestors().take(EXE_ANCESTORS).map(|d| d.join(path)));
+		}
+		candidates.dedup();
+		candidates
+	}
+
+	/// The copy of path built into the program, if there is one
+	pub fn embedded(path: &str) -> Option<&'static [u8]> {
+		EMBEDDED
+			.iter()
+			.find(|&&(name, _)| name == path)
+			.map(|&(_, bytes)| bytes)
+	}
+
+	/// Like resolve, but falling back to the copy built into the program with a warning
+	pub fn find(&self, path: &str) -> io::Result<Asset> {
+		match self.resolve(path) {
+			Ok(found) => Ok(Asset::File(found)),
+			Err(e) => match AssetDirs::embedded(path) {
+				Some(bytes) => {
+					eprintln!(
+						"warning: {} not found, using the copy built into the program",
+						path
+					);
+					Ok(Asset::Embedded(bytes))
+				},
+				None => Err(e),
+			},
+		}
+	}
+
+	/// The first place that path exists, or a NotFound error listing everywhere that was tried
+	pub fn resolve(&self, path: impl AsRef<Path>) -> io::Result<PathBuf> {
+		let candidates = self.candidates(path.as_ref());
+		if let Some(found) = candidates.iter().find(|c| c.is_file()) {
+			return Ok(found.clone());
+		}
+		let tried: Vec<String> = candidates.iter().map(|c| c.display().to_string()).collect();
+		Err(io::Error::new(
+			io::ErrorKind::NotFound,
+			format!(
+				"couldn't find {} (tried {}). use --texture-dir to say where it is",
+				path.as_ref().display(),
+				tried.join(", ")
+			),
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+
+	#[test]
+	fn test_resolution_order() {
+		let root = std::env::temp_dir().join(format!("raytracing-assets-{}", std::process::id()));
+		let (exe_dir, texture_dir) = (root.join("target/release"), root.join("custom"));
+		fs::create_dir_all(&exe_dir).unwrap();
+		fs::create_dir_all(texture_dir.join("textures")).unwrap();
+		fs::create_dir_all(root.join("textures")).unwrap();
+		let assets = AssetDirs {
+			texture_dir: Some(texture_dir.clone()),
+			exe_dir: Some(exe_dir.clone()),
+		};
+		let path = Path::new("textures/raytracing-assets-test.png");
+		let write = |p: &Path| fs::write(p, b"").unwrap();
+
+		// nowhere, and the error says where it looked
+		let e = assets.resolve(path).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::NotFound);
+		let message = e.to_string();
+		assert!(message.contains(&texture_dir.join(path).display().to_string()));
+		assert!(message.contains(&root.join(path).display().to_string()));
+
+		// two directories above the executable, like the repository root
+		write(&root.join(path));
+		assert_eq!(assets.resolve(path).unwrap(), root.join(path));
+		// the executable's own directory comes first
+		fs::create_dir_all(exe_dir.join("textures")).unwrap();
+		write(&exe_dir.join(path));
+		assert_eq!(assets.resolve(path).unwrap(), exe_dir.join(path));
+		// then the texture directory, with or without the path's directories
+		write(&texture_dir.join(path.file_name().unwrap()));
+		let expected = texture_dir.join(path.file_name().unwrap());
+		assert_eq!(assets.resolve(path).unwrap(), expected);
+		write(&texture_dir.join(path));
+		assert_eq!(assets.resolve(path).unwrap(), texture_dir.join(path));
+
+		// an absolute path is only ever itself
+		let absolute = root.join("missing.png");
+		assert_eq!(assets.candidates(&absolute), [absolute]);
+
+		fs::remove_dir_all(&root).unwrap();
+	}
+	#[test]
+	#[cfg(feature = "embedded-assets")]
+	fn test_embedded() {
+		use crate::object::texture::ImageTexture;
+		use crate::scene::IesProfile;
+
+		let earth = AssetDirs::embedded("textures/earthmap.jpg").unwrap();
+		let earth = ImageTexture::from_memory(earth).unwrap();
+		assert_eq!(earth.to_string(), "ImageTexture(512x256)");
+		let spot = AssetDirs::embedded("lights/spot.ies").unwrap();
+		let spot = IesProfile::parse(std::str::from_utf8(spot).unwrap()).unwrap();
+		assert_eq!(
+			spot.intensity(0.0),
+			IesProfile::open("lights/spot.ies").unwrap().intensity(0.0)
+		);
+		assert!(AssetDirs::embedded("textures/missing.jpg").is_none());
+	}
+}
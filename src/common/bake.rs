@@ -0,0 +1,40 @@
+use super::{Color, ImageBuffer, Point3};
+use crate::object::Texture;
+
+/// Evaluate a texture over its whole (u, v) range, with u going left to right and v going bottom
+/// to top, like the equirectangular maps that spheres use. Each pixel is sampled at its center
+///
+/// size:  width and height of the image in pixels
+/// scale: the point passed to the texture is on the plane z = 0, going from 0 to scale along x
+///        with u and along y with v. solid textures like noise only depend on this
+pub fn bake_texture(
+	texture: &dyn Texture,
+	(width, height): (usize, usize),
+	scale: f64,
+) -> ImageBuffer<Color> {
+	ImageBuffer::from_fn((width, height), |x, y| {
+		let u = (x as f64 + 0.5) / width as f64;
+		let v = 1.0 - (y as f64 + 0.5) / height as f64;
+		texture.value(u, v, Point3::new(u * scale, v * scale, 0.0))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::object::texture::FunctionTexture;
+
+	#[test]
+	fn test_bake_texture() {
+		let texture = FunctionTexture(|u, v, p: Point3| Color::new(u, v, p.x() + p.y() + p.z()));
+		let image = bake_texture(&texture, (4, 2), 8.0);
+		assert_eq!(image.size(), (4, 2));
+
+		// v = 1 is at the top
+		let (top_left, bottom_right) = (image[(0, 0)], image[(3, 1)]);
+		assert_eq!((top_left.x(), top_left.y()), (0.125, 0.75));
+		assert_eq!((bottom_right.x(), bottom_right.y()), (0.875, 0.25));
+		assert_eq!(top_left.z(), (0.125 + 0.75) * 8.0);
+		assert_eq!(bottom_right.z(), (0.875 + 0.25) * 8.0);
+	}
+}
@@ -94,6 +94,107 @@ fn linear_to_srgb(value: f64) -> f64 {
 	}
 }
 
+/// Sets of RGB primaries that images can be output in. Rendering always happens in linear sRGB
+/// (Rec. 709) primaries, and colors are converted right before being encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primaries {
+	Srgb,
+	DisplayP3,
+	Rec2020,
+}
+
+/// CIE 1931 xy coordinates of a color space's red, green, blue, and white points
+#[derive(Debug, Clone, Copy)]
+pub struct Chromaticities {
+	pub red: (f64, f64),
+	pub green: (f64, f64),
+	pub blue: (f64, f64),
+	pub white: (f64, f64),
+}
+
+/// How to handle colors that are too bright to display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightMode {
+	/// clamp each channel independently, which shifts the hue of bright saturated colors
+	Clip,
+	/// desaturate toward gray of the same luminance until every channel fits, keeping the hue
+	PreserveHue,
+}
+
+type Matrix3 = [[f64; 3]; 3];
+
+fn matrix_mul(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+	std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn matrix_apply(m: &Matrix3, c: Color) -> Color {
+	Color::new(
+		m[0][0] * c.x() + m[0][1] * c.y() + m[0][2] * c.z(),
+		m[1][0] * c.x() + m[1][1] * c.y() + m[1][2] * c.z(),
+		m[2][0] * c.x() + m[2][1] * c.y() + m[2][2] * c.z(),
+	)
+}
+
+fn matrix_inverse(m: &Matrix3) -> Matrix3 {
+	let cofactor = |i: usize, j: usize| {
+		let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+		let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+		m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+	};
+	let det = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum::<f64>();
+	// the inverse is the transposed cofactor matrix over the determinant
+	std::array::from_fn(|i| std::array::from_fn(|j| cofactor(j, i) / det))
+}
+
+impl Primaries {
+	pub fn chromaticities(&self) -> Chromaticities {
+		let d65 = (0.3127, 0.3290);
+		match self {
+			Primaries::Srgb => Chromaticities {
+				red: (0.64, 0.33),
+				green: (0.30, 0.60),
+				blue: (0.15, 0.06),
+				white: d65,
+			},
+			Primaries::DisplayP3 => Chromaticities {
+				red: (0.680, 0.320),
+				green: (0.265, 0.690),
+				blue: (0.150, 0.060),
+				white: d65,
+			},
+			Primaries::Rec2020 => Chromaticities {
+				red: (0.708, 0.292),
+				green: (0.170, 0.797),
+				blue: (0.131, 0.046),
+				white: d65,
+			},
+		}
+	}
+
+	/// matrix converting linear RGB in these primaries to CIE XYZ
+	fn xyz_matrix(&self) -> Matrix3 {
+		let c = self.chromaticities();
+		let xyz = |(x, y): (f64, f64)| [x / y, 1.0, (1.0 - x - y) / y];
+		let (r, g, b) = (xyz(c.red), xyz(c.green), xyz(c.blue));
+		let unscaled = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+		// scale each primary so that (1, 1, 1) lands on the white point
+		let white = xyz(c.white);
+		let scale = matrix_apply(
+			&matrix_inverse(&unscaled),
+			Color::new(white[0], white[1], white[2]),
+		);
+		std::array::from_fn(|i| std::array::from_fn(|j| unscaled[i][j] * scale[j]))
+	}
+
+	/// matrix converting linear RGB in the rendering primaries (sRGB) to these primaries
+	pub fn conversion_matrix(&self) -> Matrix3 {
+		matrix_mul(
+			&matrix_inverse(&self.xyz_matrix()),
+			&Primaries::Srgb.xyz_matrix(),
+		)
+	}
+}
+
 impl Color {
 	pub fn from_srgb(r: u8, g: u8, b: u8) -> Color {
 		Color::new(
@@ -111,6 +212,14 @@ impl Color {
 		)
 	}
 
+	/// the inverse of from_srgb_hex, for colors that came from it
+	pub fn to_srgb_hex(self) -> u32 {
+		let srgb = self.oetf();
+		[srgb.x(), srgb.y(), srgb.z()].iter().fold(0, |code, c| {
+			(code << 8) | (c.clamp(0.0, 1.0) * 255.0).round() as u32
+		})
+	}
+
 	fn oetf(&self) -> Color {
 		Color::new(
 			linear_to_srgb(self.x()),
@@ -119,18 +228,72 @@ impl Color {
 		)
 	}
 
-	fn clamp(&self) -> Color {
-		Color::new(
-			self.x().clamp(0.0, 1.0),
-			self.y().clamp(0.0, 1.0),
-			self.z().clamp(0.0, 1.0),
-		)
+	/// convert a linear color from the working space into the given output primaries
+	pub fn to_primaries(self, primaries: Primaries) -> Color {
+		if primaries == Primaries::Srgb {
+			return self;
+		}
+		matrix_apply(&primaries.conversion_matrix(), self)
+	}
+
+	/// Bring a color inside the gamut of whichever primaries it is expressed in. This is just a
+	/// per-channel clamp of negative values to zero; overly bright channels are left for
+	/// tonemapping to deal with.
+	pub fn clip_gamut(&self) -> Color {
+		Color::new(self.x().max(0.0), self.y().max(0.0), self.z().max(0.0))
+	}
+
+	/// Bring a linear color into the displayable range without changing its hue or luminance, by
+	/// mixing it with gray of equal luminance. Colors too bright for that are scaled down until
+	/// their brightest channel is 1 instead, which keeps the hue but not the luminance
+	fn preserve_hue(&self) -> Color {
+		let max = self.max_component();
+		if max <= 1.0 {
+			return *self;
+		}
+		let luminance = self.luminance();
+		if luminance >= 1.0 {
+			return *self / max;
+		}
+
+		let gray = Color::new(luminance, luminance, luminance);
+		// how much of the color's difference from gray can be kept so that the brightest channel
+		// ends up at exactly 1
+		let saturation = (1.0 - luminance) / (max - luminance);
+		gray + (*self - gray) * saturation
+	}
+
+	pub fn tonemap(&self, highlights: HighlightMode) -> Color {
+		match highlights {
+			HighlightMode::Clip => self.oetf().clamp(0.0, 1.0),
+			HighlightMode::PreserveHue => self.preserve_hue().oetf().clamp(0.0, 1.0),
+		}
+	}
+
+	/// relative luminance, using the Rec. 709 (sRGB) coefficients
+	pub fn luminance(&self) -> f64 {
+		0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+	}
+
+	/// whether any channel will be cut off when the color is tonemapped
+	pub fn clips(&self) -> bool {
+		let encoded = self.oetf();
+		(0..3).any(|i| encoded[i] > 1.0)
+	}
+
+	/// whether no channel is infinite or NaN
+	pub fn is_finite(&self) -> bool {
+		self.x().is_finite() && self.y().is_finite() && self.z().is_finite()
 	}
 
-	pub fn tonemap(&self) -> Color {
-		self.oetf().clamp()
+	/// a at t = 0 to b at t = 1, separately for each channel. t isn't clamped
+	pub fn lerp(a: Color, b: Color, t: Color) -> Color {
+		a + (b - a) * t
 	}
 
+	/// The fully saturated, full brightness color with the same hue, like setting saturation and
+	/// value to 1 in HSV: the darkest channel goes to 0, the brightest to 1, and the middle one
+	/// keeps its place between them. Grays have no hue, so they become black or white
 	pub fn saturate(&self) -> Color {
 		let epsilon = 1e-8;
 		if (self.x() - self.y()).abs() < epsilon && (self.x() - self.z()).abs() < epsilon {
@@ -158,3 +321,267 @@ impl Color {
 		return Color::new(channels[0].0, channels[1].0, channels[2].0);
 	}
 }
+
+pub const HISTOGRAM_BUCKETS: usize = 64;
+/// range of log2(luminance) covered by the histogram. values outside of it go into the first or
+/// last bucket
+const HISTOGRAM_MIN_STOPS: f64 = -16.0;
+const HISTOGRAM_MAX_STOPS: f64 = 16.0;
+
+/// Luminance statistics of a rendered (linear, not yet tonemapped) image
+pub struct ImageStats {
+	pub min_luminance: f64,
+	pub max_luminance: f64,
+	pub mean_luminance: f64,
+	/// number of pixels in each bucket, evenly spaced in log2(luminance)
+	pub histogram: [usize; HISTOGRAM_BUCKETS],
+	/// fraction of pixels in which at least one channel clips when tonemapped, from 0 to 1
+	pub clipped_fraction: f64,
+	/// every luminance in the image, sorted ascending
+	sorted_luminance: Vec<f64>,
+}
+
+impl ImageStats {
+	pub fn new<'a>(pixels: impl IntoIterator<Item = &'a Color>) -> ImageStats {
+		let mut histogram = [0usize; HISTOGRAM_BUCKETS];
+		let mut sorted_luminance = Vec::new();
+		let mut clipped = 0usize;
+		let mut sum = 0.0;
+
+		for p in pixels {
+			let luminance = p.luminance();
+			let stops = luminance
+				.log2()
+				.clamp(HISTOGRAM_MIN_STOPS, HISTOGRAM_MAX_STOPS);
+			let bucket = ((stops - HISTOGRAM_MIN_STOPS)
+				/ (HISTOGRAM_MAX_STOPS - HISTOGRAM_MIN_STOPS)
+				* HISTOGRAM_BUCKETS as f64) as usize;
+			// NaN luminance casts to 0, so it lands in the bottom bucket
+			histogram[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+
+			if p.clips() {
+				clipped += 1;
+			}
+			sum += luminance;
+			sorted_luminance.push(luminance);
+		}
+
+		sorted_luminance.sort_by(f64::total_cmp);
+		let count = sorted_luminance.len().max(1) as f64;
+		ImageStats {
+			min_luminance: sorted_luminance.first().copied().unwrap_or(0.0),
+			max_luminance: sorted_luminance.last().copied().unwrap_or(0.0),
+			mean_luminance: sum / count,
+			histogram,
+			clipped_fraction: clipped as f64 / count,
+			sorted_luminance,
+		}
+	}
+
+	/// luminance below which the given fraction (0 to 1) of the pixels fall
+	pub fn percentile(&self, fraction: f64) -> f64 {
+		if self.sorted_luminance.is_empty() {
+			return 0.0;
+		}
+		let index = (fraction.clamp(0.0, 1.0) * (self.sorted_luminance.len() - 1) as f64).round();
+		self.sorted_luminance[index as usize]
+	}
+
+	/// exposure multiplier that maps the 99th percentile luminance to 1.0
+	pub fn auto_exposure(&self) -> f64 {
+		let reference = self.percentile(0.99);
+		if reference > 0.0 && reference.is_finite() {
+			1.0 / reference
+		} else {
+			1.0
+		}
+	}
+
+	/// draw the histogram as a single line of text, one character per bucket
+	pub fn histogram_line(&self) -> String {
+		let ramp = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+		let peak = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+		self.histogram
+			.iter()
+			.map(|&n| {
+				if n == 0 {
+					ramp[0]
+				} else {
+					// any nonempty bucket should show up, even if it's tiny compared to the peak
+					ramp[1 + n * (ramp.len() - 2) / peak]
+				}
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_stats_uniform() {
+		let pixels = vec![Color::new(0.5, 0.5, 0.5); 100];
+		let stats = ImageStats::new(&pixels);
+		assert!((stats.min_luminance - 0.5).abs() < 1e-9);
+		assert!((stats.max_luminance - 0.5).abs() < 1e-9);
+		assert!((stats.mean_luminance - 0.5).abs() < 1e-9);
+		assert_eq!(stats.clipped_fraction, 0.0);
+		// log2(0.5) = -1 is 15 stops above the bottom of the histogram, at 2 buckets per stop
+		assert_eq!(stats.histogram[30], 100);
+		assert_eq!(stats.histogram.iter().sum::<usize>(), 100);
+	}
+
+	#[test]
+	fn test_stats_clipping_and_percentile() {
+		let mut pixels: Vec<Color> = (0..100)
+			.map(|i| {
+				let v = i as f64 / 100.0;
+				Color::new(v, v, v)
+			})
+			.collect();
+		pixels[0] = Color::new(4.0, 0.0, 0.0);
+		pixels[1] = Color::new(0.0, 2.0, 0.0);
+		let stats = ImageStats::new(&pixels);
+		assert!((stats.clipped_fraction - 0.02).abs() < 1e-9);
+		assert!((stats.max_luminance - 2.0 * 0.7152).abs() < 1e-9);
+		// the two overwritten pixels move up past the middle, so the median is 0.52 rather than 0.5
+		assert!((stats.percentile(0.5) - 0.52).abs() < 1e-9);
+	}
+
+	#[test]
+	fn test_primaries_round_trip() {
+		let colors = [
+			Color::new(1.0, 0.0, 0.0),
+			Color::new(0.2, 0.7, 0.1),
+			Color::new(3.0, 1.5, 0.25),
+		];
+		for primaries in [Primaries::DisplayP3, Primaries::Rec2020] {
+			let back = matrix_inverse(&primaries.conversion_matrix());
+			for c in colors {
+				let round_trip = matrix_apply(&back, c.to_primaries(primaries));
+				assert!(
+					(round_trip - c).length() < 1e-6,
+					"{} became {}",
+					c,
+					round_trip
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_primaries_preserve_white() {
+		let white = Color::new(1.0, 1.0, 1.0);
+		for primaries in [Primaries::Srgb, Primaries::DisplayP3, Primaries::Rec2020] {
+			assert!((white.to_primaries(primaries) - white).length() < 1e-6);
+		}
+		// sRGB red is inside the P3 gamut, so it should have no negative components there
+		let red = Color::new(1.0, 0.0, 0.0).to_primaries(Primaries::DisplayP3);
+		assert!(red.x() < 1.0 && red.y() > 0.0 && red.z() > 0.0);
+		assert_eq!(Color::new(-0.1, 0.5, 2.0).clip_gamut().x(), 0.0);
+	}
+
+	#[test]
+	fn test_preserve_hue() {
+		let in_range = Color::new(0.5, 0.2, 0.1);
+		assert!((in_range.preserve_hue() - in_range).length() < 1e-12);
+
+		// brighter than white overall still looks orange, just darker
+		let orange = Color::new(4.0, 2.0, 0.5);
+		let mapped = orange.preserve_hue();
+		assert!(
+			mapped.x() > mapped.y() && mapped.y() > mapped.z(),
+			"{}",
+			mapped
+		);
+		assert!((mapped - Color::new(1.0, 0.5, 0.125)).length() < 1e-12);
+
+		// a saturated red that only overflows in one channel keeps its luminance and hue
+		let red = Color::new(1.5, 0.2, 0.2);
+		let mapped = red.preserve_hue();
+		assert!((mapped.luminance() - red.luminance()).abs() < 1e-12);
+		assert!((mapped.x() - 1.0).abs() < 1e-12);
+		assert!((mapped.y() - mapped.z()).abs() < 1e-12);
+		// luminance is 0.4764, so about 51% of the distance from gray is kept
+		assert!((mapped.y() - 0.3350).abs() < 1e-4);
+
+		// clipping the same color just throws away the extra red
+		let clipped = red.tonemap(HighlightMode::Clip);
+		assert_eq!(clipped.x(), 1.0);
+		assert!(clipped.y() < red.tonemap(HighlightMode::PreserveHue).y());
+	}
+
+	fn channels(c: Color) -> [f64; 3] {
+		[c.x(), c.y(), c.z()]
+	}
+
+	#[test]
+	fn test_saturate() {
+		let [r, g, b] = channels(Color::new(0.4, 0.25, 0.1).saturate());
+		assert_eq!((r, b), (1.0, 0.0));
+		assert!((g - 0.5).abs() < 1e-12);
+		// two channels tied for brightest or darkest
+		assert_eq!(
+			channels(Color::new(0.3, 0.3, 0.1).saturate()),
+			[1.0, 1.0, 0.0]
+		);
+		assert_eq!(
+			channels(Color::new(0.2, 0.1, 0.1).saturate()),
+			[1.0, 0.0, 0.0]
+		);
+		assert_eq!(channels(Color::new(0.2, 0.2, 0.2).saturate()), [0.0; 3]);
+		assert_eq!(
+			channels(Color::new(0.7, 0.7, 0.7).saturate()),
+			[1.0, 1.0, 1.0]
+		);
+	}
+
+	#[test]
+	fn test_luminance() {
+		assert!((Color::new(1.0, 1.0, 1.0).luminance() - 1.0).abs() < 1e-12);
+		assert_eq!(Color::new(0.0, 1.0, 0.0).luminance(), 0.7152);
+		// green looks much brighter than blue of the same value
+		assert!(Color::new(0.0, 0.5, 0.0).luminance() > Color::new(0.0, 0.0, 1.0).luminance());
+	}
+
+	#[test]
+	fn test_lerp() {
+		let (a, b) = (Color::new(0.0, 1.0, 2.0), Color::new(1.0, 1.0, 0.0));
+		assert_eq!(channels(Color::lerp(a, b, Color::zero())), channels(a));
+		assert_eq!(
+			channels(Color::lerp(a, b, Color::new(1.0, 1.0, 1.0))),
+			channels(b)
+		);
+		assert_eq!(
+			channels(Color::lerp(a, b, Color::new(0.5, 0.0, 2.0))),
+			[0.5, 1.0, -2.0]
+		);
+	}
+
+	#[test]
+	fn test_max_component() {
+		assert_eq!(Color::new(0.1, 3.0, -5.0).max_component(), 3.0);
+		assert_eq!(Color::new(-1.0, -2.0, -0.5).max_component(), -0.5);
+	}
+
+	#[test]
+	fn test_is_finite() {
+		assert!(Color::new(1e300, -2.0, 0.0).is_finite());
+		assert!(!Color::new(0.0, f64::NAN, 0.0).is_finite());
+		assert!(!Color::new(0.0, 0.0, f64::INFINITY).is_finite());
+	}
+
+	#[test]
+	fn test_auto_exposure() {
+		let mut pixels = vec![Color::new(2.0, 2.0, 2.0); 1000];
+		// a handful of very bright pixels shouldn't affect the exposure
+		pixels[0] = Color::new(1000.0, 1000.0, 1000.0);
+		pixels[1] = Color::new(1000.0, 1000.0, 1000.0);
+		let stats = ImageStats::new(&pixels);
+		assert!((stats.auto_exposure() - 0.5).abs() < 1e-9);
+
+		let black = vec![Color::zero(); 10];
+		assert_eq!(ImageStats::new(&black).auto_exposure(), 1.0);
+	}
+}
@@ -0,0 +1,349 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const FILE_NAME: &str = "raytracing.toml";
+pub const ENV_PREFIX: &str = "RAYTRACING_";
+
+/// Every option that can be given a default, as its long name, short name, and whether it's a flag
+/// that doesn't take a value
+const OPTIONS: [(&str, Option<char>, bool); 70] = [
+	("threads", Some('t'), false),
+	("width", Some('w'), false),
+	("samples", Some('s'), false),
+	("depth", Some('d'), false),
+	("glass-depth-bonus", None, false),
+	("world-seed", Some('r'), false),
+	("sample-seed", Some('R'), false),
+	("output", Some('o'), false),
+	("verbose", Some('v'), true),
+	("scene", Some('S'), false),
+	("quality", None, false),
+	("flake-depth", None, false),
+	("menger-level", None, false),
+	("format", Some('f'), false),
+	("bit-depth", Some('b'), false),
+	("aovs", None, true),
+	("position", None, true),
+	("cryptomatte", None, true),
+	("split-lighting", None, true),
+	("alpha", None, true),
+	("debug-mode", Some('D'), false),
+	("override-material", None, false),
+	("denoise", None, false),
+	("denoise-raw", None, true),
+	("aberration", None, false),
+	("vignette", None, false),
+	("burn-in", None, true),
+	("contact-sheet", None, true),
+	("bake-texture", None, false),
+	("texture-scale", None, false),
+	("auto-expose", None, true),
+	("bake-exposure", None, true),
+	("output-primaries", None, false),
+	("highlights", None, false),
+	("fog", None, false),
+	("caustics", None, false),
+	("shutter", None, false),
+	("scene-stats", None, true),
+	("pick", None, false),
+	("focus-at", None, false),
+	("focus-distance", None, false),
+	("stereo", None, false),
+	("ipd", None, false),
+	("output-depth", None, false),
+	("depth-range", None, false),
+	("stats-out", None, false),
+	("stall-timeout", None, false),
+	("abort-on-stall", None, true),
+	("preview-samples", None, false),
+	("tile-order", None, false),
+	("rng-engine", None, false),
+	("png-chunk-size", None, false),
+	("png-compression", None, false),
+	("jpeg-quality", None, false),
+	("frames", None, false),
+	("fps", None, false),
+	("camera-path", None, false),
+	("temporal-seed", None, false),
+	("accumulate-frames", None, true),
+	("verify-output", None, true),
+	("debug-samples", None, false),
+	("debug-scale", None, false),
+	("debug", None, false),
+	("dump-bvh", None, false),
+	("dump-bvh-depth", None, false),
+	("texture-dir", None, false),
+	("f-stop", None, false),
+	("iso", None, false),
+	("shutter-speed", None, false),
+	("background-intensity", None, false),
+];
+
+/// the options read from a config file, as keys and values in the order they appear
+pub struct ConfigFile {
+	pub path: String,
+	pub options: Vec<(String, String)>,
+}
+
+/// Where the config file is looked for, in order: the current directory, then the XDG config
+/// directory
+fn file_paths() -> Vec<PathBuf> {
+	let mut paths = vec![PathBuf::from(FILE_NAME)];
+	let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+		_ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+	};
+	if let Some(dir) = config_dir {
+		paths.push(dir.join(FILE_NAME));
+	}
+	paths
+}
+
+/// Read the first config file that exists
+pub fn load_file() -> Result<Option<ConfigFile>, String> {
+	for path in file_paths() {
+		let name = path.display().to_string();
+		match fs::read_to_string(&path) {
+			Ok(contents) => {
+				let options = parse_file(&contents).map_err(|e| format!("{}: {}", name, e))?;
+				return Ok(Some(ConfigFile {
+					path: name,
+					options,
+				}));
+			},
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(format!("failed to read {}: {}", name, e)),
+		}
+	}
+	Ok(None)
+}
+
+/// Options set by RAYTRACING_* environment variables, with the prefix taken off and the rest
+/// lowercased, so that RAYTRACING_WORLD_SEED sets world-seed
+pub fn env_options() -> Vec<(String, String)> {
+	env::vars_os()
+		.filter_map(|(key, value)| {
+			let key = key.into_string().ok()?;
+			let name = key
+				.strip_prefix(ENV_PREFIX)?
+				.to_lowercase()
+				.replace('_', "-");
+			Some((name, value.into_string().ok()?))
+		})
+		.collect()
+}
+
+/// Parse the part of TOML that the config file uses: `key = value` on each line, where value is a
+/// string, number, or boolean. Comments start with #. Returns the keys and values in order, with
+/// quotes taken off of strings
+pub fn parse_file(contents: &str) -> Result<Vec<(String, String)>, String> {
+	let mut options = Vec::new();
+	for (i, line) in contents.lines().enumerate() {
+		let error = |message: &str| format!("line {}: {}", i + 1, message);
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		if line.starts_with('[') {
+			return Err(error("tables aren't supported"));
+		}
+		let (key, value) = line
+			.split_once('=')
+			.ok_or_else(|| error("expected key = value"))?;
+		let key = key.trim();
+		if key.is_empty()
+			|| !key
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+		{
+			return Err(error("expected key = value"));
+		}
+		let value = value.trim();
+		let value = match value.strip_prefix('"') {
+			Some(rest) => {
+				let end = rest.find('"').ok_or_else(|| error("unterminated string"))?;
+				let after = rest[end + 1..].trim();
+				if !after.is_empty() && !after.starts_with('#') {
+					return Err(error("unexpected text after string"));
+				}
+				rest[..end].to_string()
+			},
+			None => {
+				let value = value.split('#').next().unwrap().trim();
+				if value.is_empty() || value.contains(char::is_whitespace) {
+					return Err(error("expected a string, number, or boolean"));
+				}
+				value.to_string()
+			},
+		};
+		options.push((key.replace('_', "-"), value));
+	}
+	Ok(options)
+}
+
+/// Whether the option is given on the command line, by either of its names
+fn given(arguments: &[OsString], name: &str, short: Option<char>) -> bool {
+	let long = format!("--{}", name);
+	arguments.iter().filter_map(|a| a.to_str()).any(|a| {
+		a == long
+			|| a.strip_prefix(&long)
+				.is_some_and(|rest| rest.starts_with('='))
+			|| short.is_some_and(|short| {
+				a.strip_prefix('-')
+					.is_some_and(|rest| rest.starts_with(short) && !rest.starts_with('-'))
+			})
+	})
+}
+
+/// Put the options from the config file and environment in front of the command line arguments,
+/// except the ones already given on the command line. The environment wins over the file.
+/// Unknown options are warned about and skipped
+pub fn with_defaults(
+	arguments: Vec<OsString>,
+	file: Option<ConfigFile>,
+	env: Vec<(String, String)>,
+) -> Result<Vec<OsString>, String> {
+	let sources = file
+		.into_iter()
+		.flat_map(|file| {
+			let path = file.path;
+			file.options.into_iter().map(move |(key, value)| {
+				let source = format!("{} key {}", path, key);
+				(key, value, source)
+			})
+		})
+		.chain(env.into_iter().map(|(key, value)| {
+			let variable = format!(
+				"environment variable {}{}",
+				ENV_PREFIX,
+				key.to_uppercase().replace('-', "_")
+			);
+			(key, value, variable)
+		}));
+
+	let mut defaults: Vec<(&str, Option<String>)> = Vec::new();
+	for (key, value, source) in sources {
+		let Some(&(name, short, flag)) = OPTIONS.iter().find(|(name, ..)| *name == key) else {
+			eprintln!("warning: ignoring unknown option in {}", source);
+			continue;
+		};
+		if given(&arguments, name, short) {
+			continue;
+		}
+		let value = if flag {
+			match value.as_str() {
+				"true" | "1" => None,
+				"false" | "0" | "" => {
+					defaults.retain(|(n, _)| *n != name);
+					continue;
+				},
+				_ => return Err(format!("{}: expected true or false", source)),
+			}
+		} else {
+			Some(value)
+		};
+		defaults.retain(|(n, _)| *n != name);
+		defaults.push((name, value));
+	}
+
+	let mut result: Vec<OsString> = Vec::new();
+	for (name, value) in defaults {
+		result.push(format!("--{}", name).into());
+		if let Some(value) = value {
+			result.push(value.into());
+		}
+	}
+	result.extend(arguments);
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn strings(arguments: Vec<OsString>) -> Vec<String> {
+		arguments
+			.into_iter()
+			.map(|a| a.into_string().unwrap())
+			.collect()
+	}
+
+	fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+		pairs
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn test_parse_file() {
+		let contents = concat!(
+			"# defaults for this machine\n",
+			"threads = 8\n",
+			"format = \"exr\"  # for compositing\n",
+			"\n",
+			"fog = \"0.0005,#b0b8c8\"\n",
+			"scene_stats = true\n",
+		);
+		assert_eq!(
+			parse_file(contents).unwrap(),
+			pairs(&[
+				("threads", "8"),
+				("format", "exr"),
+				("fog", "0.0005,#b0b8c8"),
+				("scene-stats", "true"),
+			])
+		);
+
+		for (contents, error) in [
+			("threads 8", "line 1: expected key = value"),
+			("\n= 8", "line 2: expected key = value"),
+			("format = \"exr", "line 1: unterminated string"),
+			(
+				"format = \"exr\" png",
+				"line 1: unexpected text after string",
+			),
+			(
+				"threads = ",
+				"line 1: expected a string, number, or boolean",
+			),
+			("[render]", "line 1: tables aren't supported"),
+		] {
+			assert_eq!(parse_file(contents).unwrap_err(), error);
+		}
+	}
+
+	#[test]
+	fn test_precedence() {
+		let file = Some(ConfigFile {
+			path: FILE_NAME.to_string(),
+			options: pairs(&[
+				("threads", "8"),
+				("format", "exr"),
+				("samples", "10"),
+				("aovs", "true"),
+				("tile-size", "32"),
+			]),
+		});
+		let env = pairs(&[("threads", "4"), ("depth", "5"), ("aovs", "false")]);
+		let arguments = vec!["-s20".into(), "--format=png".into(), "-v".into()];
+		assert_eq!(
+			strings(with_defaults(arguments, file, env).unwrap()),
+			[
+				"--threads",
+				"4",
+				"--depth",
+				"5",
+				"-s20",
+				"--format=png",
+				"-v"
+			]
+		);
+
+		let env = pairs(&[("verbose", "maybe")]);
+		assert!(with_defaults(Vec::new(), None, env).is_err());
+	}
+}
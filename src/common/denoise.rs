@@ -0,0 +1,222 @@
+use super::raytracer::Aov;
+use super::{Color, ImageBuffer};
+
+/// number of passes of the wavelet filter; each one doubles the distance between taps
+const ITERATIONS: u32 = 5;
+
+/// B3 spline used as the kernel for every pass
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// how quickly the weight of a neighbor falls off as its guide buffers become different
+const COLOR_SIGMA: f64 = 0.6;
+const NORMAL_SIGMA: f64 = 0.3;
+const ALBEDO_SIGMA: f64 = 0.1;
+
+/// compress HDR values into [0, 1) so that very bright emitters don't dominate color distances
+fn compress(c: Color) -> Color {
+	Color::new(
+		c.x() / (1.0 + c.x().max(0.0)),
+		c.y() / (1.0 + c.y().max(0.0)),
+		c.z() / (1.0 + c.z().max(0.0)),
+	)
+}
+
+fn edge_weight(a: Color, b: Color, sigma: f64) -> f64 {
+	f64::exp(-(a - b).length_squared() / (sigma * sigma))
+}
+
+fn atrous_pass(
+	image: &ImageBuffer<Color>,
+	aovs: &ImageBuffer<Aov>,
+	step: isize,
+	color_sigma: f64,
+) -> ImageBuffer<Color> {
+	let (width, height) = (image.width() as isize, image.height() as isize);
+	let mut output = ImageBuffer::new(image.size(), Color::zero());
+
+	for y in 0..height {
+		for x in 0..width {
+			let center = image[(x as usize, y as usize)];
+			let center_aov = aovs[(x as usize, y as usize)];
+			let center_compressed = compress(center);
+
+			let mut sum = Color::zero();
+			let mut total_weight = 0.0;
+			for (dy, ky) in (-2..=2).zip(KERNEL) {
+				let qy = y + dy * step;
+				if qy < 0 || qy >= height {
+					continue;
+				}
+				for (dx, kx) in (-2..=2).zip(KERNEL) {
+					let qx = x + dx * step;
+					if qx < 0 || qx >= width {
+						continue;
+					}
+
+					let sample = image[(qx as usize, qy as usize)];
+					let sample_aov = aovs[(qx as usize, qy as usize)];
+					let weight =
+						kx * ky
+							* edge_weight(center_compressed, compress(sample), color_sigma)
+							* edge_weight(center_aov.normal, sample_aov.normal, NORMAL_SIGMA)
+							* edge_weight(center_aov.albedo, sample_aov.albedo, ALBEDO_SIGMA);
+					sum += sample * weight;
+					total_weight += weight;
+				}
+			}
+
+			// the center tap always has a weight of at least KERNEL[2]^2, so this can't divide by
+			// zero
+			output[(x as usize, y as usize)] = sum / total_weight;
+		}
+	}
+
+	output
+}
+
+/// Edge-aware À-trous wavelet denoiser, guided by the first-hit normal and albedo of each pixel so
+/// that boundaries between different surfaces stay sharp
+///
+/// image:    linear (not yet tonemapped) pixels
+/// aovs:     auxiliary buffers, with the same dimensions as image
+/// strength: how much of the filtered result to use, from 0 (none) to 1 (all)
+pub fn denoise(
+	image: &ImageBuffer<Color>,
+	aovs: &ImageBuffer<Aov>,
+	strength: f64,
+) -> ImageBuffer<Color> {
+	let strength = strength.clamp(0.0, 1.0);
+	if strength == 0.0 {
+		return image.clone();
+	}
+
+	let mut filtered = image.clone();
+	let mut color_sigma = COLOR_SIGMA;
+	for i in 0..ITERATIONS {
+		filtered = atrous_pass(&filtered, aovs, 1 << i, color_sigma);
+		// later passes cover a wider area, so they need to be more conservative about color
+		color_sigma /= 2.0;
+	}
+
+	ImageBuffer::from_pixels(
+		image.size(),
+		image
+			.pixels()
+			.iter()
+			.zip(filtered.pixels())
+			.map(|(&o, &f)| o + (f - o) * strength)
+			.collect(),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::Vec3;
+
+	const SIZE: usize = 32;
+
+	/// guide buffers for a flat, gray surface facing the camera
+	fn flat_aovs() -> ImageBuffer<Aov> {
+		ImageBuffer::new(
+			(SIZE, SIZE),
+			Aov {
+				normal: Vec3::new(0.0, 0.0, 1.0),
+				albedo: Color::new(0.5, 0.5, 0.5),
+				..Aov::default()
+			},
+		)
+	}
+
+	fn noisy(mean: f64) -> ImageBuffer<Color> {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		ImageBuffer::from_fn((SIZE, SIZE), |_, _| {
+			let v = mean + rng.gen_range(-0.1..0.1);
+			Color::new(v, v, v)
+		})
+	}
+
+	fn variance(image: &ImageBuffer<Color>) -> f64 {
+		let n = image.pixels().len() as f64;
+		let mean = image.pixels().iter().map(|p| p.x()).sum::<f64>() / n;
+		image
+			.pixels()
+			.iter()
+			.map(|p| (p.x() - mean).powi(2))
+			.sum::<f64>()
+			/ n
+	}
+
+	/// mean of the red channel in a column of the image
+	fn column_mean(image: &ImageBuffer<Color>, x: usize) -> f64 {
+		(0..SIZE).map(|y| image[(x, y)].x()).sum::<f64>() / SIZE as f64
+	}
+
+	#[test]
+	fn test_smooths_noise() {
+		let image = noisy(0.5);
+		let denoised = denoise(&image, &flat_aovs(), 1.0);
+		assert!(
+			variance(&denoised) < variance(&image) / 10.0,
+			"{} {}",
+			variance(&denoised),
+			variance(&image)
+		);
+		// halfway keeps some of the noise
+		let half = denoise(&image, &flat_aovs(), 0.5);
+		assert!(variance(&half) < variance(&image) && variance(&half) > variance(&denoised));
+	}
+
+	#[test]
+	fn test_keeps_edges() {
+		// a dark surface on the left meeting a bright one on the right, told apart by either
+		// their albedo or their normals
+		let image = ImageBuffer::from_fn((SIZE, SIZE), |x, _| {
+			let v = if x < SIZE / 2 { 0.1 } else { 0.9 };
+			Color::new(v, v, v)
+		});
+		let step =
+			|aov: fn(bool) -> Aov| ImageBuffer::from_fn((SIZE, SIZE), |x, _| aov(x < SIZE / 2));
+		let albedo = step(|left| Aov {
+			normal: Vec3::new(0.0, 0.0, 1.0),
+			albedo: if left {
+				Color::new(0.2, 0.2, 0.2)
+			} else {
+				Color::new(0.8, 0.8, 0.8)
+			},
+			..Aov::default()
+		});
+		let normal = step(|left| Aov {
+			normal: if left {
+				Vec3::new(0.0, 0.0, 1.0)
+			} else {
+				Vec3::new(1.0, 0.0, 0.0)
+			},
+			albedo: Color::new(0.5, 0.5, 0.5),
+			..Aov::default()
+		});
+		for aovs in [albedo, normal] {
+			let denoised = denoise(&image, &aovs, 1.0);
+			assert!((column_mean(&denoised, SIZE / 2 - 1) - 0.1).abs() < 0.01);
+			assert!((column_mean(&denoised, SIZE / 2) - 0.9).abs() < 0.01);
+		}
+		// without anything to guide it, the color difference alone isn't enough to stop the
+		// widest passes from bleeding a little across
+		let blurred = denoise(&image, &flat_aovs(), 1.0);
+		assert!(column_mean(&blurred, SIZE / 2 - 1) > 0.11);
+	}
+
+	#[test]
+	fn test_zero_strength() {
+		let image = noisy(0.5);
+		for strength in [0.0, -1.0] {
+			let denoised = denoise(&image, &flat_aovs(), strength);
+			for (a, b) in image.pixels().iter().zip(denoised.pixels()) {
+				assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+			}
+		}
+	}
+}
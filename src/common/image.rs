@@ -0,0 +1,160 @@
+use std::ops::{Index, IndexMut};
+use std::slice::{ChunksExact, ChunksExactMut};
+
+/// A rectangular grid of pixels stored in one flat buffer, top row first. Used for images and
+/// for the auxiliary buffers that go along with them
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageBuffer<T> {
+	width: usize,
+	height: usize,
+	pixels: Vec<T>,
+}
+
+impl<T: Clone> ImageBuffer<T> {
+	/// an image with every pixel set to value
+	pub fn new((width, height): (usize, usize), value: T) -> Self {
+		Self {
+			width,
+			height,
+			pixels: vec![value; width * height],
+		}
+	}
+}
+
+impl<T> ImageBuffer<T> {
+	/// an image with each pixel set to f(x, y)
+	pub fn from_fn((width, height): (usize, usize), mut f: impl FnMut(usize, usize) -> T) -> Self {
+		Self {
+			width,
+			height,
+			pixels: (0..height)
+				.flat_map(|y| (0..width).map(move |x| (x, y)))
+				.map(|(x, y)| f(x, y))
+				.collect(),
+		}
+	}
+
+	/// wrap pixels that are already in order, top row first. panics if there are too many or too
+	/// few of them
+	pub fn from_pixels((width, height): (usize, usize), pixels: Vec<T>) -> Self {
+		assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+		Self {
+			width,
+			height,
+			pixels,
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	/// (width, height)
+	pub fn size(&self) -> (usize, usize) {
+		(self.width, self.height)
+	}
+
+	/// every pixel, row by row from the top
+	pub fn pixels(&self) -> &[T] {
+		&self.pixels
+	}
+
+	pub fn pixels_mut(&mut self) -> &mut [T] {
+		&mut self.pixels
+	}
+
+	/// None if (x, y) is outside of the image
+	pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+		if x < self.width && y < self.height {
+			self.pixels.get_mut(y * self.width + x)
+		} else {
+			None
+		}
+	}
+
+	/// y: counted from the top
+	pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+		&mut self.pixels[y * self.width..(y + 1) * self.width]
+	}
+
+	/// every row, from the top
+	pub fn rows(&self) -> ChunksExact<'_, T> {
+		// an image with no width has no pixels, so any chunk size gives no rows
+		self.pixels.chunks_exact(self.width.max(1))
+	}
+
+	pub fn rows_mut(&mut self) -> ChunksExactMut<'_, T> {
+		self.pixels.chunks_exact_mut(self.width.max(1))
+	}
+
+	/// the image stretched or shrunk to a new size, taking the nearest pixel for each pixel of
+	/// the new image
+	pub fn resize_nearest(&self, (width, height): (usize, usize)) -> ImageBuffer<T>
+	where
+		T: Clone,
+	{
+		ImageBuffer::from_fn((width, height), |x, y| {
+			self[(x * self.width / width, y * self.height / height)].clone()
+		})
+	}
+
+	/// an image of the same size with f applied to every pixel
+	pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> ImageBuffer<U> {
+		ImageBuffer {
+			width: self.width,
+			height: self.height,
+			pixels: self.pixels.iter().map(f).collect(),
+		}
+	}
+}
+
+/// indexed by (x, y), with y counted from the top
+impl<T> Index<(usize, usize)> for ImageBuffer<T> {
+	type Output = T;
+	fn index(&self, (x, y): (usize, usize)) -> &T {
+		debug_assert!(x < self.width, "x {} out of bounds", x);
+		&self.pixels[y * self.width + x]
+	}
+}
+
+impl<T> IndexMut<(usize, usize)> for ImageBuffer<T> {
+	fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+		debug_assert!(x < self.width, "x {} out of bounds", x);
+		&mut self.pixels[y * self.width + x]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_layout() {
+		let mut image = ImageBuffer::from_fn((3, 2), |x, y| 10 * y + x);
+		assert_eq!(image.size(), (3, 2));
+		assert_eq!(image.pixels(), [0, 1, 2, 10, 11, 12]);
+		assert_eq!((image[(2, 0)], image[(0, 1)]), (2, 10));
+		assert_eq!(image.row_mut(1), [10, 11, 12]);
+		assert_eq!(image.get_mut(3, 0), None);
+		assert_eq!(image.get_mut(0, 2), None);
+
+		image[(1, 1)] = 99;
+		image.row_mut(0)[0] = 42;
+		let rows: Vec<&[usize]> = image.rows().collect();
+		assert_eq!(rows, [[42, 1, 2], [10, 99, 12]]);
+		assert_eq!(
+			image.map(|&p| p % 2 == 0).pixels()[3..],
+			[true, false, true]
+		);
+
+		let doubled = ImageBuffer::from_fn((2, 2), |x, y| 10 * y + x).resize_nearest((4, 3));
+		assert_eq!(doubled.pixels(), [0, 0, 1, 1, 0, 0, 1, 1, 10, 10, 11, 11]);
+
+		let empty: ImageBuffer<u8> = ImageBuffer::new((0, 5), 0);
+		assert_eq!(empty.rows().count(), 0);
+	}
+}
@@ -0,0 +1,103 @@
+/// The numbers from min to max, including both ends, like the part of a ray that hits are looked
+/// for in. It's empty when min is bigger than max
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+	pub min: f64,
+	pub max: f64,
+}
+
+impl Interval {
+	pub const EMPTY: Interval = Interval::new(f64::INFINITY, f64::NEG_INFINITY);
+	pub const UNIVERSE: Interval = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+
+	pub const fn new(min: f64, max: f64) -> Interval {
+		Interval { min, max }
+	}
+
+	/// the smallest interval holding both a and b
+	pub fn hull(a: Interval, b: Interval) -> Interval {
+		Interval::new(a.min.min(b.min), a.max.max(b.max))
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.min > self.max
+	}
+
+	pub fn size(&self) -> f64 {
+		self.max - self.min
+	}
+
+	/// whether x is in the interval or on either end of it
+	pub fn contains(&self, x: f64) -> bool {
+		self.min <= x && x <= self.max
+	}
+
+	/// whether x is in the interval and not on either end of it
+	#[allow(dead_code)]
+	pub fn surrounds(&self, x: f64) -> bool {
+		self.min < x && x < self.max
+	}
+
+	/// the nearest number to x in the interval, which mustn't be empty
+	pub fn clamp(&self, x: f64) -> f64 {
+		if x < self.min {
+			self.min
+		} else if x > self.max {
+			self.max
+		} else {
+			x
+		}
+	}
+
+	/// the interval made delta bigger, by half of it at each end
+	pub fn expand(&self, delta: f64) -> Interval {
+		let padding = delta / 2.0;
+		Interval::new(self.min - padding, self.max + padding)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_interval() {
+		let i = Interval::new(1.0, 3.0);
+		assert_eq!(i.size(), 2.0);
+		for (x, contains, surrounds) in [
+			(0.5, false, false),
+			(1.0, true, false),
+			(2.0, true, true),
+			(3.0, true, false),
+			(f64::INFINITY, false, false),
+			(f64::NAN, false, false),
+		] {
+			assert_eq!(i.contains(x), contains, "{}", x);
+			assert_eq!(i.surrounds(x), surrounds, "{}", x);
+		}
+		assert_eq!(i.clamp(-5.0), 1.0);
+		assert_eq!(i.clamp(2.5), 2.5);
+		assert_eq!(i.clamp(f64::INFINITY), 3.0);
+		assert_eq!(i.expand(1.0), Interval::new(0.5, 3.5));
+		assert_eq!(
+			Interval::hull(i, Interval::new(-1.0, 2.0)),
+			Interval::new(-1.0, 3.0)
+		);
+	}
+
+	#[test]
+	fn test_empty_and_universe() {
+		for x in [f64::NEG_INFINITY, -1e300, 0.0, 1e300, f64::INFINITY] {
+			assert!(!Interval::EMPTY.contains(x));
+			assert!(Interval::UNIVERSE.contains(x));
+		}
+		assert!(Interval::EMPTY.is_empty());
+		assert!(!Interval::UNIVERSE.is_empty());
+		assert!(!Interval::new(1.0, 1.0).is_empty());
+		assert!(Interval::EMPTY.size() < 0.0);
+		// the hull with nothing is the same interval, and with everything is everything
+		let i = Interval::new(-2.0, 7.0);
+		assert_eq!(Interval::hull(i, Interval::EMPTY), i);
+		assert_eq!(Interval::hull(i, Interval::UNIVERSE), Interval::UNIVERSE);
+	}
+}
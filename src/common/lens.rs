@@ -0,0 +1,117 @@
+use super::{Color, ImageBuffer};
+
+/// value of one channel at a position in pixels, interpolated between the nearest four pixel
+/// centers and clamped to the edges of the image
+fn sample_channel(image: &ImageBuffer<Color>, channel: usize, x: f64, y: f64) -> f64 {
+	let (width, height) = image.size();
+	let x = (x - 0.5).clamp(0.0, (width - 1) as f64);
+	let y = (y - 0.5).clamp(0.0, (height - 1) as f64);
+	let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+	let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+	let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+
+	let top = image[(x0, y0)][channel] * (1.0 - tx) + image[(x1, y0)][channel] * tx;
+	let bottom = image[(x0, y1)][channel] * (1.0 - tx) + image[(x1, y1)][channel] * tx;
+	top * (1.0 - ty) + bottom * ty
+}
+
+/// Lateral chromatic aberration, where a lens magnifies each color by a slightly different amount
+/// so that edges away from the center get colored fringes. Red is magnified and blue is shrunk
+/// relative to green
+///
+/// strength: difference in magnification between green and the other channels, e.g. 0.005
+pub fn chromatic_aberration(image: &ImageBuffer<Color>, strength: f64) -> ImageBuffer<Color> {
+	if strength == 0.0 || image.width() == 0 || image.height() == 0 {
+		return image.clone();
+	}
+
+	let (center_x, center_y) = (image.width() as f64 / 2.0, image.height() as f64 / 2.0);
+	// sampling closer to the center makes the channel look bigger
+	let scales = [1.0 - strength, 1.0, 1.0 + strength];
+	ImageBuffer::from_fn(image.size(), |x, y| {
+		let (dx, dy) = (x as f64 + 0.5 - center_x, y as f64 + 0.5 - center_y);
+		let mut color = Color::zero();
+		for (channel, scale) in scales.into_iter().enumerate() {
+			color[channel] =
+				sample_channel(image, channel, center_x + dx * scale, center_y + dy * scale);
+		}
+		color
+	})
+}
+
+/// Darken the image towards its corners following the cos⁴ law, as if the image were projected
+/// onto a flat sensor with the corners at 45° from the center
+///
+/// strength: how much of the falloff to apply, from 0 (none) to 1 (corners at a quarter of their
+///           original brightness)
+pub fn vignette(image: &ImageBuffer<Color>, strength: f64) -> ImageBuffer<Color> {
+	let (width, height) = (image.width() as f64, image.height() as f64);
+	let half_diagonal = f64::hypot(width, height) / 2.0;
+
+	ImageBuffer::from_fn(image.size(), |x, y| {
+		let r =
+			f64::hypot(x as f64 + 0.5 - width / 2.0, y as f64 + 0.5 - height / 2.0) / half_diagonal;
+		// cos(atan(r))⁴ = 1 / (1 + r²)²
+		let falloff = 1.0 / (1.0 + r * r).powi(2);
+		image[(x, y)] * (1.0 - strength + strength * falloff)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn gray(width: usize, height: usize) -> ImageBuffer<Color> {
+		ImageBuffer::new((width, height), Color::new(0.5, 0.5, 0.5))
+	}
+
+	fn assert_same(a: &ImageBuffer<Color>, b: &ImageBuffer<Color>) {
+		for (a, b) in a.pixels().iter().zip(b.pixels()) {
+			assert!((*a - *b).near_zero(), "{} != {}", a, b);
+		}
+	}
+
+	#[test]
+	fn test_vignette() {
+		let image = vignette(&gray(101, 51), 1.0);
+		// the center pixel is exactly in the middle, so it keeps all of its light
+		assert_eq!(image[(50, 25)].x(), 0.5);
+		// symmetric, and darker towards the corners
+		assert_eq!(image[(0, 0)].x(), image[(100, 50)].x());
+		assert!(image[(0, 0)].x() < image[(20, 10)].x());
+		assert!(image[(20, 10)].x() < image[(50, 25)].x());
+		assert!(
+			(image[(0, 0)].x() - 0.5 * 0.25).abs() < 0.01,
+			"{}",
+			image[(0, 0)]
+		);
+
+		assert_same(&vignette(&gray(4, 4), 0.0), &gray(4, 4));
+	}
+
+	#[test]
+	fn test_chromatic_aberration() {
+		// flat images don't have edges to fringe
+		assert_same(&chromatic_aberration(&gray(20, 10), 0.05), &gray(20, 10));
+
+		// a white dot to the right of the center
+		let mut image = ImageBuffer::new((101, 51), Color::zero());
+		image[(90, 25)] = Color::new(1.0, 1.0, 1.0);
+		let image = chromatic_aberration(&image, 0.05);
+
+		let centroid = |channel: usize| {
+			let (mut total, mut x_sum) = (0.0, 0.0);
+			for row in image.rows() {
+				for (x, p) in row.iter().enumerate() {
+					total += p[channel];
+					x_sum += p[channel] * x as f64;
+				}
+			}
+			x_sum / total
+		};
+		// red moves out from the center and blue moves in, but green stays put
+		assert_eq!(centroid(1), 90.0);
+		assert!(centroid(0) > 91.0, "{}", centroid(0));
+		assert!(centroid(2) < 89.0, "{}", centroid(2));
+	}
+}
@@ -1,9 +1,25 @@
 pub mod args;
+pub mod assets;
+pub mod bake;
 pub mod color;
+pub mod config;
+pub mod denoise;
+pub mod image;
+pub mod interval;
+pub mod lens;
+pub mod philox;
+pub mod pool;
+pub mod progress;
 pub mod ray;
 pub mod raytracer;
+pub mod stereo;
+pub mod temporal;
+pub mod tile_queue;
 mod vec;
+pub mod watchdog;
 
 pub use color::Color;
-pub use ray::Ray;
-pub use vec::{Point3, Vec3};
+pub use image::ImageBuffer;
+pub use interval::Interval;
+pub use ray::{Differentials, Ray, RayKind};
+pub use vec::{OrthonormalBasis, Point3, Vec3};
@@ -0,0 +1,113 @@
+use rand::RngCore;
+
+const MULTIPLIERS: [u32; 2] = [0xd251_1f53, 0xcd9e_8d57];
+/// added to the key after each round: the golden ratio and sqrt(3) - 1 as fractions of 2^32
+const KEY_STEPS: [u32; 2] = [0x9e37_79b9, 0xbb67_ae85];
+const ROUNDS: usize = 10;
+
+/// Philox4x32-10, from Salmon et al., "Parallel Random Numbers: As Easy as 1, 2, 3". Each block of
+/// 4 numbers is a hash of a counter and a key, so there's no state carried from one number to the
+/// next, and any stream can be started anywhere just by choosing its counter
+pub fn philox(mut counter: [u32; 4], mut key: [u32; 2]) -> [u32; 4] {
+	for round in 0..ROUNDS {
+		if round > 0 {
+			key = [
+				key[0].wrapping_add(KEY_STEPS[0]),
+				key[1].wrapping_add(KEY_STEPS[1]),
+			];
+		}
+		let product0 = MULTIPLIERS[0] as u64 * counter[0] as u64;
+		let product1 = MULTIPLIERS[1] as u64 * counter[2] as u64;
+		counter = [
+			(product1 >> 32) as u32 ^ counter[1] ^ key[0],
+			product1 as u32,
+			(product0 >> 32) as u32 ^ counter[3] ^ key[1],
+			product0 as u32,
+		];
+	}
+	counter
+}
+
+/// A stream of random numbers from Philox, counting up through the last word of the counter
+#[derive(Debug, Clone)]
+pub struct Philox {
+	counter: [u32; 4],
+	key: [u32; 2],
+	block: [u32; 4],
+	/// how many of block have been used
+	used: usize,
+}
+
+impl Philox {
+	/// stream: which stream to draw from, any three words that no other stream uses
+	pub fn new(key: u64, stream: [u32; 3]) -> Philox {
+		Philox {
+			counter: [stream[0], stream[1], stream[2], 0],
+			key: [key as u32, (key >> 32) as u32],
+			block: [0; 4],
+			used: 4,
+		}
+	}
+}
+
+impl RngCore for Philox {
+	fn next_u32(&mut self) -> u32 {
+		if self.used == 4 {
+			self.block = philox(self.counter, self.key);
+			self.counter[3] = self.counter[3].wrapping_add(1);
+			self.used = 0;
+		}
+		self.used += 1;
+		self.block[self.used - 1]
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		let low = self.next_u32() as u64;
+		(self.next_u32() as u64) << 32 | low
+	}
+
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		for chunk in dest.chunks_mut(4) {
+			let bytes = self.next_u32().to_le_bytes();
+			chunk.copy_from_slice(&bytes[..chunk.len()]);
+		}
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+		self.fill_bytes(dest);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_known_answers() {
+		// from the known answer tests that come with Random123
+		assert_eq!(
+			philox([0; 4], [0; 2]),
+			[0x6627_e8d5, 0xe169_c58d, 0xbc57_ac4c, 0x9b00_dbd8]
+		);
+		assert_eq!(
+			philox([u32::MAX; 4], [u32::MAX; 2]),
+			[0x408f_276d, 0x41c8_3b0e, 0xa20b_c7c6, 0x6d54_51fd]
+		);
+		assert_eq!(
+			philox(
+				[0x243f_6a88, 0x85a3_08d3, 0x1319_8a2e, 0x0370_7344],
+				[0xa409_3822, 0x299f_31d0]
+			),
+			[0xd16c_fe09, 0x94fd_cceb, 0x5001_e420, 0x2412_6ea1]
+		);
+	}
+
+	#[test]
+	fn test_stream() {
+		let mut stream = Philox::new(0, [0; 3]);
+		let first: Vec<u32> = (0..8).map(|_| stream.next_u32()).collect();
+		assert_eq!(first[..4], philox([0; 4], [0; 2]));
+		assert_eq!(first[4..], philox([0, 0, 0, 1], [0; 2]));
+	}
+}
@@ -0,0 +1,89 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads that run jobs as they come in, so that several renders in a row don't
+/// each have to start their own threads
+pub struct ThreadPool {
+	jobs: Option<mpsc::Sender<Job>>,
+	workers: Vec<JoinHandle<()>>,
+}
+
+/// The result of a job sent to a ThreadPool, like a JoinHandle for a thread
+pub struct JobHandle<T>(mpsc::Receiver<thread::Result<T>>);
+
+impl<T> JobHandle<T> {
+	/// wait for the job to finish. Err with the panic payload if it panicked
+	pub fn join(self) -> thread::Result<T> {
+		self.0
+			.recv()
+			.unwrap_or_else(|_| Err(Box::new("worker thread exited")))
+	}
+}
+
+impl ThreadPool {
+	pub fn new(threads: usize) -> ThreadPool {
+		let (send, recv) = mpsc::channel::<Job>();
+		let recv = Arc::new(Mutex::new(recv));
+		let workers = (0..threads)
+			.map(|_| {
+				let recv = recv.clone();
+				thread::spawn(move || loop {
+					// the lock is only held while waiting for a job, not while running it
+					let job = recv.lock().unwrap().recv();
+					match job {
+						Ok(job) => job(),
+						// the pool was dropped
+						Err(_) => break,
+					}
+				})
+			})
+			.collect();
+		ThreadPool {
+			jobs: Some(send),
+			workers,
+		}
+	}
+
+	/// Run f on the next free thread. Panics in f are caught, so the thread stays usable
+	pub fn spawn<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> JobHandle<T> {
+		let (send, recv) = mpsc::channel();
+		let job = Box::new(move || {
+			// nobody might be waiting for the result anymore, which is fine
+			let _ = send.send(panic::catch_unwind(AssertUnwindSafe(f)));
+		});
+		self.jobs.as_ref().unwrap().send(job).unwrap();
+		JobHandle(recv)
+	}
+}
+
+impl Drop for ThreadPool {
+	fn drop(&mut self) {
+		// closing the channel makes every worker stop once it runs out of jobs
+		self.jobs.take();
+		for worker in self.workers.drain(..) {
+			let _ = worker.join();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_reuse_after_panic() {
+		let pool = ThreadPool::new(2);
+		let handles: Vec<JobHandle<usize>> = (0..8).map(|i| pool.spawn(move || i * i)).collect();
+		let results: Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+		assert_eq!(results, [0, 1, 4, 9, 16, 25, 36, 49]);
+
+		assert!(pool.spawn(|| panic!("job failed")).join().is_err());
+		assert_eq!(
+			pool.spawn(|| "still working").join().unwrap(),
+			"still working"
+		);
+	}
+}
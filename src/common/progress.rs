@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// how far back the rolling throughput looks
+const WINDOW: Duration = Duration::from_secs(10);
+
+/// minimum number of completions within the window before it is trusted on its own
+const MIN_WINDOW_SAMPLES: usize = 8;
+
+/// Estimates the time remaining in a render from how quickly pixels have been completed recently,
+/// so that the estimate follows the render into slower or faster regions of the image
+pub struct ProgressEstimator {
+	total: usize,
+	done: usize,
+	/// (time since the start, pixels done at that time) for each completion within the window,
+	/// plus the last one before it
+	history: VecDeque<(Duration, usize)>,
+}
+
+impl ProgressEstimator {
+	/// total: number of pixels in the whole render
+	pub fn new(total: usize) -> ProgressEstimator {
+		ProgressEstimator {
+			total,
+			done: 0,
+			history: VecDeque::from([(Duration::ZERO, 0)]),
+		}
+	}
+
+	/// elapsed: time since the render started
+	/// pixels:  number of pixels just completed
+	pub fn record(&mut self, elapsed: Duration, pixels: usize) {
+		self.done += pixels;
+		self.history.push_back((elapsed, self.done));
+		// keep one entry from before the window so that it spans the whole window
+		while self.history.len() > 2 && elapsed.saturating_sub(self.history[1].0) > WINDOW {
+			self.history.pop_front();
+		}
+	}
+
+	/// fraction of the render done, from 0 to 1
+	pub fn progress(&self) -> f64 {
+		if self.total == 0 {
+			1.0
+		} else {
+			self.done as f64 / self.total as f64
+		}
+	}
+
+	/// estimated time until every pixel is done, or None before anything has been completed
+	pub fn remaining(&self, elapsed: Duration) -> Option<Duration> {
+		if self.done == 0 || elapsed.is_zero() {
+			return None;
+		}
+		let global_rate = self.done as f64 / elapsed.as_secs_f64();
+
+		let &(window_start, done_at_start) = self.history.front().unwrap();
+		let span = elapsed.saturating_sub(window_start).as_secs_f64();
+		let rate = if span > 0.0 {
+			let window_rate = (self.done - done_at_start) as f64 / span;
+			// early on, or when tiles are slow, the window only has a few completions in it,
+			// which are too noisy to use by themselves
+			let completions = self.history.len() - 1;
+			let confidence = f64::min(
+				completions as f64 / MIN_WINDOW_SAMPLES as f64,
+				span / WINDOW.as_secs_f64(),
+			)
+			.min(1.0);
+			confidence * window_rate + (1.0 - confidence) * global_rate
+		} else {
+			global_rate
+		};
+
+		let left = self.total.saturating_sub(self.done) as f64;
+		Some(Duration::from_secs_f64(left / rate))
+	}
+}
+
+/// A text progress bar, like [=====>    ]
+pub struct ProgressBar {
+	pub progress: f64,
+	pub width: usize,
+}
+
+impl std::fmt::Display for ProgressBar {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let filled = ((self.progress.clamp(0.0, 1.0) * self.width as f64) as usize).min(self.width);
+		write!(f, "[{}", "=".repeat(filled))?;
+		if filled < self.width {
+			write!(f, ">{}", " ".repeat(self.width - filled - 1))?;
+		}
+		write!(f, "]")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn secs(s: f64) -> Duration {
+		Duration::from_secs_f64(s)
+	}
+
+	#[test]
+	fn test_constant_rate() {
+		// 100 pixels every second
+		let mut estimator = ProgressEstimator::new(10_000);
+		for i in 1..=30 {
+			estimator.record(secs(i as f64), 100);
+		}
+		let remaining = estimator.remaining(secs(30.0)).unwrap().as_secs_f64();
+		assert!((remaining - 70.0).abs() < 1e-6, "{}", remaining);
+		assert_eq!(estimator.progress(), 0.3);
+	}
+
+	#[test]
+	fn test_slowdown() {
+		// 100 pixels per second for a minute, then 10 per second for the next 20 seconds
+		let mut estimator = ProgressEstimator::new(10_000);
+		for i in 1..=60 {
+			estimator.record(secs(i as f64), 100);
+		}
+		for i in 61..=80 {
+			estimator.record(secs(i as f64), 10);
+		}
+
+		// the global average would say (10000 - 6200) / 77.5 ≈ 49 seconds
+		let remaining = estimator.remaining(secs(80.0)).unwrap().as_secs_f64();
+		assert!((remaining - 380.0).abs() < 1e-6, "{}", remaining);
+	}
+
+	#[test]
+	fn test_sparse_window() {
+		let mut estimator = ProgressEstimator::new(1000);
+		assert_eq!(estimator.remaining(secs(1.0)), None);
+
+		// a single completion isn't enough to trust the window, but both rates are the same here
+		estimator.record(secs(2.0), 100);
+		let remaining = estimator.remaining(secs(2.0)).unwrap().as_secs_f64();
+		assert!((remaining - 18.0).abs() < 1e-6, "{}", remaining);
+
+		// after a long gap, the window holds only one slow completion, so the estimate should sit
+		// between the global and window rates
+		estimator.record(secs(40.0), 100);
+		let remaining = estimator.remaining(secs(40.0)).unwrap().as_secs_f64();
+		let global = 800.0 / (200.0 / 40.0);
+		let window = 800.0 / (100.0 / 38.0);
+		assert!(global < remaining && remaining < window, "{}", remaining);
+	}
+
+	#[test]
+	fn test_progress_bar() {
+		let bar = |progress| {
+			ProgressBar {
+				progress,
+				width: 10,
+			}
+			.to_string()
+		};
+		assert_eq!(bar(0.0), "[>         ]");
+		assert_eq!(bar(0.55), "[=====>    ]");
+		assert_eq!(bar(1.0), "[==========]");
+	}
+}
@@ -1,20 +1,87 @@
 use crate::common::{Point3, Vec3};
 
+/// How the rays through the next pixel to the right and the next pixel up differ from a camera
+/// ray, as offsets from its origin and direction
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Differentials {
+	pub dx_origin: Vec3,
+	pub dx_direction: Vec3,
+	pub dy_origin: Vec3,
+	pub dy_direction: Vec3,
+}
+
+/// What a ray is being traced for, so that objects can choose which rays see them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RayKind {
+	/// from the camera, through a pixel
+	Camera,
+	/// scattered off of a surface, or leaving a light
+	Secondary,
+	/// checking whether a light is blocked
+	Shadow,
+}
+
+/// bits of Ray's flags
+const DEBUG_BVH: u8 = 1;
+const SECONDARY: u8 = 2;
+const SHADOW: u8 = 4;
+
 #[derive(Default, Copy, Clone)]
 pub struct Ray {
 	orig: Point3,
 	dir: Vec3,
 	tm: f64,
-	debug_bvh: bool,
+	/// DEBUG_BVH, and SECONDARY or SHADOW for rays that aren't camera rays
+	flags: u8,
+	spread: f64,
+	differentials: Option<Differentials>,
 }
 
 impl Ray {
+	/// starts out as a camera ray. see with_kind
 	pub fn new(orig: Point3, dir: Vec3, tm: f64, debug_bvh: bool) -> Self {
 		Self {
 			orig,
 			dir,
 			tm,
-			debug_bvh: debug_bvh,
+			flags: if debug_bvh { DEBUG_BVH } else { 0 },
+			spread: 0.0,
+			differentials: None,
+		}
+	}
+
+	/// The same ray, traced for a different reason
+	pub fn with_kind(self, kind: RayKind) -> Self {
+		let flags = self.flags & DEBUG_BVH;
+		Self {
+			flags: match kind {
+				RayKind::Camera => flags,
+				RayKind::Secondary => flags | SECONDARY,
+				RayKind::Shadow => flags | SHADOW,
+			},
+			..self
+		}
+	}
+
+	/// The same ray, starting from somewhere else in another direction, for moving it into an
+	/// object's coordinates. Everything else about it is kept
+	pub fn moved(self, orig: Point3, dir: Vec3) -> Self {
+		Self { orig, dir, ..self }
+	}
+
+	/// The same ray, widening by spread (in radians) as it travels, so that surfaces it hits can
+	/// tell how much of them one sample covers. Only camera rays have a spread; rays that start
+	/// at a surface have none
+	pub fn with_spread(self, spread: f64) -> Self {
+		Self { spread, ..self }
+	}
+
+	/// The same ray, carrying where the rays through the neighboring pixels go, so that surfaces
+	/// it hits can work out the footprint of a pixel on them
+	pub fn with_differentials(self, differentials: Option<Differentials>) -> Self {
+		Self {
+			differentials,
+			..self
 		}
 	}
 
@@ -31,10 +98,53 @@ impl Ray {
 	}
 
 	pub fn debug_bvh(&self) -> bool {
-		self.debug_bvh
+		self.flags & DEBUG_BVH != 0
+	}
+
+	pub fn kind(&self) -> RayKind {
+		if self.flags & SHADOW != 0 {
+			RayKind::Shadow
+		} else if self.flags & SECONDARY != 0 {
+			RayKind::Secondary
+		} else {
+			RayKind::Camera
+		}
+	}
+
+	pub fn spread(&self) -> f64 {
+		self.spread
+	}
+
+	pub fn differentials(&self) -> Option<Differentials> {
+		self.differentials
+	}
+
+	/// How far the rays through the next pixel to the right and the next pixel up hit from p, on
+	/// the plane through p facing normal. None without differentials, or if either of those rays
+	/// runs along the plane
+	pub fn hit_differentials(&self, p: Point3, normal: Vec3) -> Option<(Vec3, Vec3)> {
+		let differentials = self.differentials?;
+		let offset = |d_origin: Vec3, d_direction: Vec3| {
+			let (origin, direction) = (self.orig + d_origin, self.dir + d_direction);
+			let denominator = normal.dot(direction);
+			if denominator == 0.0 {
+				return None;
+			}
+			let t = normal.dot(p - origin) / denominator;
+			Some(origin + t * direction - p)
+		};
+		Some((
+			offset(differentials.dx_origin, differentials.dx_direction)?,
+			offset(differentials.dy_origin, differentials.dy_direction)?,
+		))
 	}
 
 	pub fn at(&self, t: f64) -> Point3 {
 		self.orig + t * self.dir
 	}
+
+	/// width of the ray at parameter t, in world units
+	pub fn width_at(&self, t: f64) -> f64 {
+		self.spread * t * self.dir.length()
+	}
 }
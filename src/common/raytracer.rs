@@ -1,18 +1,129 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::any::Any;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
 use std::time::{Duration, Instant};
 
-use rand::{Rng, SeedableRng};
+use rand::{Rng, RngCore, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
+use smallvec::SmallVec;
 
 use crate::args::DebugMode;
-use crate::common::{Color, Ray};
-use crate::object::Hittable;
-use crate::scene::Camera;
+use crate::common::philox::Philox;
+use crate::common::tile_queue::TileQueue;
+use crate::common::watchdog::Heartbeat;
+use crate::common::{Color, ImageBuffer, Interval, Point3, Ray, RayKind, Vec3};
+use crate::object::material::MediumStack;
+use crate::object::pdf::{HittableListPdf, MixturePdf, Pdf};
+use crate::object::{HitRecord, Hittable, Material};
+use crate::scene::{Camera, CameraSample, Light, Photon, PhotonMap, SceneLights};
 
 pub const TILE_SIZE: usize = 16;
 
+/// Most rays traced for one sample no matter what max_depth is, so that a path that somehow keeps
+/// going can't hold up a render forever. rays are traced recursively, so this also keeps the stack
+/// from overflowing
+const MAX_BOUNCES: i32 = 256;
+
+/// How many more times a path can bounce before it stops, along with the bounces through glass
+/// that it's been let off for. MAX_BOUNCES still stops paths that keep getting let off
+#[derive(Debug, Clone, Copy)]
+struct Bounces {
+	left: i32,
+	/// rays traced along the path so far, including free ones, which MAX_BOUNCES limits
+	taken: i32,
+	/// how many bounces haven't counted towards the depth so far
+	free: u32,
+	/// --glass-depth-bonus
+	glass_bonus: u32,
+}
+
+impl Bounces {
+	fn new(max_depth: usize, glass_bonus: u32) -> Bounces {
+		Bounces {
+			left: max_depth as i32,
+			taken: 0,
+			free: 0,
+			glass_bonus,
+		}
+	}
+
+	/// The bounces left after scattering off material. Going through a material with a depth
+	/// bonus is free until the path has used it up, and everything else costs one bounce
+	fn after(self, material: &dyn Material, transmitted: bool) -> Bounces {
+		if transmitted && self.free < material.depth_bonus(self.glass_bonus) {
+			Bounces {
+				taken: self.taken + 1,
+				free: self.free + 1,
+				..self
+			}
+		} else {
+			Bounces {
+				left: self.left - 1,
+				taken: self.taken + 1,
+				..self
+			}
+		}
+	}
+}
+
+/// Running totals of a pixel's samples, which are enough to find their mean and variance without
+/// keeping every sample
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accumulator {
+	sum: Color,
+	/// sum of the squares of each channel
+	sum_sq: Color,
+	count: u32,
+}
+
+impl Accumulator {
+	pub fn add_sample(&mut self, color: Color) {
+		self.sum += color;
+		self.sum_sq += color * color;
+		self.count += 1;
+	}
+
+	pub fn count(&self) -> u32 {
+		self.count
+	}
+
+	/// average of the samples, or black if there aren't any
+	pub fn mean(&self) -> Color {
+		if self.count == 0 {
+			return Color::zero();
+		}
+		self.sum * (1.0 / self.count as f64)
+	}
+
+	/// unbiased variance of each channel of the samples, or zero with fewer than two
+	#[allow(dead_code)]
+	pub fn variance(&self) -> Color {
+		if self.count < 2 {
+			return Color::zero();
+		}
+		let n = self.count as f64;
+		let variance = (self.sum_sq - self.sum * self.sum / n) / (n - 1.0);
+		// rounding can make it slightly negative when every sample is about the same
+		Color::new(
+			variance.x().max(0.0),
+			variance.y().max(0.0),
+			variance.z().max(0.0),
+		)
+	}
+
+	/// add in the samples of another accumulator for the same pixel
+	#[allow(dead_code)]
+	pub fn merge(&mut self, other: Accumulator) {
+		self.sum += other.sum;
+		self.sum_sq += other.sum_sq;
+		self.count += other.count;
+	}
+}
+
 pub struct Tile {
-	pub pixels: [[Color; TILE_SIZE]; TILE_SIZE],
+	pub samples: [[Accumulator; TILE_SIZE]; TILE_SIZE],
+	pub aovs: [[Aov; TILE_SIZE]; TILE_SIZE],
 	pub x: usize,
 	pub y: usize,
 	pub duration: Duration,
@@ -21,129 +132,2166 @@ pub struct Tile {
 impl Tile {
 	fn new(x: usize, y: usize) -> Self {
 		Self {
-			pixels: [[Color::zero(); TILE_SIZE]; TILE_SIZE],
+			samples: [[Accumulator::default(); TILE_SIZE]; TILE_SIZE],
+			aovs: [[Aov::default(); TILE_SIZE]; TILE_SIZE],
 			x,
 			y,
 			duration: Duration::ZERO,
 		}
 	}
+
+	/// The columns of an image of the given width that this tile covers. Tiles along the right
+	/// edge are clipped
+	pub fn columns(&self, width: usize) -> Range<usize> {
+		self.x.min(width)..(self.x + TILE_SIZE).min(width)
+	}
+
+	/// The rows of an image of the given height that this tile covers, counted from the top like
+	/// the image is stored. Tiles along the top edge are clipped
+	pub fn rows(&self, height: usize) -> Range<usize> {
+		// tile rows go up from y, so the tile's last row is the highest one in the image
+		height.saturating_sub(self.y + TILE_SIZE)..height.saturating_sub(self.y)
+	}
+
+	/// Copy the mean of each of this tile's pixels into a full image, and its auxiliary buffers,
+	/// which are stored with the top row first. Returns the number of pixels copied
+	pub fn copy_to(&self, image: &mut ImageBuffer<Color>, aovs: &mut ImageBuffer<Aov>) -> usize {
+		let (width, height) = image.size();
+		let (columns, rows) = (self.columns(width), self.rows(height));
+		let tile_columns = (columns.start - self.x)..(columns.end - self.x);
+		for image_y in rows.clone() {
+			let tile_y = height - 1 - image_y - self.y;
+			for (pixel, samples) in image.row_mut(image_y)[columns.clone()]
+				.iter_mut()
+				.zip(&self.samples[tile_y][tile_columns.clone()])
+			{
+				*pixel = samples.mean();
+			}
+			aovs.row_mut(image_y)[columns.clone()]
+				.copy_from_slice(&self.aovs[tile_y][tile_columns.clone()]);
+		}
+		columns.len() * rows.len()
+	}
 }
 
-fn ray_color(
+/// how many of the objects covering each pixel are kept track of, most covered first
+pub const COVERAGE_RANK: usize = 4;
+
+/// Auxiliary buffers describing the first surface hit by a camera ray, used to guide the
+/// denoiser and written out as extra OpenEXR layers
+#[derive(Clone, Copy)]
+pub struct Aov {
+	pub normal: Vec3,
+	pub albedo: Color,
+	/// distance from the camera to the closest hit, or infinity if nothing was hit
+	pub depth: f64,
+	/// world space position of the closest hit, or NaN if nothing was hit
+	pub position: Point3,
+	/// coverage of the pixel by objects, from 0 for only background to 1 for fully covered.
+	/// shadow catchers count as the fraction of light they have blocked
+	pub alpha: f64,
+	/// samples taken in the pixel, including ones that hit nothing
+	pub sample_count: u32,
+	/// label id of the object hit, for a single sample
+	pub object: Option<u32>,
+	/// label ids of the objects hit first in a pixel and the fraction of samples that hit each,
+	/// most covered first. unused entries have no coverage
+	pub coverage: [(u32, f64); COVERAGE_RANK],
+	/// the pixel's color split into direct light, which is a Radiance's emitted and direct light,
+	/// and indirect light. they add up to the color
+	pub direct: Color,
+	pub indirect: Color,
+}
+
+impl Default for Aov {
+	fn default() -> Self {
+		Self {
+			normal: Vec3::zero(),
+			albedo: Color::zero(),
+			depth: f64::INFINITY,
+			position: Point3::new(f64::NAN, f64::NAN, f64::NAN),
+			alpha: 0.0,
+			sample_count: 0,
+			object: None,
+			coverage: [(0, 0.0); COVERAGE_RANK],
+			direct: Color::zero(),
+			indirect: Color::zero(),
+		}
+	}
+}
+
+impl Aov {
+	fn accumulate(&mut self, other: Aov) {
+		self.normal += other.normal;
+		self.albedo += other.albedo;
+		// depth isn't averaged, since averaging a foreground and background depth gives a
+		// distance where there isn't any object. the same goes for position
+		if other.depth < self.depth {
+			self.depth = other.depth;
+			self.position = other.position;
+		}
+		self.alpha += other.alpha;
+		self.direct += other.direct;
+		self.indirect += other.indirect;
+	}
+}
+
+/// Light arriving along a ray, split by how many times it bounced on the way. Direct light is
+/// emitted and direct together, and indirect light is smoother, so a denoiser can blur it more
+#[derive(Debug, Clone, Copy)]
+pub struct Radiance {
+	/// given off by the first thing the ray hits, or the background if it hits nothing
+	pub emitted: Color,
+	/// from a light, reflected by the first thing the ray hits
+	pub direct: Color,
+	/// reflected by something else before the first thing the ray hits
+	pub indirect: Color,
+}
+
+impl Radiance {
+	fn emitted(color: Color) -> Radiance {
+		Radiance {
+			emitted: color,
+			direct: Color::zero(),
+			indirect: Color::zero(),
+		}
+	}
+
+	pub fn total(self) -> Color {
+		self.emitted + self.direct + self.indirect
+	}
+}
+
+/// Move the origin of a scattered ray slightly off of the surface it left, to the side it is
+/// travelling towards, so that rounding error in the hit point can't make it hit the same surface
+/// again. The distance scales with the magnitude of the hit point's coordinates, since that is
+/// what the rounding error scales with.
+fn offset_from_surface(scattered: Ray, rec: &HitRecord) -> Ray {
+	let magnitude = rec.p.abs().max_component().max(1.0);
+	let epsilon = magnitude * 1e-9;
+	let offset = if scattered.direction().dot(rec.normal) < 0.0 {
+		-epsilon * rec.normal
+	} else {
+		epsilon * rec.normal
+	};
+	scattered.moved(scattered.origin() + offset, scattered.direction())
+}
+
+/// Light reaching a surface directly from the lights in the light list and reflected back along r
+fn direct_light(
 	rng: &mut impl Rng,
 	r: Ray,
-	background: Color,
+	rec: &HitRecord,
 	world: &dyn Hittable,
-	depth: i32,
-	peak_depth: &mut i32,
+	lights: &[Arc<dyn Light>],
 ) -> Color {
-	*peak_depth += 1;
-	if depth <= 0 {
-		return Color::zero();
+	let mut total = Color::zero();
+	for light in lights {
+		let sample = light.sample(rng, rec.p);
+		let response = rec.mat_ptr.light_response(&r, rec, sample.direction);
+		if response.near_zero() {
+			continue;
+		}
+
+		let shadow_ray =
+			Ray::new(rec.p, sample.direction, r.time(), false).with_kind(RayKind::Shadow);
+		let shadow_ray = offset_from_surface(shadow_ray, rec);
+		if !world.occluded(rng, shadow_ray, Interval::new(0.001, sample.distance)) {
+			total += response * sample.irradiance;
+		}
+	}
+	total
+}
+
+/// Fraction of the light reaching a shadow catcher that is blocked by other objects. Without any
+/// lights to sample, this is how much of the sky is blocked instead, estimated with one ray
+fn shadow_occlusion(
+	rng: &mut impl Rng,
+	r: Ray,
+	rec: &HitRecord,
+	world: &dyn Hittable,
+	lights: &[Arc<dyn Light>],
+) -> f64 {
+	if lights.is_empty() {
+		let mut direction = rec.normal + Vec3::random_unit_vector(rng);
+		if direction.near_zero() {
+			direction = rec.normal;
+		}
+		let ray = Ray::new(rec.p, direction, r.time(), false).with_kind(RayKind::Shadow);
+		let ray = offset_from_surface(ray, rec);
+		return match world.hit(rng, ray, Interval::new(0.001, f64::INFINITY)) {
+			// lights don't cast shadows
+			Some(hit) if hit.mat_ptr.emitted(&hit).near_zero() => 1.0,
+			_ => 0.0,
+		};
+	}
+
+	let mut total = 0.0;
+	let mut blocked = 0.0;
+	for light in lights {
+		let sample = light.sample(rng, rec.p);
+		let cosine = sample.direction.dot(rec.normal);
+		if cosine <= 0.0 {
+			continue;
+		}
+		let amount = sample.irradiance.luminance() * cosine;
+		total += amount;
+
+		let shadow_ray =
+			Ray::new(rec.p, sample.direction, r.time(), false).with_kind(RayKind::Shadow);
+		let shadow_ray = offset_from_surface(shadow_ray, rec);
+		if world.occluded(rng, shadow_ray, Interval::new(0.001, sample.distance)) {
+			blocked += amount;
+		}
+	}
+	if total > 0.0 {
+		blocked / total
+	} else {
+		0.0
+	}
+}
+
+/// Global fog that fades everything towards a single color with distance from the camera
+#[derive(Debug, Clone, Copy)]
+pub struct Fog {
+	/// fraction of light absorbed and replaced by the fog color per unit distance
+	pub density: f64,
+	pub color: Color,
+}
+
+impl Fog {
+	/// fraction of light that makes it through a segment of a path of the given length
+	fn transmittance(&self, distance: f64) -> f64 {
+		f64::exp(-self.density * distance)
 	}
 
-	if let Some(rec) = world.hit(rng, r, 0.001, f64::INFINITY) {
-		let emitted = rec.mat_ptr.emitted(rec.u, rec.v, rec.p);
-		if let Some(res) = rec.mat_ptr.scatter(rng, &r, &rec) {
-			emitted
-				+ res.attenuation
-					* ray_color(rng, res.scattered, background, world, depth - 1, peak_depth)
+	/// color: light arriving at the end of a segment of a path of the given length
+	fn apply(&self, color: Color, distance: f64) -> Color {
+		let transmittance = self.transmittance(distance);
+		color * transmittance + self.color * (1.0 - transmittance)
+	}
+
+	/// The same as apply, with the light the fog gives off counted as emitted
+	fn apply_radiance(&self, radiance: Radiance, distance: f64) -> Radiance {
+		let transmittance = self.transmittance(distance);
+		Radiance {
+			emitted: self.apply(radiance.emitted, distance),
+			direct: radiance.direct * transmittance,
+			indirect: radiance.indirect * transmittance,
+		}
+	}
+}
+
+/// The parts of a scene that every path traced through it looks at
+#[derive(Clone, Copy)]
+pub struct SceneContext<'a> {
+	/// light from everywhere that rays don't hit anything
+	pub background: Color,
+	pub world: &'a dyn Hittable,
+	pub lights: &'a SceneLights,
+	pub fog: Option<Fog>,
+}
+
+/// rays:  incremented for every ray traced along the path, which is at most depth of them
+/// media: dielectrics that the ray starts inside of, updated as the path goes in and out of them
+/// aov:   if present, filled in with information about the first thing this ray hits. this is only
+///        passed for camera rays
+fn ray_color(
+	rng: &mut impl Rng,
+	r: Ray,
+	scene: &SceneContext,
+	depth: Bounces,
+	rays: &mut u64,
+	media: &mut MediumStack,
+	aov: Option<&mut Aov>,
+) -> Radiance {
+	if depth.left <= 0 || depth.taken >= MAX_BOUNCES {
+		return Radiance::emitted(Color::zero());
+	}
+	let SceneContext {
+		background,
+		world,
+		lights,
+		fog,
+	} = *scene;
+	*rays += 1;
+
+	let rec = match world.hit(rng, r, Interval::new(0.001, f64::INFINITY)) {
+		Some(rec) => rec,
+		None => {
+			if let Some(aov) = aov {
+				aov.albedo = background;
+			}
+			return Radiance::emitted(match fog {
+				Some(fog) => fog.apply(background, f64::INFINITY),
+				None => background,
+			});
+		},
+	};
+
+	let radiance = if rec.mat_ptr.is_shadow_catcher() {
+		// the same ray carrying on past the shadow catcher, so it's still a camera ray if r was
+		let through = offset_from_surface(r.moved(rec.p, r.direction()), &rec);
+		let occlusion = match aov {
+			Some(aov) => {
+				let occlusion = shadow_occlusion(rng, r, &rec, world, &lights.lights);
+				aov.normal = rec.normal;
+				aov.depth = rec.t * r.direction().length();
+				aov.position = rec.p;
+				aov.object = rec.label.map(|label| label.id);
+				aov.albedo = background;
+				aov.alpha = occlusion;
+				occlusion
+			},
+			// only camera rays see the shadows
+			None => 0.0,
+		};
+		let behind = ray_color(
+			rng,
+			through,
+			scene,
+			depth.after(rec.mat_ptr, false),
+			rays,
+			media,
+			None,
+		);
+		// the ray carries on in a straight line, so what it sees is split the same way
+		Radiance {
+			emitted: behind.emitted * (1.0 - occlusion),
+			direct: behind.direct * (1.0 - occlusion),
+			indirect: behind.indirect * (1.0 - occlusion),
+		}
+	} else {
+		let scattered = rec.mat_ptr.scatter_in(rng, &r, &rec, media);
+		// a mirror or glass only sends light on in one direction, which a sampled light is never in
+		let specular = scattered.as_ref().is_some_and(|res| res.specular);
+		let emitted = rec.mat_ptr.emitted(&rec);
+		let mut direct = Color::zero();
+		// caustics from the photon map, which got here by way of a mirror or glass
+		let mut caustics = Color::zero();
+		if !specular {
+			direct = direct_light(rng, r, &rec, world, &lights.lights);
+			let photons = lights
+				.photons
+				.as_ref()
+				.filter(|photons| !photons.is_empty());
+			if let (Some(photons), Some(albedo)) = (photons, rec.mat_ptr.diffuse_albedo(&rec)) {
+				// the BRDF of a perfectly diffuse surface is albedo / π
+				caustics = albedo / std::f64::consts::PI * photons.irradiance(rec.p, rec.normal);
+			}
+		}
+		if let Some(aov) = aov {
+			aov.normal = rec.normal;
+			aov.depth = rec.t * r.direction().length();
+			aov.position = rec.p;
+			aov.object = rec.label.map(|label| label.id);
+			aov.alpha = 1.0;
+			aov.albedo = match scattered {
+				Some(ref res) => res.attenuation,
+				None => emitted + direct + caustics,
+			};
+		}
+		let scattered = scattered.and_then(|res| match rec.mat_ptr.scattering_pdf(&rec) {
+			// send half of the rays towards the objects giving off light, and weight them all by
+			// how likely either half was to go that way
+			Some(material_pdf) if !res.specular && !lights.emitters.is_empty() => {
+				let towards_lights = HittableListPdf::new(&lights.emitters, rec.p);
+				let mixture = MixturePdf::new(&towards_lights, material_pdf.as_ref(), 0.5);
+				let direction = mixture.generate(rng);
+				let pdf = mixture.value(direction);
+				let response = rec.mat_ptr.light_response(&r, &rec, direction);
+				(pdf > 0.0 && !response.near_zero())
+					.then(|| (Ray::new(rec.p, direction, r.time(), false), response / pdf))
+			},
+			_ => Some((res.scattered, res.attenuation)),
+		});
+		if let Some((scattered, attenuation)) = scattered {
+			// glass sends the ray through to the other side of the surface, which the normal
+			// faces away from
+			let transmitted = specular && scattered.direction().dot(rec.normal) < 0.0;
+			// scattered rays don't have differentials, so as a rough guess, they widen at the
+			// same rate as the ray that hit the surface, starting from nothing
+			let scattered = offset_from_surface(scattered, &rec)
+				.with_kind(RayKind::Secondary)
+				.with_spread(r.spread());
+			let bounced = ray_color(
+				rng,
+				scattered,
+				scene,
+				depth.after(rec.mat_ptr, transmitted),
+				rays,
+				media,
+				None,
+			);
+			// light given off by the next thing hit has only bounced here, so it's direct
+			Radiance {
+				emitted,
+				direct: direct + attenuation * bounced.emitted,
+				indirect: caustics + attenuation * (bounced.direct + bounced.indirect),
+			}
+		} else {
+			Radiance {
+				emitted,
+				direct,
+				indirect: caustics,
+			}
+		}
+	};
+
+	match fog {
+		// a back face was hit from inside an object, so there's no fog along this segment
+		Some(fog) if rec.front_face => fog.apply_radiance(radiance, rec.t * r.direction().length()),
+		_ => radiance,
+	}
+}
+
+/// Number of rays a path takes before it stops, counted the same way as ray_color's peak_depth,
+/// without the light sampling and colors that the depth debug mode doesn't show
+/// rays: incremented for every ray traced along the path
+fn path_depth(
+	rng: &mut impl Rng,
+	mut r: Ray,
+	world: &dyn Hittable,
+	mut bounces: Bounces,
+	rays: &mut u64,
+) -> i32 {
+	let mut media = MediumStack::default();
+	let mut depth = 0;
+	loop {
+		depth += 1;
+		if bounces.left <= 0 || depth > MAX_BOUNCES {
+			return depth;
+		}
+		*rays += 1;
+		let Some(rec) = world.hit(rng, r, Interval::new(0.001, f64::INFINITY)) else {
+			return depth;
+		};
+		(r, bounces) = if rec.mat_ptr.is_shadow_catcher() {
+			(
+				offset_from_surface(r.moved(rec.p, r.direction()), &rec),
+				bounces.after(rec.mat_ptr, false),
+			)
 		} else {
-			emitted
+			match rec.mat_ptr.scatter_in(rng, &r, &rec, &mut media) {
+				Some(res) => {
+					let transmitted =
+						res.specular && res.scattered.direction().dot(rec.normal) < 0.0;
+					(
+						offset_from_surface(res.scattered, &rec).with_kind(RayKind::Secondary),
+						bounces.after(rec.mat_ptr, transmitted),
+					)
+				},
+				None => return depth,
+			}
+		};
+	}
+}
+
+/// How many photons to trace for --caustics, and how far to gather them from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Caustics {
+	/// split evenly between the lights
+	pub photons: usize,
+	/// as a fraction of the length of the diagonal of the scene's bounding box
+	pub radius: f64,
+}
+
+/// Trace photons from the lights through mirrors and glass, and keep the ones that then land on a
+/// diffuse surface. Light reaching a surface any other way is found by sampling the lights
+/// directly, which can't find light that was focused on the way, so between them everything is
+/// counted once. Objects that give off light aren't traced from, since rays scattered through
+/// glass can already hit them
+/// photons:   how many to send out, split evenly between the lights
+/// radius:    how far from a point photons are gathered from, in the units of the scene
+/// max_depth: most surfaces a photon can hit
+/// shutter:   times the photons leave the lights between
+pub fn trace_photons(
+	rng: &mut impl Rng,
+	world: &dyn Hittable,
+	lights: &[Arc<dyn Light>],
+	photons: usize,
+	radius: f64,
+	max_depth: i32,
+	(time0, time1): (f64, f64),
+) -> PhotonMap {
+	let mut map = PhotonMap::new(radius);
+	if lights.is_empty() {
+		return map;
+	}
+	let per_light = (photons / lights.len()).max(1);
+	for light in lights {
+		for _ in 0..per_light {
+			let time = if time1 > time0 {
+				rng.gen_range(time0..time1)
+			} else {
+				time0
+			};
+			let Some(emission) = light.emit(rng, time) else {
+				break;
+			};
+			let mut r = emission.ray.with_kind(RayKind::Secondary);
+			let mut power = emission.power / per_light as f64;
+			let mut media = MediumStack::default();
+			let mut focused = false;
+			for _ in 0..max_depth.min(MAX_BOUNCES) {
+				if power.near_zero() {
+					break;
+				}
+				let Some(rec) = world.hit(rng, r, Interval::new(0.001, f64::INFINITY)) else {
+					break;
+				};
+				let Some(res) = rec.mat_ptr.scatter_in(rng, &r, &rec, &mut media) else {
+					break;
+				};
+				if res.specular {
+					power *= res.attenuation;
+					r = offset_from_surface(res.scattered, &rec).with_kind(RayKind::Secondary);
+					focused = true;
+					continue;
+				}
+				if focused && rec.mat_ptr.diffuse_albedo(&rec).is_some() {
+					map.add(Photon {
+						position: rec.p,
+						direction: r.direction().unit_vector(),
+						power,
+					});
+				}
+				break;
+			}
 		}
+	}
+	map
+}
+
+/// Position of a point in a pixel on the camera's viewport, from (0, 0) at the bottom left corner
+/// of the image to (1, 1) at the top right
+/// offset: position within the pixel, from 0 to 1 on each axis
+fn pixel_uv(
+	(i, j): (usize, usize),
+	(width, height): (usize, usize),
+	(offset_x, offset_y): (f64, f64),
+) -> (f64, f64) {
+	(
+		(i as f64 + offset_x) / width as f64,
+		(j as f64 + offset_y) / height as f64,
+	)
+}
+
+/// Find what the ray through the center of a pixel hits first, ignoring depth of field
+/// (x, y): pixel coordinates, from the top left
+pub fn pick<'a>(
+	rng: &mut dyn RngCore,
+	world: &'a dyn Hittable,
+	cam: &Camera,
+	(width, height): (usize, usize),
+	(x, y): (usize, usize),
+) -> Option<HitRecord<'a>> {
+	// rows are rendered from the bottom up
+	let (u, v) = pixel_uv((x, height - 1 - y), (width, height), (0.5, 0.5));
+	let r = cam
+		.with_aperture(0.0)
+		.get_ray(rng, u, v, false)
+		.with_spread(cam.pixel_spread(height));
+	world.hit(rng, r, Interval::new(0.001, f64::INFINITY))
+}
+
+/// Performance of one rendering thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+	/// time from when the thread started until it ran out of tiles
+	pub wall_time: Duration,
+	/// time spent rendering tiles, excluding waiting for the next tile and sending results
+	pub busy_time: Duration,
+	pub pixels: usize,
+	pub tiles: usize,
+	/// samples taken, each of which starts with one ray from the camera
+	pub samples: u64,
+	/// rays traced from the camera and scattered off of surfaces. shadow rays aren't counted
+	pub rays: u64,
+	/// the tile that took the longest, as (duration, x, y)
+	pub slowest_tile: Option<(Duration, usize, usize)>,
+}
+
+impl RenderStats {
+	/// rays per second of busy time
+	pub fn rays_per_sec(&self) -> f64 {
+		self.per_busy_sec(self.rays)
+	}
+
+	/// camera rays per second of busy time
+	pub fn samples_per_sec(&self) -> f64 {
+		self.per_busy_sec(self.samples)
+	}
+
+	fn per_busy_sec(&self, count: u64) -> f64 {
+		let secs = self.busy_time.as_secs_f64();
+		if secs > 0.0 {
+			count as f64 / secs
+		} else {
+			0.0
+		}
+	}
+
+	/// add up the work done by the same thread in two renders
+	pub fn combine(&self, other: &RenderStats) -> RenderStats {
+		RenderStats {
+			wall_time: self.wall_time + other.wall_time,
+			busy_time: self.busy_time + other.busy_time,
+			pixels: self.pixels + other.pixels,
+			tiles: self.tiles + other.tiles,
+			samples: self.samples + other.samples,
+			rays: self.rays + other.rays,
+			slowest_tile: self
+				.slowest_tile
+				.into_iter()
+				.chain(other.slowest_tile)
+				.max_by_key(|&(duration, _, _)| duration),
+		}
+	}
+}
+
+/// A panic in a rendering thread, caught so that it can be reported instead of leaving a hole in
+/// the image
+#[derive(Debug, Clone)]
+pub struct RenderPanic {
+	pub message: String,
+	/// coordinates of the tile that was being rendered
+	pub x: usize,
+	pub y: usize,
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+	if let Some(s) = payload.downcast_ref::<&str>() {
+		s.to_string()
+	} else if let Some(s) = payload.downcast_ref::<String>() {
+		s.clone()
 	} else {
-		background
+		"unknown panic payload".to_string()
+	}
+}
+
+/// flat color for an object ID in the id debug mode. IDs are hashed so that neighboring objects,
+/// which usually have consecutive IDs, get very different colors
+pub fn id_color(id: u32) -> Color {
+	let z = mix(id as u64);
+	// keep every channel away from black, which is used for unlabeled objects and the background
+	let channel = |shift: u32| 0.2 + 0.8 * ((z >> shift) & 0xff) as f64 / 255.0;
+	Color::new(channel(0), channel(8), channel(16))
+}
+
+/// Which kind of random number generator the samples of each pixel are drawn from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngEngine {
+	/// xoshiro256++, seeded with a hash of the sample seed, pixel, and sample
+	Xoshiro,
+	/// Philox4x32-10, which is keyed with the sample seed and counts from the pixel and sample
+	Philox,
+}
+
+impl RngEngine {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Xoshiro => "xoshiro",
+			Self::Philox => "philox",
+		}
+	}
+}
+
+/// The random numbers for one sample of one pixel
+#[derive(Debug, Clone)]
+pub enum SampleRng {
+	Xoshiro(Xoshiro256PlusPlus),
+	Philox(Philox),
+}
+
+impl RngCore for SampleRng {
+	fn next_u32(&mut self) -> u32 {
+		match self {
+			Self::Xoshiro(rng) => rng.next_u32(),
+			Self::Philox(rng) => rng.next_u32(),
+		}
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		match self {
+			Self::Xoshiro(rng) => rng.next_u64(),
+			Self::Philox(rng) => rng.next_u64(),
+		}
 	}
+
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		match self {
+			Self::Xoshiro(rng) => rng.fill_bytes(dest),
+			Self::Philox(rng) => rng.fill_bytes(dest),
+		}
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+		match self {
+			Self::Xoshiro(rng) => rng.try_fill_bytes(dest),
+			Self::Philox(rng) => rng.try_fill_bytes(dest),
+		}
+	}
+}
+
+/// Gives every sample of every pixel its own stream of random numbers, which only depends on the
+/// seed, the pixel, and which sample it is. So a pixel renders the same no matter which thread
+/// renders it or in what order, and any sample can be rendered again on its own
+#[derive(Debug, Clone, Copy)]
+pub struct RngFactory {
+	engine: RngEngine,
+	seed: u64,
+}
+
+impl RngFactory {
+	pub fn new(engine: RngEngine, seed: u64) -> RngFactory {
+		RngFactory { engine, seed }
+	}
+
+	/// Two random numbers for one dimension of a sample, which come from a stream of their own
+	/// so that they don't depend on how many numbers the rest of the sample uses. Camera rays
+	/// are made from these, so that the lens and time aren't tied to each other or to bounces
+	pub fn dimension(
+		&self,
+		(x, y): (usize, usize),
+		sample: usize,
+		dimension: Dimension,
+	) -> [f64; 2] {
+		let tag = dimension as u64;
+		let mut rng = match self.engine {
+			RngEngine::Xoshiro => {
+				let mut key = self.seed;
+				for word in [x as u64, y as u64, sample as u64, tag] {
+					key = mix(key ^ word);
+				}
+				SampleRng::Xoshiro(Xoshiro256PlusPlus::seed_from_u64(key))
+			},
+			RngEngine::Philox => SampleRng::Philox(Philox::new(
+				mix(self.seed ^ tag),
+				[x, y, sample].map(|w| w as u32),
+			)),
+		};
+		[rng.gen(), rng.gen()]
+	}
+
+	/// the stream for a sample of the pixel at (x, y)
+	pub fn sample(&self, (x, y): (usize, usize), sample: usize) -> SampleRng {
+		match self.engine {
+			RngEngine::Xoshiro => {
+				// seed_from_u64 mixes its seed with SplitMix64, so this only has to make sure
+				// that no two samples share one
+				let mut key = self.seed;
+				for word in [x, y, sample] {
+					key = mix(key ^ word as u64);
+				}
+				SampleRng::Xoshiro(Xoshiro256PlusPlus::seed_from_u64(key))
+			},
+			RngEngine::Philox => {
+				SampleRng::Philox(Philox::new(self.seed, [x, y, sample].map(|w| w as u32)))
+			},
+		}
+	}
+}
+
+/// What the numbers from RngFactory::dimension are used for, which picks their stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+	/// where in the pixel the ray goes through
+	Pixel = 1,
+	Lens,
+	/// only the first number is used
+	Time,
+}
+
+/// the SplitMix64 finalizer, which spreads every bit of its input over all of the output
+fn mix(z: u64) -> u64 {
+	let mut z = z.wrapping_add(0x9e3779b97f4a7c15);
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+	z ^ (z >> 31)
+}
+
+/// How much work render puts into each pixel of an image
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+	pub size: (usize, usize),
+	pub samples_per_pixel: usize,
+	/// maximum number of light bounces per sample
+	pub max_depth: usize,
+	pub glass_depth_bonus: u32,
+	pub debug_mode: Option<DebugMode>,
 }
 
 /// Render a scene
 /// out:         queue to send completed tiles into
-/// max_depth:   maximum number of light bounces per sample
-/// current_pos: shared tracker of the next tile to render (top left corner)
+/// tiles:       shared queue of the tiles left to render
+/// heartbeat:   updated as the render goes, so that the caller can tell if this thread is stuck
 pub fn render(
 	out: mpsc::Sender<Tile>,
-	seed: u64,
-	world: Arc<dyn Hittable>,
+	rngs: RngFactory,
+	scene: &SceneContext,
 	cam: Camera,
-	background: Color,
-	(width, height): (usize, usize),
-	samples_per_pixel: usize,
-	max_depth: usize,
-	current_pos: Arc<Mutex<(usize, usize)>>,
-	debug_mode: Option<DebugMode>,
-) -> (Duration, usize) {
-	let mut total_time = Duration::ZERO;
-	let mut total_pixels = 0usize;
-	let mut done = false;
-
-	while !done {
-		let (x, y) = {
-			let mut guard = current_pos.lock().unwrap();
-			let previous_coords = *guard;
-			guard.0 += TILE_SIZE;
-			if guard.0 >= width {
-				guard.0 = 0;
-				guard.1 += TILE_SIZE;
-			}
-			if guard.1 >= height {
-				done = true;
-				// in this case, another thread already rendered the last tile (putting guard at
-				// (0, max height)) so we just need to return
-				if guard.0 == TILE_SIZE {
-					guard.0 = 0;
-					return (total_time, total_pixels);
+	settings: &RenderSettings,
+	tiles: Arc<TileQueue>,
+	heartbeat: Arc<Heartbeat>,
+) -> Result<RenderStats, RenderPanic> {
+	let RenderSettings {
+		size: (width, height),
+		samples_per_pixel,
+		max_depth,
+		glass_depth_bonus,
+		debug_mode,
+	} = *settings;
+	let world = scene.world;
+	let start = Instant::now();
+	let mut stats = RenderStats::default();
+	let spread = cam.pixel_spread(height);
+	let differentials = cam.pixel_differentials((width, height));
+	let exposure = cam.exposure_scale();
+
+	while let Some((x, y)) = tiles.next() {
+		let mut tile = Tile::new(x, y);
+
+		let instant = Instant::now();
+		let result = panic::catch_unwind(AssertUnwindSafe(|| {
+			for j in (y..(y + TILE_SIZE)).rev() {
+				if j >= height {
+					continue;
+				}
+				heartbeat.beat((x, y));
+
+				for i in x..(x + TILE_SIZE) {
+					if i >= width {
+						continue;
+					}
+
+					let mut pixel_samples = Accumulator::default();
+					let mut pixel_aov = Aov::default();
+					// samples that hit each object
+					let mut objects: SmallVec<[(u32, f64); COVERAGE_RANK]> = SmallVec::new();
+					for sample in 0..samples_per_pixel {
+						let mut rng = rngs.sample((i, j), sample);
+						let mut sample_aov = Aov::default();
+						let [jitter_x, jitter_y] = rngs.dimension((i, j), sample, Dimension::Pixel);
+						let (u, v) = pixel_uv((i, j), (width, height), (jitter_x, jitter_y));
+						let camera_sample = CameraSample {
+							lens: rngs.dimension((i, j), sample, Dimension::Lens),
+							time: rngs.dimension((i, j), sample, Dimension::Time)[0],
+						};
+						let r = cam
+							.sample_ray(u, v, camera_sample, debug_mode == Some(DebugMode::Bvh))
+							.with_spread(spread)
+							.with_differentials(Some(differentials));
+						if debug_mode == Some(DebugMode::Id) {
+							let hit = world.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY));
+							pixel_samples.add_sample(
+								hit.and_then(|rec| rec.label)
+									.map_or(Color::zero(), |label| id_color(label.id)),
+							);
+							stats.rays += 1;
+							stats.samples += 1;
+							continue;
+						}
+						if debug_mode == Some(DebugMode::Depth) {
+							let depth = path_depth(
+								&mut rng,
+								r,
+								world,
+								Bounces::new(max_depth, glass_depth_bonus),
+								&mut stats.rays,
+							);
+							let shade = depth as f64 / max_depth as f64;
+							pixel_samples.add_sample(Color::new(shade, shade, shade));
+							stats.samples += 1;
+							continue;
+						}
+						let radiance = ray_color(
+							&mut rng,
+							r,
+							scene,
+							Bounces::new(max_depth, glass_depth_bonus),
+							&mut stats.rays,
+							&mut MediumStack::default(),
+							Some(&mut sample_aov),
+						);
+						sample_aov.direct = (radiance.emitted + radiance.direct) * exposure;
+						sample_aov.indirect = radiance.indirect * exposure;
+						pixel_aov.accumulate(sample_aov);
+						stats.samples += 1;
+						if let Some(id) = sample_aov.object {
+							match objects.iter_mut().find(|(object, _)| *object == id) {
+								Some((_, n)) => *n += 1.0,
+								None => objects.push((id, 1.0)),
+							}
+						}
+
+						pixel_samples.add_sample(radiance.total() * exposure);
+					}
+					let factor = 1.0 / samples_per_pixel as f64;
+					objects.sort_by(|a, b| b.1.total_cmp(&a.1));
+					for (coverage, (id, n)) in pixel_aov.coverage.iter_mut().zip(objects) {
+						*coverage = (id, n * factor);
+					}
+					tile.samples[j - y][i - x] = pixel_samples;
+					tile.aovs[j - y][i - x] = Aov {
+						normal: pixel_aov.normal * factor,
+						albedo: pixel_aov.albedo * factor,
+						alpha: pixel_aov.alpha * factor,
+						direct: pixel_aov.direct * factor,
+						indirect: pixel_aov.indirect * factor,
+						sample_count: pixel_samples.count(),
+						..pixel_aov
+					};
+					stats.pixels += 1;
 				}
 			}
+		}));
+		if let Err(payload) = result {
+			// stop the other threads from taking more tiles, since the render is going to be
+			// thrown away
+			tiles.cancel();
+			heartbeat.finish();
+			return Err(RenderPanic {
+				message: panic_message(payload),
+				x,
+				y,
+			});
+		}
+		tile.duration = instant.elapsed();
+		stats.busy_time += tile.duration;
+		stats.tiles += 1;
+		if stats
+			.slowest_tile
+			.is_none_or(|(duration, _, _)| tile.duration > duration)
+		{
+			stats.slowest_tile = Some((tile.duration, x, y));
+		}
 
-			previous_coords
-		};
+		out.send(tile).unwrap();
+	}
 
-		let mut tile = Tile::new(x, y);
-		let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed ^ x as u64 ^ y as u64);
+	heartbeat.finish();
+	stats.wall_time = start.elapsed();
+	Ok(stats)
+}
 
-		let instant = Instant::now();
-		for j in (y..(y + TILE_SIZE)).rev() {
-			if j >= height {
-				continue;
+/// Estimate how long each tile takes to render by timing one sample at a few points in it, so
+/// that the tiles can be handed out with TileQueue::by_cost. this traces 4 rays per tile where the
+/// render traces one per sample per pixel, so it's cheap next to the render itself. tiles that
+/// panic are given the highest cost, so that the render reaches them first and reports the panic
+/// Returns the costs in scanline order
+pub fn estimate_tile_costs(
+	scene: &SceneContext,
+	cam: &Camera,
+	settings: &RenderSettings,
+) -> Vec<Duration> {
+	let RenderSettings {
+		size: (width, height),
+		max_depth,
+		glass_depth_bonus,
+		..
+	} = *settings;
+	let spread = cam.pixel_spread(height);
+	let differentials = cam.pixel_differentials((width, height));
+	let offsets = [TILE_SIZE / 4, TILE_SIZE * 3 / 4];
+	TileQueue::scanline((width, height))
+		.tiles()
+		.iter()
+		.map(|&(x, y)| {
+			// the estimate doesn't change the image, so it doesn't need to use the sample seed
+			let rngs = RngFactory::new(RngEngine::Xoshiro, 0);
+			let instant = Instant::now();
+			let result = panic::catch_unwind(AssertUnwindSafe(|| {
+				for dy in offsets {
+					for dx in offsets {
+						let (i, j) = ((x + dx).min(width - 1), (y + dy).min(height - 1));
+						let mut rng = rngs.sample((i, j), 0);
+						let (u, v) = pixel_uv((i, j), (width, height), (0.5, 0.5));
+						let r = cam
+							.get_ray(&mut rng, u, v, false)
+							.with_spread(spread)
+							.with_differentials(Some(differentials));
+						ray_color(
+							&mut rng,
+							r,
+							scene,
+							Bounces::new(max_depth, glass_depth_bonus),
+							&mut 0,
+							&mut MediumStack::default(),
+							None,
+						);
+					}
+				}
+			}));
+			match result {
+				Ok(()) => instant.elapsed(),
+				Err(_) => Duration::MAX,
 			}
+		})
+		.collect()
+}
 
-			for i in x..(x + TILE_SIZE) {
-				if i >= width {
-					continue;
+#[cfg(test)]
+mod tests {
+	use std::f64::consts::PI;
+
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+
+	use std::fmt::{self, Display, Formatter};
+	use std::thread;
+
+	use crate::object::material::{
+		Dielectric, DiffuseLight, Lambertian, Material, Metal, ScatterResult, ShadowCatcher,
+	};
+	use crate::object::pdf::EmitterList;
+	use crate::object::texture::FunctionTexture;
+	use crate::object::{Sphere, Visibility, VisibleTo, XYRect, XZRect};
+	use crate::scene::{scenes, DirectionalLight, HittableList, PointLight};
+
+	/// light reflected straight up from the origin of a gray floor
+	fn floor_direct_light(lights: &[Arc<dyn Light>], blocker: bool) -> Color {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let gray = Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5)));
+		let mut world = HittableList::new();
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			0.0,
+			gray.clone(),
+		)));
+		if blocker {
+			world.add(Arc::new(Sphere::new(
+				Point3::new(0.0, 1.5, 0.0),
+				0.25,
+				gray,
+			)));
+		}
+
+		let r = Ray::new(
+			Point3::new(0.0, 1.0, 0.0),
+			Vec3::new(0.0, -1.0, 0.0),
+			0.0,
+			false,
+		);
+		let rec = world
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap();
+		direct_light(&mut rng, r, &rec, &world, lights)
+	}
+
+	#[test]
+	fn test_rng_streams() {
+		for engine in [RngEngine::Xoshiro, RngEngine::Philox] {
+			let rngs = RngFactory::new(engine, 12345);
+			let draws = |pixel, sample| -> Vec<u64> {
+				let mut rng = rngs.sample(pixel, sample);
+				(0..8).map(|_| rng.next_u64()).collect()
+			};
+			// the same sample gets the same numbers every time, from any copy of the factory
+			assert_eq!(draws((3, 4), 5), draws((3, 4), 5));
+			assert_eq!(draws((3, 4), 5)[..], {
+				let mut rng = RngFactory::new(engine, 12345).sample((3, 4), 5);
+				(0..8).map(|_| rng.next_u64()).collect::<Vec<_>>()
+			});
+			// and every other sample, pixel, and seed gets different ones, even where the old
+			// per-tile seeds, the seed xored with x and y, would have been the same
+			assert_ne!(draws((3, 4), 5), draws((3, 4), 6));
+			assert_ne!(draws((3, 4), 5), draws((4, 3), 5));
+			let other = RngFactory::new(engine, 12345 ^ 3 ^ 4)
+				.sample((0, 0), 5)
+				.next_u64();
+			assert_ne!(draws((3, 4), 5)[0], other);
+
+			// the first numbers of neighbouring pixels aren't correlated
+			let size = 64;
+			let first = |x, y| rngs.sample((x, y), 0).gen::<f64>();
+			let pairs: Vec<(f64, f64)> = (0..size)
+				.flat_map(|y| (0..size).map(move |x| (x, y)))
+				.flat_map(|(x, y)| {
+					[
+						(first(x, y), first(x + 1, y)),
+						(first(x, y), first(x, y + 1)),
+					]
+				})
+				.collect();
+			let n = pairs.len() as f64;
+			let correlation = uniform_correlation(&pairs);
+			// about four standard deviations of the correlation of independent samples
+			assert!(
+				correlation.abs() < 4.0 / n.sqrt(),
+				"{:?}: {}",
+				engine,
+				correlation
+			);
+			assert!((pairs.iter().map(|p| p.0).sum::<f64>() / n - 0.5).abs() < 0.02);
+		}
+	}
+
+	/// the correlation of pairs of numbers that are each uniform in [0, 1)
+	fn uniform_correlation(pairs: &[(f64, f64)]) -> f64 {
+		let n = pairs.len() as f64;
+		let (mean_a, mean_b) = (
+			pairs.iter().map(|p| p.0).sum::<f64>() / n,
+			pairs.iter().map(|p| p.1).sum::<f64>() / n,
+		);
+		let covariance = pairs
+			.iter()
+			.map(|(a, b)| (a - mean_a) * (b - mean_b))
+			.sum::<f64>()
+			/ n;
+		covariance / (1.0 / 12.0)
+	}
+
+	#[test]
+	fn test_dimension_streams() {
+		for engine in [RngEngine::Xoshiro, RngEngine::Philox] {
+			let rngs = RngFactory::new(engine, 12345);
+			let pixel = (3, 4);
+			assert_eq!(
+				rngs.dimension(pixel, 5, Dimension::Lens),
+				rngs.dimension(pixel, 5, Dimension::Lens)
+			);
+			// each dimension is a stream of its own, apart from the one that bounces use
+			let first = rngs.sample(pixel, 5).gen::<f64>();
+			for dimension in [Dimension::Pixel, Dimension::Lens, Dimension::Time] {
+				assert_ne!(rngs.dimension(pixel, 5, dimension)[0], first);
+			}
+
+			// over many samples of a pixel, no two of the numbers a camera ray is made from move
+			// together, nor do they with the first bounce
+			let samples = 4096;
+			let numbers: Vec<[f64; 6]> = (0..samples)
+				.map(|sample| {
+					let [jitter_x, jitter_y] = rngs.dimension(pixel, sample, Dimension::Pixel);
+					let lens = rngs.dimension(pixel, sample, Dimension::Lens);
+					let time = rngs.dimension(pixel, sample, Dimension::Time)[0];
+					let bounce = rngs.sample(pixel, sample).gen::<f64>();
+					[jitter_x, jitter_y, lens[0], lens[1], time, bounce]
+				})
+				.collect();
+			for a in 0..6 {
+				for b in 0..a {
+					let pairs: Vec<(f64, f64)> = numbers.iter().map(|n| (n[a], n[b])).collect();
+					let correlation = uniform_correlation(&pairs);
+					assert!(
+						correlation.abs() < 4.0 / (samples as f64).sqrt(),
+						"{:?} {} {}: {}",
+						engine,
+						a,
+						b,
+						correlation
+					);
 				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_point_light_falloff() {
+		// 8 W/sr from 2 units away is 2 W/m², and a diffuse surface with albedo 0.5 reflects
+		// 0.5 / π of that per steradian
+		let lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::new(
+			Point3::new(0.0, 2.0, 0.0),
+			0.0,
+			Color::new(8.0, 8.0, 8.0),
+		))];
+		let direct = floor_direct_light(&lights, false);
+		assert!((direct.x() - 1.0 / PI).abs() < 1e-9, "{}", direct);
+		assert_eq!(floor_direct_light(&lights, true).x(), 0.0);
+	}
+
+	#[test]
+	fn test_directional_light_cosine() {
+		// 60° from the normal, so the floor receives half the irradiance
+		let angle = 60.0f64.to_radians();
+		let lights: Vec<Arc<dyn Light>> = vec![Arc::new(DirectionalLight::new(
+			Vec3::new(angle.sin(), angle.cos(), 0.0),
+			0.0,
+			Color::new(3.0, 3.0, 3.0),
+		))];
+		let direct = floor_direct_light(&lights, false);
+		assert!(
+			(direct.x() - 0.5 / PI * 3.0 * 0.5).abs() < 1e-9,
+			"{}",
+			direct
+		);
+	}
+
+	#[test]
+	fn test_shadow_catcher_alpha() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::new(
+			Point3::new(0.0, 2.0, 0.0),
+			0.0,
+			Color::new(8.0, 8.0, 8.0),
+		))];
+		let background = Color::new(0.5, 0.6, 0.7);
+		let mut world = HittableList::new();
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			0.0,
+			Arc::new(ShadowCatcher),
+		)));
+		world.add(Arc::new(Sphere::new(
+			Point3::new(0.0, 1.5, 0.0),
+			0.25,
+			Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5))),
+		)));
+
+		// the point directly below the sphere is fully shadowed, and one far away isn't at all
+		for (x, expected) in [(0.0, 0.0), (50.0, 1.0)] {
+			let r = Ray::new(
+				Point3::new(x, 1.0, 0.0),
+				Vec3::new(0.0, -1.0, 0.0),
+				0.0,
+				false,
+			);
+			let mut aov = Aov::default();
+			let color = ray_color(
+				&mut rng,
+				r,
+				&SceneContext {
+					background,
+					world: &world,
+					lights: &SceneLights::from(lights.clone()),
+					fog: None,
+				},
+				Bounces::new(10, 0),
+				&mut 0,
+				&mut MediumStack::default(),
+				Some(&mut aov),
+			)
+			.total();
+			assert_eq!(aov.alpha, 1.0 - expected);
+			assert!((color - background * expected).near_zero(), "{}", color);
+		}
+	}
+
+	#[test]
+	fn test_visibility() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let gray = Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5)));
+		let mut world = HittableList::new();
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			0.0,
+			gray.clone(),
+		)));
+		// a ceiling that lights the floor but can't be seen
+		world.add(Arc::new(VisibleTo::new(
+			Visibility::SECONDARY | Visibility::SHADOW,
+			Arc::new(XZRect::new(
+				-100.0,
+				100.0,
+				-100.0,
+				100.0,
+				2.0,
+				Arc::new(DiffuseLight::with_color(Color::new(1.0, 1.0, 1.0))),
+			)),
+		)));
+		let mut trace = |direction: Vec3| {
+			ray_color(
+				&mut rng,
+				Ray::new(Point3::new(0.0, 1.0, 0.0), direction, 0.0, false),
+				&SceneContext {
+					background: Color::zero(),
+					world: &world,
+					lights: &SceneLights::default(),
+					fog: None,
+				},
+				Bounces::new(2, 0),
+				&mut 0,
+				&mut MediumStack::default(),
+				None,
+			)
+			.total()
+		};
+		assert!(trace(Vec3::new(0.0, 1.0, 0.0)).near_zero());
+		// nearly all of the floor's sky is the ceiling, and it reflects half of it
+		let n = 1000;
+		let floor: f64 = (0..n)
+			.map(|_| trace(Vec3::new(0.0, -1.0, 0.0)).x())
+			.sum::<f64>()
+			/ n as f64;
+		assert!((floor - 0.5).abs() < 0.05, "{}", floor);
+
+		// and a blocker that casts a shadow without being seen
+		let lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::new(
+			Point3::new(0.0, 2.0, 0.0),
+			0.0,
+			Color::new(8.0, 8.0, 8.0),
+		))];
+		let mut world = HittableList::new();
+		world.add(Arc::new(VisibleTo::new(
+			Visibility::SHADOW,
+			Arc::new(Sphere::new(Point3::new(0.0, 1.5, 0.0), 0.25, gray.clone())),
+		)));
+		world.add(Arc::new(XZRect::new(
+			-100.0, 100.0, -100.0, 100.0, 0.0, gray,
+		)));
+		let r = Ray::new(
+			Point3::new(0.0, 1.0, 0.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			0.0,
+			false,
+		);
+		assert!(world
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.is_none());
+		let down = Ray::new(r.origin(), -r.direction(), 0.0, false);
+		let rec = world
+			.hit(&mut rng, down, Interval::new(0.001, f64::INFINITY))
+			.unwrap();
+		assert!(direct_light(&mut rng, down, &rec, &world, &lights).near_zero());
+		assert!(!world.occluded(
+			&mut rng,
+			r.with_kind(RayKind::Secondary),
+			Interval::new(0.001, 10.0)
+		));
+	}
+
+	#[test]
+	fn test_fog_falloff() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut world = HittableList::new();
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			0.0,
+			Arc::new(DiffuseLight::with_color(Color::new(1.0, 1.0, 1.0))),
+		)));
+		let fog = Fog {
+			density: 0.1,
+			color: Color::new(0.2, 0.4, 0.6),
+		};
 
-				let mut pixel_color = Color::zero();
-				for _ in 0..samples_per_pixel {
-					let mut peak_depth: i32 = 0;
-					let u = (i as f64 + rng.gen::<f64>()) / (width - 1) as f64;
-					let v = (j as f64 + rng.gen::<f64>()) / (height - 1) as f64;
-					let r = cam.get_ray(&mut rng, u, v, debug_mode == Some(DebugMode::Bvh));
+		// the direction isn't normalized, so this also checks that distance isn't taken from t
+		let r = Ray::new(
+			Point3::new(0.0, 5.0, 0.0),
+			Vec3::new(0.0, -2.0, 0.0),
+			0.0,
+			false,
+		);
+		let color = ray_color(
+			&mut rng,
+			r,
+			&SceneContext {
+				background: Color::zero(),
+				world: &world,
+				lights: &SceneLights::default(),
+				fog: Some(fog),
+			},
+			Bounces::new(10, 0),
+			&mut 0,
+			&mut MediumStack::default(),
+			None,
+		)
+		.total();
+		let transmittance = f64::exp(-0.1 * 5.0);
+		let expected =
+			Color::new(1.0, 1.0, 1.0) * transmittance + fog.color * (1.0 - transmittance);
+		assert!((color - expected).near_zero(), "{}", color);
+
+		// nothing is visible through infinitely much fog
+		let up = Ray::new(r.origin(), -r.direction(), 0.0, false);
+		let color = ray_color(
+			&mut rng,
+			up,
+			&SceneContext {
+				background: Color::zero(),
+				world: &world,
+				lights: &SceneLights::default(),
+				fog: Some(fog),
+			},
+			Bounces::new(10, 0),
+			&mut 0,
+			&mut MediumStack::default(),
+			None,
+		)
+		.total();
+		assert!((color - fog.color).near_zero(), "{}", color);
+	}
+
+	#[test]
+	fn test_split_lighting() {
+		// a floor with a point light over it, looked down at from between the two
+		let gray = Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5)));
+		let floor = Arc::new(XZRect::new(-100.0, 100.0, -100.0, 100.0, 0.0, gray.clone()));
+		let lights = SceneLights {
+			lights: vec![Arc::new(PointLight::new(
+				Point3::new(0.0, 2.0, 0.0),
+				0.0,
+				Color::new(8.0, 8.0, 8.0),
+			))],
+			..SceneLights::default()
+		};
+		let trace = |world: &HittableList, direction: Vec3| {
+			ray_color(
+				&mut Xoshiro256PlusPlus::seed_from_u64(0),
+				Ray::new(Point3::new(0.0, 1.0, 0.0), direction, 0.0, false),
+				&SceneContext {
+					background: Color::zero(),
+					world,
+					lights: &lights,
+					fog: None,
+				},
+				Bounces::new(10, 0),
+				&mut 0,
+				&mut MediumStack::default(),
+				None,
+			)
+		};
+
+		// nothing for the floor's light to bounce off of
+		let mut world = HittableList::new();
+		world.add(floor.clone());
+		let alone = trace(&world, Vec3::new(0.0, -1.0, 0.0));
+		assert!(alone.emitted.near_zero() && alone.indirect.near_zero());
+		assert!(
+			(alone.direct.x() - 1.0 / PI).abs() < 1e-9,
+			"{}",
+			alone.direct
+		);
+
+		// a ceiling sends some of the light back down
+		world.add(Arc::new(XZRect::new(
+			-100.0, 100.0, -100.0, 100.0, 4.0, gray,
+		)));
+		let covered = trace(&world, Vec3::new(0.0, -1.0, 0.0));
+		assert_eq!(covered.direct.x(), alone.direct.x());
+		assert!(covered.indirect.x() > 0.0);
+
+		// light seen straight from the camera is emitted, and seen in a mirror it is direct
+		let mut world = HittableList::new();
+		world.add(floor);
+		let white = Color::new(1.0, 1.0, 1.0);
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			3.0,
+			Arc::new(DiffuseLight::with_color(white)),
+		)));
+		let light = trace(&world, Vec3::new(0.0, 1.0, 0.0));
+		assert!((light.emitted - white).near_zero(), "{}", light.emitted);
+		assert!(light.direct.near_zero() && light.indirect.near_zero());
+		let mut world = HittableList::new();
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			0.0,
+			Arc::new(Metal::with_color(Color::new(0.5, 0.5, 0.5), 0.0)),
+		)));
+		world.add(Arc::new(XZRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			3.0,
+			Arc::new(DiffuseLight::with_color(white)),
+		)));
+		let mirror = trace(&world, Vec3::new(0.0, -1.0, 0.0));
+		assert!(
+			(mirror.direct - white * 0.5).near_zero(),
+			"{}",
+			mirror.direct
+		);
+		assert!(mirror.indirect.near_zero());
+	}
+
+	#[test]
+	fn test_render_panic() {
+		let texture = FunctionTexture(|_u, _v, _p| -> Color { panic!("broken texture") });
+		let mut world = HittableList::new();
+		world.add(Arc::new(Sphere::new(
+			Point3::zero(),
+			1.0,
+			Arc::new(Lambertian::new(Arc::new(texture))),
+		)));
+		let cam = Camera::new(
+			Point3::new(0.0, 0.0, 5.0),
+			Point3::zero(),
+			Vec3::new(0.0, 1.0, 0.0),
+			40.0,
+			1.0,
+			0.0,
+			5.0,
+			0.0,
+			1.0,
+		);
+
+		let (send, _recv) = mpsc::channel();
+		let tiles = Arc::new(TileQueue::scanline((32, 32)));
+		let result = render(
+			send,
+			RngFactory::new(RngEngine::Xoshiro, 0),
+			&SceneContext {
+				background: Color::zero(),
+				world: &world,
+				lights: &SceneLights::default(),
+				fog: None,
+			},
+			cam,
+			&RenderSettings {
+				size: (32, 32),
+				samples_per_pixel: 1,
+				max_depth: 10,
+				glass_depth_bonus: 0,
+				debug_mode: None,
+			},
+			tiles.clone(),
+			Arc::new(Heartbeat::new()),
+		);
+		assert_eq!(result.unwrap_err().message, "broken texture");
+		// no other thread should start another tile
+		assert_eq!(tiles.next(), None);
+	}
+
+	#[test]
+	fn test_depth_aov() {
+		// a wall 2 units away on the left, another 5 units away in the top right, and nothing in
+		// the bottom right. the field of view is narrow so that every ray is nearly straight ahead
+		let gray = Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5)));
+		let mut world = HittableList::new();
+		world.add(Arc::new(XYRect::new(
+			-100.0,
+			0.0,
+			-100.0,
+			100.0,
+			-2.0,
+			gray.clone(),
+		)));
+		world.add(Arc::new(XYRect::new(0.0, 100.0, 0.0, 100.0, -5.0, gray)));
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			1.0,
+			1.0,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		);
+
+		let size = 2 * TILE_SIZE;
+		let (send, recv) = mpsc::channel();
+		render(
+			send,
+			RngFactory::new(RngEngine::Xoshiro, 0),
+			&SceneContext {
+				background: Color::zero(),
+				world: &world,
+				lights: &SceneLights::default(),
+				fog: None,
+			},
+			cam,
+			&RenderSettings {
+				size: (size, size),
+				samples_per_pixel: 4,
+				max_depth: 10,
+				glass_depth_bonus: 0,
+				debug_mode: None,
+			},
+			Arc::new(TileQueue::scanline((size, size))),
+			Arc::new(Heartbeat::new()),
+		)
+		.unwrap();
+		let mut image = ImageBuffer::new((size, size), Color::zero());
+		let mut aovs = ImageBuffer::new((size, size), Aov::default());
+		for tile in recv.try_iter() {
+			tile.copy_to(&mut image, &mut aovs);
+		}
+
+		for (y, row) in aovs.rows().enumerate() {
+			for (x, aov) in row.iter().enumerate() {
+				let expected = if x < size / 2 {
+					2.0
+				} else if y < size / 2 {
+					5.0
+				} else {
+					f64::INFINITY
+				};
+				if expected.is_finite() {
+					assert!(
+						(aov.depth - expected).abs() < 1e-3,
+						"{} at {},{}",
+						aov.depth,
+						x,
+						y
+					);
+				} else {
+					assert_eq!(aov.depth, expected, "at {},{}", x, y);
+				}
+			}
+		}
+	}
+
+	/// render from the center of a mirrored sphere, where every ray bounces until max_depth
+	fn mirror_ball_stats(max_depth: usize) -> RenderStats {
+		let mut world = HittableList::new();
+		world.add(Arc::new(Sphere::new(
+			Point3::zero(),
+			10.0,
+			Arc::new(Metal::with_color(Color::new(0.9, 0.9, 0.9), 0.0)),
+		)));
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		);
+		let (send, _recv) = mpsc::channel();
+		render(
+			send,
+			RngFactory::new(RngEngine::Xoshiro, 0),
+			&SceneContext {
+				background: Color::zero(),
+				world: &world,
+				lights: &SceneLights::default(),
+				fog: None,
+			},
+			cam,
+			&RenderSettings {
+				size: (TILE_SIZE, TILE_SIZE),
+				samples_per_pixel: 4,
+				max_depth,
+				glass_depth_bonus: 0,
+				debug_mode: None,
+			},
+			Arc::new(TileQueue::scanline((TILE_SIZE, TILE_SIZE))),
+			Arc::new(Heartbeat::new()),
+		)
+		.unwrap()
+	}
+
+	/// average of every sample in a grid of pixels over the Cornell box with a glass ball on the
+	/// short block and a mirror ball in front of the tall one, and of the ones that see the balls
+	fn cornell_balls_means(sample_emitters: bool) -> (Color, Color) {
+		let (mut world, cam, background, _) = scenes::cornell_box();
+		let glass = Arc::new(Sphere::new(
+			Point3::new(212.0, 240.0, 147.0),
+			75.0,
+			Arc::new(Dielectric::new(1.5)),
+		));
+		let mirror = Arc::new(Sphere::new(
+			Point3::new(420.0, 70.0, 140.0),
+			70.0,
+			Arc::new(Metal::with_color(Color::new(0.9, 0.9, 0.9), 0.0)),
+		));
+		world.add(glass.clone());
+		world.add(mirror.clone());
+		let lights = SceneLights {
+			lights: Vec::new(),
+			emitters: EmitterList::new(
+				if sample_emitters {
+					world.emitters()
+				} else {
+					Vec::new()
+				},
+				true,
+			),
+			photons: None,
+		};
+
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let (size, samples) = (16, 256);
+		let (mut all, mut balls, mut ball_samples) = (Color::zero(), Color::zero(), 0);
+		for j in 0..size {
+			for i in 0..size {
+				for _ in 0..samples {
+					let s = (i as f64 + rng.gen::<f64>()) / size as f64;
+					let t = (j as f64 + rng.gen::<f64>()) / size as f64;
+					let r = cam.get_ray(&mut rng, s, t, false);
 					let color = ray_color(
 						&mut rng,
 						r,
-						background,
-						world.as_ref(),
-						max_depth as i32,
-						&mut peak_depth,
-					);
-
-					if debug_mode == Some(DebugMode::Depth) {
-						let shade = peak_depth as f64 / max_depth as f64;
-						pixel_color += Color::new(shade, shade, shade);
-					} else {
-						pixel_color += color;
+						&SceneContext {
+							background,
+							world: &world,
+							lights: &lights,
+							fog: None,
+						},
+						Bounces::new(50, 0),
+						&mut 0,
+						&mut MediumStack::default(),
+						None,
+					)
+					.total();
+					all += color;
+					let mut ball = |b: &Arc<Sphere>| {
+						b.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+							.is_some()
+					};
+					if ball(&glass) || ball(&mirror) {
+						balls += color;
+						ball_samples += 1;
 					}
 				}
-				let factor = 1.0 / samples_per_pixel as f64;
-				tile.pixels[j - y][i - x] = pixel_color * factor;
-				total_pixels += 1;
 			}
 		}
-		tile.duration = instant.elapsed();
-		total_time += tile.duration;
+		(
+			all / (size * size * samples) as f64,
+			balls / ball_samples as f64,
+		)
+	}
 
-		out.send(tile).unwrap();
+	#[test]
+	fn test_specular_light_sampling() {
+		// sampling the ceiling light shouldn't change what the glass and the mirror show
+		let (all, balls) = cornell_balls_means(true);
+		let (expected_all, expected_balls) = cornell_balls_means(false);
+		for channel in 0..3 {
+			// caustics make the render without light sampling noisy, even at 256 samples
+			let close = |a: f64, b: f64| (a - b).abs() < 0.15 * b;
+			assert!(
+				close(all[channel], expected_all[channel]),
+				"{} {}",
+				all,
+				expected_all
+			);
+			assert!(
+				close(balls[channel], expected_balls[channel]),
+				"{} {}",
+				balls,
+				expected_balls
+			);
+		}
+	}
+
+	#[test]
+	fn test_bounce_cap() {
+		let mut world = HittableList::new();
+		world.add(Arc::new(Sphere::new(
+			Point3::zero(),
+			10.0,
+			Arc::new(Metal::with_color(Color::new(0.9, 0.9, 0.9), 0.0)),
+		)));
+		let mut rays = 0;
+		ray_color(
+			&mut Xoshiro256PlusPlus::seed_from_u64(0),
+			Ray::new(Point3::zero(), Vec3::new(0.3, 0.2, -1.0), 0.0, false),
+			&SceneContext {
+				background: Color::zero(),
+				world: &world,
+				lights: &SceneLights::default(),
+				fog: None,
+			},
+			Bounces::new(i32::MAX as usize, 0),
+			&mut rays,
+			&mut MediumStack::default(),
+			None,
+		)
+		.total();
+		assert_eq!(rays, MAX_BOUNCES as u64);
 	}
 
-	return (total_time, total_pixels);
+	#[test]
+	fn test_glass_depth_bonus() {
+		// panes that don't bend light, in front of a light
+		let panes = |n: usize, glass: Arc<Dielectric>| {
+			let mut world = HittableList::new();
+			for i in 0..n {
+				let z = -1.0 - i as f64;
+				world.add(Arc::new(XYRect::new(
+					-5.0,
+					5.0,
+					-5.0,
+					5.0,
+					z,
+					glass.clone(),
+				)));
+			}
+			world.add(Arc::new(XYRect::new(
+				-5.0,
+				5.0,
+				-5.0,
+				5.0,
+				-(n as f64) - 5.0,
+				Arc::new(DiffuseLight::with_color(Color::new(1.0, 1.0, 1.0))),
+			)));
+			world
+		};
+		let trace = |world: &HittableList, bounces: Bounces| {
+			let mut rays = 0;
+			let color = ray_color(
+				&mut Xoshiro256PlusPlus::seed_from_u64(0),
+				Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0, false),
+				&SceneContext {
+					background: Color::zero(),
+					world,
+					lights: &SceneLights::default(),
+					fog: None,
+				},
+				bounces,
+				&mut rays,
+				&mut MediumStack::default(),
+				None,
+			)
+			.total();
+			(color, rays)
+		};
+
+		let world = panes(4, Arc::new(Dielectric::new(1.0)));
+		assert!(trace(&world, Bounces::new(3, 0)).0.near_zero());
+		assert!(trace(&world, Bounces::new(3, 1)).0.near_zero());
+		assert!((trace(&world, Bounces::new(3, 2)).0 - Color::new(1.0, 1.0, 1.0)).near_zero());
+		// a material's own bonus wins over the global one
+		let world = panes(4, Arc::new(Dielectric::new(1.0).with_depth_bonus(4)));
+		assert!((trace(&world, Bounces::new(1, 0)).0 - Color::new(1.0, 1.0, 1.0)).near_zero());
+		let world = panes(4, Arc::new(Dielectric::new(1.0).with_depth_bonus(0)));
+		assert!(trace(&world, Bounces::new(3, 100)).0.near_zero());
+
+		// however big the bonus is, paths still stop after MAX_BOUNCES
+		let world = panes(MAX_BOUNCES as usize, Arc::new(Dielectric::new(1.0)));
+		let (color, rays) = trace(&world, Bounces::new(1, u32::MAX));
+		assert!(color.near_zero());
+		assert_eq!(rays, MAX_BOUNCES as u64);
+	}
+
+	/// takes a long time to scatter, like a material stuck on a pathological ray
+	#[derive(Debug)]
+	struct Sleepy(Duration);
+
+	impl Display for Sleepy {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			write!(f, "Sleepy({:?})", self.0)
+		}
+	}
+
+	impl Material for Sleepy {
+		fn scatter(
+			&self,
+			_rng: &mut dyn RngCore,
+			_r_in: &Ray,
+			_rec: &HitRecord,
+		) -> Option<ScatterResult> {
+			thread::sleep(self.0);
+			None
+		}
+	}
+
+	#[test]
+	fn test_heartbeat_stall() {
+		let mut world = HittableList::new();
+		world.add(Arc::new(XYRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			-1.0,
+			Arc::new(Sleepy(Duration::from_millis(400))),
+		)));
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		);
+		let heartbeat = Arc::new(Heartbeat::new());
+		let (send, _recv) = mpsc::channel();
+		let thread = {
+			let heartbeat = heartbeat.clone();
+			thread::spawn(move || {
+				render(
+					send,
+					RngFactory::new(RngEngine::Xoshiro, 0),
+					&SceneContext {
+						background: Color::zero(),
+						world: &world,
+						lights: &SceneLights::default(),
+						fog: None,
+					},
+					cam,
+					&RenderSettings {
+						size: (1, 1),
+						samples_per_pixel: 1,
+						max_depth: 10,
+						glass_depth_bonus: 0,
+						debug_mode: None,
+					},
+					Arc::new(TileQueue::scanline((1, 1))),
+					heartbeat,
+				)
+			})
+		};
+
+		// the only pixel is stuck in the material, so the heartbeat falls behind
+		thread::sleep(Duration::from_millis(200));
+		let (since, tile) = heartbeat.since_last().unwrap();
+		assert!(since >= Duration::from_millis(150), "{:?}", since);
+		assert_eq!(tile, (0, 0));
+
+		assert!(thread.join().unwrap().is_ok());
+		assert!(heartbeat.since_last().is_none());
+	}
+
+	#[test]
+	fn test_ray_count() {
+		let samples = (TILE_SIZE * TILE_SIZE * 4) as u64;
+		// only camera rays
+		let stats = mirror_ball_stats(1);
+		assert_eq!((stats.samples, stats.rays), (samples, samples));
+		// and then a ray for every bounce
+		let stats = mirror_ball_stats(10);
+		assert_eq!(stats.samples, samples);
+		assert!(stats.rays > samples * 5, "{}", stats.rays);
+	}
+
+	#[test]
+	fn test_position_aov() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut world = HittableList::new();
+		let center = Point3::new(1.0, 2.0, 3.0);
+		world.add(Arc::new(Sphere::new(
+			center,
+			0.5,
+			Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5))),
+		)));
+
+		let mut sample = |direction: Vec3| {
+			let mut aov = Aov::default();
+			let r = Ray::new(Point3::new(1.0, 2.0, 10.0), direction, 0.0, false);
+			ray_color(
+				&mut rng,
+				r,
+				&SceneContext {
+					background: Color::zero(),
+					world: &world,
+					lights: &SceneLights::default(),
+					fog: None,
+				},
+				Bounces::new(10, 0),
+				&mut 0,
+				&mut MediumStack::default(),
+				Some(&mut aov),
+			)
+			.total();
+			aov
+		};
+		// straight at the center of the sphere, which is hit on the side facing the camera
+		let hit = sample(Vec3::new(0.0, 0.0, -1.0));
+		assert!((hit.position - Point3::new(1.0, 2.0, 3.5)).near_zero());
+		// misses are easy to tell apart from anything that was hit
+		let miss = sample(Vec3::new(0.0, 0.0, 1.0));
+		assert!(miss.position.x().is_nan());
+
+		// a pixel keeps the position of its closest sample, like depth
+		let mut pixel = Aov::default();
+		pixel.accumulate(miss);
+		assert!(pixel.position.x().is_nan());
+		pixel.accumulate(hit);
+		pixel.accumulate(miss);
+		assert!((pixel.position - hit.position).near_zero());
+	}
+
+	#[test]
+	fn test_accumulator() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let samples: Vec<Color> = (0..100).map(|_| Color::random(&mut rng) * 3.0).collect();
+		let mut all = Accumulator::default();
+		for &sample in &samples {
+			all.add_sample(sample);
+		}
+		assert_eq!(all.count(), 100);
+
+		// the same as summing and then scaling, like pixels always were
+		let mut sum = Color::zero();
+		for &sample in &samples {
+			sum += sample;
+		}
+		let (mean, expected) = (all.mean(), sum * (1.0 / samples.len() as f64));
+		assert_eq!(
+			[mean.x(), mean.y(), mean.z()],
+			[expected.x(), expected.y(), expected.z()]
+		);
+
+		// the same as the variance found from the mean in a second pass
+		let mean = all.mean();
+		let mut squares = Color::zero();
+		for &sample in &samples {
+			squares += (sample - mean) * (sample - mean);
+		}
+		assert!((all.variance() - squares / 99.0).near_zero());
+
+		// split in two and merged back together
+		let (mut first, mut second) = (Accumulator::default(), Accumulator::default());
+		for &sample in &samples[..30] {
+			first.add_sample(sample);
+		}
+		for &sample in &samples[30..] {
+			second.add_sample(sample);
+		}
+		first.merge(second);
+		assert_eq!(first.count(), all.count());
+		assert!((first.mean() - all.mean()).near_zero());
+		assert!((first.variance() - all.variance()).near_zero());
+
+		let mut one = Accumulator::default();
+		assert!(one.mean().near_zero());
+		one.add_sample(Color::new(1.0, 2.0, 3.0));
+		assert!(one.variance().near_zero());
+	}
+
+	#[test]
+	fn test_tiles_cover_awkward_sizes() {
+		for (width, height) in [(601, 399), (1, 1), (17, 33)] {
+			let mut image = ImageBuffer::new((width, height), Color::zero());
+			let mut aovs = ImageBuffer::new((width, height), Aov::default());
+			let mut pixels = 0;
+			for y in (0..height).step_by(TILE_SIZE) {
+				for x in (0..width).step_by(TILE_SIZE) {
+					let mut tile = Tile::new(x, y);
+					let mut white = Accumulator::default();
+					white.add_sample(Color::new(1.0, 1.0, 1.0));
+					tile.samples = [[white; TILE_SIZE]; TILE_SIZE];
+					pixels += tile.copy_to(&mut image, &mut aovs);
+				}
+			}
+			assert_eq!(pixels, width * height);
+			assert!(image.pixels().iter().all(|p| p.x() == 1.0));
+		}
+	}
+
+	#[test]
+	fn test_tile_rows_in_order() {
+		// looking at a light that gets brighter towards the top, so every row of the image should
+		// be brighter than the one below it
+		let gradient = FunctionTexture(|_, _, p: Point3| {
+			let y = p.y() + 1.0;
+			Color::new(y, y, y)
+		});
+		let mut world = HittableList::new();
+		world.add(Arc::new(XYRect::new(
+			-100.0,
+			100.0,
+			-100.0,
+			100.0,
+			-1.0,
+			Arc::new(DiffuseLight::new(Arc::new(gradient))),
+		)));
+		let world: Arc<dyn Hittable> = Arc::new(world);
+
+		for height in [17, 31, 399] {
+			let width = 3;
+			let cam = Camera::new(
+				Point3::zero(),
+				Point3::new(0.0, 0.0, -1.0),
+				Vec3::new(0.0, 1.0, 0.0),
+				90.0,
+				width as f64 / height as f64,
+				0.0,
+				1.0,
+				0.0,
+				0.0,
+			);
+			let (send, recv) = mpsc::channel();
+			render(
+				send,
+				RngFactory::new(RngEngine::Xoshiro, 0),
+				&SceneContext {
+					background: Color::zero(),
+					world: world.as_ref(),
+					lights: &SceneLights::default(),
+					fog: None,
+				},
+				cam,
+				&RenderSettings {
+					size: (width, height),
+					samples_per_pixel: 2,
+					max_depth: 2,
+					glass_depth_bonus: 0,
+					debug_mode: None,
+				},
+				Arc::new(TileQueue::scanline((width, height))),
+				Arc::new(Heartbeat::new()),
+			)
+			.unwrap();
+			let mut image = ImageBuffer::new((width, height), Color::zero());
+			let mut aovs = ImageBuffer::new((width, height), Aov::default());
+			let mut pixels = 0;
+			for tile in recv.try_iter() {
+				pixels += tile.copy_to(&mut image, &mut aovs);
+			}
+			assert_eq!(pixels, width * height);
+
+			let rows: Vec<f64> = image.rows().map(|row| row[1].x()).collect();
+			for (y, pair) in rows.windows(2).enumerate() {
+				assert!(
+					pair[0] > pair[1],
+					"rows {} and {} of {} out of order: {:?}",
+					y,
+					y + 1,
+					height,
+					pair
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_corner_rays_symmetric() {
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.0,
+			1.0,
+			0.0,
+			1.0,
+		);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		for (width, height) in [(1, 1), (2, 1), (601, 399), (16, 9)] {
+			let cam = cam.with_aspect_ratio(width as f64 / height as f64);
+			let mut ray = |i, j| {
+				let (u, v) = pixel_uv((i, j), (width, height), (0.5, 0.5));
+				cam.get_ray(&mut rng, u, v, false).direction()
+			};
+			let bottom_left = ray(0, 0);
+			let top_right = ray(width - 1, height - 1);
+			assert!(
+				(bottom_left + top_right - 2.0 * Vec3::new(0.0, 0.0, -1.0)).near_zero(),
+				"{}x{}: {} {}",
+				width,
+				height,
+				bottom_left,
+				top_right
+			);
+
+			// pixels should be square
+			if width > 1 && height > 1 {
+				let right = ray(1, 0) - bottom_left;
+				let up = ray(0, 1) - bottom_left;
+				assert!((right.length() - up.length()).abs() < 1e-12);
+			}
+		}
+	}
 }
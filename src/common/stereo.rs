@@ -0,0 +1,47 @@
+use super::{Color, ImageBuffer};
+
+/// Put two images of the same size next to each other, with left on the left. Works for any
+/// per-pixel buffer, not just colors
+pub fn side_by_side<T: Clone>(left: &ImageBuffer<T>, right: &ImageBuffer<T>) -> ImageBuffer<T> {
+	ImageBuffer::from_pixels(
+		(left.width() + right.width(), left.height()),
+		left.rows()
+			.zip(right.rows())
+			.flat_map(|(l, r)| l.iter().chain(r).cloned())
+			.collect(),
+	)
+}
+
+/// Combine two views into one image for red/cyan glasses, with the red channel from the left eye
+/// and green and blue from the right. The images should be linear, since the channels are taken
+/// as they are
+pub fn anaglyph(left: &ImageBuffer<Color>, right: &ImageBuffer<Color>) -> ImageBuffer<Color> {
+	ImageBuffer::from_fn(left.size(), |x, y| {
+		let (l, r) = (left[(x, y)], right[(x, y)]);
+		Color::new(l.x(), r.y(), r.z())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_side_by_side() {
+		let left = ImageBuffer::from_pixels((2, 2), vec![1, 2, 3, 4]);
+		let right = ImageBuffer::from_pixels((2, 2), vec![5, 6, 7, 8]);
+		let image = side_by_side(&left, &right);
+		assert_eq!(image.size(), (4, 2));
+		assert_eq!(image.pixels(), [1, 2, 5, 6, 3, 4, 7, 8]);
+	}
+
+	#[test]
+	fn test_anaglyph() {
+		let left = ImageBuffer::new((3, 2), Color::new(0.1, 0.2, 0.3));
+		let right = ImageBuffer::new((3, 2), Color::new(0.4, 0.5, 0.6));
+		let image = anaglyph(&left, &right);
+		assert_eq!(image.size(), (3, 2));
+		let p = image[(2, 1)];
+		assert_eq!((p.x(), p.y(), p.z()), (0.1, 0.5, 0.6));
+	}
+}
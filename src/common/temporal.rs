@@ -0,0 +1,111 @@
+use super::args::TemporalSeed;
+use super::{Color, ImageBuffer};
+
+/// The sample seed for one frame of an animation. Pixels are seeded with the sample seed xored
+/// with their coordinates, so varying seeds are spread over all of the bits: seeds that only
+/// differ in their low bits would give each frame the last frame's noise moved over a few pixels
+pub fn frame_seed(mode: TemporalSeed, sample_seed: u64, frame: u32) -> u64 {
+	match mode {
+		TemporalSeed::Fixed => sample_seed,
+		// the golden ratio as a fraction of 2^64, like SplitMix64 steps by
+		TemporalSeed::Varying => sample_seed ^ (frame as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+	}
+}
+
+/// The running average of every frame rendered so far, for --accumulate-frames
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+	sum: ImageBuffer<Color>,
+	frames: usize,
+}
+
+impl Accumulator {
+	pub fn new(size: (usize, usize)) -> Accumulator {
+		Accumulator {
+			sum: ImageBuffer::new(size, Color::zero()),
+			frames: 0,
+		}
+	}
+
+	/// image: linear, and the same size as the accumulator
+	pub fn add(&mut self, image: &ImageBuffer<Color>) {
+		assert_eq!(
+			image.size(),
+			self.sum.size(),
+			"frames must be the same size"
+		);
+		for (sum, &pixel) in self.sum.pixels_mut().iter_mut().zip(image.pixels()) {
+			*sum += pixel;
+		}
+		self.frames += 1;
+	}
+
+	pub fn frames(&self) -> usize {
+		self.frames
+	}
+
+	/// the average of the frames added so far
+	pub fn mean(&self) -> ImageBuffer<Color> {
+		let frames = self.frames.max(1) as f64;
+		self.sum.map(|&sum| sum / frames)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+
+	#[test]
+	fn test_frame_seed() {
+		let seed = 12345;
+		for frame in 0..100 {
+			assert_eq!(frame_seed(TemporalSeed::Fixed, seed, frame), seed);
+		}
+		assert_eq!(frame_seed(TemporalSeed::Varying, seed, 0), seed);
+		let seeds: Vec<u64> = (0..1000)
+			.map(|frame| frame_seed(TemporalSeed::Varying, seed, frame))
+			.collect();
+		for (i, &a) in seeds.iter().enumerate() {
+			for &b in &seeds[..i] {
+				// a difference that pixel coordinates could make up for would repeat the noise
+				assert!(a ^ b > 1 << 32, "{:x} {:x}", a, b);
+			}
+		}
+	}
+
+	#[test]
+	fn test_accumulation() {
+		// frames of noise around a flat grey, like renders of a still scene with different seeds
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let size = (64, 64);
+		let truth = 0.5;
+		// standard deviation of a uniform distribution 1 wide
+		let sigma = (1.0f64 / 12.0).sqrt();
+		let mut accumulator = Accumulator::new(size);
+		for frames in 1..=64 {
+			let noise = ImageBuffer::from_fn(size, |_, _| {
+				let v = truth + rng.gen_range(-0.5..0.5);
+				Color::new(v, v, v)
+			});
+			accumulator.add(&noise);
+			assert_eq!(accumulator.frames(), frames);
+			if frames.is_power_of_two() {
+				let mean = accumulator.mean();
+				let squared: f64 = mean.pixels().iter().map(|c| (c.x() - truth).powi(2)).sum();
+				let rmse = (squared / mean.pixels().len() as f64).sqrt();
+				// the error goes down like 1/sqrt(n)
+				let expected = sigma / (frames as f64).sqrt();
+				assert!(
+					(rmse / expected - 1.0).abs() < 0.05,
+					"{}: {} {}",
+					frames,
+					rmse,
+					expected
+				);
+			}
+		}
+	}
+}
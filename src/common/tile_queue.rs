@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::common::raytracer::TILE_SIZE;
+
+/// The tiles of an image in the order they're handed out to rendering threads. Each tile is
+/// identified by its bottom left corner
+pub struct TileQueue {
+	tiles: Vec<(usize, usize)>,
+	next: AtomicUsize,
+}
+
+impl TileQueue {
+	/// Every tile covering an image of the given size, going left to right and then up a row
+	pub fn scanline((width, height): (usize, usize)) -> TileQueue {
+		let tiles = (0..height)
+			.step_by(TILE_SIZE)
+			.flat_map(|y| (0..width).step_by(TILE_SIZE).map(move |x| (x, y)))
+			.collect();
+		TileQueue {
+			tiles,
+			next: AtomicUsize::new(0),
+		}
+	}
+
+	/// Every tile covering an image of the given size, most expensive first, so that the slow
+	/// tiles don't end up as the last ones being rendered while the other threads sit idle
+	/// costs: estimated time to render each tile, in scanline order. tiles with the same cost
+	///        stay in scanline order
+	pub fn by_cost(size: (usize, usize), costs: &[Duration]) -> TileQueue {
+		let mut queue = TileQueue::scanline(size);
+		assert_eq!(queue.tiles.len(), costs.len());
+		let mut order: Vec<usize> = (0..costs.len()).collect();
+		order.sort_by(|&a, &b| costs[b].cmp(&costs[a]));
+		queue.tiles = order.into_iter().map(|i| queue.tiles[i]).collect();
+		queue
+	}
+
+	/// Take the next tile to render, or None if there aren't any left
+	pub fn next(&self) -> Option<(usize, usize)> {
+		self.tiles
+			.get(self.next.fetch_add(1, Ordering::Relaxed))
+			.copied()
+	}
+
+	/// Stop handing out tiles, so that the other threads finish as soon as they're done with the
+	/// ones they have
+	pub fn cancel(&self) {
+		self.next.store(self.tiles.len(), Ordering::Relaxed);
+	}
+
+	/// All of the tiles in order, whether or not they've been taken yet
+	pub fn tiles(&self) -> &[(usize, usize)] {
+		&self.tiles
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+
+	use super::*;
+
+	/// take every tile from the queue on the given number of threads
+	fn drain(queue: &TileQueue, threads: usize) -> Vec<(usize, usize)> {
+		let mut taken: Vec<(usize, usize)> = thread::scope(|scope| {
+			let handles: Vec<_> = (0..threads)
+				.map(|_| {
+					scope.spawn(|| {
+						let mut taken = Vec::new();
+						while let Some(tile) = queue.next() {
+							taken.push(tile);
+						}
+						taken
+					})
+				})
+				.collect();
+			handles
+				.into_iter()
+				.flat_map(|h| h.join().unwrap())
+				.collect()
+		});
+		taken.sort_unstable();
+		taken
+	}
+
+	#[test]
+	fn test_every_tile_once() {
+		// sizes that are and aren't multiples of the tile size in each direction
+		let sizes = [
+			(1, 1),
+			(TILE_SIZE, TILE_SIZE),
+			(2 * TILE_SIZE, TILE_SIZE + 4),
+			(TILE_SIZE + 4, 2 * TILE_SIZE),
+			(TILE_SIZE + 1, 2 * TILE_SIZE + 1),
+			(3 * TILE_SIZE, 5),
+			(100, 37),
+		];
+		for (width, height) in sizes {
+			let mut expected = Vec::new();
+			for y in 0..height.div_ceil(TILE_SIZE) {
+				for x in 0..width.div_ceil(TILE_SIZE) {
+					expected.push((x * TILE_SIZE, y * TILE_SIZE));
+				}
+			}
+			expected.sort_unstable();
+			let costs: Vec<Duration> = (0..expected.len() as u64)
+				.map(|i| Duration::from_millis(i * 7 % 5))
+				.collect();
+			for threads in [1, 2, 3, 8] {
+				for queue in [
+					TileQueue::scanline((width, height)),
+					TileQueue::by_cost((width, height), &costs),
+				] {
+					let taken = drain(&queue, threads);
+					assert_eq!(
+						taken, expected,
+						"{}x{} on {} threads",
+						width, height, threads
+					);
+					assert_eq!(queue.next(), None);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_by_cost() {
+		let costs = [1, 5, 2, 5, 0, 3].map(Duration::from_millis);
+		let queue = TileQueue::by_cost((3 * TILE_SIZE, TILE_SIZE + 1), &costs);
+		let expected = [(16, 0), (0, 16), (32, 16), (32, 0), (0, 0), (16, 16)];
+		assert_eq!(queue.tiles(), expected);
+
+		let mut taken = Vec::new();
+		while let Some(tile) = queue.next() {
+			taken.push(tile);
+		}
+		assert_eq!(taken, expected);
+		assert_eq!(queue.next(), None);
+	}
+
+	#[test]
+	fn test_cancel() {
+		let queue = TileQueue::scanline((40, 40));
+		assert_eq!(queue.tiles().len(), 9);
+		assert_eq!(queue.next(), Some((0, 0)));
+		assert_eq!(queue.next(), Some((16, 0)));
+		queue.cancel();
+		assert_eq!(queue.next(), None);
+	}
+}
@@ -1,4 +1,5 @@
 use std::{
+	f64::consts::TAU,
 	fmt::Display,
 	iter::Sum,
 	ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign},
@@ -16,6 +17,14 @@ impl Vec3 {
 		Self { e: [e0, e1, e2] }
 	}
 
+	pub fn from_array(e: [f64; 3]) -> Self {
+		Self { e }
+	}
+
+	pub fn to_array(self) -> [f64; 3] {
+		self.e
+	}
+
 	pub fn x(self) -> f64 {
 		self.e[0]
 	}
@@ -68,18 +77,20 @@ impl Vec3 {
 		)
 	}
 
+	/// A uniformly distributed point inside the unit sphere: a random direction, scaled by the cube
+	/// root of a uniform number since the volume within radius r grows as r^3
 	pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Self {
-		loop {
-			let v = Self::random_range(rng, -1.0, 1.0);
-			if v.length_squared() >= 1.0 {
-				continue;
-			}
-			return v;
-		}
+		let r: f64 = rng.gen();
+		Self::random_unit_vector(rng) * r.cbrt()
 	}
 
+	/// A uniformly distributed direction. z is uniform in [-1, 1] because every slice of a sphere
+	/// of the same thickness has the same area, so only z and the angle around the z axis are drawn
 	pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Self {
-		Self::random_in_unit_sphere(rng).unit_vector()
+		let z: f64 = rng.gen_range(-1.0..1.0);
+		let phi: f64 = rng.gen_range(0.0..TAU);
+		let r = (1.0 - z * z).sqrt();
+		Self::new(r * phi.cos(), r * phi.sin(), z)
 	}
 
 	pub fn random_in_hemisphere<R: Rng + ?Sized>(rng: &mut R, normal: Self) -> Self {
@@ -108,14 +119,18 @@ impl Vec3 {
 		r_out_perp + r_out_parallel
 	}
 
+	/// A uniformly distributed point inside the unit disk in the xy plane, with the radius scaled by
+	/// a square root since the area within radius r grows as r^2
 	pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Self {
-		loop {
-			let v = Self::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-			if v.length_squared() >= 1.0 {
-				continue;
-			}
-			return v;
-		}
+		Self::in_unit_disk([rng.gen(), rng.gen()])
+	}
+
+	/// The point in the unit disk for two uniform numbers in [0, 1), which are spread evenly over
+	/// it like random_in_unit_disk's
+	pub fn in_unit_disk([u, v]: [f64; 2]) -> Self {
+		let r = u.sqrt();
+		let phi = TAU * v;
+		Self::new(r * phi.cos(), r * phi.sin(), 0.0)
 	}
 
 	pub fn min(&self, v: Vec3) -> Self {
@@ -125,6 +140,88 @@ impl Vec3 {
 	pub fn max(&self, v: Vec3) -> Self {
 		Self::new(self[0].max(v[0]), self[1].max(v[1]), self[2].max(v[2]))
 	}
+
+	/// the absolute value of each component
+	pub fn abs(self) -> Self {
+		Self::from_array(self.e.map(f64::abs))
+	}
+
+	/// each component clamped to [min, max]
+	pub fn clamp(self, min: f64, max: f64) -> Self {
+		Self::from_array(self.e.map(|c| c.clamp(min, max)))
+	}
+
+	/// the largest component
+	pub fn max_component(self) -> f64 {
+		self.e[0].max(self.e[1]).max(self.e[2])
+	}
+
+	/// index of the largest component, the first of them if there's a tie
+	#[allow(dead_code)]
+	pub fn max_axis(self) -> usize {
+		if self.e[0] >= self.e[1] && self.e[0] >= self.e[2] {
+			0
+		} else if self.e[1] >= self.e[2] {
+			1
+		} else {
+			2
+		}
+	}
+
+	/// the components in a different order, taking x from component x, and so on
+	#[allow(dead_code)]
+	pub fn permute(self, x: usize, y: usize, z: usize) -> Self {
+		Self::new(self.e[x], self.e[y], self.e[z])
+	}
+}
+
+/// Three unit vectors at right angles to each other, for turning directions that were made
+/// around the z axis into ones around w
+#[derive(Debug, Clone, Copy)]
+pub struct OrthonormalBasis {
+	pub u: Vec3,
+	pub v: Vec3,
+	pub w: Vec3,
+}
+
+impl OrthonormalBasis {
+	/// A basis around w, which is a unit vector, with u and v pointing any way around it
+	pub fn new(w: Vec3) -> Self {
+		// an axis that's far from parallel to w, so that the cross product is accurate
+		let a = if w.x().abs() > 0.9 {
+			Vec3::new(0.0, 1.0, 0.0)
+		} else {
+			Vec3::new(1.0, 0.0, 0.0)
+		};
+		let v = w.cross(a).unit_vector();
+		Self {
+			u: w.cross(v),
+			v,
+			w,
+		}
+	}
+
+	/// A basis around w, which is a unit vector, with v as close to up as it can be and u to the
+	/// side. Up can't be parallel to w
+	pub fn with_up(w: Vec3, up: Vec3) -> Self {
+		let u = up.cross(w).unit_vector();
+		Self {
+			u,
+			v: w.cross(u),
+			w,
+		}
+	}
+
+	/// a direction given in the basis, in world space
+	pub fn to_world(self, a: Vec3) -> Vec3 {
+		a.x() * self.u + a.y() * self.v + a.z() * self.w
+	}
+
+	/// a direction in world space, in the basis
+	#[allow(dead_code)]
+	pub fn to_local(self, a: Vec3) -> Vec3 {
+		Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+	}
 }
 
 impl Neg for Vec3 {
@@ -250,3 +347,138 @@ impl Display for Vec3 {
 }
 
 pub type Point3 = Vec3;
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+
+	const N: usize = 100_000;
+
+	/// mean of the points, and the fraction of them within each tenth of the radius
+	fn distribution(sample: impl Fn(&mut Xoshiro256PlusPlus) -> Vec3) -> (Vec3, [f64; 10]) {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut sum = Vec3::zero();
+		let mut shells = [0.0; 10];
+		for _ in 0..N {
+			let v = sample(&mut rng);
+			sum += v;
+			shells[((v.length() * 10.0) as usize).min(9)] += 1.0 / N as f64;
+		}
+		(sum / N as f64, shells)
+	}
+
+	fn assert_near(actual: f64, expected: f64) {
+		assert!(
+			(actual - expected).abs() < 0.01,
+			"{} should be about {}",
+			actual,
+			expected
+		);
+	}
+
+	#[test]
+	fn test_components() {
+		let v = Vec3::new(-3.0, 0.5, 2.0);
+		assert_eq!(v.abs().to_array(), [3.0, 0.5, 2.0]);
+		assert_eq!(v.clamp(-1.0, 1.0).to_array(), [-1.0, 0.5, 1.0]);
+		assert_eq!(Vec3::from_array(v.to_array()).to_array(), v.to_array());
+		assert_eq!(v.max_component(), 2.0);
+		assert_eq!(v.abs().max_component(), 3.0);
+		assert_eq!(v.max_axis(), 2);
+		assert_eq!(v.abs().max_axis(), 0);
+		// ties go to the first
+		assert_eq!(Vec3::new(1.0, 4.0, 4.0).max_axis(), 1);
+		assert_eq!(Vec3::new(0.0, 0.0, 0.0).max_axis(), 0);
+		assert_eq!(v.permute(2, 0, 1).to_array(), [2.0, -3.0, 0.5]);
+		assert_eq!(v.permute(1, 1, 0).to_array(), [0.5, 0.5, -3.0]);
+	}
+
+	#[test]
+	fn test_orthonormal_basis() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let axes = [
+			Vec3::new(1.0, 0.0, 0.0),
+			Vec3::new(0.0, -1.0, 0.0),
+			Vec3::new(0.0, 0.0, 1.0),
+		];
+		let directions = axes
+			.into_iter()
+			.chain((0..100).map(|_| Vec3::random_unit_vector(&mut rng)))
+			.collect::<Vec<_>>();
+		for w in directions {
+			let up = Vec3::random_unit_vector(&mut rng);
+			for basis in [OrthonormalBasis::new(w), OrthonormalBasis::with_up(w, up)] {
+				assert_eq!(basis.w.to_array(), w.to_array());
+				for (a, b) in [(basis.u, basis.v), (basis.v, basis.w), (basis.w, basis.u)] {
+					assert!((a.length() - 1.0).abs() < 1e-12);
+					assert!(a.dot(b).abs() < 1e-12, "{} {}", a, b);
+				}
+				// z in the basis is w, and going there and back changes nothing
+				assert!((basis.to_world(Vec3::new(0.0, 0.0, 1.0)) - w).length() < 1e-12);
+				let d = Vec3::random_range(&mut rng, -1.0, 1.0);
+				assert!((basis.to_local(basis.to_world(d)) - d).length() < 1e-12);
+				assert!((basis.to_world(basis.to_local(d)) - d).length() < 1e-12);
+			}
+			// v is as close to up as it can be: up is in the plane of v and w, on v's side
+			let basis = OrthonormalBasis::with_up(w, up);
+			assert!(up.dot(basis.u).abs() < 1e-12);
+			assert!(up.dot(basis.v) >= 0.0);
+		}
+	}
+
+	#[test]
+	fn test_unit_vector() {
+		let (mean, _) = distribution(|rng| {
+			let v = Vec3::random_unit_vector(rng);
+			assert!((v.length() - 1.0).abs() < 1e-12);
+			v
+		});
+		for axis in 0..3 {
+			assert_near(mean[axis], 0.0);
+		}
+		// as many points above 60 degrees of latitude as there are in the band of the same
+		// thickness around the equator
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+		let (mut top, mut middle) = (0, 0);
+		for _ in 0..N {
+			let z = Vec3::random_unit_vector(&mut rng).z();
+			if z > 0.5 {
+				top += 1;
+			} else if z.abs() < 0.25 {
+				middle += 1;
+			}
+		}
+		assert_near(top as f64 / N as f64, 0.25);
+		assert_near(middle as f64 / N as f64, 0.25);
+	}
+
+	#[test]
+	fn test_in_unit_sphere() {
+		let (mean, shells) = distribution(Vec3::random_in_unit_sphere);
+		for axis in 0..3 {
+			assert_near(mean[axis], 0.0);
+		}
+		for (i, fraction) in shells.iter().enumerate() {
+			let (inner, outer) = (i as f64 / 10.0, (i + 1) as f64 / 10.0);
+			assert_near(*fraction, outer.powi(3) - inner.powi(3));
+		}
+	}
+
+	#[test]
+	fn test_in_unit_disk() {
+		let (mean, shells) = distribution(|rng| {
+			let v = Vec3::random_in_unit_disk(rng);
+			assert_eq!(v.z(), 0.0);
+			v
+		});
+		assert_near(mean.x(), 0.0);
+		assert_near(mean.y(), 0.0);
+		for (i, fraction) in shells.iter().enumerate() {
+			let (inner, outer) = (i as f64 / 10.0, (i + 1) as f64 / 10.0);
+			assert_near(*fraction, outer.powi(2) - inner.powi(2));
+		}
+	}
+}
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// value of last once the thread has run out of tiles, since it can't stall after that
+const FINISHED: u64 = u64::MAX;
+
+/// Lets a rendering thread show that it's still making progress, so that a thread stuck on one
+/// tile can be noticed from another thread
+pub struct Heartbeat {
+	start: Instant,
+	/// milliseconds from start to the last beat
+	last: AtomicU64,
+	/// the tile being rendered at the last beat, with x in the high half and y in the low half
+	tile: AtomicU64,
+}
+
+impl Heartbeat {
+	pub fn new() -> Heartbeat {
+		Heartbeat {
+			start: Instant::now(),
+			last: AtomicU64::new(0),
+			tile: AtomicU64::new(0),
+		}
+	}
+
+	/// record progress on the tile at (x, y)
+	pub fn beat(&self, (x, y): (usize, usize)) {
+		self.tile.store(
+			((x as u64) << 32) | (y as u64 & 0xffffffff),
+			Ordering::Relaxed,
+		);
+		self.last
+			.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+	}
+
+	/// the thread is done rendering, so it isn't going to beat anymore
+	pub fn finish(&self) {
+		self.last.store(FINISHED, Ordering::Relaxed);
+	}
+
+	/// How long it has been since the last beat, and the tile the thread was on then. None once
+	/// the thread has finished
+	pub fn since_last(&self) -> Option<(Duration, (usize, usize))> {
+		let last = self.last.load(Ordering::Relaxed);
+		if last == FINISHED {
+			return None;
+		}
+		let tile = self.tile.load(Ordering::Relaxed);
+		Some((
+			self.start
+				.elapsed()
+				.saturating_sub(Duration::from_millis(last)),
+			((tile >> 32) as usize, (tile & 0xffffffff) as usize),
+		))
+	}
+}
+
+impl Default for Heartbeat {
+	fn default() -> Heartbeat {
+		Heartbeat::new()
+	}
+}
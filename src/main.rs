@@ -6,28 +6,56 @@ mod output;
 mod scene;
 
 use std::fmt::{self, Display, Formatter};
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Write};
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread::{self, JoinHandle};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use exr::image::{write::WritableImage, Image};
-use exr::image::{AnyChannel, AnyChannels, FlatSamples};
-use exr::math::Vec2;
-use exr::meta::attribute::Chromaticities;
-use half::f16;
+use exr::image::{AnyChannel, FlatSamples};
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
 use smallvec::smallvec;
 use time::OffsetDateTime;
 
-use common::args::{self, FileFormat, WhichScene};
-use common::raytracer::{render, Tile, TILE_SIZE};
-use common::Color;
-use output::png::PngRenderingIntent;
-use output::{ImageWriter, PngWriter, PpmWriter};
-use scene::{scenes, BvhNode};
+use common::args::{
+	self, Args, DebugMode, DepthRange, OverrideMaterial, Pick, Shutter, Stereo, TileOrder,
+	WhichScene, WhichTexture,
+};
+use common::assets::AssetDirs;
+use common::bake::bake_texture;
+use common::color::{ImageStats, Primaries};
+use common::denoise::denoise;
+use common::lens::{chromatic_aberration, vignette};
+use common::pool::{JobHandle, ThreadPool};
+use common::progress::{ProgressBar, ProgressEstimator};
+use common::raytracer::{
+	estimate_tile_costs, pick, render, trace_photons, Aov, RenderPanic, RenderSettings,
+	RenderStats, RngFactory, SceneContext, Tile, TILE_SIZE,
+};
+use common::stereo::{anaglyph, side_by_side};
+use common::temporal::{frame_seed, Accumulator};
+use common::tile_queue::TileQueue;
+use common::watchdog::Heartbeat;
+use common::{Color, ImageBuffer};
+use object::material::Clay;
+use object::pdf::EmitterList;
+use object::texture::{CheckerTexture, SolidColor, StripeTexture};
+use object::{Hittable, Labeled, MaterialOverride, Texture};
+use output::burn_in::burn_in;
+use output::bvh_dump::BvhDump;
+use output::cryptomatte;
+use output::depth::{depth_range, normalize_depth};
+use output::exr::{plane_from_image, write_exr};
+use output::font::{draw_text, GLYPH_HEIGHT, GLYPH_WIDTH};
+use output::png;
+use output::stats::{peak_rss, StatsReport, SCHEMA_VERSION};
+use output::{
+	rawvideo, ApngWriter, ImageWriter, OutputOptions, PngWriter, RenderMetadata, EXR, PNG,
+};
+use scene::scenes::{self, Scene};
+use scene::{BvhNode, Camera, CameraPath, PhysicalExposure, SceneLights, SceneStats};
 
 struct RayRate(f64);
 
@@ -66,224 +94,1470 @@ impl Display for Eta {
 	}
 }
 
-struct CheckValidChannel<const CHANNEL: u8>;
+/// width of each scene in a contact sheet
+const CONTACT_WIDTH: usize = 320;
+const CONTACT_SAMPLES: usize = 32;
+/// world and sample seed for every scene in a contact sheet, so that sheets can be compared
+const CONTACT_SEED: u64 = 0;
 
-impl<const CHANNEL: u8> CheckValidChannel<CHANNEL> {
-	const OK: () = assert!(CHANNEL < 3);
+/// Why the program gave up, which decides its exit code so that scripts can tell failures apart
+enum Failure {
+	/// the arguments couldn't be parsed or don't make sense together. exit code 2
+	Usage(String),
+	/// the scene couldn't be built, usually because a texture or light profile it needs is
+	/// missing. exit code 3
+	Scene(String),
+	/// a render thread panicked or stalled, so the render was abandoned. exit code 4
+	Render(String),
+	/// the output couldn't be written. exit code 5
+	Output(io::Error),
 }
 
-// fake enum since rust doesn't support enums in const generics
-mod channel {
-	pub const RED: u8 = 0;
-	pub const GREEN: u8 = 1;
-	pub const BLUE: u8 = 2;
+impl Failure {
+	fn code(&self) -> i32 {
+		match self {
+			Self::Usage(_) => 2,
+			Self::Scene(_) => 3,
+			Self::Render(_) => 4,
+			Self::Output(_) => 5,
+		}
+	}
+
+	fn exit(self) -> ! {
+		eprintln!("error: {}", self);
+		std::process::exit(self.code());
+	}
 }
 
-fn channel_from_image<const CHANNEL: u8>(image: &Vec<Vec<Color>>, bit_depth: u8) -> FlatSamples {
-	let _ = CheckValidChannel::<CHANNEL>::OK;
-	match bit_depth {
-		16 => FlatSamples::F16(
-			image
-				.iter()
-				.flat_map(|row| {
-					row.iter().map(|pixel| {
-						f16::from_f64(match CHANNEL {
-							channel::RED => pixel.x(),
-							channel::GREEN => pixel.y(),
-							channel::BLUE => pixel.z(),
-							_ => unreachable!(),
-						})
-					})
-				})
-				.collect(),
-		),
-		32 => FlatSamples::F32(
-			image
-				.iter()
-				.flat_map(|row| {
-					row.iter().map(|pixel| {
-						(match CHANNEL {
-							channel::RED => pixel.x(),
-							channel::GREEN => pixel.y(),
-							channel::BLUE => pixel.z(),
-							_ => unreachable!(),
-						}) as f32
-					})
-				})
-				.collect(),
+impl Display for Failure {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Usage(e) | Self::Scene(e) | Self::Render(e) => write!(f, "{}", e),
+			Self::Output(e) => write!(f, "failed to write output: {}", e),
+		}
+	}
+}
+
+impl From<io::Error> for Failure {
+	fn from(value: io::Error) -> Self {
+		Self::Output(value)
+	}
+}
+
+fn load_scene(
+	which: WhichScene,
+	args: &Args,
+	rng: &mut Xoshiro256PlusPlus,
+) -> Result<Scene, String> {
+	let texture_error = |e| format!("failed to load texture: {}", e);
+	let assets = AssetDirs::new(args.texture_dir.as_deref());
+	Ok(match which {
+		WhichScene::Weekend => scenes::random_scene(rng, false, false),
+		WhichScene::Gay => scenes::random_scene(rng, false, true),
+		WhichScene::Tuesday => scenes::random_scene(rng, true, false),
+		WhichScene::Perlin => scenes::perlin_spheres(rng),
+		WhichScene::Earth => scenes::earth(&assets).map_err(texture_error)?,
+		WhichScene::Cornell => scenes::cornell_box(),
+		WhichScene::Bisexual => scenes::bisexual_lighting(rng),
+		WhichScene::Week => scenes::week(rng, &assets).map_err(texture_error)?,
+		WhichScene::Prism => scenes::prism(),
+		WhichScene::Sunny => scenes::sunny(rng),
+		WhichScene::Spot => scenes::cornell_spot(&assets)
+			.map_err(|e| format!("failed to load light profile: {}", e))?,
+		WhichScene::Softbox => scenes::softbox(),
+		WhichScene::Glass => scenes::glass(),
+		WhichScene::Spin => scenes::spin(),
+		WhichScene::Catcher => scenes::shadow_catcher(),
+		WhichScene::Water => scenes::water(),
+		WhichScene::Sphereflake => {
+			let scene = scenes::sphereflake(args.flake_depth);
+			eprintln!("sphereflake: {} objects", scene.0.len());
+			scene
+		},
+		WhichScene::Menger => {
+			let scene = scenes::menger(args.menger_level);
+			eprintln!("menger: {} objects", scene.0.len());
+			scene
+		},
+		WhichScene::Figure19 => scenes::figure19(),
+		WhichScene::Refraction => scenes::refraction(),
+		WhichScene::Golf => scenes::golf(rng),
+	})
+}
+
+fn load_texture(
+	which: WhichTexture,
+	args: &Args,
+	rng: &mut Xoshiro256PlusPlus,
+) -> Result<Arc<dyn Texture>, String> {
+	Ok(match which {
+		WhichTexture::Checker => Arc::new(CheckerTexture::with_colors(
+			Color::new(0.2, 0.3, 0.1),
+			Color::new(0.9, 0.9, 0.9),
+		)),
+		WhichTexture::Trans => StripeTexture::<SolidColor>::trans_sphere(),
+		WhichTexture::Rainbow => StripeTexture::<SolidColor>::rainbow_sphere(),
+		WhichTexture::Enby => StripeTexture::<SolidColor>::enby_sphere(),
+		WhichTexture::Bi => StripeTexture::<SolidColor>::bi_sphere(),
+		WhichTexture::Noise => scenes::noise_texture(rng),
+		WhichTexture::Marble => scenes::marble_texture(rng),
+		WhichTexture::Earth => Arc::new(
+			scenes::earth_texture(&AssetDirs::new(args.texture_dir.as_deref()))
+				.map_err(|e| format!("failed to load texture: {}", e))?,
 		),
-		_ => unreachable!(),
+	})
+}
+
+fn check_pixel(Pick { x, y }: Pick, (width, height): (usize, usize)) {
+	if x >= width || y >= height {
+		Failure::Usage(format!(
+			"pixel {},{} is outside the {}x{} image",
+			x, y, width, height
+		))
+		.exit();
 	}
 }
 
-fn main() -> io::Result<()> {
-	let args = args::parse().unwrap_or_else(|e| {
-		eprintln!("{}", e);
-		args::show_help();
-		std::process::exit(1);
-	});
-	let mut world_rng = Xoshiro256PlusPlus::seed_from_u64(args.world_seed);
+/// A scene with its camera set up for a particular image size, ready to render
+#[derive(Clone)]
+struct Prepared {
+	world: Arc<dyn Hittable>,
+	lights: Arc<SceneLights>,
+	cam: Camera,
+	background: Color,
+	/// size to render at
+	size: (usize, usize),
+	/// size of the image once the render is scaled up for --debug-scale, which is the same as size
+	/// otherwise
+	full_size: (usize, usize),
+	/// names of the labeled objects, indexed by label id
+	objects: Vec<String>,
+	bvh_time: Duration,
+}
+
+fn prepare(
+	args: &Args,
+	(world, cam, background, lights): Scene,
+	world_rng: &mut Xoshiro256PlusPlus,
+	image_width: usize,
+) -> Prepared {
+	let image_height = ((image_width as f64 / cam.aspect_ratio()).round() as usize).max(1);
+	// rounding changes the aspect ratio a little, so match the camera to it to keep pixels square
+	let cam = cam.with_aspect_ratio(image_width as f64 / image_height as f64);
+	let cam = match args.shutter {
+		Some(Shutter::Open(open, close)) => cam.with_shutter(open, close),
+		Some(Shutter::Static(time)) => cam.with_static_shutter(time),
+		None => cam,
+	};
+	let cam = match args.focus_distance {
+		Some(distance) => cam.with_focus_distance(distance),
+		None => cam,
+	};
+	let cam = match args.f_stop {
+		Some(f_number) => cam.with_physical_exposure(PhysicalExposure {
+			f_number,
+			iso: args.iso.unwrap_or(100.0),
+			shutter_speed: args.shutter_speed.unwrap_or(0.01),
+		}),
+		None => cam,
+	};
 
-	let mut output: Box<dyn Write> = if let Some(filename) = args.output {
-		Box::new(File::create(filename)?)
+	let world = world.flatten();
+	let (time0, time1) = cam.shutter();
+	if args.scene_stats {
+		eprintln!("{}", SceneStats::new(&world, time0, time1));
+	}
+	let mut objects = Vec::new();
+	let world = if args.debug_mode == Some(DebugMode::Id) || args.pick.is_some() || args.cryptomatte
+	{
+		world
+			.as_ref()
+			.iter()
+			.enumerate()
+			.map(|(i, o)| -> Arc<dyn Hittable> {
+				let name = format!("{} {}", o.type_name(), i);
+				objects.push(name.clone());
+				Arc::new(Labeled::new(i as u32, name, o.clone()))
+			})
+			.collect()
 	} else {
-		Box::new(io::stdout())
+		world
+	};
+	// brighter lights get more of the rays aimed at lights
+	let emitters = EmitterList::new(world.emitters(), true);
+	let bvh_start = Instant::now();
+	let bvh = BvhNode::new(world_rng, world.as_ref(), time0, time1).unwrap_or_else(|e| {
+		discard_partial(args);
+		Failure::Scene(format!("failed to construct BVH: {:?}", e)).exit();
+	});
+	// trees that scenes build for their own groups of objects have their own intervals
+	if !bvh.bounds(time0, time1) {
+		discard_partial(args);
+		Failure::Scene(format!(
+			"objects in the scene move out of their BVH's boxes while the shutter is open, from \
+			 {} to {}",
+			time0, time1
+		))
+		.exit();
+	}
+	let bvh_time = bvh_start.elapsed();
+	if args.verbose {
+		eprintln!(
+			"objects:    {} ({} emissive, BVH built in {:.2?})",
+			world.len(),
+			world.emitters().len(),
+			bvh_time
+		);
+	}
+	if let Some(ref path) = args.dump_bvh {
+		let dump = BvhDump::new(&bvh, args.dump_bvh_depth);
+		dump.save(path).unwrap_or_else(|e| {
+			discard_partial(args);
+			Failure::Output(io::Error::new(e.kind(), format!("{}: {}", path, e))).exit();
+		});
+		if args.verbose {
+			eprintln!(
+				"BVH:        {} nodes, depth {}, written to {}",
+				dump.node_count, dump.max_depth, path
+			);
+		}
+	}
+	let bbox = bvh.bbox();
+	let world: Arc<dyn Hittable> = match args.override_material {
+		Some(material) => Arc::new(MaterialOverride::new(
+			Arc::new(bvh),
+			Arc::new(Clay::new(material == OverrideMaterial::Wireframe)),
+		)),
+		None => Arc::new(bvh),
 	};
+	let photons = args.caustics.map(|caustics| {
+		let photons_start = Instant::now();
+		let photons = trace_photons(
+			world_rng,
+			world.as_ref(),
+			&lights,
+			caustics.photons,
+			caustics.radius * (bbox.max() - bbox.min()).length(),
+			args.depth as i32,
+			(time0, time1),
+		);
+		if args.verbose {
+			eprintln!(
+				"caustics:   {} of {} photons focused, traced in {:.2?}",
+				photons.len(),
+				caustics.photons,
+				photons_start.elapsed()
+			);
+		}
+		photons
+	});
 
-	let (world, cam, background) = match args.scene {
-		WhichScene::Weekend => scenes::random_scene(&mut world_rng, false, false),
-		WhichScene::Gay => scenes::random_scene(&mut world_rng, false, true),
-		WhichScene::Tuesday => scenes::random_scene(&mut world_rng, true, false),
-		WhichScene::Perlin => scenes::perlin_spheres(&mut world_rng),
-		WhichScene::Earth => scenes::earth().expect("failed to load texture"),
-		WhichScene::Cornell => scenes::cornell_box(),
-		WhichScene::Bisexual => scenes::bisexual_lighting(),
-		WhichScene::Week => scenes::week(&mut world_rng).expect("failed to load texture"),
+	let size = (image_width, image_height);
+	let cam = match args.focus_at {
+		Some(Pick { x, y }) => {
+			check_pixel(Pick { x, y }, size);
+			match pick(world_rng, world.as_ref(), &cam, size, (x, y)) {
+				Some(rec) => {
+					let distance = cam.distance_to_plane(rec.p);
+					eprintln!("focusing at distance {}", distance);
+					cam.with_focus_distance(distance)
+				},
+				None => {
+					eprintln!(
+						"warning: nothing to focus on at {},{}, using the scene's focus distance",
+						x, y
+					);
+					cam
+				},
+			}
+		},
+		None => cam,
 	};
-	let world = Arc::new(
-		BvhNode::new(&mut world_rng, world.as_ref(), 0.0, 1.0).unwrap_or_else(|e| {
-			eprintln!("error constructing BVH: {:?}", e);
-			std::process::exit(1);
+
+	Prepared {
+		world,
+		lights: Arc::new(SceneLights {
+			lights,
+			emitters,
+			photons,
 		}),
-	);
+		cam,
+		background: background * args.background_intensity,
+		size: (
+			image_width.div_ceil(args.debug_scale),
+			image_height.div_ceil(args.debug_scale),
+		),
+		full_size: size,
+		objects,
+		bvh_time,
+	}
+}
 
-	let aspect_ratio = cam.aspect_ratio();
-	let image_width = args.width;
-	let image_height = (image_width as f64 / aspect_ratio) as usize;
-	let samples_per_pixel = args.samples;
-	let max_depth = args.depth;
-	let num_threads = args.threads;
+/// A finished render, before any post-processing
+struct Render {
+	/// with the background taken out if --alpha was given
+	image: ImageBuffer<Color>,
+	aovs: ImageBuffer<Aov>,
+	time: Duration,
+	/// names of the objects that the label ids in the AOVs refer to
+	objects: Vec<String>,
+	/// performance of each rendering thread, added up over every render that went into the image
+	threads: Vec<RenderStats>,
+	bvh_time: Duration,
+}
 
-	let mut handles: Vec<JoinHandle<(Duration, usize)>> = Vec::with_capacity(num_threads);
+/// Warn about render threads that haven't made progress in a while, or exit if --abort-on-stall was
+/// given
+/// stalled: whether each thread has already been warned about, cleared once it makes progress
+fn check_stalls(
+	args: &Args,
+	heartbeats: &[Arc<Heartbeat>],
+	stalled: &mut [bool],
+	timeout: Duration,
+) {
+	for (i, heartbeat) in heartbeats.iter().enumerate() {
+		let (since, (x, y)) = match heartbeat.since_last() {
+			Some(last) if last.0 >= timeout => last,
+			_ => {
+				stalled[i] = false;
+				continue;
+			},
+		};
+		if args.abort_on_stall {
+			eprintln!();
+			discard_partial(args);
+			Failure::Render(format!(
+				"render thread {} has made no progress on the tile at ({}, {}) for {}s",
+				i,
+				x,
+				y,
+				since.as_secs()
+			))
+			.exit();
+		}
+		if !stalled[i] {
+			eprintln!(
+				"\nwarning: render thread {} has made no progress on the tile at ({}, {}) for {}s",
+				i,
+				x,
+				y,
+				since.as_secs()
+			);
+			stalled[i] = true;
+		}
+	}
+}
 
-	let mut image: Vec<Vec<Color>> = vec![vec![Color::zero(); image_width]; image_height];
-	let current_pos = Arc::new(Mutex::new((0usize, 0usize)));
+/// progress: shown before the progress bar, to tell renders apart
+fn render_prepared(
+	pool: &ThreadPool,
+	args: &Args,
+	scene: &Prepared,
+	samples_per_pixel: usize,
+	sample_seed: u64,
+	progress: &str,
+) -> Render {
+	let (image_width, image_height) = scene.size;
+	let mut image = ImageBuffer::new(scene.size, Color::zero());
+	let mut aovs = ImageBuffer::new(scene.size, Aov::default());
+	let settings = RenderSettings {
+		size: scene.size,
+		samples_per_pixel,
+		max_depth: args.depth,
+		glass_depth_bonus: args.glass_depth_bonus,
+		debug_mode: args.debug_mode,
+	};
+	let tiles = Arc::new(match args.tile_order {
+		TileOrder::Scanline => TileQueue::scanline(scene.size),
+		TileOrder::Cost => {
+			let start = Instant::now();
+			let context = SceneContext {
+				background: scene.background,
+				world: scene.world.as_ref(),
+				lights: &scene.lights,
+				fog: args.fog,
+			};
+			let costs = estimate_tile_costs(&context, &scene.cam, &settings);
+			if args.verbose {
+				eprintln!(
+					"estimated tile costs in {:.1}ms",
+					start.elapsed().as_secs_f64() * 1000.0
+				);
+			}
+			TileQueue::by_cost(scene.size, &costs)
+		},
+	});
+	let heartbeats: Vec<Arc<Heartbeat>> = (0..args.threads)
+		.map(|_| Arc::new(Heartbeat::new()))
+		.collect();
 
 	// sender is scoped in this block so that the main thread's sender gets dropped
 	// that way the channel is closed as soon as every worker thread has finished
-	let recv = {
+	let (handles, recv) = {
 		let (send, recv) = mpsc::channel::<Tile>();
-		for _ in 0..num_threads {
-			let w = world.clone();
-			let pos = current_pos.clone();
-			let q = send.clone();
-			handles.push(thread::spawn(move || {
-				render(
-					q,
-					args.sample_seed,
-					w,
-					cam,
-					background,
-					(image_width, image_height),
-					samples_per_pixel,
-					max_depth,
-					pos,
-					args.debug_mode,
-				)
-			}));
-		}
-		recv
+		let handles: Vec<JobHandle<Result<RenderStats, RenderPanic>>> = (0..args.threads)
+			.map(|i| {
+				let w = scene.world.clone();
+				let l = scene.lights.clone();
+				let t = tiles.clone();
+				let q = send.clone();
+				let heartbeat = heartbeats[i].clone();
+				let rngs = RngFactory::new(args.rng_engine, sample_seed);
+				let (cam, background, fog) = (scene.cam, scene.background, args.fog);
+				pool.spawn(move || {
+					let context = SceneContext {
+						background,
+						world: w.as_ref(),
+						lights: &l,
+						fog,
+					};
+					render(q, rngs, &context, cam, &settings, t, heartbeat)
+				})
+			})
+			.collect();
+		(handles, recv)
 	};
 
 	let mut pixels_so_far = 0;
+	let mut estimator = ProgressEstimator::new(image_width * image_height);
+	let total_tiles = tiles.tiles().len();
+	let mut tiles_so_far = 0;
 	let start_time = Instant::now();
+	let stall_timeout = Duration::from_secs(args.stall_timeout);
+	// so that each stall is only warned about once
+	let mut stalled = vec![false; heartbeats.len()];
 
-	while let Ok(tile) = recv.recv() {
-		for i in tile.y..(tile.y + TILE_SIZE) {
-			if i >= image_height {
+	loop {
+		let tile = match recv.recv_timeout(Duration::from_secs(1)) {
+			Ok(tile) => tile,
+			Err(RecvTimeoutError::Timeout) => {
+				check_stalls(args, &heartbeats, &mut stalled, stall_timeout);
 				continue;
-			}
-
-			let width = usize::min(TILE_SIZE, image_width - tile.x);
-			let final_x = tile.x + width;
-			image[image_height - i - 1][tile.x..final_x]
-				.copy_from_slice(&tile.pixels[i - tile.y][0..width]);
-			pixels_so_far += width;
-		}
+			},
+			Err(RecvTimeoutError::Disconnected) => break,
+		};
+		check_stalls(args, &heartbeats, &mut stalled, stall_timeout);
+		let pixels = tile.copy_to(&mut image, &mut aovs);
+		pixels_so_far += pixels;
+		tiles_so_far += 1;
 
-		let progress = pixels_so_far as f64 / (image_width * image_height) as f64;
 		let elapsed = start_time.elapsed();
-		let remaining = (elapsed.div_f64(progress)) - elapsed;
+		estimator.record(elapsed, pixels);
+		let fraction = estimator.progress();
 		eprint!(
-			"\rprogress: {:6.2}% | eta: {}s  ",
-			progress * 100.0,
-			Eta(remaining),
+			"\r{}{} {:6.2}% | tiles {}/{} | eta: ",
+			progress,
+			ProgressBar {
+				progress: fraction,
+				width: 30
+			},
+			fraction * 100.0,
+			tiles_so_far,
+			total_tiles,
 		);
+		match estimator.remaining(elapsed) {
+			Some(remaining) => eprint!("{}s  ", Eta(remaining)),
+			None => eprint!("?  "),
+		}
 	}
 
-	eprint!("\n");
+	eprintln!();
+	let render_time = start_time.elapsed();
 
-	if args.verbose {
-		let total_rays_sec: f64 = handles
-			.into_iter()
-			.map(|h| h.join().unwrap())
-			.enumerate()
-			.map(|(i, (duration, pixels))| {
-				let rays = pixels * samples_per_pixel;
-				let rays_sec = (rays as f64) / (duration.as_millis() as f64) * 1000.0;
-				eprintln!("thread {:3}: {}", i, RayRate(rays_sec));
-				rays_sec
-			})
-			.sum();
-		eprintln!("total:      {}", RayRate(total_rays_sec));
-	}
-
-	match args.format {
-		FileFormat::Png | FileFormat::Ppm => {
-			let mut output_writer: Box<dyn ImageWriter> = match args.format {
-				FileFormat::Png => Box::new(PngWriter::new(
-					output,
-					(image_width, image_height),
-					args.bit_depth,
-					Some(OffsetDateTime::now_utc()),
-					Some(PngRenderingIntent::Perceptual),
-				)),
-				FileFormat::Ppm => Box::new(PpmWriter::new(
-					output,
-					(image_width, image_height),
-					args.bit_depth,
-				)),
-				_ => unreachable!(),
+	let stats: Vec<RenderStats> = handles
+		.into_iter()
+		.enumerate()
+		.map(|(i, handle)| {
+			let error = match handle.join() {
+				Ok(Ok(stats)) => return stats,
+				Ok(Err(panic)) => format!(
+					"render thread {} panicked while rendering the tile at ({}, {}): {}",
+					i, panic.x, panic.y, panic.message
+				),
+				// panicked outside of rendering a tile
+				Err(_) => format!("render thread {} panicked", i),
 			};
-
-			output_writer.write_header()?;
-			for mut row in image {
-				row.iter_mut().for_each(|p| *p = p.tonemap());
-				output_writer.write_pixels(&row)?;
+			discard_partial(args);
+			Failure::Render(error).exit();
+		})
+		.collect();
+	// every thread finished without panicking, so every tile should have been delivered
+	assert_eq!(pixels_so_far, image_width * image_height);
+	if args.verbose {
+		// tiles are numbered from the bottom of the image, so flip them to be from the top
+		let tile_rows = |y: usize| {
+			(
+				image_height.saturating_sub(y + TILE_SIZE),
+				image_height - y - 1,
+			)
+		};
+		for (i, thread_stats) in stats.iter().enumerate() {
+			// utilization is relative to the whole render, so threads that ran out of work early
+			// show up as idle rather than fast
+			let utilization = thread_stats.busy_time.as_secs_f64() / render_time.as_secs_f64();
+			eprint!(
+				"thread {:3}: camera {} | all {} | {:4} tiles | busy {:.2}s of {:.2}s ({:5.1}%)",
+				i,
+				RayRate(thread_stats.samples_per_sec()),
+				RayRate(thread_stats.rays_per_sec()),
+				thread_stats.tiles,
+				thread_stats.busy_time.as_secs_f64(),
+				thread_stats.wall_time.as_secs_f64(),
+				utilization * 100.0,
+			);
+			if let Some((duration, x, y)) = thread_stats.slowest_tile {
+				eprint!(
+					" | slowest tile {:.3}s at ({}, {})",
+					duration.as_secs_f64(),
+					x,
+					tile_rows(y).0
+				);
 			}
-			output_writer.end()?;
+			eprintln!();
+		}
+		let total_samples_sec: f64 = stats.iter().map(|s| s.samples_per_sec()).sum();
+		let total_rays_sec: f64 = stats.iter().map(|s| s.rays_per_sec()).sum();
+		let busy: Duration = stats.iter().map(|s| s.busy_time).sum();
+		eprintln!(
+			"total:      camera {} | all {} | utilization {:.1}%",
+			RayRate(total_samples_sec),
+			RayRate(total_rays_sec),
+			busy.as_secs_f64() / (render_time.as_secs_f64() * stats.len() as f64) * 100.0,
+		);
+		if let Some((duration, x, y)) = stats
+			.iter()
+			.filter_map(|s| s.slowest_tile)
+			.max_by_key(|&(duration, _, _)| duration)
+		{
+			let (top, bottom) = tile_rows(y);
+			eprintln!(
+				"slowest:    {:.3}s for the tile at x {}-{}, y {}-{} from the top",
+				duration.as_secs_f64(),
+				x,
+				usize::min(x + TILE_SIZE, image_width) - 1,
+				top,
+				bottom,
+			);
+		}
+	}
+
+	if args.alpha {
+		// every sample that didn't hit an object added the background color weighted by how
+		// transparent it was, so removing that leaves the color premultiplied by alpha
+		let background = scene.background * scene.cam.exposure_scale();
+		for (p, aov) in image.pixels_mut().iter_mut().zip(aovs.pixels_mut()) {
+			let seen = background * (1.0 - aov.alpha);
+			*p -= seen;
+			// the background is only ever seen directly
+			aov.direct -= seen;
+		}
+	}
+
+	if scene.full_size != scene.size {
+		image = image.resize_nearest(scene.full_size);
+		aovs = aovs.resize_nearest(scene.full_size);
+	}
+
+	Render {
+		image,
+		aovs,
+		time: render_time,
+		objects: scene.objects.clone(),
+		threads: stats,
+		bvh_time: scene.bvh_time,
+	}
+}
+
+/// size of the image that will be written for the scene, which is wider than the camera's for
+/// side-by-side stereo
+fn output_size(args: &Args, scene: &Prepared) -> (usize, usize) {
+	match args.stereo {
+		Some(Stereo::SideBySide) => (scene.full_size.0 * 2, scene.full_size.1),
+		_ => scene.full_size,
+	}
+}
+
+/// Render the scene once for each eye and combine the two views. The views are combined before
+/// any post-processing, which is left to the caller
+/// progress: shown before each eye's progress bar
+fn render_stereo(
+	pool: &ThreadPool,
+	args: &Args,
+	scene: &Prepared,
+	mode: Stereo,
+	samples_per_pixel: usize,
+	sample_seed: u64,
+	progress: &str,
+) -> Render {
+	let [left, right] = [("left  ", -0.5), ("right ", 0.5)].map(|(eye_name, side)| {
+		let eye = Prepared {
+			cam: scene.cam.with_eye_offset(side * args.ipd),
+			..scene.clone()
+		};
+		render_prepared(
+			pool,
+			args,
+			&eye,
+			samples_per_pixel,
+			sample_seed,
+			&format!("{}{}", progress, eye_name),
+		)
+	});
+	let time = left.time + right.time;
+	let threads = combine_threads(&left.threads, &right.threads);
+	// both eyes share the same BVH
+	let bvh_time = scene.bvh_time;
+	match mode {
+		Stereo::SideBySide => Render {
+			image: side_by_side(&left.image, &right.image),
+			aovs: side_by_side(&left.aovs, &right.aovs),
+			time,
+			objects: left.objects,
+			threads,
+			bvh_time,
 		},
-		FileFormat::Exr => {
-			let channels = AnyChannels::sort(smallvec![
-				AnyChannel::new(
-					"R",
-					channel_from_image::<{ channel::RED }>(&image, args.bit_depth)
-				),
-				AnyChannel::new(
-					"G",
-					channel_from_image::<{ channel::GREEN }>(&image, args.bit_depth)
-				),
-				AnyChannel::new(
-					"B",
-					channel_from_image::<{ channel::BLUE }>(&image, args.bit_depth)
-				),
-			]);
-			let mut image = Image::from_channels((image_width, image_height), channels);
-			// // sRGB
-			image.attributes.chromaticities = Some(Chromaticities {
-				red: Vec2(0.64, 0.33),
-				green: Vec2(0.30, 0.60),
-				blue: Vec2(0.15, 0.06),
-				white: Vec2(0.3127, 0.3290),
+		// most of the pixels' light comes from the right eye, so its buffers guide the denoiser
+		Stereo::Anaglyph => Render {
+			image: anaglyph(&left.image, &right.image),
+			aovs: right.aovs,
+			time,
+			objects: right.objects,
+			threads,
+			bvh_time,
+		},
+	}
+}
+
+/// Where the frames of an animation go
+enum AnimationOutput<'a> {
+	Apng(ApngWriter<&'a mut dyn Write>),
+	/// each frame is written straight to output after the one before
+	Raw(&'a mut dyn Write),
+}
+
+/// Render the scene as an animation, with each frame covering the next part of the time the
+/// shutter is open, and write the frames to output as an animated PNG or raw video. Returns the
+/// last frame, with the time and thread stats of every frame, and its image stats
+/// path: where the camera is in each frame, instead of where the scene puts it
+fn render_animation(
+	pool: &ThreadPool,
+	args: &Args,
+	scene: &Prepared,
+	frames: u32,
+	path: Option<&CameraPath>,
+	output: &mut dyn Write,
+) -> Result<(Render, ImageStats), Failure> {
+	let (time0, time1) = scene.cam.shutter();
+	if frames > 1 && time0 == time1 && !args.accumulate_frames {
+		eprintln!("warning: the shutter doesn't stay open, so every frame will be the same");
+	}
+	let step = (time1 - time0) / frames as f64;
+	let mut animation = if *args.format == PNG {
+		AnimationOutput::Apng(ApngWriter::new(output, frames, args.fps))
+	} else {
+		let (width, height) = output_size(args, scene);
+		eprintln!(
+			"encode with: ffmpeg -f rawvideo -pix_fmt {} -s {}x{} -r {} -i {} out.mp4",
+			rawvideo::pixel_format(args.bit_depth),
+			width,
+			height,
+			args.fps,
+			args.output.as_deref().unwrap_or("-")
+		);
+		AnimationOutput::Raw(output)
+	};
+	let mut threads = vec![RenderStats::default(); args.threads];
+	let mut time = Duration::ZERO;
+	let mut last = None;
+	let mut accumulator: Option<Accumulator> = None;
+	for i in 0..frames {
+		let cam = match path {
+			Some(path) => {
+				let key = path.frame(i, frames);
+				scene.cam.with_view(key.from, key.at, key.fov)
+			},
+			None => scene.cam,
+		};
+		// accumulated frames are all of the same moment, so they each see the whole shutter
+		let frame = Prepared {
+			cam: if args.accumulate_frames {
+				cam
+			} else {
+				cam.with_shutter(time0 + step * i as f64, time0 + step * (i + 1) as f64)
+			},
+			..scene.clone()
+		};
+		let progress = format!("frame {}/{} ", i + 1, frames);
+		let seed = frame_seed(args.temporal_seed, args.sample_seed, i);
+		let mut render = match args.stereo {
+			Some(mode) => render_stereo(pool, args, &frame, mode, args.samples, seed, &progress),
+			None => render_prepared(pool, args, &frame, args.samples, seed, &progress),
+		};
+		threads = combine_threads(&threads, &render.threads);
+		time += render.time;
+
+		let mut samples = args.samples;
+		if args.accumulate_frames {
+			let accumulator =
+				accumulator.get_or_insert_with(|| Accumulator::new(render.image.size()));
+			accumulator.add(&render.image);
+			render.image = accumulator.mean();
+			samples *= accumulator.frames();
+		}
+		let metadata = render_metadata(
+			args,
+			args.scene.name().to_string(),
+			(args.world_seed, args.sample_seed),
+			samples,
+			render.time,
+		);
+		let stats = match animation {
+			AnimationOutput::Apng(ref mut apng) => {
+				let mut png = Vec::new();
+				let stats = write_image(args, &mut png, &render, &metadata)?;
+				apng.write_frame(&png)?;
+				stats
+			},
+			AnimationOutput::Raw(ref mut output) => write_image(args, *output, &render, &metadata)?,
+		};
+		last = Some((render, stats));
+	}
+	if let AnimationOutput::Apng(mut apng) = animation {
+		apng.end()?;
+	}
+	let (render, stats) = last.unwrap();
+	Ok((
+		Render {
+			time,
+			threads,
+			..render
+		},
+		stats,
+	))
+}
+
+/// per-thread stats of two renders added together, thread by thread
+fn combine_threads(a: &[RenderStats], b: &[RenderStats]) -> Vec<RenderStats> {
+	a.iter().zip(b).map(|(a, b)| a.combine(b)).collect()
+}
+
+/// Render every scene small and put them next to each other in a grid, labeled with their names.
+/// Scenes that fail to load get a tile with the error instead
+fn contact_sheet(pool: &ThreadPool, args: &Args) -> Render {
+	let start_time = Instant::now();
+	let count = WhichScene::ALL.len();
+	let renders: Vec<(WhichScene, Result<Render, String>)> = WhichScene::ALL
+		.iter()
+		.enumerate()
+		.map(|(i, &which)| {
+			let mut world_rng = Xoshiro256PlusPlus::seed_from_u64(CONTACT_SEED);
+			let render = load_scene(which, args, &mut world_rng).map(|scene| {
+				let scene = prepare(args, scene, &mut world_rng, CONTACT_WIDTH);
+				let progress = format!("{:2}/{} {:9}", i + 1, count, which.name());
+				render_prepared(pool, args, &scene, CONTACT_SAMPLES, CONTACT_SEED, &progress)
+			});
+			if let Err(ref e) = render {
+				eprintln!("{:2}/{} {:9} error: {}", i + 1, count, which.name(), e);
+			}
+			(which, render)
+		})
+		.collect();
+
+	let columns = (count as f64).sqrt().ceil() as usize;
+	let rows = count.div_ceil(columns);
+	// as tall as the tallest scene, or 3:2 if none of them loaded
+	let cell_height = renders
+		.iter()
+		.filter_map(|(_, render)| render.as_ref().ok().map(|r| r.image.height()))
+		.max()
+		.unwrap_or(CONTACT_WIDTH * 2 / 3);
+	let size = (columns * CONTACT_WIDTH, rows * cell_height);
+	let mut image = ImageBuffer::new(size, Color::zero());
+	let mut aovs = ImageBuffer::new(size, Aov::default());
+	let mut threads = vec![RenderStats::default(); args.threads];
+	let mut bvh_time = Duration::ZERO;
+
+	let white = Color::new(1.0, 1.0, 1.0);
+	for (i, (which, render)) in renders.into_iter().enumerate() {
+		let (x, y) = ((i % columns) * CONTACT_WIDTH, (i / columns) * cell_height);
+		match render {
+			Ok(render) => {
+				threads = combine_threads(&threads, &render.threads);
+				bvh_time += render.bvh_time;
+				// centered vertically in the cell
+				let top = y + (cell_height - render.image.height()) / 2;
+				for (j, (row, aov_row)) in render.image.rows().zip(render.aovs.rows()).enumerate() {
+					image.row_mut(top + j)[x..x + CONTACT_WIDTH].copy_from_slice(row);
+					aovs.row_mut(top + j)[x..x + CONTACT_WIDTH].copy_from_slice(aov_row);
+				}
+				draw_text(&mut image, which.name(), (x + 4, y + 4), 1, white);
+			},
+			Err(e) => {
+				for row in y..y + cell_height {
+					image.row_mut(row)[x..x + CONTACT_WIDTH].fill(Color::new(0.3, 0.0, 0.0));
+					aovs.row_mut(row)[x..x + CONTACT_WIDTH]
+						.iter_mut()
+						.for_each(|aov| aov.alpha = 1.0);
+				}
+				let chars_per_line = (CONTACT_WIDTH - 8) / (GLYPH_WIDTH + 1);
+				let text: Vec<char> = format!("{}: {}", which.name(), e).chars().collect();
+				for (j, line) in text.chunks(chars_per_line).enumerate() {
+					let line: String = line.iter().collect();
+					draw_text(
+						&mut image,
+						&line,
+						(x + 4, y + 4 + j * (GLYPH_HEIGHT + 2)),
+						1,
+						white,
+					);
+				}
+			},
+		}
+	}
+
+	Render {
+		image,
+		aovs,
+		time: start_time.elapsed(),
+		objects: Vec::new(),
+		threads,
+		bvh_time,
+	}
+}
+
+/// Post-process a render and write it to output in the format from args. Returns the luminance
+/// statistics of the image before exposure
+fn write_image(
+	args: &Args,
+	mut output: &mut dyn Write,
+	render: &Render,
+	metadata: &RenderMetadata,
+) -> io::Result<ImageStats> {
+	let Render {
+		image: ref raw_image,
+		ref aovs,
+		ref objects,
+		..
+	} = *render;
+	let (image_width, image_height) = raw_image.size();
+
+	let mut image = match args.denoise {
+		Some(strength) => denoise(raw_image, aovs, strength),
+		None => raw_image.clone(),
+	};
+	// after denoising, since the guide buffers don't have the fringes and falloff
+	if let Some(strength) = args.aberration {
+		image = chromatic_aberration(&image, strength);
+	}
+	if let Some(strength) = args.vignette {
+		image = vignette(&image, strength);
+	}
+	// the EXR output is meant to be the unprocessed render unless the user asks otherwise
+	let exr_image = if args.denoise_raw { &image } else { raw_image };
+
+	let stats = ImageStats::new(image.pixels());
+	let exposure = exposure(args, &stats);
+
+	let primaries = args.output_primaries;
+	let exr_exposure = if args.bake_exposure { exposure } else { 1.0 };
+	let converted_image: ImageBuffer<Color>;
+	let exr_image = if exr_exposure != 1.0 || primaries != Primaries::Srgb {
+		converted_image = exr_image.map(|&p| (p * exr_exposure).to_primaries(primaries));
+		&converted_image
+	} else {
+		exr_image
+	};
+
+	match args.format.writer {
+		Some(writer) => {
+			let mut image = image;
+			let mut alpha = aovs.map(|a| a.alpha);
+			if args.burn_in {
+				let (xs, ys) = burn_in(&mut image, metadata, &stats, exposure);
+				// keep the text readable over a transparent background
+				for y in ys {
+					alpha.row_mut(y)[xs.clone()].fill(1.0);
+				}
+			}
+			let options = OutputOptions {
+				bit_depth: args.bit_depth,
+				alpha: args.alpha,
+				primaries,
+				time: Some(OffsetDateTime::now_utc()),
+				png_chunk_size: args.png_chunk_size,
+				png_compression: args.png_compression,
+				jpeg_quality: args.jpeg_quality,
+				threads: args.threads,
+			};
+			// written here first, so that it can be checked once it's been passed on
+			let verify_png = args.verify_output && *args.format == PNG;
+			let mut written = Vec::new();
+			let dest: &mut dyn Write = if verify_png { &mut written } else { output };
+			let mut output_writer = writer(Box::new(dest), (image_width, image_height), &options);
+			output_writer.set_metadata(metadata);
+			if args.alpha {
+				// PNG alpha isn't premultiplied
+				image
+					.pixels_mut()
+					.iter_mut()
+					.zip(alpha.pixels())
+					.for_each(|(p, &alpha)| {
+						*p = if alpha > 0.0 {
+							*p / alpha
+						} else {
+							Color::zero()
+						}
+					});
+			}
+			image.pixels_mut().iter_mut().for_each(|p| {
+				*p = (*p * exposure)
+					.to_primaries(primaries)
+					.clip_gamut()
+					.tonemap(args.highlights)
 			});
-			let mut image_data = io::Cursor::new(vec![0u8; 0]);
-			image
-				.write()
-				.to_buffered(&mut image_data)
-				.expect("error writing output image");
-			io::copy(&mut image_data.into_inner().as_slice(), &mut output)?;
+			write_or_abort(output_writer.as_mut(), &image, args.alpha.then_some(&alpha))?;
+			drop(output_writer);
+			if verify_png {
+				output.write_all(&written)?;
+				output.flush()?;
+				let alpha = args.alpha.then_some(&alpha);
+				let rows = [0, image_height / 2, image_height.saturating_sub(1)];
+				let verified = png::verify(&written)
+					.and_then(|png| {
+						png.compare(&image, alpha, args.bit_depth, rows)
+							.map(|_| png)
+					})
+					.map_err(|e| {
+						io::Error::new(io::ErrorKind::InvalidData, format!("invalid PNG: {}", e))
+					})?;
+				if args.verbose {
+					eprintln!(
+						"verified:   {} chunks, {}x{} at {} bits",
+						verified.chunks.len(),
+						verified.width,
+						verified.height,
+						args.bit_depth
+					);
+				}
+			}
 		},
+		// OpenEXR
+		None => {
+			let bits = args.bit_depth;
+			let mut channels = smallvec![
+				AnyChannel::new("R", plane_from_image(exr_image, bits, |p| p.x())),
+				AnyChannel::new("G", plane_from_image(exr_image, bits, |p| p.y())),
+				AnyChannel::new("B", plane_from_image(exr_image, bits, |p| p.z())),
+			];
+			if args.alpha {
+				channels.push(AnyChannel::new(
+					"A",
+					plane_from_image(aovs, bits, |a| a.alpha),
+				));
+			}
+			if args.aovs {
+				// layers are named with a "layer.channel" prefix, which is how Nuke and Blender
+				// group them back together
+				channels.extend([
+					AnyChannel::new("normal.R", plane_from_image(aovs, bits, |a| a.normal.x())),
+					AnyChannel::new("normal.G", plane_from_image(aovs, bits, |a| a.normal.y())),
+					AnyChannel::new("normal.B", plane_from_image(aovs, bits, |a| a.normal.z())),
+					AnyChannel::new("albedo.R", plane_from_image(aovs, bits, |a| a.albedo.x())),
+					AnyChannel::new("albedo.G", plane_from_image(aovs, bits, |a| a.albedo.y())),
+					AnyChannel::new("albedo.B", plane_from_image(aovs, bits, |a| a.albedo.z())),
+					AnyChannel::new(
+						"depth.Z",
+						plane_from_image(aovs, args.z_bit_depth, |a| a.depth),
+					),
+					AnyChannel::new(
+						"sampleCount",
+						FlatSamples::U32(aovs.pixels().iter().map(|a| a.sample_count).collect()),
+					),
+				]);
+			}
+			let attributes = if args.cryptomatte {
+				channels.extend(cryptomatte::channels(aovs, objects));
+				cryptomatte::attributes(objects)
+			} else {
+				Vec::new()
+			};
+			if args.position {
+				let bits = args.z_bit_depth;
+				channels.extend([
+					AnyChannel::new("P.X", plane_from_image(aovs, bits, |a| a.position.x())),
+					AnyChannel::new("P.Y", plane_from_image(aovs, bits, |a| a.position.y())),
+					AnyChannel::new("P.Z", plane_from_image(aovs, bits, |a| a.position.z())),
+				]);
+			}
+			write_exr(
+				&mut output,
+				(image_width, image_height),
+				channels,
+				primaries.chromaticities(),
+				Some(metadata),
+				&attributes,
+			)?;
+		},
+	}
+	Ok(stats)
+}
+
+/// Write a whole image, including the header and end. if that fails partway through, the writer is
+/// aborted so that the rows that made it out can still be read
+/// alpha: if present, written alongside the pixels
+fn write_or_abort(
+	writer: &mut dyn ImageWriter,
+	image: &ImageBuffer<Color>,
+	alpha: Option<&ImageBuffer<f64>>,
+) -> io::Result<()> {
+	let mut write = || {
+		writer.write_header()?;
+		match alpha {
+			Some(alpha) => {
+				for (row, alpha_row) in image.rows().zip(alpha.rows()) {
+					writer.write_pixels_with_alpha(row, alpha_row)?;
+				}
+			},
+			None => {
+				for row in image.rows() {
+					writer.write_pixels(row)?;
+				}
+			},
+		}
+		writer.end()
+	};
+	let result = write();
+	if result.is_err() {
+		let _ = writer.abort();
+	}
+	result
+}
+
+/// brightness multiplier for the image, from --auto-expose
+fn exposure(args: &Args, stats: &ImageStats) -> f64 {
+	if args.auto_expose {
+		stats.auto_exposure()
+	} else {
+		1.0
+	}
+}
+
+/// metadata to embed in the output image
+fn render_metadata(
+	args: &Args,
+	scene: String,
+	(world_seed, sample_seed): (u64, u64),
+	samples: usize,
+	render_time: Duration,
+) -> RenderMetadata {
+	let arguments: Vec<String> = std::env::args_os()
+		.map(|a| a.to_string_lossy().into_owned())
+		.collect();
+	RenderMetadata {
+		software: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+		command_line: arguments.join(" "),
+		reproduce: args.reproduce_command(),
+		scene,
+		world_seed,
+		sample_seed,
+		samples,
+		render_time,
+	}
+}
+
+/// The layers that --split-lighting writes, in the order of their AOVs
+const LIGHTING_LAYERS: [&str; 2] = ["direct", "indirect"];
+
+/// Where --split-lighting writes a layer, next to the output with its extension replaced, so
+/// render.png has render.direct.exr
+fn lighting_layer_path(output: &str, layer: &str) -> PathBuf {
+	Path::new(output).with_extension(format!("{}.exr", layer))
+}
+
+/// Check that a file can be written without changing it, by opening it for writing if it exists or
+/// creating and removing it if it doesn't
+fn check_writable(path: &str) -> io::Result<()> {
+	match fs::OpenOptions::new().write(true).open(path) {
+		Ok(_) => Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			File::create(path)?;
+			fs::remove_file(path)
+		},
+		Err(e) => Err(e),
+	}
+}
+
+/// Load everything a render needs and check that the output can be written, then print what
+/// would be rendered instead of rendering it
+fn dry_run(args: &Args) -> Result<(), Failure> {
+	let outputs = [
+		args.output.as_deref(),
+		args.output_depth.as_ref().map(|(path, _)| path.as_str()),
+		args.stats_out.as_deref(),
+	];
+	let layers = match args.output {
+		Some(ref output) if args.split_lighting => LIGHTING_LAYERS
+			.iter()
+			.map(|layer| {
+				lighting_layer_path(output, layer)
+					.to_string_lossy()
+					.into_owned()
+			})
+			.collect(),
+		_ => Vec::new(),
+	};
+	for path in outputs
+		.into_iter()
+		.flatten()
+		.chain(layers.iter().map(String::as_str))
+	{
+		check_writable(path)
+			.map_err(|e| Failure::Output(io::Error::new(e.kind(), format!("{}: {}", path, e))))?;
+	}
+
+	let mut world_rng = Xoshiro256PlusPlus::seed_from_u64(args.world_seed);
+	let (description, size) = if args.contact_sheet {
+		let mut world_rng = Xoshiro256PlusPlus::seed_from_u64(CONTACT_SEED);
+		for which in WhichScene::ALL {
+			let scene = load_scene(which, args, &mut world_rng)
+				.map_err(|e| Failure::Scene(format!("{}: {}", which.name(), e)))?;
+			prepare(args, scene, &mut world_rng, CONTACT_WIDTH);
+		}
+		let description = format!("contact sheet of {} scenes", WhichScene::ALL.len());
+		(description, None)
+	} else if let Some(which) = args.bake_texture {
+		load_texture(which, args, &mut world_rng).map_err(Failure::Scene)?;
+		let size = (args.width, (args.width / 2).max(1));
+		(format!("texture {}", which.name()), Some(size))
+	} else {
+		let scene = load_scene(args.scene, args, &mut world_rng).map_err(Failure::Scene)?;
+		let scene = prepare(args, scene, &mut world_rng, args.width);
+		if let Some(ref path) = args.camera_path {
+			CameraPath::load(path).map_err(Failure::Scene)?;
+		}
+		if let Some(pixel) = args.pick {
+			check_pixel(pixel, scene.full_size);
+		}
+		(
+			format!("scene {}", args.scene.name()),
+			Some(output_size(args, &scene)),
+		)
+	};
+
+	eprintln!("dry run:    {}, nothing rendered", description);
+	if let Some((width, height)) = size {
+		eprintln!("size:       {}x{}", width, height);
+	}
+	eprintln!(
+		"samples:    {} per pixel, depth {}",
+		args.samples, args.depth
+	);
+	eprintln!("seeds:      -r {} -R {}", args.world_seed, args.sample_seed);
+	eprintln!("threads:    {}", args.threads);
+	match args.output {
+		Some(ref path) => eprintln!(
+			"output:     {} ({}, {} bits)",
+			path,
+			args.format.name(),
+			args.bit_depth
+		),
+		None => eprintln!(
+			"output:     stdout ({}, {} bits)",
+			args.format.name(),
+			args.bit_depth
+		),
+	}
+	// the scene and BVH are already in memory, and the image and its auxiliary buffers are the
+	// biggest things allocated for the render
+	let scene_bytes = peak_rss().unwrap_or(0);
+	let image_bytes = size.map_or(0, |(width, height)| {
+		width * height * (std::mem::size_of::<Color>() + std::mem::size_of::<Aov>())
+	}) as u64;
+	let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+	eprintln!(
+		"memory:     about {:.1} MiB ({:.1} MiB so far, {:.1} MiB for the image)",
+		mib(scene_bytes + image_bytes),
+		mib(scene_bytes),
+		mib(image_bytes)
+	);
+	Ok(())
+}
+
+fn main() {
+	let start_time = Instant::now();
+	let args = args::parse().unwrap_or_else(|e| {
+		args::show_help();
+		Failure::Usage(e.to_string()).exit();
+	});
+	if let Err(failure) = run(&args, start_time) {
+		discard_partial(&args);
+		failure.exit();
+	}
+}
+
+/// Where the image is written until it's finished, so that a render that fails doesn't replace a
+/// good image that was already at output
+fn partial_path(output: &str) -> String {
+	format!("{}.partial", output)
+}
+
+/// Remove the unfinished image after the render fails, leaving whatever was at the output before
+fn discard_partial(args: &Args) {
+	if let Some(ref filename) = args.output {
+		let _ = fs::remove_file(partial_path(filename));
+	}
+}
+
+/// start_time: when the program started, for the wall time in --stats-out
+fn run(args: &Args, start_time: Instant) -> Result<(), Failure> {
+	if args.dry_run {
+		return dry_run(args);
 	}
 
+	let mut output: Box<dyn Write> = if let Some(ref filename) = args.output {
+		Box::new(File::create(partial_path(filename))?)
+	} else {
+		Box::new(io::stdout())
+	};
+
+	let pool = ThreadPool::new(args.threads);
+	// stats of an image that was already written, for an animation
+	let mut written_stats = None;
+	let (render, scene_name, seeds, samples_per_pixel) = if args.contact_sheet {
+		(
+			contact_sheet(&pool, args),
+			"contact sheet".to_string(),
+			(CONTACT_SEED, CONTACT_SEED),
+			CONTACT_SAMPLES,
+		)
+	} else if let Some(which) = args.bake_texture {
+		let start_time = Instant::now();
+		let mut world_rng = Xoshiro256PlusPlus::seed_from_u64(args.world_seed);
+		let texture = load_texture(which, args, &mut world_rng).map_err(Failure::Scene)?;
+		// u goes all the way around a sphere but v only goes from pole to pole
+		let size = (args.width, (args.width / 2).max(1));
+		let image = bake_texture(texture.as_ref(), size, args.texture_scale);
+		let opaque = Aov {
+			alpha: 1.0,
+			..Aov::default()
+		};
+		(
+			Render {
+				image,
+				aovs: ImageBuffer::new(size, opaque),
+				time: start_time.elapsed(),
+				objects: Vec::new(),
+				threads: Vec::new(),
+				bvh_time: Duration::ZERO,
+			},
+			format!("texture {}", which.name()),
+			(args.world_seed, args.sample_seed),
+			1,
+		)
+	} else {
+		let mut world_rng = Xoshiro256PlusPlus::seed_from_u64(args.world_seed);
+		let scene = load_scene(args.scene, args, &mut world_rng).map_err(Failure::Scene)?;
+		let scene = prepare(args, scene, &mut world_rng, args.width);
+
+		if let Some(Pick { x, y }) = args.pick {
+			check_pixel(Pick { x, y }, scene.full_size);
+			match pick(
+				&mut world_rng,
+				scene.world.as_ref(),
+				&scene.cam,
+				scene.full_size,
+				(x, y),
+			) {
+				Some(rec) => {
+					match rec.label {
+						Some(label) => eprintln!("object:     {} (id {})", label.name, label.id),
+						None => eprintln!("object:     unlabeled"),
+					}
+					eprintln!("material:   {}", rec.mat_ptr);
+					eprintln!("t:          {}", rec.t);
+					eprintln!("point:      {:?}", rec.p);
+					eprintln!("normal:     {:?}", rec.normal);
+					eprintln!("uv:         {}, {}", rec.u, rec.v);
+					eprintln!("front face: {}", rec.front_face);
+				},
+				None => eprintln!("nothing hit at {},{}", x, y),
+			}
+			return Ok(());
+		}
+
+		let render_samples = |samples, progress| match args.stereo {
+			Some(mode) => render_stereo(
+				&pool,
+				args,
+				&scene,
+				mode,
+				samples,
+				args.sample_seed,
+				progress,
+			),
+			None => render_prepared(&pool, args, &scene, samples, args.sample_seed, progress),
+		};
+		let path = match args.camera_path {
+			Some(ref path) => Some(CameraPath::load(path).map_err(Failure::Scene)?),
+			None => None,
+		};
+		let render = match (args.frames, args.preview_samples) {
+			(Some(frames), _) => {
+				let (render, stats) =
+					render_animation(&pool, args, &scene, frames, path.as_ref(), output.as_mut())?;
+				written_stats = Some(stats);
+				render
+			},
+			(None, Some(preview_samples)) => {
+				let preview = render_samples(preview_samples, "preview ");
+				let metadata = render_metadata(
+					args,
+					args.scene.name().to_string(),
+					(args.world_seed, args.sample_seed),
+					preview_samples,
+					preview.time,
+				);
+				// checked by args to be there
+				let filename = args.output.as_ref().unwrap();
+				write_image(args, &mut File::create(filename)?, &preview, &metadata)?;
+				eprintln!("wrote preview to {}", filename);
+
+				// the final image replaces the preview once it's finished
+				render_samples(args.samples, "final   ")
+			},
+			(None, None) => render_samples(args.samples, ""),
+		};
+		(
+			render,
+			args.scene.name().to_string(),
+			(args.world_seed, args.sample_seed),
+			args.samples,
+		)
+	};
+	let metadata = render_metadata(args, scene_name, seeds, samples_per_pixel, render.time);
+	let stats = match written_stats {
+		Some(stats) => stats,
+		None => write_image(args, output.as_mut(), &render, &metadata)?,
+	};
+	output.flush()?;
+	drop(output);
+	if let Some(ref filename) = args.output {
+		fs::rename(partial_path(filename), filename)?;
+	}
+	if args.verbose {
+		eprintln!(
+			"luminance:  min {:.4} | max {:.4} | mean {:.4}",
+			stats.min_luminance, stats.max_luminance, stats.mean_luminance
+		);
+		eprintln!("histogram:  [{}]", stats.histogram_line());
+		eprintln!("clipped:    {:.2}%", stats.clipped_fraction * 100.0);
+		if args.auto_expose {
+			eprintln!("exposure:   {:.4}", exposure(args, &stats));
+		}
+	}
+
+	let Render {
+		aovs,
+		time: render_time,
+		threads,
+		bvh_time,
+		..
+	} = render;
+	let (image_width, image_height) = aovs.size();
+	let primaries = args.output_primaries;
+
+	if let Some((ref path, format)) = args.output_depth {
+		let mut file = File::create(path)?;
+		if *format == EXR {
+			write_exr(
+				&mut file,
+				(image_width, image_height),
+				smallvec![AnyChannel::new(
+					"Z",
+					plane_from_image(&aovs, 32, |a| a.depth)
+				)],
+				primaries.chromaticities(),
+				Some(&metadata),
+				&[],
+			)?;
+		} else {
+			let range = match args.depth_range {
+				Some(DepthRange { near, far }) => (near, far),
+				None => depth_range(&aovs).unwrap_or((0.0, 1.0)),
+			};
+			if args.verbose {
+				eprintln!("depth:      {:.4} to {:.4}", range.0, range.1);
+			}
+			// raw values, not colors, so there's no gamma or color space
+			let mut writer = PngWriter::new(
+				file,
+				(image_width, image_height),
+				16,
+				Some(OffsetDateTime::now_utc()),
+				None,
+				None,
+			)
+			.with_chunk_size(args.png_chunk_size)
+			.with_compression(args.png_compression, args.threads);
+			writer.set_metadata(&metadata);
+			let depth = aovs.map(|a| {
+				let d = normalize_depth(a.depth, range);
+				Color::new(d, d, d)
+			});
+			write_or_abort(&mut writer, &depth, None)?;
+		}
+	}
+
+	if let (true, Some(output)) = (args.split_lighting, &args.output) {
+		let images = [aovs.map(|a| a.direct), aovs.map(|a| a.indirect)];
+		for (layer, image) in LIGHTING_LAYERS.into_iter().zip(images) {
+			// converted the same way as the OpenEXR output, so that the layers add up to it
+			let image = image.map(|p| p.to_primaries(primaries));
+			// full precision whatever the output is, since they're for compositing and denoising
+			let bits = 32;
+			write_exr(
+				&mut File::create(lighting_layer_path(output, layer))?,
+				(image_width, image_height),
+				smallvec![
+					AnyChannel::new("R", plane_from_image(&image, bits, |p| p.x())),
+					AnyChannel::new("G", plane_from_image(&image, bits, |p| p.y())),
+					AnyChannel::new("B", plane_from_image(&image, bits, |p| p.z())),
+				],
+				primaries.chromaticities(),
+				Some(&metadata),
+				&[],
+			)?;
+		}
+	}
+
+	if let Some(ref path) = args.stats_out {
+		let report = StatsReport {
+			schema_version: SCHEMA_VERSION,
+			software: metadata.software,
+			arguments: std::env::args_os()
+				.map(|a| a.to_string_lossy().into_owned())
+				.collect(),
+			scene: metadata.scene,
+			world_seed: metadata.world_seed,
+			sample_seed: metadata.sample_seed,
+			samples: metadata.samples,
+			width: image_width,
+			height: image_height,
+			wall_time: start_time.elapsed().as_secs_f64(),
+			render_time: 0.0,
+			bvh_build_time: bvh_time.as_secs_f64(),
+			camera_rays: 0,
+			rays: 0,
+			rays_per_sec: 0.0,
+			peak_rss_bytes: peak_rss(),
+			threads: Vec::new(),
+		}
+		.with_threads(&threads, render_time);
+		let file = io::BufWriter::new(File::create(path)?);
+		serde_json::to_writer_pretty(file, &report).map_err(io::Error::from)?;
+	}
+
+	eprintln!("reproduce with: {}", metadata.reproduce);
+
 	Ok(())
 }
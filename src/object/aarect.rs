@@ -1,11 +1,32 @@
 use std::sync::Arc;
 
-use rand::RngCore;
+use rand::{Rng, RngCore};
 
+use super::texture::Footprint;
 use super::{HitRecord, Hittable, Material};
-use crate::common::{Point3, Ray, Vec3};
+use crate::common::{Interval, Point3, Ray, Vec3};
 use crate::scene::Aabb;
 
+/// The footprint of a ray's pixel where it hits a rect facing normal at p, with u and v running
+/// along the axes with the given indices over the given lengths
+fn rect_footprint(
+	r: Ray,
+	t: f64,
+	p: Point3,
+	normal: Vec3,
+	(u_axis, u_length): (usize, f64),
+	(v_axis, v_length): (usize, f64),
+) -> Footprint {
+	match r.hit_differentials(p, normal) {
+		Some((dp_dx, dp_dy)) => Footprint::from_differentials(
+			(dp_dx, dp_dy),
+			(dp_dx[u_axis] / u_length, dp_dx[v_axis] / v_length),
+			(dp_dy[u_axis] / u_length, dp_dy[v_axis] / v_length),
+		),
+		None => Footprint::new(r.width_at(t), v_length),
+	}
+}
+
 #[derive(Debug)]
 pub struct XYRect {
 	mat_ptr: Arc<dyn Material>,
@@ -29,16 +50,15 @@ impl XYRect {
 	}
 }
 
-impl Hittable for XYRect {
-	fn hit<'a>(
-		&'a self,
-		_rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
+impl XYRect {
+	fn area(&self) -> f64 {
+		(self.x1 - self.x0) * (self.y1 - self.y0)
+	}
+
+	/// t, x, and y where r hits the rect in ray_t, if it does
+	fn intersect(&self, r: Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
 		let t = (self.k - r.origin().z()) / r.direction().z();
-		if t < t_min || t > t_max {
+		if !ray_t.contains(t) {
 			return None;
 		}
 
@@ -47,25 +67,81 @@ impl Hittable for XYRect {
 		if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
 			return None;
 		}
+		Some((t, x, y))
+	}
+}
+
+impl Hittable for XYRect {
+	fn occluded(&self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.mat_ptr.casts_shadows() && self.intersect(r, ray_t).is_some()
+	}
+
+	fn hit<'a>(&'a self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		let (t, x, y) = self.intersect(r, ray_t)?;
 
+		let p = r.at(t);
+		let outward_normal = Vec3::new(0.0, 0.0, 1.0);
 		let mut rec = HitRecord {
 			u: (x - self.x0) / (self.x1 - self.x0),
 			v: (y - self.y0) / (self.y1 - self.y0),
+			has_edges: true,
 			t,
 			mat_ptr: self.mat_ptr.as_ref(),
-			p: r.at(t),
+			p,
 			normal: Vec3::zero(),
 			front_face: false,
+			footprint: rect_footprint(
+				r,
+				t,
+				p,
+				outward_normal,
+				(0, self.x1 - self.x0),
+				(1, self.y1 - self.y0),
+			),
+			label: None,
 		};
-		rec.set_face_normal(r, Vec3::new(0.0, 0.0, 1.0));
+		rec.set_face_normal(r, outward_normal);
 		Some(rec)
 	}
 
+	fn is_emissive(&self) -> bool {
+		self.mat_ptr.is_emissive()
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		match self.intersect(
+			Ray::new(origin, direction, 0.0, false),
+			Interval::new(0.001, f64::INFINITY),
+		) {
+			// the area the direction covers shrinks with the distance squared, and grows as the
+			// rect turns away
+			Some((t, ..)) => t * t / (direction.z().abs() * self.area()),
+			None => 0.0,
+		}
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		let x = rng.gen_range(self.x0..=self.x1);
+		let y = rng.gen_range(self.y0..=self.y1);
+		(Point3::new(x, y, self.k) - origin).unit_vector()
+	}
+
+	fn light_power(&self) -> f64 {
+		self.area() * self.mat_ptr.average_emission().luminance()
+	}
+
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.mat_ptr)
+	}
+
 	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-		Some(Aabb::new(
-			Point3::new(self.x0, self.y0, self.k - 0.0001),
-			Point3::new(self.x1, self.y1, self.k + 0.0001),
-		))
+		Some(
+			Aabb::new(
+				Point3::new(self.x0, self.y0, self.k),
+				Point3::new(self.x1, self.y1, self.k),
+			)
+			.pad_relative(Aabb::FLAT_PADDING),
+		)
 	}
 }
 
@@ -92,16 +168,15 @@ impl XZRect {
 	}
 }
 
-impl Hittable for XZRect {
-	fn hit<'a>(
-		&'a self,
-		_rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
+impl XZRect {
+	fn area(&self) -> f64 {
+		(self.x1 - self.x0) * (self.z1 - self.z0)
+	}
+
+	/// t, x, and z where r hits the rect in ray_t, if it does
+	fn intersect(&self, r: Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
 		let t = (self.k - r.origin().y()) / r.direction().y();
-		if t < t_min || t > t_max {
+		if !ray_t.contains(t) {
 			return None;
 		}
 
@@ -110,25 +185,81 @@ impl Hittable for XZRect {
 		if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 {
 			return None;
 		}
+		Some((t, x, z))
+	}
+}
 
+impl Hittable for XZRect {
+	fn occluded(&self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.mat_ptr.casts_shadows() && self.intersect(r, ray_t).is_some()
+	}
+
+	fn hit<'a>(&'a self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		let (t, x, z) = self.intersect(r, ray_t)?;
+
+		let p = r.at(t);
+		let outward_normal = Vec3::new(0.0, 1.0, 0.0);
 		let mut rec = HitRecord {
 			u: (x - self.x0) / (self.x1 - self.x0),
 			v: (z - self.z0) / (self.z1 - self.z0),
+			has_edges: true,
 			t,
 			mat_ptr: self.mat_ptr.as_ref(),
-			p: r.at(t),
+			p,
 			normal: Vec3::zero(),
 			front_face: false,
+			footprint: rect_footprint(
+				r,
+				t,
+				p,
+				outward_normal,
+				(0, self.x1 - self.x0),
+				(2, self.z1 - self.z0),
+			),
+			label: None,
 		};
-		rec.set_face_normal(r, Vec3::new(0.0, 1.0, 0.0));
+		rec.set_face_normal(r, outward_normal);
 		Some(rec)
 	}
 
+	fn is_emissive(&self) -> bool {
+		self.mat_ptr.is_emissive()
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		match self.intersect(
+			Ray::new(origin, direction, 0.0, false),
+			Interval::new(0.001, f64::INFINITY),
+		) {
+			// the area the direction covers shrinks with the distance squared, and grows as the
+			// rect turns away
+			Some((t, ..)) => t * t / (direction.y().abs() * self.area()),
+			None => 0.0,
+		}
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		let x = rng.gen_range(self.x0..=self.x1);
+		let z = rng.gen_range(self.z0..=self.z1);
+		(Point3::new(x, self.k, z) - origin).unit_vector()
+	}
+
+	fn light_power(&self) -> f64 {
+		self.area() * self.mat_ptr.average_emission().luminance()
+	}
+
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.mat_ptr)
+	}
+
 	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-		Some(Aabb::new(
-			Point3::new(self.x0, self.k - 0.0001, self.z0),
-			Point3::new(self.x1, self.k + 0.0001, self.z1),
-		))
+		Some(
+			Aabb::new(
+				Point3::new(self.x0, self.k, self.z0),
+				Point3::new(self.x1, self.k, self.z1),
+			)
+			.pad_relative(Aabb::FLAT_PADDING),
+		)
 	}
 }
 
@@ -155,16 +286,15 @@ impl YZRect {
 	}
 }
 
-impl Hittable for YZRect {
-	fn hit<'a>(
-		&'a self,
-		_rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
+impl YZRect {
+	fn area(&self) -> f64 {
+		(self.y1 - self.y0) * (self.z1 - self.z0)
+	}
+
+	/// t, y, and z where r hits the rect in ray_t, if it does
+	fn intersect(&self, r: Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
 		let t = (self.k - r.origin().x()) / r.direction().x();
-		if t < t_min || t > t_max {
+		if !ray_t.contains(t) {
 			return None;
 		}
 
@@ -173,24 +303,166 @@ impl Hittable for YZRect {
 		if y < self.y0 || y > self.y1 || z < self.z0 || z > self.z1 {
 			return None;
 		}
+		Some((t, y, z))
+	}
+}
+
+impl Hittable for YZRect {
+	fn occluded(&self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.mat_ptr.casts_shadows() && self.intersect(r, ray_t).is_some()
+	}
 
+	fn hit<'a>(&'a self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		let (t, y, z) = self.intersect(r, ray_t)?;
+
+		let p = r.at(t);
+		let outward_normal = Vec3::new(1.0, 0.0, 0.0);
 		let mut rec = HitRecord {
 			u: (y - self.y0) / (self.y1 - self.y0),
 			v: (z - self.z0) / (self.z1 - self.z0),
+			has_edges: true,
 			t,
 			mat_ptr: self.mat_ptr.as_ref(),
-			p: r.at(t),
+			p,
 			normal: Vec3::zero(),
 			front_face: false,
+			footprint: rect_footprint(
+				r,
+				t,
+				p,
+				outward_normal,
+				(1, self.y1 - self.y0),
+				(2, self.z1 - self.z0),
+			),
+			label: None,
 		};
-		rec.set_face_normal(r, Vec3::new(1.0, 0.0, 0.0));
+		rec.set_face_normal(r, outward_normal);
 		Some(rec)
 	}
 
+	fn is_emissive(&self) -> bool {
+		self.mat_ptr.is_emissive()
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		match self.intersect(
+			Ray::new(origin, direction, 0.0, false),
+			Interval::new(0.001, f64::INFINITY),
+		) {
+			// the area the direction covers shrinks with the distance squared, and grows as the
+			// rect turns away
+			Some((t, ..)) => t * t / (direction.x().abs() * self.area()),
+			None => 0.0,
+		}
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		let y = rng.gen_range(self.y0..=self.y1);
+		let z = rng.gen_range(self.z0..=self.z1);
+		(Point3::new(self.k, y, z) - origin).unit_vector()
+	}
+
+	fn light_power(&self) -> f64 {
+		self.area() * self.mat_ptr.average_emission().luminance()
+	}
+
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.mat_ptr)
+	}
+
 	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-		Some(Aabb::new(
-			Point3::new(self.k - 0.0001, self.y0, self.z0),
-			Point3::new(self.k + 0.0001, self.y1, self.z1),
-		))
+		Some(
+			Aabb::new(
+				Point3::new(self.k, self.y0, self.z0),
+				Point3::new(self.k, self.y1, self.z1),
+			)
+			.pad_relative(Aabb::FLAT_PADDING),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::Color;
+	use crate::object::material::Lambertian;
+	use crate::scene::Camera;
+
+	fn close(a: f64, b: f64) -> bool {
+		(a - b).abs() < 1e-9
+	}
+
+	/// a 90 degree camera at origin looking down -z, with rays for a 100x100 image
+	fn camera_ray(origin: Point3, s: f64, t: f64) -> Ray {
+		let cam = Camera::new(
+			origin,
+			origin - Vec3::new(0.0, 0.0, 1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.0,
+			1.0,
+			0.0,
+			0.0,
+		);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		cam.get_ray(&mut rng, s, t, false)
+			.with_differentials(Some(cam.pixel_differentials((100, 100))))
+	}
+
+	#[test]
+	fn test_footprint_facing() {
+		let mat: Arc<dyn Material> = Arc::new(Lambertian::with_color(Color::zero()));
+		// 10 units wide and 4 tall, seen from 5 away, where the image is 10 units across
+		let rect = XYRect::new(-5.0, 5.0, -2.0, 2.0, 0.0, mat);
+		let r = camera_ray(Point3::new(0.0, 0.0, 5.0), 0.5, 0.5);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let footprint = rect
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap()
+			.footprint;
+		assert!(close(footprint.world, 0.1));
+		assert!(close(footprint.duv_dx.0, 0.01) && close(footprint.duv_dx.1, 0.0));
+		assert!(close(footprint.duv_dy.0, 0.0) && close(footprint.duv_dy.1, 0.025));
+		assert!(close(footprint.uv, 0.025));
+
+		// without differentials, the spread is used instead
+		let r = Ray::new(
+			Point3::new(0.0, 0.0, 5.0),
+			Vec3::new(0.0, 0.0, -1.0),
+			0.0,
+			false,
+		)
+		.with_spread(0.02);
+		let footprint = rect
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap()
+			.footprint;
+		assert!(close(footprint.world, 0.1));
+		assert_eq!(footprint.duv_dx, (0.0, 0.0));
+	}
+
+	#[test]
+	fn test_footprint_oblique() {
+		let mat: Arc<dyn Material> = Arc::new(Lambertian::with_color(Color::zero()));
+		let floor = XZRect::new(-10.0, 10.0, -10.0, 0.0, -1.0, mat);
+		// the ray goes down 1 for every 2 forward, so it hits the floor 2 away. the next pixel up
+		// is 0.02 higher in direction, so it hits at 1 / 0.48
+		let r = camera_ray(Point3::zero(), 0.5, 0.25);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let footprint = floor
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap()
+			.footprint;
+		let depth = 2.0 - 1.0 / 0.48;
+		assert!(close(footprint.duv_dx.0, 0.02 * 2.0 / 20.0));
+		assert!(close(footprint.duv_dx.1, 0.0));
+		assert!(close(footprint.duv_dy.0, 0.0));
+		assert!(close(footprint.duv_dy.1, depth / 10.0));
+		// stretched along the floor, away from the camera
+		assert!(close(footprint.world, -depth));
 	}
 }
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use rand::RngCore;
 
 use super::{HitRecord, Hittable, Material, XYRect, XZRect, YZRect};
-use crate::common::{Point3, Ray};
+use crate::common::{Interval, Point3, Ray, Vec3};
 use crate::scene::{Aabb, HittableList};
 
 #[derive(Debug)]
@@ -68,18 +68,47 @@ impl Block {
 		)));
 
 		Block {
-			aabb: Aabb::new(p0, p1),
+			aabb: Aabb::new(p0, p1).pad_relative(Aabb::FLAT_PADDING),
 			sides,
 		}
 	}
 }
 
 impl Hittable for Block {
+	fn is_emissive(&self) -> bool {
+		self.sides.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		self.sides.as_ref()
+	}
+
 	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
 		Some(self.aabb)
 	}
 
-	fn hit(&self, rng: &mut dyn RngCore, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-		self.sides.hit(rng, r, t_min, t_max)
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.sides.occluded(rng, r, ray_t)
+	}
+
+	fn hit(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord> {
+		self.sides.hit(rng, r, ray_t).map(|mut rec| {
+			// each side's rectangle has its normal pointing towards positive coordinates, which
+			// is inward for the sides at the minimum corner. figure out which side was hit so
+			// that front_face is relative to the block as a whole
+			let axis = (0..3)
+				.max_by(|&a, &b| rec.normal[a].abs().total_cmp(&rec.normal[b].abs()))
+				.unwrap();
+			let mut outward_normal = Vec3::zero();
+			outward_normal[axis] = if (rec.p[axis] - self.aabb.min()[axis]).abs()
+				< (rec.p[axis] - self.aabb.max()[axis]).abs()
+			{
+				-1.0
+			} else {
+				1.0
+			};
+			rec.set_face_normal(r, outward_normal);
+			rec
+		})
 	}
 }
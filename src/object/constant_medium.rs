@@ -2,10 +2,10 @@ use std::sync::Arc;
 
 use rand::{Rng, RngCore};
 
-use super::material::Isotropic;
-use super::texture::SolidColor;
-use super::{HitRecord, Hittable, Material, Sphere, Texture};
-use crate::common::{Color, Ray, Vec3};
+use super::material::{check_positive, Isotropic, MaterialError};
+use super::texture::{Footprint, SolidColor};
+use super::{HitRecord, Hittable, Material, Texture};
+use crate::common::{Color, Interval, Ray, Vec3};
 use crate::scene::Aabb;
 
 #[derive(Debug)]
@@ -16,24 +16,35 @@ pub struct ConstantMedium {
 }
 
 impl ConstantMedium {
+	/// A volume that scatters light in every direction
+	/// boundary: surface of the volume, whose material isn't used
+	/// density:  how likely light is to scatter per unit of distance, so light goes 1 / density
+	///           through the volume on average before scattering. panics unless it's positive
+	///           and finite
+	/// texture:  fraction of light scattered at each wavelength
 	pub fn new(
 		boundary: Arc<dyn Hittable>,
 		density: f64,
 		texture: Arc<dyn Texture>,
 	) -> ConstantMedium {
-		ConstantMedium {
+		ConstantMedium::try_new(boundary, density, texture).unwrap_or_else(|e| panic!("{}", e))
+	}
+
+	/// like new, but returning an error for a density that isn't positive and finite
+	pub fn try_new(
+		boundary: Arc<dyn Hittable>,
+		density: f64,
+		texture: Arc<dyn Texture>,
+	) -> Result<ConstantMedium, MaterialError> {
+		Ok(ConstantMedium {
 			boundary,
-			neg_inv_density: -1.0 / density,
+			neg_inv_density: -1.0 / check_positive("density", density)?,
 			phase_function: Arc::new(Isotropic::new(texture)),
-		}
+		})
 	}
 
 	pub fn with_color(boundary: Arc<dyn Hittable>, density: f64, color: Color) -> ConstantMedium {
-		ConstantMedium {
-			boundary,
-			neg_inv_density: -1.0 / density,
-			phase_function: Arc::new(Isotropic::new(Arc::new(SolidColor::new(color)))),
-		}
+		ConstantMedium::new(boundary, density, Arc::new(SolidColor::new(color)))
 	}
 }
 
@@ -42,21 +53,23 @@ impl Hittable for ConstantMedium {
 		self.boundary.bounding_box(time0, time1)
 	}
 
-	fn hit<'a>(
-		&'a self,
-		rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
-		if let Some(mut rec1) = self.boundary.hit(rng, r, f64::NEG_INFINITY, f64::INFINITY) {
-			if let Some(mut rec2) = self.boundary.hit(rng, r, rec1.t + 0.0001, f64::INFINITY) {
-				if rec1.t < t_min {
-					rec1.t = t_min;
-				}
-				if rec2.t > t_max {
-					rec2.t = t_max;
-				}
+	/// the material of the volume inside, not of the boundary
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.phase_function)
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.boundary)
+	}
+
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		if let Some(mut rec1) = self.boundary.hit(rng, r, Interval::UNIVERSE) {
+			if let Some(mut rec2) =
+				self.boundary
+					.hit(rng, r, Interval::new(rec1.t + 0.0001, f64::INFINITY))
+			{
+				rec1.t = ray_t.clamp(rec1.t);
+				rec2.t = ray_t.clamp(rec2.t);
 				if rec1.t >= rec2.t {
 					return None;
 				}
@@ -74,23 +87,65 @@ impl Hittable for ConstantMedium {
 
 				let t = rec1.t + hit_distance / ray_length;
 				let p = r.at(t);
-				let v = if let Some(bbox) = self.bounding_box(r.time(), r.time()) {
-					let center = (bbox.min() + bbox.max()) / 2.0;
-					Sphere::get_sphere_uv((p - center) / (bbox.max().x() - center.x())).1
-				} else {
-					1.0 // arbitrary
-				};
-				return Some(HitRecord {
+				let (u, v) = self
+					.boundary
+					.uv_at(p, r.time())
+					.or_else(|| {
+						// fall back to projecting the bounding box onto the xy plane
+						self.bounding_box(r.time(), r.time()).map(|bbox| {
+							let size = bbox.max() - bbox.min();
+							(
+								(p.x() - bbox.min().x()) / size.x(),
+								(p.y() - bbox.min().y()) / size.y(),
+							)
+						})
+					})
+					.unwrap_or((0.5, 0.5));
+				let mut rec = HitRecord {
 					t,
-					p: r.at(t),
-					normal: Vec3::new(1.0, 0.0, 0.0), // arbitrary
-					front_face: true,                 // arbitrary
-					u: 1.0,                           // arbitrary
+					p,
+					normal: Vec3::zero(),
+					front_face: true,
+					footprint: Footprint::default(),
+					label: None,
+					u,
 					v,
+					has_edges: false,
 					mat_ptr: self.phase_function.as_ref(),
-				});
+				};
+				// there's no surface here, so face back towards where the ray came from
+				rec.set_face_normal(r, -r.direction().unit_vector());
+				return Some(rec);
 			}
 		}
 		None
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::Point3;
+	use crate::object::material::Lambertian;
+	use crate::object::Sphere;
+
+	#[test]
+	fn test_density() {
+		let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(
+			Point3::zero(),
+			1.0,
+			Arc::new(Lambertian::with_color(Color::zero())),
+		));
+		let texture: Arc<dyn Texture> = Arc::new(SolidColor::new(Color::zero()));
+		let medium = |density| ConstantMedium::try_new(boundary.clone(), density, texture.clone());
+		assert_eq!(medium(0.5).unwrap().neg_inv_density, -2.0);
+		for density in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+			let e = medium(density).unwrap_err();
+			assert_eq!(e.parameter, "density");
+		}
+		assert_eq!(
+			medium(0.0).unwrap_err().to_string(),
+			"density must be positive and finite, not 0"
+		);
+	}
+}
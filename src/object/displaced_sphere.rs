@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use rand::RngCore;
+
+use super::{HitRecord, Hittable, Material, Sphere, Texture};
+use crate::common::{Interval, OrthonormalBasis, Point3, Ray, Vec3};
+use crate::scene::Aabb;
+
+/// how many times the interval holding a crossing of the surface is halved to find it
+const REFINE_STEPS: usize = 40;
+/// angle in radians between the points the normal is found from
+const NORMAL_STEP: f64 = 1e-4;
+
+/// A sphere whose radius is raised or lowered at each point by a texture, for bumps that show up
+/// in its silhouette and shadows. Rays are marched through the shell between the lowest and
+/// highest the surface can be, so this is much slower than a sphere
+#[derive(Debug)]
+pub struct DisplacedSphere {
+	center: Point3,
+	radius: f64,
+	/// brightness from 0 to 1 at each point of the undisplaced sphere
+	height: Arc<dyn Texture>,
+	/// how far a height of 1 moves the surface outwards. negative for dents
+	scale: f64,
+	max_steps: usize,
+	mat_ptr: Arc<dyn Material>,
+}
+
+impl DisplacedSphere {
+	/// height:    how far to move the surface, from 0 to 1 of scale. it's looked up with the
+	///            point on the undisplaced sphere, so textures that depend on the position work
+	/// max_steps: most points a ray is tested at on its way through the shell. every step goes at
+	///            least this fraction of the way across, so rays that graze the surface can't
+	///            take forever, but bumps narrower than a step can be missed
+	pub fn new(
+		center: Point3,
+		radius: f64,
+		height: Arc<dyn Texture>,
+		scale: f64,
+		max_steps: usize,
+		mat_ptr: Arc<dyn Material>,
+	) -> DisplacedSphere {
+		assert!(radius > 0.0 && radius + scale.min(0.0) > 0.0);
+		assert!(max_steps > 0);
+		DisplacedSphere {
+			center,
+			radius,
+			height,
+			scale,
+			max_steps,
+			mat_ptr,
+		}
+	}
+
+	/// radii of the spheres that the surface is always between
+	fn shell(&self) -> (f64, f64) {
+		(
+			self.radius + self.scale.min(0.0),
+			self.radius + self.scale.max(0.0),
+		)
+	}
+
+	/// position of the surface in a direction (a unit vector) from the center
+	fn surface(&self, direction: Vec3) -> Point3 {
+		let (u, v) = Sphere::get_sphere_uv(direction);
+		let base = self.center + self.radius * direction;
+		let height = self.height.value(u, v, base).luminance().clamp(0.0, 1.0);
+		self.center + (self.radius + self.scale * height) * direction
+	}
+
+	/// how far p is outside of the surface, measured along the line from the center. this isn't
+	/// the distance to the nearest point where the surface slopes, but has the same sign
+	fn distance(&self, p: Point3) -> f64 {
+		let offset = p - self.center;
+		let length = offset.length();
+		if length == 0.0 {
+			return -self.shell().0;
+		}
+		length - (self.surface(offset / length) - self.center).length()
+	}
+
+	/// the t of the closest place in ray_t where r crosses the surface
+	fn intersect(&self, r: Ray, ray_t: Interval) -> Option<f64> {
+		let (inner, outer) = self.shell();
+		// a little bigger, so that a ray coming from outside starts outside even where the
+		// surface is as high as it goes
+		let (near, far) = Sphere::roots(self.center, outer * (1.0 + 1e-9), r)?;
+		let (mut t0, mut t1) = (ray_t.clamp(near), ray_t.clamp(far));
+		if t0 >= t1 {
+			return None;
+		}
+		let mut distance = self.distance(r.at(t0));
+		let outside = distance > 0.0;
+		if outside {
+			// everything inside the inner sphere is inside the surface, so it's crossed by there
+			if let Some((enter, _)) = Sphere::roots(self.center, inner, r) {
+				if enter > t0 {
+					t1 = t1.min(enter);
+				}
+			}
+		}
+
+		let speed = r.direction().length();
+		let min_step = (t1 - t0) / self.max_steps as f64;
+		for _ in 0..self.max_steps {
+			// the surface is at least this far along the line to the center, but can be nearer
+			// along the ray where it slopes, so only go part of the way there
+			let t = (t0 + (0.5 * distance.abs() / speed).max(min_step)).min(t1);
+			let next = self.distance(r.at(t));
+			if (next > 0.0) != outside {
+				return Some(self.refine(r, (t0, t), outside));
+			}
+			if t >= t1 {
+				return None;
+			}
+			(t0, distance) = (t, next);
+		}
+		None
+	}
+
+	/// The crossing between two ts on either side of the surface, by halving the interval
+	fn refine(&self, r: Ray, (mut t0, mut t1): (f64, f64), outside: bool) -> f64 {
+		for _ in 0..REFINE_STEPS {
+			let t = 0.5 * (t0 + t1);
+			if (self.distance(r.at(t)) > 0.0) == outside {
+				t0 = t;
+			} else {
+				t1 = t;
+			}
+		}
+		t1
+	}
+
+	/// The outward normal in a direction from the center, from the slope of the surface between
+	/// points on either side of it
+	fn outward_normal(&self, direction: Vec3) -> Vec3 {
+		let basis = OrthonormalBasis::new(direction);
+		let slope = |axis: Vec3| {
+			self.surface((direction + NORMAL_STEP * axis).unit_vector())
+				- self.surface((direction - NORMAL_STEP * axis).unit_vector())
+		};
+		let normal = slope(basis.u).cross(slope(basis.v)).unit_vector();
+		if normal.dot(direction) < 0.0 {
+			-normal
+		} else {
+			normal
+		}
+	}
+}
+
+impl Hittable for DisplacedSphere {
+	fn hit<'a>(&'a self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		let t = self.intersect(r, ray_t)?;
+		let p = r.at(t);
+		let direction = (p - self.center).unit_vector();
+		let (u, v) = Sphere::get_sphere_uv(direction);
+		let base = self.center + self.radius * direction;
+		let mut rec = HitRecord {
+			t,
+			p,
+			normal: Vec3::zero(),
+			front_face: false,
+			// of the undisplaced sphere, which is close enough for filtering textures
+			footprint: Sphere::footprint(r, t, base, self.center, self.radius, (u, v)),
+			label: None,
+			mat_ptr: self.mat_ptr.as_ref(),
+			u,
+			v,
+			has_edges: false,
+		};
+		rec.set_face_normal(r, self.outward_normal(direction));
+		Some(rec)
+	}
+
+	fn occluded(&self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.mat_ptr.casts_shadows() && self.intersect(r, ray_t).is_some()
+	}
+
+	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+		let (_, outer) = self.shell();
+		let extent = Vec3::new(outer, outer, outer);
+		Some(Aabb::new(self.center - extent, self.center + extent))
+	}
+
+	fn uv_at(&self, p: Point3, _time: f64) -> Option<(f64, f64)> {
+		Some(Sphere::get_sphere_uv((p - self.center).unit_vector()))
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.mat_ptr.is_emissive()
+	}
+
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.mat_ptr)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::Color;
+	use crate::object::material::Lambertian;
+	use crate::object::texture::{FunctionTexture, SolidColor};
+
+	fn bumpy(max_steps: usize) -> DisplacedSphere {
+		// eight bumps around the equator, and four from pole to pole
+		let height = FunctionTexture(|u: f64, v: f64, _p: Point3| {
+			let h = 0.5
+				+ 0.5
+					* (16.0 * std::f64::consts::PI * u).sin()
+					* (8.0 * std::f64::consts::PI * v).cos();
+			Color::new(h, h, h)
+		});
+		DisplacedSphere::new(
+			Point3::new(1.0, 2.0, 3.0),
+			1.0,
+			Arc::new(height),
+			0.2,
+			max_steps,
+			Arc::new(Lambertian::with_color(Color::zero())),
+		)
+	}
+
+	#[test]
+	fn test_flat_is_a_sphere() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let flat = DisplacedSphere::new(
+			Point3::zero(),
+			1.0,
+			Arc::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))),
+			0.2,
+			128,
+			Arc::new(Lambertian::with_color(Color::zero())),
+		);
+		let r = Ray::new(
+			Point3::new(0.3, 0.1, -5.0),
+			Vec3::new(0.0, 0.0, 1.0),
+			0.0,
+			false,
+		);
+		let rec = flat
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap();
+		let t =
+			Sphere::intersect(Point3::zero(), 1.2, r, Interval::new(0.001, f64::INFINITY)).unwrap();
+		assert!((rec.t - t).abs() < 1e-9, "{} {}", rec.t, t);
+		assert!((rec.normal - rec.p / 1.2).length() < 1e-6, "{}", rec.normal);
+	}
+
+	#[test]
+	fn test_hits_are_on_the_surface() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let sphere = bumpy(128);
+		let bbox = sphere.bounding_box(0.0, 0.0).unwrap();
+		let mut hits = 0;
+		for _ in 0..500 {
+			// aimed anywhere near the sphere from all around, so plenty of them graze it
+			let origin = sphere.center + 4.0 * Vec3::random_unit_vector(&mut rng);
+			let target = sphere.center + Vec3::random_range(&mut rng, -1.2, 1.2);
+			let r = Ray::new(origin, target - origin, rng.gen(), false);
+			let Some(rec) = sphere.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY)) else {
+				continue;
+			};
+			hits += 1;
+			assert!(sphere.distance(rec.p).abs() < 1e-6, "{}", rec.p);
+			for axis in 0..3 {
+				assert!(bbox.min()[axis] <= rec.p[axis] && rec.p[axis] <= bbox.max()[axis]);
+			}
+			assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+			assert!(rec.normal.dot(r.direction()) < 0.0);
+			// nothing is in front of it
+			assert!(!sphere.occluded(&mut rng, r, Interval::new(0.001, rec.t - 1e-6)));
+		}
+		assert!(hits > 100, "{}", hits);
+
+		// from inside, a ray always finds its way out
+		let r = Ray::new(sphere.center, Vec3::new(0.3, 1.0, 0.2), 0.0, false);
+		let rec = sphere
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap();
+		assert!(!rec.front_face);
+		assert!(sphere.distance(rec.p).abs() < 1e-6);
+	}
+
+	#[test]
+	fn test_grazing_rays_stop() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let sphere = bumpy(16);
+		// skimming the top of the shell, where the surface is only reached at the very top of a
+		// bump if at all
+		for i in 0..100 {
+			let height = 1.15 + i as f64 * 0.0005;
+			let r = Ray::new(
+				sphere.center + Vec3::new(-3.0, height, 0.1),
+				Vec3::new(1.0, 0.0, 0.0),
+				0.0,
+				false,
+			);
+			if let Some(rec) = sphere.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY)) {
+				assert!(sphere.distance(rec.p).abs() < 1e-6, "{}", rec.p);
+			}
+		}
+		// and one that misses the shell entirely never marches
+		let r = Ray::new(
+			sphere.center + Vec3::new(-3.0, 1.3, 0.0),
+			Vec3::new(1.0, 0.0, 0.0),
+			0.0,
+			false,
+		);
+		assert!(sphere
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.is_none());
+	}
+}
@@ -1,11 +1,13 @@
 use std::fmt::Debug;
 use std::sync::Arc;
 
+use once_cell::sync::OnceCell;
 use rand::RngCore;
 
+use super::texture::Footprint;
 use super::Material;
-use crate::common::{Point3, Ray, Vec3};
-use crate::scene::Aabb;
+use crate::common::{Differentials, Interval, Point3, Ray, RayKind, Vec3};
+use crate::scene::{Aabb, BvhNode, HittableList};
 
 #[derive(Debug)]
 pub struct HitRecord<'a> {
@@ -15,7 +17,13 @@ pub struct HitRecord<'a> {
 	pub t: f64,
 	pub u: f64,
 	pub v: f64,
+	/// whether u and v go from 0 to 1 between the edges of a flat face, like on rectangles,
+	/// rather than meeting at seams and poles. --override-material wireframe draws the edges
+	pub has_edges: bool,
 	pub front_face: bool,
+	pub footprint: Footprint,
+	/// label of the innermost Labeled object around what was hit
+	pub label: Option<&'a Label>,
 }
 
 impl HitRecord<'_> {
@@ -30,127 +38,1118 @@ impl HitRecord<'_> {
 }
 
 pub trait Hittable: Sync + Send + Debug {
-	fn hit<'a>(
-		&'a self,
-		rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>>;
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>>;
 	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+	/// Whether r hits anything that casts shadows in ray_t, for shadow rays.
+	/// Unlike hit, this can stop at the first hit it finds instead of looking for the closest
+	/// one. The default uses hit, which is only right for objects with one material, so objects
+	/// made of others should ask their children instead
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.hit(rng, r, ray_t)
+			.is_some_and(|rec| rec.mat_ptr.casts_shadows())
+	}
+
+	/// Texture coordinates of a point on or inside this object at the given time, for objects
+	/// that have a natural parameterization (used to texture volumes bounded by the object)
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		// mark as unused without underscores in the signature
+		let _ = (p, time);
+		None
+	}
+
+	/// whether any part of this object gives off light, so that lights can be found in a scene
+	fn is_emissive(&self) -> bool {
+		false
+	}
+
+	/// Probability density per steradian of random picking direction (a unit vector) from origin,
+	/// which is 0 for directions that miss this object
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		let _ = (origin, direction);
+		0.0
+	}
+
+	/// A unit vector from origin towards a random point on this object, for aiming rays at lights
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		let _ = (rng, origin);
+		Vec3::new(1.0, 0.0, 0.0)
+	}
+
+	/// Roughly how much light this object gives off, as its area times how bright its material is,
+	/// for deciding how often to aim rays at it. 0 for objects that random doesn't work for
+	fn light_power(&self) -> f64 {
+		0.0
+	}
+
+	/// The objects this one is made of, if it is a group or a transformation of other objects.
+	/// Leaves of the scene graph have none
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		&[]
+	}
+
+	/// calls f for every object below this one in the scene graph, parents before their children
+	fn visit(&self, f: &mut dyn FnMut(&dyn Hittable)) {
+		for child in self.children() {
+			f(child.as_ref());
+			child.visit(f);
+		}
+	}
+
+	/// the material of this object's surface, for objects that have exactly one. groups and
+	/// transformations have none of their own
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		None
+	}
+
+	/// name of the type of this object, for statistics
+	fn type_name(&self) -> &'static str {
+		let name = std::any::type_name::<Self>();
+		// strip the module path and any generic parameters
+		let name = name.split('<').next().unwrap_or(name);
+		name.rsplit("::").next().unwrap_or(name)
+	}
+
+	/// Applies the same transformation as this object to another one, so that a child can be
+	/// placed in the world on its own. Groups don't move their children, so they return it as is
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		child
+	}
+
+	/// this object as a list of other objects, if it is one, so that lists can be flattened
+	fn as_hittable_list(&self) -> Option<&HittableList> {
+		None
+	}
+
+	/// this object as a BVH node, if it is one, so that trees can be refitted
+	fn as_bvh_node(&self) -> Option<&BvhNode> {
+		None
+	}
+}
+
+/// Bounding boxes that an object has already found, for the last few time intervals it was asked
+/// about. BVH construction asks for each object's box at time 0 and over the shutter interval,
+/// so that is how many are kept
+#[derive(Debug, Default)]
+struct BoundsCache([OnceCell<(f64, f64, Option<Aabb>)>; 2]);
+
+impl BoundsCache {
+	fn get(&self, time0: f64, time1: f64, compute: impl FnOnce() -> Option<Aabb>) -> Option<Aabb> {
+		for slot in &self.0 {
+			match slot.get() {
+				Some(&(t0, t1, bbox)) if t0 == time0 && t1 == time1 => return bbox,
+				Some(_) => {},
+				None => {
+					let bbox = compute();
+					// another thread may have filled the slot first, which is fine since it found
+					// the box for the same or some other interval
+					let _ = slot.set((time0, time1, bbox));
+					return bbox;
+				},
+			}
+		}
+		compute()
+	}
 }
 
 #[derive(Debug)]
 pub struct Translate {
 	child: Arc<dyn Hittable>,
 	offset: Vec3,
+	bounds: BoundsCache,
 }
 
 impl Translate {
 	pub fn new(child: Arc<dyn Hittable>, offset: Vec3) -> Translate {
-		Translate { child, offset }
+		Translate {
+			child,
+			offset,
+			bounds: BoundsCache::default(),
+		}
+	}
+
+	/// r in the child's coordinates
+	fn transform_ray(&self, r: Ray) -> Ray {
+		r.moved(r.origin() - self.offset, r.direction())
 	}
 }
 
 impl Hittable for Translate {
 	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
-		self.child
-			.bounding_box(time0, time1)
-			.map(|bb| Aabb::new(bb.min() + self.offset, bb.max() + self.offset))
+		self.bounds.get(time0, time1, || {
+			self.child
+				.bounding_box(time0, time1)
+				.map(|bb| Aabb::new(bb.min() + self.offset, bb.max() + self.offset))
+		})
 	}
 
-	fn hit(&self, rng: &mut dyn RngCore, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-		let translated_ray = Ray::new(
-			r.origin() - self.offset,
-			r.direction(),
-			r.time(),
-			r.debug_bvh(),
-		);
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(p - self.offset, time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(Translate::new(child, self.offset))
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		self.child.pdf_value(origin - self.offset, direction)
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		self.child.random(rng, origin - self.offset)
+	}
+
+	fn light_power(&self) -> f64 {
+		self.child.light_power()
+	}
+
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.child.occluded(rng, self.transform_ray(r), ray_t)
+	}
+
+	fn hit(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord> {
 		self.child
-			.hit(rng, translated_ray, t_min, t_max)
+			.hit(rng, self.transform_ray(r), ray_t)
 			.map(|mut rec| {
+				// translation doesn't change directions, so the child's normal and facing are
+				// still correct
 				rec.p += self.offset;
-				rec.set_face_normal(translated_ray, rec.normal);
 				rec
 			})
 	}
 }
 
+/// A rotation about the y axis
+#[derive(Debug, Clone, Copy)]
+struct Rotation {
+	sin_theta: f64,
+	cos_theta: f64,
+}
+
+impl Rotation {
+	/// angle: in degrees, counterclockwise looking down from +y
+	fn new(angle: f64) -> Rotation {
+		let radians = angle.to_radians();
+		Rotation {
+			sin_theta: radians.sin(),
+			cos_theta: radians.cos(),
+		}
+	}
+
+	/// v in the child's coordinates
+	fn to_child(self, v: Vec3) -> Vec3 {
+		Vec3::new(
+			self.cos_theta * v[0] - self.sin_theta * v[2],
+			v[1],
+			self.sin_theta * v[0] + self.cos_theta * v[2],
+		)
+	}
+
+	/// v in the rotated object's coordinates, from the child's
+	fn to_parent(self, v: Vec3) -> Vec3 {
+		Vec3::new(
+			self.cos_theta * v[0] + self.sin_theta * v[2],
+			v[1],
+			-self.sin_theta * v[0] + self.cos_theta * v[2],
+		)
+	}
+
+	/// r in the child's coordinates
+	fn ray_to_child(self, r: Ray) -> Ray {
+		let differentials = r.differentials().map(|d| Differentials {
+			dx_origin: self.to_child(d.dx_origin),
+			dx_direction: self.to_child(d.dx_direction),
+			dy_origin: self.to_child(d.dy_origin),
+			dy_direction: self.to_child(d.dy_direction),
+		});
+		r.moved(self.to_child(r.origin()), self.to_child(r.direction()))
+			.with_differentials(differentials)
+	}
+
+	/// a hit on the child, moved back out of its coordinates
+	fn hit_to_parent(self, mut rec: HitRecord) -> HitRecord {
+		// the child already oriented the normal against the rotated ray, and rotating both of
+		// them back preserves that, so front_face doesn't need to be recomputed
+		rec.p = self.to_parent(rec.p);
+		rec.normal = self.to_parent(rec.normal);
+		rec
+	}
+}
+
+/// The eight corners of a box
+fn corners(bbox: Aabb) -> impl Iterator<Item = Point3> {
+	(0..8).map(move |i| {
+		let pick = |axis: usize| {
+			if i & (1 << axis) == 0 {
+				bbox.min()[axis]
+			} else {
+				bbox.max()[axis]
+			}
+		};
+		Point3::new(pick(0), pick(1), pick(2))
+	})
+}
+
 #[derive(Debug)]
 pub struct RotateY {
 	child: Arc<dyn Hittable>,
-	sin_theta: f64,
-	cos_theta: f64,
+	rotation: Rotation,
+	bounds: BoundsCache,
 }
 
 impl RotateY {
 	pub fn new(child: Arc<dyn Hittable>, angle: f64) -> RotateY {
-		let radians = angle.to_radians();
-		let sin_theta = radians.sin();
-		let cos_theta = radians.cos();
-
 		RotateY {
 			child,
-			sin_theta,
-			cos_theta,
+			rotation: Rotation::new(angle),
+			bounds: BoundsCache::default(),
 		}
 	}
-}
 
-impl Hittable for RotateY {
-	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+	/// the child's box rotated, which is a larger box containing all eight corners
+	fn rotated_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
 		self.child.bounding_box(time0, time1).map(|bbox| {
 			let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
 			let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
-
-			for i in 0..2 {
-				for j in 0..2 {
-					for k in 0..2 {
-						let (i_f, j_f, k_f) = (i as f64, j as f64, k as f64);
-						let x = i_f * bbox.max().x() + (1.0 - i_f) * bbox.min().x();
-						let y = j_f * bbox.max().y() + (1.0 - j_f) * bbox.min().y();
-						let z = k_f * bbox.max().z() + (1.0 - k_f) * bbox.min().z();
-
-						let new_x = self.cos_theta * x + self.sin_theta * z;
-						let new_z = -self.sin_theta * x + self.cos_theta * z;
-
-						let tester = Vec3::new(new_x, y, new_z);
-						min = min.min(tester);
-						max = max.max(tester);
-					}
-				}
+			for corner in corners(bbox) {
+				let rotated = self.rotation.to_parent(corner);
+				min = min.min(rotated);
+				max = max.max(rotated);
 			}
-
 			Aabb::new(min, max)
 		})
 	}
+}
+
+impl Hittable for RotateY {
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		self.bounds
+			.get(time0, time1, || self.rotated_box(time0, time1))
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(self.rotation.to_child(p), time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(RotateY {
+			child,
+			rotation: self.rotation,
+			bounds: BoundsCache::default(),
+		})
+	}
 
-	fn hit(&self, rng: &mut dyn RngCore, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-		let mut origin = r.origin();
-		let mut direction = r.direction();
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		self.child.pdf_value(
+			self.rotation.to_child(origin),
+			self.rotation.to_child(direction),
+		)
+	}
 
-		origin[0] = self.cos_theta * r.origin()[0] - self.sin_theta * r.origin()[2];
-		origin[2] = self.sin_theta * r.origin()[0] + self.cos_theta * r.origin()[2];
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		self.rotation
+			.to_parent(self.child.random(rng, self.rotation.to_child(origin)))
+	}
 
-		direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
-		direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
+	fn light_power(&self) -> f64 {
+		self.child.light_power()
+	}
 
-		let rotated_ray = Ray::new(origin, direction, r.time(), r.debug_bvh());
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
 		self.child
-			.hit(rng, rotated_ray, t_min, t_max)
-			.map(|mut rec| {
-				let mut p = rec.p;
-				let mut normal = rec.normal;
+			.occluded(rng, self.rotation.ray_to_child(r), ray_t)
+	}
+
+	fn hit(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord> {
+		self.child
+			.hit(rng, self.rotation.ray_to_child(r), ray_t)
+			.map(|rec| self.rotation.hit_to_parent(rec))
+	}
+}
+
+/// Fraction of the way from time0 to time1 that time is, or 0 if they're the same
+fn progress(time: f64, (time0, time1): (f64, f64)) -> f64 {
+	if time1 == time0 {
+		0.0
+	} else {
+		(time - time0) / (time1 - time0)
+	}
+}
+
+/// Translate, but moving in a straight line during the shutter interval, for motion blur
+#[derive(Debug)]
+pub struct AnimatedTranslate {
+	child: Arc<dyn Hittable>,
+	offset0: Vec3,
+	offset1: Vec3,
+	times: (f64, f64),
+	bounds: BoundsCache,
+}
+
+impl AnimatedTranslate {
+	/// the child is moved by offset0 at time0 and by offset1 at time1. if time0 and time1 are the
+	/// same, it stays at offset0
+	pub fn new(
+		child: Arc<dyn Hittable>,
+		offset0: Vec3,
+		offset1: Vec3,
+		time0: f64,
+		time1: f64,
+	) -> AnimatedTranslate {
+		AnimatedTranslate {
+			child,
+			offset0,
+			offset1,
+			times: (time0, time1),
+			bounds: BoundsCache::default(),
+		}
+	}
+
+	fn offset(&self, time: f64) -> Vec3 {
+		self.offset0 + progress(time, self.times) * (self.offset1 - self.offset0)
+	}
+
+	/// r in the child's coordinates
+	fn transform_ray(&self, r: Ray) -> Ray {
+		r.moved(r.origin() - self.offset(r.time()), r.direction())
+	}
+}
+
+impl Hittable for AnimatedTranslate {
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		// the offset moves in a straight line, so the boxes at the ends cover everything between
+		self.bounds.get(time0, time1, || {
+			self.child.bounding_box(time0, time1).map(|bb| {
+				let at = |time| {
+					let offset = self.offset(time);
+					Aabb::new(bb.min() + offset, bb.max() + offset)
+				};
+				Aabb::surrounding_box(at(time0), at(time1))
+			})
+		})
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(p - self.offset(time), time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
 
-				p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-				p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(AnimatedTranslate::new(
+			child,
+			self.offset0,
+			self.offset1,
+			self.times.0,
+			self.times.1,
+		))
+	}
 
-				normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-				normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.child.occluded(rng, self.transform_ray(r), ray_t)
+	}
 
-				rec.p = p;
-				rec.set_face_normal(rotated_ray, normal);
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		self.child
+			.hit(rng, self.transform_ray(r), ray_t)
+			.map(|mut rec| {
+				rec.p += self.offset(r.time());
 				rec
 			})
 	}
 }
+
+/// RotateY, but turning at a steady rate during the shutter interval, for motion blur
+#[derive(Debug)]
+pub struct AnimatedRotateY {
+	child: Arc<dyn Hittable>,
+	angle0: f64,
+	angle1: f64,
+	times: (f64, f64),
+	bounds: BoundsCache,
+}
+
+impl AnimatedRotateY {
+	/// the child is rotated by angle0 degrees at time0 and by angle1 at time1, turning through
+	/// every angle between, so it can go around more than once. if time0 and time1 are the same,
+	/// it stays at angle0
+	pub fn new(
+		child: Arc<dyn Hittable>,
+		angle0: f64,
+		angle1: f64,
+		time0: f64,
+		time1: f64,
+	) -> AnimatedRotateY {
+		AnimatedRotateY {
+			child,
+			angle0,
+			angle1,
+			times: (time0, time1),
+			bounds: BoundsCache::default(),
+		}
+	}
+
+	fn angle(&self, time: f64) -> f64 {
+		self.angle0 + progress(time, self.times) * (self.angle1 - self.angle0)
+	}
+
+	/// A box around the child's box at every angle it turns through between time0 and time1.
+	/// Each corner moves around a circle, so its furthest points along x and z are either where it
+	/// starts and stops or where it crosses one of the axes
+	fn swept_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		let bbox = self.child.bounding_box(time0, time1)?;
+		let (a0, a1) = (self.angle(time0), self.angle(time1));
+		let (a0, a1) = (a0.min(a1), a0.max(a1));
+		let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+		let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+		for corner in corners(bbox) {
+			let mut include = |angle: f64| {
+				let rotated = Rotation::new(angle).to_parent(corner);
+				min = min.min(rotated);
+				max = max.max(rotated);
+			};
+			include(a0);
+			include(a1);
+			// rotating by angle moves the corner to (its own angle - angle) around y, which is on
+			// an axis when that's a multiple of 90°
+			let own = corner.z().atan2(corner.x()).to_degrees();
+			let first = ((own - a1) / 90.0).ceil() as i64;
+			let last = ((own - a0) / 90.0).floor() as i64;
+			// more than a full turn already reaches every axis
+			for k in first..=last.min(first + 3) {
+				include(own - k as f64 * 90.0);
+			}
+		}
+		Some(Aabb::new(min, max))
+	}
+}
+
+impl Hittable for AnimatedRotateY {
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		self.bounds
+			.get(time0, time1, || self.swept_box(time0, time1))
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child
+			.uv_at(Rotation::new(self.angle(time)).to_child(p), time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(AnimatedRotateY::new(
+			child,
+			self.angle0,
+			self.angle1,
+			self.times.0,
+			self.times.1,
+		))
+	}
+
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		let rotation = Rotation::new(self.angle(r.time()));
+		self.child.occluded(rng, rotation.ray_to_child(r), ray_t)
+	}
+
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		let rotation = Rotation::new(self.angle(r.time()));
+		self.child
+			.hit(rng, rotation.ray_to_child(r), ray_t)
+			.map(|rec| rotation.hit_to_parent(rec))
+	}
+}
+
+/// Swaps which side of an object counts as its front, for one-sided lights facing the other way
+#[derive(Debug)]
+pub struct FlipFace {
+	child: Arc<dyn Hittable>,
+}
+
+impl FlipFace {
+	pub fn new(child: Arc<dyn Hittable>) -> FlipFace {
+		FlipFace { child }
+	}
+}
+
+impl Hittable for FlipFace {
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.child.occluded(rng, r, ray_t)
+	}
+
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		self.child.hit(rng, r, ray_t).map(|mut rec| {
+			rec.front_face = !rec.front_face;
+			rec
+		})
+	}
+
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		self.child.bounding_box(time0, time1)
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(p, time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(FlipFace::new(child))
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		self.child.pdf_value(origin, direction)
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		self.child.random(rng, origin)
+	}
+
+	fn light_power(&self) -> f64 {
+		self.child.light_power()
+	}
+}
+
+/// A name and number for an object, shown by --pick and used to color it in --debug-mode id
+#[derive(Debug, Clone)]
+pub struct Label {
+	pub id: u32,
+	pub name: String,
+}
+
+/// Attaches a label to an object and everything inside it that doesn't have its own
+#[derive(Debug)]
+pub struct Labeled {
+	label: Label,
+	child: Arc<dyn Hittable>,
+}
+
+impl Labeled {
+	pub fn new(id: u32, name: impl Into<String>, child: Arc<dyn Hittable>) -> Labeled {
+		Labeled {
+			label: Label {
+				id,
+				name: name.into(),
+			},
+			child,
+		}
+	}
+}
+
+impl Hittable for Labeled {
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.child.occluded(rng, r, ray_t)
+	}
+
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		self.child.hit(rng, r, ray_t).map(|mut rec| {
+			rec.label.get_or_insert(&self.label);
+			rec
+		})
+	}
+
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		self.child.bounding_box(time0, time1)
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(p, time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(Labeled {
+			label: self.label.clone(),
+			child,
+		})
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		self.child.pdf_value(origin, direction)
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		self.child.random(rng, origin)
+	}
+
+	fn light_power(&self) -> f64 {
+		self.child.light_power()
+	}
+}
+
+/// Which kinds of rays can hit an object, as a set of RayKinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility(u8);
+
+impl Visibility {
+	pub const CAMERA: Visibility = Visibility(1);
+	pub const SECONDARY: Visibility = Visibility(2);
+	pub const SHADOW: Visibility = Visibility(4);
+
+	fn of(kind: RayKind) -> Visibility {
+		match kind {
+			RayKind::Camera => Visibility::CAMERA,
+			RayKind::Secondary => Visibility::SECONDARY,
+			RayKind::Shadow => Visibility::SHADOW,
+		}
+	}
+
+	pub fn contains(self, other: Visibility) -> bool {
+		self.0 & other.0 == other.0
+	}
+
+	pub fn sees(self, kind: RayKind) -> bool {
+		self.contains(Visibility::of(kind))
+	}
+}
+
+impl std::ops::BitOr for Visibility {
+	type Output = Visibility;
+
+	fn bitor(self, rhs: Visibility) -> Visibility {
+		Visibility(self.0 | rhs.0)
+	}
+}
+
+/// Hides an object from some kinds of rays, like a light that shines on the scene without being
+/// seen by the camera, or an object that casts a shadow without showing up in reflections
+#[derive(Debug)]
+pub struct VisibleTo {
+	child: Arc<dyn Hittable>,
+	visibility: Visibility,
+}
+
+impl VisibleTo {
+	pub fn new(visibility: Visibility, child: Arc<dyn Hittable>) -> VisibleTo {
+		VisibleTo { child, visibility }
+	}
+}
+
+impl Hittable for VisibleTo {
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.visibility.sees(r.kind()) && self.child.occluded(rng, r, ray_t)
+	}
+
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		if !self.visibility.sees(r.kind()) {
+			return None;
+		}
+		self.child.hit(rng, r, ray_t)
+	}
+
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		self.child.bounding_box(time0, time1)
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(p, time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(VisibleTo {
+			child,
+			visibility: self.visibility,
+		})
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		self.child.pdf_value(origin, direction)
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		self.child.random(rng, origin)
+	}
+
+	fn light_power(&self) -> f64 {
+		// scattered rays aimed at it would go straight through
+		if self.visibility.sees(RayKind::Secondary) {
+			self.child.light_power()
+		} else {
+			0.0
+		}
+	}
+}
+
+/// Shows everything in an object except for lights with one material instead of their own, for
+/// --override-material. This swaps the material when something is hit, so the scene doesn't need
+/// to be built again
+#[derive(Debug)]
+pub struct MaterialOverride {
+	child: Arc<dyn Hittable>,
+	material: Arc<dyn Material>,
+}
+
+impl MaterialOverride {
+	pub fn new(child: Arc<dyn Hittable>, material: Arc<dyn Material>) -> MaterialOverride {
+		MaterialOverride { child, material }
+	}
+}
+
+impl Hittable for MaterialOverride {
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		// the materials that are replaced cast shadows already, apart from ones made for testing
+		self.child.occluded(rng, r, ray_t)
+	}
+
+	fn hit<'a>(&'a self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+		self.child.hit(rng, r, ray_t).map(|mut rec| {
+			// lights keep shining, so that the scene is lit the same way
+			if !rec.mat_ptr.is_emissive() {
+				rec.mat_ptr = self.material.as_ref();
+			}
+			rec
+		})
+	}
+
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		self.child.bounding_box(time0, time1)
+	}
+
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		self.child.uv_at(p, time)
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.child.is_emissive()
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		std::slice::from_ref(&self.child)
+	}
+
+	fn wrap(&self, child: Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+		Arc::new(MaterialOverride {
+			child,
+			material: self.material.clone(),
+		})
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		self.child.pdf_value(origin, direction)
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		self.child.random(rng, origin)
+	}
+
+	fn light_power(&self) -> f64 {
+		self.child.light_power()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::Color;
+	use crate::object::material::{Clay, DiffuseLight, Lambertian};
+	use crate::object::{Block, MovingSphere, XZRect};
+
+	/// a unit cube, rotated 90° and then translated so that it covers x in [10, 11], y in [0, 1],
+	/// and z in [9, 10]
+	fn transformed_block() -> Translate {
+		let block = Arc::new(Block::new(
+			Point3::zero(),
+			Point3::new(1.0, 1.0, 1.0),
+			Arc::new(Lambertian::with_color(Color::zero())),
+		));
+		Translate::new(
+			Arc::new(RotateY::new(block, 90.0)),
+			Vec3::new(10.0, 0.0, 10.0),
+		)
+	}
+
+	fn assert_hit(origin: Point3, direction: Vec3, front_face: bool, normal: Vec3) {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let block = transformed_block();
+		let r = Ray::new(origin, direction, 0.0, false);
+		let rec = block
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.expect("ray should hit the block");
+		assert_eq!(rec.front_face, front_face, "ray {} {}", origin, direction);
+		assert!(
+			(rec.normal - normal).length() < 1e-9,
+			"ray {} {}: normal {}",
+			origin,
+			direction,
+			rec.normal
+		);
+	}
+
+	#[test]
+	fn test_transformed_block_outside() {
+		let x = Vec3::new(1.0, 0.0, 0.0);
+		let y = Vec3::new(0.0, 1.0, 0.0);
+		let z = Vec3::new(0.0, 0.0, 1.0);
+		assert_hit(Point3::new(5.0, 0.5, 9.5), x, true, -x);
+		assert_hit(Point3::new(15.0, 0.5, 9.5), -x, true, x);
+		assert_hit(Point3::new(10.5, 5.0, 9.5), -y, true, y);
+		assert_hit(Point3::new(10.5, 0.5, 5.0), z, true, -z);
+		assert_hit(Point3::new(10.5, 0.5, 15.0), -z, true, z);
+	}
+
+	#[test]
+	fn test_transformed_block_inside() {
+		let x = Vec3::new(1.0, 0.0, 0.0);
+		let y = Vec3::new(0.0, 1.0, 0.0);
+		let z = Vec3::new(0.0, 0.0, 1.0);
+		let center = Point3::new(10.5, 0.5, 9.5);
+		// the normal always faces against the ray, so from inside it points inward
+		assert_hit(center, x, false, -x);
+		assert_hit(center, -x, false, x);
+		assert_hit(center, y, false, -y);
+		assert_hit(center, z, false, -z);
+		assert_hit(center, -z, false, z);
+	}
+
+	#[test]
+	fn test_cached_bounds() {
+		// a sphere moving from x = 0 at time 0 to x = 10 at time 1, so each interval has a
+		// different box
+		let sphere = Arc::new(MovingSphere::new(
+			Point3::zero(),
+			Point3::new(10.0, 0.0, 0.0),
+			0.0,
+			1.0,
+			1.0,
+			Arc::new(Lambertian::with_color(Color::zero())),
+		));
+		let moved = Translate::new(Arc::new(RotateY::new(sphere, 90.0)), Vec3::zero());
+
+		// more intervals than the cache holds, each asked for twice in a row and then again later
+		let intervals = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.0, 0.5)];
+		for _ in 0..2 {
+			for (time0, time1) in intervals {
+				// rotating by 90° turns x into -z
+				let min_z = -10.0 * time1 - 1.0;
+				for _ in 0..2 {
+					let bbox = moved.bounding_box(time0, time1).unwrap();
+					assert!((bbox.min().z() - min_z).abs() < 1e-9, "{:?}", bbox);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_animated_bounds() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let block: Arc<dyn Hittable> = Arc::new(Block::new(
+			Point3::new(1.0, 0.0, 0.0),
+			Point3::new(2.0, 1.0, 1.0),
+			Arc::new(Lambertian::with_color(Color::zero())),
+		));
+		let (angle0, angle1) = (-30.0, 420.0);
+		let (offset0, offset1) = (Vec3::zero(), Vec3::new(5.0, 2.0, 0.0));
+		let animated = AnimatedTranslate::new(
+			Arc::new(AnimatedRotateY::new(
+				block.clone(),
+				angle0,
+				angle1,
+				0.0,
+				1.0,
+			)),
+			offset0,
+			offset1,
+			0.0,
+			1.0,
+		);
+		// the same block standing still where the animated one is at time
+		let at = |time: f64| {
+			Translate::new(
+				Arc::new(RotateY::new(
+					block.clone(),
+					angle0 + time * (angle1 - angle0),
+				)),
+				offset0 + time * (offset1 - offset0),
+			)
+		};
+
+		for (time0, time1) in [(0.0, 1.0), (0.1, 0.15), (0.3, 0.5), (0.7, 0.7)] {
+			let bbox = animated.bounding_box(time0, time1).unwrap();
+			for i in 0..=20 {
+				let time = time0 + (time1 - time0) * i as f64 / 20.0;
+				let still_block = at(time);
+				let still = still_block.bounding_box(time, time).unwrap();
+				for axis in 0..3 {
+					assert!(
+						bbox.min()[axis] <= still.min()[axis] + 1e-9,
+						"{} {:?}",
+						time,
+						bbox
+					);
+					assert!(
+						bbox.max()[axis] >= still.max()[axis] - 1e-9,
+						"{} {:?}",
+						time,
+						bbox
+					);
+				}
+
+				// and rays at that time see it there
+				let center = (still.min() + still.max()) / 2.0;
+				let r = Ray::new(
+					center + Vec3::new(0.0, 5.0, 0.0),
+					Vec3::new(0.0, -1.0, 0.0),
+					time,
+					false,
+				);
+				let rec = animated
+					.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+					.unwrap();
+				let expected = still_block
+					.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+					.unwrap();
+				assert!((rec.p - expected.p).length() < 1e-9);
+			}
+		}
+
+		// more than a full turn reaches all the way around, and no further
+		let reach = 5.0f64.sqrt();
+		let bbox = animated.bounding_box(0.0, 1.0).unwrap();
+		assert!((bbox.min().x() + reach).abs() < 1e-9, "{:?}", bbox);
+		assert!((bbox.max().x() - reach - 5.0).abs() < 1e-9, "{:?}", bbox);
+		assert!((bbox.max().y() - 3.0).abs() < 1e-9, "{:?}", bbox);
+	}
+
+	#[test]
+	fn test_one_sided_light() {
+		let light = Arc::new(DiffuseLight::with_color(Color::new(1.0, 1.0, 1.0)).one_sided());
+		let rect = Arc::new(XZRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, light));
+		let flipped = FlipFace::new(rect.clone());
+		assert!(flipped.is_emissive());
+
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let emitted = |object: &dyn Hittable, rng: &mut Xoshiro256PlusPlus, from_y: f64| {
+			let r = Ray::new(
+				Point3::new(0.0, from_y, 0.0),
+				Vec3::new(0.0, -from_y, 0.0),
+				0.0,
+				false,
+			);
+			let rec = object
+				.hit(rng, r, Interval::new(0.001, f64::INFINITY))
+				.unwrap();
+			rec.mat_ptr.emitted(&rec).x()
+		};
+		// the rectangle faces up, so flipping it makes the light shine down
+		assert_eq!(emitted(rect.as_ref(), &mut rng, 1.0), 1.0);
+		assert_eq!(emitted(rect.as_ref(), &mut rng, -1.0), 0.0);
+		assert_eq!(emitted(&flipped, &mut rng, 1.0), 0.0);
+		assert_eq!(emitted(&flipped, &mut rng, -1.0), 1.0);
+	}
+
+	#[test]
+	fn test_innermost_label() {
+		let block = Arc::new(transformed_block());
+		let inner = Labeled::new(1, "inner", block.clone());
+		let outer = Labeled::new(2, "outer", Arc::new(inner));
+		let bare = Labeled::new(3, "bare", block);
+
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let r = Ray::new(
+			Point3::new(5.0, 0.5, 9.5),
+			Vec3::new(1.0, 0.0, 0.0),
+			0.0,
+			false,
+		);
+		let label = |object: &dyn Hittable, rng: &mut Xoshiro256PlusPlus| {
+			object
+				.hit(rng, r, Interval::new(0.001, f64::INFINITY))
+				.unwrap()
+				.label
+				.unwrap()
+				.id
+		};
+		assert_eq!(label(&outer, &mut rng), 1);
+		assert_eq!(label(&bare, &mut rng), 3);
+		assert!(transformed_block()
+			.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+			.unwrap()
+			.label
+			.is_none());
+	}
+
+	#[test]
+	fn test_material_override() {
+		let mut world = HittableList::new();
+		let gray = Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5)));
+		world.add(Arc::new(XZRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, gray)));
+		let light = Arc::new(DiffuseLight::with_color(Color::new(1.0, 1.0, 1.0)));
+		world.add(Arc::new(XZRect::new(-1.0, 1.0, -1.0, 1.0, 2.0, light)));
+		let clay = MaterialOverride::new(Arc::new(world), Arc::new(Clay::new(true)));
+
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let hit = |x: f64, up: bool, rng: &mut Xoshiro256PlusPlus| {
+			let direction = Vec3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+			// about 0.01 wide where it hits, which is 0.005 of the rectangles' width
+			let r = Ray::new(Point3::new(x, 1.0, 0.0), direction, 0.0, false).with_spread(0.01);
+			clay.hit(rng, r, Interval::new(0.001, f64::INFINITY))
+				.unwrap()
+		};
+		let attenuation = |rec: &HitRecord, rng: &mut Xoshiro256PlusPlus| {
+			let r = Ray::new(rec.p, Vec3::new(0.0, -1.0, 0.0), 0.0, false);
+			rec.mat_ptr.scatter(rng, &r, rec).unwrap().attenuation.x()
+		};
+
+		// the light is still a light, and the floor is clay, darkened right next to its edge
+		let rec = hit(0.0, true, &mut rng);
+		assert_eq!(rec.mat_ptr.emitted(&rec).x(), 1.0);
+		let rec = hit(0.0, false, &mut rng);
+		assert_eq!(rec.mat_ptr.to_string(), "Clay(edges)");
+		assert_eq!(attenuation(&rec, &mut rng), 0.7);
+		let rec = hit(-0.996, false, &mut rng);
+		assert!(attenuation(&rec, &mut rng) < 0.5 * 0.7);
+	}
+}
@@ -1,25 +1,127 @@
-use std::fmt::Debug;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::sync::Arc;
 
 use rand::{Rng, RngCore};
+use smallvec::SmallVec;
 
+use super::pdf::{CosinePdf, Pdf};
 use super::texture::SolidColor;
 use super::HitRecord;
 use super::Texture;
 use crate::common::{Color, Point3, Ray, Vec3};
 
+/// A parameter given to a material that doesn't make physical sense
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialError {
+	pub parameter: &'static str,
+	pub value: f64,
+	/// the values it can have, e.g. "positive"
+	pub expected: &'static str,
+}
+
+impl Display for MaterialError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} must be {}, not {}",
+			self.parameter, self.expected, self.value
+		)
+	}
+}
+
+impl Error for MaterialError {}
+
+/// value if it's finite and greater than 0
+pub(crate) fn check_positive(parameter: &'static str, value: f64) -> Result<f64, MaterialError> {
+	if value.is_finite() && value > 0.0 {
+		Ok(value)
+	} else {
+		Err(MaterialError {
+			parameter,
+			value,
+			expected: "positive and finite",
+		})
+	}
+}
+
 pub struct ScatterResult {
 	pub attenuation: Color,
 	pub scattered: Ray,
+	/// whether scattered is the only direction the material could have sent the ray, like a
+	/// perfect mirror or glass. light_response is zero everywhere for these, so lights aren't
+	/// sampled and the ray's color is passed straight through
+	pub specular: bool,
 }
 
-pub trait Material: Debug + Sync + Send {
+/// Display should give a short description of the material and its textures, for printing scenes
+pub trait Material: Debug + Display + Sync + Send {
 	fn scatter(&self, rng: &mut dyn RngCore, r_in: &Ray, rec: &HitRecord) -> Option<ScatterResult>;
-	fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
+	/// How much of the light arriving from direction (pointing away from the surface) is sent back
+	/// along r_in, including the cosine factor for surfaces. Used for lights that are sampled
+	/// directly instead of being hit by scattered rays. Materials that only scatter into specific
+	/// directions, like mirrors and glass, can't be lit this way and return zero.
+	fn light_response(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+		let _ = (r_in, rec, direction);
+		Color::zero()
+	}
+	/// Like scatter, for a path that is inside of the dielectric media in media. Materials that take
+	/// part in nested dielectrics read the index of refraction on the other side of the surface from
+	/// it, and update it when the path crosses the surface
+	fn scatter_in(
+		&self,
+		rng: &mut dyn RngCore,
+		r_in: &Ray,
+		rec: &HitRecord,
+		media: &mut MediumStack,
+	) -> Option<ScatterResult> {
+		let _ = media;
+		self.scatter(rng, r_in, rec)
+	}
+	fn emitted(&self, rec: &HitRecord) -> Color {
 		// mark as unused without underscores in the signature
-		(u, v, p);
+		let _ = rec;
+		Color::zero()
+	}
+	/// whether emitted can ever return something other than zero
+	fn is_emissive(&self) -> bool {
+		false
+	}
+	/// roughly what emitted returns on average, for deciding how often to aim rays at lights
+	fn average_emission(&self) -> Color {
 		Color::zero()
 	}
+	/// The distribution that scatter picks directions from, for materials that can have it mixed
+	/// with aiming at lights. light_response, divided by this distribution's value, must be the
+	/// attenuation for a ray scattered in any direction. None for materials that only scatter in
+	/// specific directions, or where that isn't worth it
+	fn scattering_pdf(&self, rec: &HitRecord) -> Option<Box<dyn Pdf>> {
+		let _ = rec;
+		None
+	}
+	/// Color of a perfectly diffuse surface, which is what photons from --caustics are collected
+	/// on. None for everything else, which the photons either pass through or are lost at
+	fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+		let _ = rec;
+		None
+	}
+	/// whether camera rays hitting this surface should only show the shadows cast onto it. see
+	/// ShadowCatcher
+	fn is_shadow_catcher(&self) -> bool {
+		false
+	}
+	/// whether this surface blocks shadow rays. materials that let light through without
+	/// scattering it can return false to stay out of shadow queries
+	fn casts_shadows(&self) -> bool {
+		true
+	}
+	/// How many times a path can go through this material without it counting towards the
+	/// path's depth, where glass is --glass-depth-bonus. Only glass gets any by default
+	fn depth_bonus(&self, glass: u32) -> u32 {
+		let _ = glass;
+		0
+	}
 }
 
 #[derive(Debug)]
@@ -39,6 +141,12 @@ impl Lambertian {
 	}
 }
 
+impl Display for Lambertian {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Lambertian(albedo={})", self.albedo)
+	}
+}
+
 impl Material for Lambertian {
 	fn scatter(&self, rng: &mut dyn RngCore, r_in: &Ray, rec: &HitRecord) -> Option<ScatterResult> {
 		let mut scatter_direction = rec.normal + Vec3::random_unit_vector(rng);
@@ -48,9 +156,105 @@ impl Material for Lambertian {
 
 		Some(ScatterResult {
 			scattered: Ray::new(rec.p, scatter_direction, r_in.time(), false),
-			attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+			attenuation: self
+				.albedo
+				.filtered_value(rec.u, rec.v, rec.p, rec.footprint),
+			specular: false,
 		})
 	}
+
+	fn light_response(&self, _r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+		let cosine = direction.dot(rec.normal);
+		if cosine <= 0.0 {
+			return Color::zero();
+		}
+		// the BRDF of a perfectly diffuse surface is albedo / π
+		self.albedo
+			.filtered_value(rec.u, rec.v, rec.p, rec.footprint)
+			* (cosine / PI)
+	}
+
+	fn scattering_pdf(&self, rec: &HitRecord) -> Option<Box<dyn Pdf>> {
+		Some(Box::new(CosinePdf::new(rec.normal)))
+	}
+
+	fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+		Some(
+			self.albedo
+				.filtered_value(rec.u, rec.v, rec.p, rec.footprint),
+		)
+	}
+}
+
+/// Plain gray diffuse material that --override-material puts in place of every other, to show the
+/// shapes in a scene without their colors. With edges, the edges of rectangles and blocks are
+/// drawn as dark lines, like a wireframe
+#[derive(Debug)]
+pub struct Clay {
+	diffuse: Lambertian,
+	edges: bool,
+}
+
+impl Clay {
+	pub fn new(edges: bool) -> Clay {
+		Clay {
+			diffuse: Lambertian::with_color(Color::new(0.7, 0.7, 0.7)),
+			edges,
+		}
+	}
+
+	/// How much of the color is left at a hit, from 0 on an edge to 1 a pixel or more away from
+	/// one
+	fn edge_factor(&self, rec: &HitRecord) -> f64 {
+		if !self.edges || !rec.has_edges {
+			return 1.0;
+		}
+		// how far u and v change across a pixel. rays without differentials only know the width
+		// along v, which is close enough
+		let footprint = rec.footprint;
+		let across = |dx: f64, dy: f64| match dx.abs().max(dy.abs()) {
+			d if d > 0.0 => d,
+			_ => footprint.uv,
+		};
+		let du = across(footprint.duv_dx.0, footprint.duv_dy.0);
+		let dv = across(footprint.duv_dx.1, footprint.duv_dy.1);
+		let pixels = f64::min(rec.u.min(1.0 - rec.u) / du, rec.v.min(1.0 - rec.v) / dv);
+		if pixels.is_nan() {
+			1.0
+		} else {
+			pixels.clamp(0.0, 1.0)
+		}
+	}
+}
+
+impl Display for Clay {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if self.edges {
+			write!(f, "Clay(edges)")
+		} else {
+			write!(f, "Clay")
+		}
+	}
+}
+
+impl Material for Clay {
+	fn scatter(&self, rng: &mut dyn RngCore, r_in: &Ray, rec: &HitRecord) -> Option<ScatterResult> {
+		let mut res = self.diffuse.scatter(rng, r_in, rec)?;
+		res.attenuation *= self.edge_factor(rec);
+		Some(res)
+	}
+
+	fn light_response(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+		self.diffuse.light_response(r_in, rec, direction) * self.edge_factor(rec)
+	}
+
+	fn scattering_pdf(&self, rec: &HitRecord) -> Option<Box<dyn Pdf>> {
+		self.diffuse.scattering_pdf(rec)
+	}
+
+	fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+		Some(self.diffuse.diffuse_albedo(rec)? * self.edge_factor(rec))
+	}
 }
 
 #[derive(Debug)]
@@ -60,8 +264,19 @@ pub struct Metal {
 }
 
 impl Metal {
+	/// albedo: fraction of light reflected at each wavelength
+	/// fuzz:   radius of the sphere that reflected directions are moved randomly within, from 0
+	///         for a perfect mirror to 1 for the roughest metal that still reflects most rays
+	///         away from the surface. clamped to that range, with NaN counting as 0
 	pub fn new(albedo: Arc<dyn Texture>, fuzz: f64) -> Metal {
-		Metal { albedo, fuzz }
+		Metal {
+			albedo,
+			fuzz: if fuzz.is_nan() {
+				0.0
+			} else {
+				fuzz.clamp(0.0, 1.0)
+			},
+		}
 	}
 
 	pub fn with_color(color: Color, fuzz: f64) -> Metal {
@@ -69,33 +284,208 @@ impl Metal {
 	}
 }
 
+impl Display for Metal {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Metal(fuzz={}, albedo={})", self.fuzz, self.albedo)
+	}
+}
+
+/// Fuzzed directions Metal::scatter tries before falling back to the mirror direction
+const FUZZ_ATTEMPTS: usize = 16;
+
 impl Material for Metal {
 	fn scatter(&self, rng: &mut dyn RngCore, r_in: &Ray, rec: &HitRecord) -> Option<ScatterResult> {
 		let reflected = r_in.direction().unit_vector().reflect(rec.normal);
-		let scattered = Ray::new(
-			rec.p,
-			reflected + self.fuzz * Vec3::random_in_unit_sphere(rng),
-			r_in.time(),
-			false,
-		);
-		if scattered.direction().dot(rec.normal) > 0.0 {
-			Some(ScatterResult {
-				attenuation: self.albedo.value(rec.u, rec.v, rec.p),
-				scattered,
-			})
+		// fuzz can push the direction into the surface or cancel it out. dropping those samples
+		// would lose the light they carry and make rough metal too dark, so pick another instead
+		let direction = (0..FUZZ_ATTEMPTS)
+			.map(|_| reflected + self.fuzz * Vec3::random_in_unit_sphere(rng))
+			.find(|direction| direction.dot(rec.normal) > 0.0 && !direction.near_zero())
+			.unwrap_or(reflected);
+		// only a ray skimming along the surface has nowhere to go
+		if direction.dot(rec.normal) <= 0.0 {
+			return None;
+		}
+		Some(ScatterResult {
+			attenuation: self
+				.albedo
+				.filtered_value(rec.u, rec.v, rec.p, rec.footprint),
+			scattered: Ray::new(rec.p, direction, r_in.time(), false),
+			specular: self.fuzz == 0.0,
+		})
+	}
+}
+
+/// A dielectric that a path can be inside of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+	/// address of the material, so that objects sharing a material count as the same medium
+	id: usize,
+	ir: f64,
+	priority: u32,
+}
+
+/// The dielectric media that a path is currently inside of. Where media overlap, the one with the
+/// highest priority fills the overlap and the surfaces of the others are ignored; between equal
+/// priorities, the one entered last wins. Outside of every medium is a vacuum
+#[derive(Debug, Clone, Default)]
+pub struct MediumStack {
+	media: SmallVec<[Medium; 4]>,
+}
+
+impl MediumStack {
+	/// the medium that the path is in, if any
+	fn current(media: impl Iterator<Item = Medium>) -> Option<Medium> {
+		media.max_by_key(|m| m.priority)
+	}
+
+	/// Index of refraction on the other side of a medium's surface, or None if the surface is
+	/// inside of a medium with a higher priority and should be passed straight through
+	/// entering: whether the path is going into the medium (hitting the front face)
+	pub fn outside(&self, medium: Medium, entering: bool) -> Option<f64> {
+		let top = MediumStack::current(self.media.iter().copied());
+		let outside = if entering {
+			if top.is_some_and(|m| m.priority > medium.priority) {
+				return None;
+			}
+			top
 		} else {
-			None
+			match self.find(medium) {
+				Some(_) if top.map(|m| m.id) != Some(medium.id) => return None,
+				Some(i) => MediumStack::current(
+					self.media
+						.iter()
+						.enumerate()
+						.filter(|&(j, _)| j != i)
+						.map(|(_, &m)| m),
+				),
+				// the path left a medium it never entered, like a camera inside of glass, so only
+				// the other side is known
+				None => top,
+			}
+		};
+		Some(outside.map_or(1.0, |m| m.ir))
+	}
+
+	/// record the path going through a medium's surface
+	pub fn cross(&mut self, medium: Medium, entering: bool) {
+		if entering {
+			self.media.push(medium);
+		} else if let Some(i) = self.find(medium) {
+			self.media.remove(i);
 		}
 	}
+
+	/// position of the most recently entered copy of medium
+	fn find(&self, medium: Medium) -> Option<usize> {
+		self.media.iter().rposition(|m| m.id == medium.id)
+	}
 }
 
+/// wavelengths in nanometers used to represent the red, green, and blue channels when dispersing
+/// light
+const CHANNEL_WAVELENGTHS: [f64; 3] = [610.0, 550.0, 465.0];
+
 #[derive(Debug)]
 pub struct Dielectric {
 	/// index of refraction
 	pub ir: f64,
+	/// Cauchy equation coefficients (A, B in nm²) for wavelength-dependent refraction, or None for
+	/// the same index of refraction at every wavelength
+	cauchy: Option<(f64, f64)>,
+	/// priority when overlapping other dielectrics, or None to ignore them and always be surrounded
+	/// by a vacuum. see MediumStack
+	priority: Option<u32>,
+	/// multiplied into light each time it refracts through the surface
+	tint: Color,
+	/// bounces through this that don't count towards a path's depth, or None for
+	/// --glass-depth-bonus
+	depth_bonus: Option<u32>,
 }
 
 impl Dielectric {
+	/// ir: index of refraction relative to the space outside of the object, like 1.5 for glass
+	///     in air. less than 1 for something less dense than what's around it, like an air
+	///     bubble in water. panics unless it's positive and finite
+	pub fn new(ir: f64) -> Dielectric {
+		Dielectric::try_new(ir).unwrap_or_else(|e| panic!("{}", e))
+	}
+
+	/// like new, but returning an error for an index of refraction that isn't positive and finite
+	pub fn try_new(ir: f64) -> Result<Dielectric, MaterialError> {
+		check_positive("index of refraction", ir).map(Dielectric::new_unchecked)
+	}
+
+	/// Like new, without checking the index of refraction. for the boundaries of volumes like
+	/// ConstantMedium, which are never shaded, so any material will do
+	pub fn new_unchecked(ir: f64) -> Dielectric {
+		Dielectric {
+			ir,
+			cauchy: None,
+			priority: None,
+			tint: Color::new(1.0, 1.0, 1.0),
+			depth_bonus: None,
+		}
+	}
+
+	/// ir:   index of refraction at the sodium d line (587.6nm)
+	/// abbe: Abbe number of the material; lower values disperse light more. ~60 for crown glass,
+	///       ~30 for flint glass
+	/// panics unless both are positive and finite
+	pub fn with_dispersion(ir: f64, abbe: f64) -> Dielectric {
+		if let Err(e) = check_positive("index of refraction", ir)
+			.and_then(|_| check_positive("Abbe number", abbe))
+		{
+			panic!("{}", e);
+		}
+		let (d, f, c): (f64, f64, f64) = (587.6, 486.1, 656.3);
+		// abbe = (n_d - 1) / (n_f - n_c) where n(λ) = A + B/λ²
+		let b = (ir - 1.0) / (abbe * (f.powi(-2) - c.powi(-2)));
+		let a = ir - b / (d * d);
+		Dielectric {
+			ir,
+			cauchy: Some((a, b)),
+			priority: None,
+			tint: Color::new(1.0, 1.0, 1.0),
+			depth_bonus: None,
+		}
+	}
+
+	/// The same material, but nested inside of or overlapping other dielectrics that have
+	/// priorities. Where two overlap, the one with the higher priority fills the overlap, so a
+	/// liquid can overlap the walls of its container to avoid gaps between them
+	pub fn with_priority(self, priority: u32) -> Dielectric {
+		Dielectric {
+			priority: Some(priority),
+			..self
+		}
+	}
+
+	/// The same material, but colored glass: light that refracts into or out of it is multiplied by
+	/// tint, so passing all the way through tints it twice. this ignores how far the light travels
+	/// inside, so thin and thick parts are the same color
+	pub fn with_tint(self, tint: Color) -> Dielectric {
+		Dielectric { tint, ..self }
+	}
+
+	/// The same material, but letting paths go through it bonus times without counting towards
+	/// their depth, instead of --glass-depth-bonus times. for panes and shells that take a lot
+	/// of bounces to see through
+	pub fn with_depth_bonus(self, bonus: u32) -> Dielectric {
+		Dielectric {
+			depth_bonus: Some(bonus),
+			..self
+		}
+	}
+
+	pub fn medium(&self) -> Option<Medium> {
+		self.priority.map(|priority| Medium {
+			id: self as *const Dielectric as usize,
+			ir: self.ir,
+			priority,
+		})
+	}
+
 	fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
 		let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
 		r0 = r0 * r0;
@@ -103,12 +493,77 @@ impl Dielectric {
 	}
 }
 
+/// only shows the options that were changed from Dielectric::new
+impl Display for Dielectric {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Dielectric(ir={}", self.ir)?;
+		if let Some((a, b)) = self.cauchy {
+			write!(f, ", cauchy=({}, {})", a, b)?;
+		}
+		if let Some(priority) = self.priority {
+			write!(f, ", priority={}", priority)?;
+		}
+		if self.tint.x() != 1.0 || self.tint.y() != 1.0 || self.tint.z() != 1.0 {
+			write!(f, ", tint={}", self.tint)?;
+		}
+		if let Some(bonus) = self.depth_bonus {
+			write!(f, ", depth_bonus={}", bonus)?;
+		}
+		write!(f, ")")
+	}
+}
+
 impl Material for Dielectric {
 	fn scatter(&self, rng: &mut dyn RngCore, r_in: &Ray, rec: &HitRecord) -> Option<ScatterResult> {
+		self.scatter_in(rng, r_in, rec, &mut MediumStack::default())
+	}
+
+	fn depth_bonus(&self, glass: u32) -> u32 {
+		self.depth_bonus.unwrap_or(glass)
+	}
+
+	fn scatter_in(
+		&self,
+		rng: &mut dyn RngCore,
+		r_in: &Ray,
+		rec: &HitRecord,
+		media: &mut MediumStack,
+	) -> Option<ScatterResult> {
+		let medium = self.medium();
+		let outside = match medium {
+			Some(medium) => match media.outside(medium, rec.front_face) {
+				Some(ir) => ir,
+				None => {
+					// the surface is inside of something with a higher priority, so it isn't
+					// really there
+					media.cross(medium, rec.front_face);
+					return Some(ScatterResult {
+						attenuation: Color::new(1.0, 1.0, 1.0),
+						scattered: Ray::new(rec.p, r_in.direction(), r_in.time(), false),
+						specular: true,
+					});
+				},
+			},
+			None => 1.0,
+		};
+
+		let (ir, mut attenuation) = if let Some((a, b)) = self.cauchy {
+			// only follow one channel's wavelength. the other two are dropped and this one is
+			// tripled so that the expected brightness stays the same
+			let channel = rng.gen_range(0..3);
+			let wavelength = CHANNEL_WAVELENGTHS[channel];
+			let mut attenuation = Color::zero();
+			attenuation[channel] = 3.0;
+			(a + b / (wavelength * wavelength), attenuation)
+		} else {
+			(self.ir, Color::new(1.0, 1.0, 1.0))
+		};
+
+		// other media use their index of refraction at the sodium d line, even with dispersion
 		let refraction_ratio = if rec.front_face {
-			1.0 / self.ir
+			outside / ir
 		} else {
-			self.ir
+			ir / outside
 		};
 
 		let unit_direction = r_in.direction().unit_vector();
@@ -121,12 +576,17 @@ impl Material for Dielectric {
 		{
 			unit_direction.reflect(rec.normal)
 		} else {
+			if let Some(medium) = medium {
+				media.cross(medium, rec.front_face);
+			}
+			attenuation *= self.tint;
 			unit_direction.refract(rec.normal, refraction_ratio)
 		};
 
 		Some(ScatterResult {
-			attenuation: Color::new(1.0, 1.0, 1.0),
+			attenuation,
 			scattered: Ray::new(rec.p, direction, r_in.time(), false),
+			specular: true,
 		})
 	}
 }
@@ -134,20 +594,41 @@ impl Material for Dielectric {
 #[derive(Debug)]
 pub struct DiffuseLight {
 	emit: Arc<dyn Texture>,
+	one_sided: bool,
 }
 
 impl DiffuseLight {
 	pub fn new(emit: Arc<dyn Texture>) -> DiffuseLight {
-		DiffuseLight { emit }
+		DiffuseLight {
+			emit,
+			one_sided: false,
+		}
 	}
 
 	pub fn with_color(color: Color) -> DiffuseLight {
+		DiffuseLight::new(Arc::new(SolidColor::new(color)))
+	}
+
+	/// The same light, but only emitting from the front of surfaces (the side their outward
+	/// normals point to). See FlipFace to change which side that is
+	pub fn one_sided(self) -> DiffuseLight {
 		DiffuseLight {
-			emit: Arc::new(SolidColor::new(color)),
+			one_sided: true,
+			..self
 		}
 	}
 }
 
+impl Display for DiffuseLight {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "DiffuseLight(emit={}", self.emit)?;
+		if self.one_sided {
+			write!(f, ", one_sided")?;
+		}
+		write!(f, ")")
+	}
+}
+
 impl Material for DiffuseLight {
 	fn scatter(
 		&self,
@@ -158,8 +639,20 @@ impl Material for DiffuseLight {
 		None
 	}
 
-	fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
-		self.emit.value(u, v, p)
+	fn emitted(&self, rec: &HitRecord) -> Color {
+		if self.one_sided && !rec.front_face {
+			return Color::zero();
+		}
+		self.emit.filtered_value(rec.u, rec.v, rec.p, rec.footprint)
+	}
+
+	fn is_emissive(&self) -> bool {
+		true
+	}
+
+	fn average_emission(&self) -> Color {
+		// exact for solid colors, which most lights are
+		self.emit.value(0.5, 0.5, Point3::zero())
 	}
 }
 
@@ -174,11 +667,259 @@ impl Isotropic {
 	}
 }
 
+impl Display for Isotropic {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Isotropic(albedo={})", self.albedo)
+	}
+}
+
 impl Material for Isotropic {
 	fn scatter(&self, rng: &mut dyn RngCore, r_in: &Ray, rec: &HitRecord) -> Option<ScatterResult> {
 		Some(ScatterResult {
 			attenuation: self.albedo.value(rec.u, rec.v, rec.p),
 			scattered: Ray::new(rec.p, Vec3::random_in_unit_sphere(rng), r_in.time(), false),
+			specular: false,
 		})
 	}
+
+	fn light_response(&self, _r_in: &Ray, rec: &HitRecord, _direction: Vec3) -> Color {
+		// light is scattered equally over the whole sphere of directions
+		self.albedo.value(rec.u, rec.v, rec.p) / (4.0 * PI)
+	}
+}
+
+/// Surface that is invisible except for the shadows cast onto it, for compositing renders onto
+/// photos. Camera rays see whatever is behind it, darkened by the fraction of light blocked, and
+/// the pixel's alpha is that fraction. Other rays pass straight through it.
+#[derive(Debug)]
+pub struct ShadowCatcher;
+
+impl Display for ShadowCatcher {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "ShadowCatcher")
+	}
+}
+
+impl Material for ShadowCatcher {
+	fn scatter(
+		&self,
+		_rng: &mut dyn RngCore,
+		_r_in: &Ray,
+		_rec: &HitRecord,
+	) -> Option<ScatterResult> {
+		// shading is handled by the renderer, since it depends on whether this is a camera ray
+		None
+	}
+
+	fn is_shadow_catcher(&self) -> bool {
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::Point3;
+	use crate::object::texture::{CheckerTexture, Footprint, StripeTexture};
+
+	#[test]
+	fn test_display() {
+		let checker = Lambertian::new(Arc::new(CheckerTexture::with_colors(
+			Color::new(0.2, 0.3, 0.1),
+			Color::new(0.9, 0.9, 0.9),
+		)));
+		assert_eq!(
+			checker.to_string(),
+			"Lambertian(albedo=Checker(SolidColor(0.2, 0.3, 0.1), SolidColor(0.9, 0.9, 0.9)))"
+		);
+		let flag = Metal::new(StripeTexture::<SolidColor>::rainbow_sphere(), 0.3);
+		assert_eq!(flag.to_string(), "Metal(fuzz=0.3, albedo=Stripe[6])");
+
+		assert_eq!(Dielectric::new(1.5).to_string(), "Dielectric(ir=1.5)");
+		let water = Dielectric::new(1.33)
+			.with_priority(1)
+			.with_tint(Color::new(0.8, 0.9, 1.0));
+		assert_eq!(
+			water.to_string(),
+			"Dielectric(ir=1.33, priority=1, tint=(0.8, 0.9, 1))"
+		);
+
+		let light = DiffuseLight::with_color(Color::new(4.0, 4.0, 4.0)).one_sided();
+		assert_eq!(
+			light.to_string(),
+			"DiffuseLight(emit=SolidColor(4, 4, 4), one_sided)"
+		);
+	}
+
+	#[test]
+	fn test_validation() {
+		let fuzz = |fuzz| Metal::with_color(Color::zero(), fuzz).fuzz;
+		assert_eq!(fuzz(0.3), 0.3);
+		assert_eq!(fuzz(1.0), 1.0);
+		assert_eq!(fuzz(5.0), 1.0);
+		assert_eq!(fuzz(-1.0), 0.0);
+		assert_eq!(fuzz(f64::NAN), 0.0);
+
+		assert_eq!(Dielectric::try_new(0.8).unwrap().ir, 0.8);
+		for ir in [0.0, -1.5, f64::NAN, f64::INFINITY] {
+			let e = Dielectric::try_new(ir).unwrap_err();
+			assert_eq!(e.parameter, "index of refraction");
+		}
+		assert_eq!(
+			Dielectric::try_new(0.0).unwrap_err().to_string(),
+			"index of refraction must be positive and finite, not 0"
+		);
+		// for scenes that need a placeholder
+		assert!(Dielectric::new_unchecked(f64::NAN).ir.is_nan());
+
+		let dispersion =
+			|ir, abbe| std::panic::catch_unwind(|| Dielectric::with_dispersion(ir, abbe)).is_ok();
+		assert!(dispersion(1.5, 40.0));
+		assert!(!dispersion(0.0, 40.0));
+		assert!(!dispersion(1.5, 0.0));
+	}
+
+	#[test]
+	fn test_dispersion_conserves_energy() {
+		let glass = Dielectric::with_dispersion(1.5, 20.0);
+		let rec = HitRecord {
+			p: Point3::zero(),
+			normal: Vec3::new(0.0, 1.0, 0.0),
+			mat_ptr: &glass,
+			t: 1.0,
+			u: 0.0,
+			v: 0.0,
+			has_edges: false,
+			front_face: true,
+			footprint: Footprint::default(),
+			label: None,
+		};
+		let r_in = Ray::new(
+			Point3::new(-1.0, 1.0, 0.0),
+			Vec3::new(1.0, -1.0, 0.0),
+			0.0,
+			false,
+		);
+
+		// like a white furnace: on average, every channel should make it through unchanged
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let n = 100_000;
+		let total: Color = (0..n)
+			.map(|_| glass.scatter(&mut rng, &r_in, &rec).unwrap().attenuation)
+			.sum();
+		let mean = total / n as f64;
+		for channel in 0..3 {
+			assert!(
+				(mean[channel] - 1.0).abs() < 0.02,
+				"mean attenuation {}",
+				mean
+			);
+		}
+	}
+
+	#[test]
+	fn test_fuzzy_metal_conserves_energy() {
+		let metal = Metal::with_color(Color::new(1.0, 1.0, 1.0), 1.0);
+		let rec = HitRecord {
+			p: Point3::zero(),
+			normal: Vec3::new(0.0, 1.0, 0.0),
+			mat_ptr: &metal,
+			t: 1.0,
+			u: 0.0,
+			v: 0.0,
+			has_edges: false,
+			front_face: true,
+			footprint: Footprint::default(),
+			label: None,
+		};
+
+		// like a white furnace: every sample should be reflected, even at grazing angles where
+		// fuzz sends a lot of directions into the surface
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let n = 10_000;
+		for degrees in [0.0, 45.0, 80.0, 89.0] {
+			let angle = f64::to_radians(degrees);
+			let r_in = Ray::new(
+				Point3::new(-angle.sin(), angle.cos(), 0.0),
+				Vec3::new(angle.sin(), -angle.cos(), 0.0),
+				0.0,
+				false,
+			);
+			// how often one fuzzed direction stays out of the surface, which is what was
+			// reflected when the others were dropped
+			let reflected = r_in.direction().reflect(rec.normal);
+			let kept = (0..n)
+				.filter(|_| (reflected + Vec3::random_in_unit_sphere(&mut rng)).y() > 0.0)
+				.count();
+			let mut total = Color::zero();
+			let mut forward = 0;
+			for _ in 0..n {
+				let res = metal.scatter(&mut rng, &r_in, &rec).unwrap();
+				let direction = res.scattered.direction();
+				assert!(
+					direction.y() > 0.0 && !direction.near_zero(),
+					"{}",
+					direction
+				);
+				total += res.attenuation;
+				if direction.x() > 0.0 {
+					forward += 1;
+				}
+			}
+			let mean = total / n as f64;
+			assert!((mean - Color::new(1.0, 1.0, 1.0)).near_zero(), "{}", mean);
+			// still spread around the mirror direction
+			assert!(
+				degrees == 0.0 || forward > n / 2,
+				"{}°: {}",
+				degrees,
+				forward
+			);
+			if degrees >= 80.0 {
+				assert!(kept < n * 9 / 10, "{}°: {}", degrees, kept);
+			}
+		}
+	}
+
+	#[test]
+	fn test_nested_media() {
+		// media are told apart by address, so the materials have to stay alive
+		let materials = [
+			Dielectric::new(1.5).with_priority(2),
+			Dielectric::new(1.33).with_priority(1),
+			Dielectric::new(1.0).with_priority(3),
+		];
+		let [glass, water, air] = materials.each_ref().map(|m| m.medium().unwrap());
+		assert_eq!(Dielectric::new(1.5).medium(), None);
+
+		// a path going through the wall of a glass that water overlaps, through a bubble in the
+		// water, and out through the other wall
+		let mut media = MediumStack::default();
+		let mut step = |medium, entering| {
+			let outside = media.outside(medium, entering);
+			media.cross(medium, entering);
+			outside
+		};
+		assert_eq!(step(glass, true), Some(1.0));
+		assert_eq!(step(water, true), None);
+		assert_eq!(step(glass, false), Some(1.33));
+		assert_eq!(step(air, true), Some(1.33));
+		assert_eq!(step(air, false), Some(1.33));
+		assert_eq!(step(glass, true), Some(1.33));
+		assert_eq!(step(water, false), None);
+		assert_eq!(step(glass, false), Some(1.0));
+		assert!(media.media.is_empty());
+
+		// two overlapping objects with the same material act like one
+		let mut media = MediumStack::default();
+		media.cross(glass, true);
+		assert_eq!(media.outside(glass, true), Some(1.5));
+		media.cross(glass, true);
+		assert_eq!(media.outside(glass, false), Some(1.5));
+		media.cross(glass, false);
+		assert_eq!(media.outside(glass, false), Some(1.0));
+	}
 }
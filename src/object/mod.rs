@@ -1,19 +1,27 @@
 mod aarect;
 mod block;
 mod constant_medium;
+mod displaced_sphere;
 mod hittable;
 pub mod material;
 mod moving_sphere;
+pub mod pdf;
 mod perlin;
 mod sphere;
 pub mod texture;
+mod worley;
 
 pub use aarect::{XYRect, XZRect, YZRect};
 pub use block::Block;
 pub use constant_medium::ConstantMedium;
-pub use hittable::{HitRecord, Hittable, RotateY, Translate};
+pub use displaced_sphere::DisplacedSphere;
+pub use hittable::{
+	AnimatedRotateY, AnimatedTranslate, FlipFace, HitRecord, Hittable, Labeled, MaterialOverride,
+	RotateY, Translate, Visibility, VisibleTo,
+};
 pub use material::Material;
 pub use moving_sphere::MovingSphere;
 pub use perlin::Perlin;
 pub use sphere::Sphere;
 pub use texture::Texture;
+pub use worley::Worley;
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use rand::RngCore;
 
 use super::{HitRecord, Hittable, Material, Sphere};
-use crate::common::{Point3, Ray, Vec3};
+use crate::common::{Interval, Point3, Ray, Vec3};
 use crate::scene::Aabb;
 
 #[derive(Debug)]
@@ -17,6 +17,8 @@ pub struct MovingSphere {
 }
 
 impl MovingSphere {
+	/// the sphere moves in a straight line from center0 at time0 to center1 at time1. if time0 and
+	/// time1 are the same, it stays at center0
 	pub fn new(
 		center0: Point3,
 		center1: Point3,
@@ -36,12 +38,23 @@ impl MovingSphere {
 	}
 
 	fn center(&self, time: f64) -> Point3 {
+		if self.time1 == self.time0 {
+			return self.center0;
+		}
 		self.center0
 			+ ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
 	}
 }
 
 impl Hittable for MovingSphere {
+	fn is_emissive(&self) -> bool {
+		self.mat_ptr.is_emissive()
+	}
+
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.mat_ptr)
+	}
+
 	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
 		let radius = self.radius.abs();
 		let radius_vec = Vec3::new(radius, radius, radius);
@@ -57,20 +70,47 @@ impl Hittable for MovingSphere {
 		))
 	}
 
-	fn hit<'a>(
-		&'a self,
-		_rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
+	fn uv_at(&self, p: Point3, time: f64) -> Option<(f64, f64)> {
+		Some(Sphere::get_sphere_uv((p - self.center(time)).unit_vector()))
+	}
+
+	fn occluded(&self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.mat_ptr.casts_shadows()
+			&& Sphere::intersect(self.center(r.time()), self.radius, r, ray_t).is_some()
+	}
+
+	fn hit<'a>(&'a self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
 		return Sphere::hit_implementation(
 			self.center(r.time()),
 			self.radius,
 			self.mat_ptr.as_ref(),
 			r,
-			t_min,
-			t_max,
+			ray_t,
 		);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::Color;
+	use crate::object::material::Lambertian;
+
+	#[test]
+	fn test_zero_length_motion() {
+		let sphere = MovingSphere::new(
+			Point3::new(1.0, 2.0, 3.0),
+			Point3::new(4.0, 5.0, 6.0),
+			0.5,
+			0.5,
+			1.0,
+			Arc::new(Lambertian::with_color(Color::zero())),
+		);
+		for time in [0.0, 0.5, 1.0] {
+			assert_eq!(sphere.center(time).x(), 1.0);
+		}
+		let bbox = sphere.bounding_box(0.0, 1.0).unwrap();
+		assert_eq!(bbox.min().x(), 0.0);
+		assert_eq!(bbox.max().z(), 4.0);
+	}
+}
@@ -0,0 +1,363 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::{Rng, RngCore};
+
+use super::Hittable;
+use crate::common::{OrthonormalBasis, Point3, Vec3};
+
+/// A distribution of directions to send rays in, for sending more of them where the light is
+pub trait Pdf {
+	/// probability density per steradian of generate picking direction, which is a unit vector
+	fn value(&self, direction: Vec3) -> f64;
+	/// a random unit vector
+	fn generate(&self, rng: &mut dyn RngCore) -> Vec3;
+}
+
+/// Directions around a surface normal, more of them the closer they are to it, which is how a
+/// diffuse surface scatters light
+#[derive(Debug, Clone, Copy)]
+pub struct CosinePdf {
+	normal: Vec3,
+}
+
+impl CosinePdf {
+	/// normal: unit vector
+	pub fn new(normal: Vec3) -> CosinePdf {
+		CosinePdf { normal }
+	}
+}
+
+impl Pdf for CosinePdf {
+	fn value(&self, direction: Vec3) -> f64 {
+		direction.dot(self.normal).max(0.0) / PI
+	}
+
+	fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+		// a random point on the unit sphere that touches the surface at the normal
+		let direction = self.normal + Vec3::random_unit_vector(rng);
+		if direction.near_zero() {
+			self.normal
+		} else {
+			direction.unit_vector()
+		}
+	}
+}
+
+/// Directions from a point towards one object
+#[derive(Debug, Clone, Copy)]
+pub struct HittablePdf<'a> {
+	object: &'a dyn Hittable,
+	origin: Point3,
+}
+
+impl HittablePdf<'_> {
+	pub fn new(object: &dyn Hittable, origin: Point3) -> HittablePdf<'_> {
+		HittablePdf { object, origin }
+	}
+}
+
+impl Pdf for HittablePdf<'_> {
+	fn value(&self, direction: Vec3) -> f64 {
+		self.object.pdf_value(self.origin, direction)
+	}
+
+	fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+		self.object.random(rng, self.origin)
+	}
+}
+
+/// Picks from one of two distributions at random
+#[derive(Clone, Copy)]
+pub struct MixturePdf<'a> {
+	a: &'a dyn Pdf,
+	b: &'a dyn Pdf,
+	/// chance of picking from a
+	weight: f64,
+}
+
+impl<'a> MixturePdf<'a> {
+	/// weight: chance of picking from a rather than b, from 0 to 1
+	pub fn new(a: &'a dyn Pdf, b: &'a dyn Pdf, weight: f64) -> MixturePdf<'a> {
+		MixturePdf { a, b, weight }
+	}
+}
+
+impl Pdf for MixturePdf<'_> {
+	fn value(&self, direction: Vec3) -> f64 {
+		self.weight * self.a.value(direction) + (1.0 - self.weight) * self.b.value(direction)
+	}
+
+	fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+		if rng.gen::<f64>() < self.weight {
+			self.a.generate(rng)
+		} else {
+			self.b.generate(rng)
+		}
+	}
+}
+
+/// Objects that give off light, for aiming scattered rays at
+#[derive(Debug, Clone, Default)]
+pub struct EmitterList {
+	objects: Vec<Arc<dyn Hittable>>,
+	/// chance of picking each object, adding up to 1
+	chances: Vec<f64>,
+	/// sum of the chances up to and including each object
+	cumulative: Vec<f64>,
+}
+
+impl EmitterList {
+	/// objects:  usually from HittableList::emitters. ones with no light_power can't be aimed at,
+	///           and are left out
+	/// by_power: pick each object with a chance in proportion to its light_power, so that big and
+	///           bright lights get more rays than small and dim ones, instead of all equally
+	pub fn new(objects: Vec<Arc<dyn Hittable>>, by_power: bool) -> EmitterList {
+		let (objects, weights): (Vec<_>, Vec<_>) = objects
+			.into_iter()
+			.filter_map(|o| {
+				let power = o.light_power();
+				(power > 0.0 && power.is_finite())
+					.then_some((o, if by_power { power } else { 1.0 }))
+			})
+			.unzip();
+		let total: f64 = weights.iter().sum();
+		let chances: Vec<f64> = weights.iter().map(|w| w / total).collect();
+		let cumulative = chances
+			.iter()
+			.scan(0.0, |sum, chance| {
+				*sum += chance;
+				Some(*sum)
+			})
+			.collect();
+		EmitterList {
+			objects,
+			chances,
+			cumulative,
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.objects.is_empty()
+	}
+
+	/// the object to aim at for a uniform random number u
+	fn pick(&self, u: f64) -> &dyn Hittable {
+		let i = self.cumulative.partition_point(|&c| c <= u);
+		self.objects[i.min(self.objects.len() - 1)].as_ref()
+	}
+}
+
+/// Directions from a point towards one of a list of objects, picked by their chances in the list
+#[derive(Debug, Clone, Copy)]
+pub struct HittableListPdf<'a> {
+	emitters: &'a EmitterList,
+	origin: Point3,
+}
+
+impl HittableListPdf<'_> {
+	/// emitters: must not be empty
+	pub fn new(emitters: &EmitterList, origin: Point3) -> HittableListPdf<'_> {
+		assert!(!emitters.is_empty(), "no objects to aim at");
+		HittableListPdf { emitters, origin }
+	}
+}
+
+impl Pdf for HittableListPdf<'_> {
+	fn value(&self, direction: Vec3) -> f64 {
+		self.emitters
+			.objects
+			.iter()
+			.zip(&self.emitters.chances)
+			.map(|(o, chance)| chance * HittablePdf::new(o.as_ref(), self.origin).value(direction))
+			.sum()
+	}
+
+	fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+		let object = self.emitters.pick(rng.gen());
+		HittablePdf::new(object, self.origin).generate(rng)
+	}
+}
+
+/// A direction in the cone around axis (a unit vector) with the given solid angle, spread evenly
+/// over it, along with the cosine and sine of its angle from the axis. A solid angle of 4π covers
+/// every direction
+pub(crate) fn sample_cone(rng: &mut dyn RngCore, axis: Vec3, solid_angle: f64) -> (Vec3, f64, f64) {
+	let (u, v) = (rng.gen::<f64>(), rng.gen::<f64>());
+	let one_minus_cos = u * solid_angle / (2.0 * PI);
+	let cos_theta = 1.0 - one_minus_cos;
+	let sin_theta = (one_minus_cos * (2.0 - one_minus_cos)).max(0.0).sqrt();
+	let phi = 2.0 * PI * v;
+
+	let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+	let direction = OrthonormalBasis::new(axis).to_world(local);
+	(direction, cos_theta, sin_theta)
+}
+
+/// Solid angle of the cone of directions from p that hit a sphere, or the whole sphere of
+/// directions from inside it
+pub(crate) fn sphere_solid_angle(center: Point3, radius: f64, p: Point3) -> f64 {
+	let d2 = (center - p).length_squared();
+	if d2 <= radius * radius {
+		return 4.0 * PI;
+	}
+	// 1 - cos of the cone's angle, written so that it doesn't round to 0 for tiny or faraway
+	// spheres
+	let sin2_max = radius * radius / d2;
+	2.0 * PI * sin2_max / (1.0 + (1.0 - sin2_max).max(0.0).sqrt())
+}
+
+/// A direction from p towards a sphere, spread evenly over the cone of directions that the sphere
+/// covers, along with the distance to the sphere's surface that way and the cone's solid angle.
+/// From inside the sphere, every direction is picked evenly instead
+pub(crate) fn sample_sphere(
+	rng: &mut dyn RngCore,
+	center: Point3,
+	radius: f64,
+	p: Point3,
+) -> (Vec3, f64, f64) {
+	let solid_angle = sphere_solid_angle(center, radius, p);
+	let to_center = center - p;
+	let d2 = to_center.length_squared();
+	if d2 <= radius * radius {
+		let direction = Vec3::random_unit_vector(rng);
+		// where p + t * direction leaves the sphere
+		let b = direction.dot(-to_center);
+		let t = -b + (b * b - (d2 - radius * radius)).max(0.0).sqrt();
+		return (direction, t, solid_angle);
+	}
+
+	let d = d2.sqrt();
+	let (direction, cos_theta, sin_theta) = sample_cone(rng, to_center / d, solid_angle);
+	// the nearer of the two places the direction meets the sphere
+	let distance = d * cos_theta
+		- (radius * radius - d2 * sin_theta * sin_theta)
+			.max(0.0)
+			.sqrt();
+	(direction, distance, solid_angle)
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::Color;
+	use crate::object::material::{DiffuseLight, Lambertian, Material};
+	use crate::object::{RotateY, Sphere, Translate, XZRect};
+
+	fn light(brightness: f64) -> Arc<dyn Material> {
+		Arc::new(DiffuseLight::with_color(Color::new(
+			brightness, brightness, brightness,
+		)))
+	}
+
+	/// Check that pdf's values integrate to 1 over the sphere of directions, and that the
+	/// fraction of the directions it generates that are in region matches its values there
+	fn check_consistent(pdf: &dyn Pdf, region: impl Fn(Vec3) -> bool) {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let n = 200_000;
+		let (mut integral, mut in_region) = (0.0, 0.0);
+		for _ in 0..n {
+			let direction = Vec3::random_unit_vector(&mut rng);
+			let value = pdf.value(direction) * 4.0 * PI / n as f64;
+			integral += value;
+			if region(direction) {
+				in_region += value;
+			}
+		}
+		assert!((integral - 1.0).abs() < 0.02, "integral {}", integral);
+
+		let generated = (0..n)
+			.map(|_| pdf.generate(&mut rng))
+			.inspect(|d| assert!((d.length() - 1.0).abs() < 1e-9))
+			.filter(|&d| region(d))
+			.count() as f64
+			/ n as f64;
+		assert!(
+			(generated - in_region).abs() < 0.02,
+			"{} generated in the region, but the values there add up to {}",
+			generated,
+			in_region
+		);
+		assert!(
+			in_region > 0.1 && in_region < 0.9,
+			"region covers {}",
+			in_region
+		);
+	}
+
+	#[test]
+	fn test_consistent() {
+		let normal = Vec3::new(1.0, 2.0, 2.0).unit_vector();
+		let cosine = CosinePdf::new(normal);
+		check_consistent(&cosine, |d| d.x() > 0.3);
+
+		let origin = Point3::new(0.5, -1.0, 0.0);
+		let sphere = Sphere::new(Point3::new(1.0, 1.0, 0.5), 1.0, light(1.0));
+		let towards_sphere = HittablePdf::new(&sphere, origin);
+		check_consistent(&towards_sphere, |d| d.x() > 0.25);
+		// from inside, it's every direction
+		let inside = HittablePdf::new(&sphere, Point3::new(1.2, 0.8, 0.5));
+		check_consistent(&inside, |d| d.x() > 0.0);
+
+		// a rect that is rotated and moved, so the transformations have to be undone both ways
+		let rect: Arc<dyn Hittable> = Arc::new(XZRect::new(-1.0, 1.0, -0.5, 0.5, 0.0, light(1.0)));
+		let rect = Translate::new(Arc::new(RotateY::new(rect, 30.0)), Vec3::new(0.0, 1.0, 0.0));
+		let towards_rect = HittablePdf::new(&rect, origin);
+		check_consistent(&towards_rect, |d| d.x() > 0.0);
+
+		let mixture = MixturePdf::new(&towards_sphere, &cosine, 0.3);
+		check_consistent(&mixture, |d| d.x() > 0.3);
+		let direction = Vec3::new(0.3, 0.9, 0.2).unit_vector();
+		assert!(
+			(mixture.value(direction)
+				- (0.3 * towards_sphere.value(direction) + 0.7 * cosine.value(direction)))
+			.abs() < 1e-12
+		);
+		assert!(towards_sphere.value(direction) > 0.0 && cosine.value(direction) > 0.0);
+
+		let emitters = EmitterList::new(vec![Arc::new(sphere), Arc::new(rect)], true);
+		check_consistent(&HittableListPdf::new(&emitters, origin), |d| d.x() > 0.4);
+	}
+
+	#[test]
+	fn test_emitter_chances() {
+		let objects: Vec<Arc<dyn Hittable>> = vec![
+			// 4π, 16π, and 4π times as bright
+			Arc::new(Sphere::new(Point3::zero(), 1.0, light(1.0))),
+			Arc::new(Sphere::new(Point3::zero(), 2.0, light(1.0))),
+			Arc::new(Sphere::new(Point3::zero(), 1.0, light(4.0))),
+			// can't be aimed at
+			Arc::new(Sphere::new(
+				Point3::zero(),
+				1.0,
+				Arc::new(Lambertian::with_color(Color::new(1.0, 1.0, 1.0))),
+			)),
+		];
+		let by_power = EmitterList::new(objects.clone(), true);
+		let close = |a: &[f64], b: &[f64]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12);
+		assert!(close(&by_power.chances, &[1.0 / 9.0, 4.0 / 9.0, 4.0 / 9.0]));
+		assert!((by_power.cumulative[2] - 1.0).abs() < 1e-12);
+		let uniform = EmitterList::new(objects, false);
+		assert!(close(&uniform.chances, &[1.0 / 3.0; 3]));
+
+		// each one is picked as often as its chance says
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut counts = [0; 3];
+		for _ in 0..90_000 {
+			let picked = by_power.pick(rng.gen());
+			let i = by_power
+				.objects
+				.iter()
+				.position(|o| std::ptr::addr_eq(o.as_ref(), picked))
+				.unwrap();
+			counts[i] += 1;
+		}
+		assert!((counts[0] as f64 - 10_000.0).abs() < 500.0, "{:?}", counts);
+		assert!((counts[1] as f64 - 40_000.0).abs() < 1000.0, "{:?}", counts);
+
+		assert!(EmitterList::new(Vec::new(), true).is_empty());
+	}
+}
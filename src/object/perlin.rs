@@ -65,7 +65,7 @@ impl Perlin {
 		acc.abs()
 	}
 
-	fn generate_perm<R: Rng + ?Sized>(rng: &mut R) -> Vec<usize> {
+	pub(super) fn generate_perm<R: Rng + ?Sized>(rng: &mut R) -> Vec<usize> {
 		let mut p = vec![0usize; POINT_COUNT];
 		for i in 0..POINT_COUNT {
 			p[i] = i;
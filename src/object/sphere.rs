@@ -3,8 +3,10 @@ use std::sync::Arc;
 
 use rand::RngCore;
 
+use super::pdf::{sample_sphere, sphere_solid_angle};
+use super::texture::Footprint;
 use super::{HitRecord, Hittable, Material};
-use crate::common::{Point3, Ray, Vec3};
+use crate::common::{Interval, Point3, Ray, Vec3};
 use crate::scene::Aabb;
 
 #[derive(Debug)]
@@ -23,6 +25,35 @@ impl Sphere {
 		(u, v)
 	}
 
+	/// The footprint of a ray's pixel where it hits a sphere at p, which has texture coordinates uv
+	pub(super) fn footprint(
+		r: Ray,
+		t: f64,
+		p: Point3,
+		center: Point3,
+		radius: f64,
+		(u, v): (f64, f64),
+	) -> Footprint {
+		let outward_normal = (p - center) / radius;
+		let Some((dp_dx, dp_dy)) = r.hit_differentials(p, outward_normal) else {
+			return Footprint::new(r.width_at(t), PI * radius);
+		};
+		// the change in uv to where the neighboring rays hit the tangent plane, projected back
+		// onto the sphere
+		let duv = |dp: Vec3| {
+			let (u1, v1) = Sphere::get_sphere_uv((p + dp - center).unit_vector());
+			let mut du = u1 - u;
+			// across the seam, u goes the short way around
+			if du > 0.5 {
+				du -= 1.0;
+			} else if du < -0.5 {
+				du += 1.0;
+			}
+			(du, v1 - v)
+		};
+		Footprint::from_differentials((dp_dx, dp_dy), duv(dp_dx), duv(dp_dy))
+	}
+
 	pub fn new(center: Point3, radius: f64, mat_ptr: Arc<dyn Material>) -> Self {
 		Self {
 			center,
@@ -31,33 +62,50 @@ impl Sphere {
 		}
 	}
 
-	pub fn hit_implementation<'a>(
-		center: Point3,
-		radius: f64,
-		mat_ptr: &'a dyn Material,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
+	/// the t of the closest place in ray_t where r hits a sphere, if there is one
+	pub fn intersect(center: Point3, radius: f64, r: Ray, ray_t: Interval) -> Option<f64> {
+		let (near, far) = Sphere::roots(center, radius, r)?;
+		let mut root = near;
+		if !ray_t.contains(root) {
+			root = far;
+			if !ray_t.contains(root) {
+				return None;
+			}
+		}
+
+		Some(root)
+	}
+
+	/// the t of both places where r's line crosses a sphere, nearest first, if it does
+	pub fn roots(center: Point3, radius: f64, r: Ray) -> Option<(f64, f64)> {
 		let oc = r.origin() - center;
 		let a = r.direction().length_squared();
 		let half_b = Vec3::dot(oc, r.direction());
 		let c = oc.length_squared() - radius * radius;
 
-		let discriminant = half_b * half_b - a * c;
+		// half_b² - ac loses most of its precision when the ray passes far from a large sphere, so
+		// compute it from the part of oc perpendicular to the ray instead (PBRT 4th ed., 6.8.1)
+		let rejection = oc - (half_b / a) * r.direction();
+		let discriminant = a * (radius * radius - rejection.length_squared());
 		if discriminant < 0.0 {
 			return None;
 		}
 		let sqrtd = discriminant.sqrt();
 
-		let mut root = (-half_b - sqrtd) / a;
-		if root < t_min || t_max < root {
-			root = (-half_b + sqrtd) / a;
-			if root < t_min || t_max < root {
-				return None;
-			}
-		}
+		// avoid subtracting two nearly-equal numbers for whichever root that would happen to
+		let q = -half_b - sqrtd.copysign(half_b);
+		let (t0, t1) = (q / a, c / q);
+		Some(if t0 < t1 { (t0, t1) } else { (t1, t0) })
+	}
 
+	pub fn hit_implementation<'a>(
+		center: Point3,
+		radius: f64,
+		mat_ptr: &'a dyn Material,
+		r: Ray,
+		ray_t: Interval,
+	) -> Option<HitRecord<'a>> {
+		let root = Sphere::intersect(center, radius, r, ray_t)?;
 		let p = r.at(root);
 		let outward_normal = (p - center) / radius;
 		let (u, v) = Sphere::get_sphere_uv(outward_normal);
@@ -66,9 +114,12 @@ impl Sphere {
 			p,
 			normal: Vec3::zero(),
 			front_face: false,
+			footprint: Sphere::footprint(r, root, p, center, radius, (u, v)),
+			label: None,
 			mat_ptr,
 			u,
 			v,
+			has_edges: false,
 		};
 		hr.set_face_normal(r, outward_normal);
 		Some(hr)
@@ -76,23 +127,54 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-	fn hit<'a>(
-		&'a self,
-		_rng: &mut dyn RngCore,
-		r: Ray,
-		t_min: f64,
-		t_max: f64,
-	) -> Option<HitRecord<'a>> {
+	fn hit<'a>(&'a self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
 		return Sphere::hit_implementation(
 			self.center,
 			self.radius,
 			self.mat_ptr.as_ref(),
 			r,
-			t_min,
-			t_max,
+			ray_t,
 		);
 	}
 
+	fn occluded(&self, _rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.mat_ptr.casts_shadows()
+			&& Sphere::intersect(self.center, self.radius, r, ray_t).is_some()
+	}
+
+	fn uv_at(&self, p: Point3, _time: f64) -> Option<(f64, f64)> {
+		Some(Sphere::get_sphere_uv((p - self.center).unit_vector()))
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.mat_ptr.is_emissive()
+	}
+
+	fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+		let r = Ray::new(origin, direction, 0.0, false);
+		match Sphere::intersect(
+			self.center,
+			self.radius,
+			r,
+			Interval::new(0.001, f64::INFINITY),
+		) {
+			Some(_) => 1.0 / sphere_solid_angle(self.center, self.radius.abs(), origin),
+			None => 0.0,
+		}
+	}
+
+	fn random(&self, rng: &mut dyn RngCore, origin: Point3) -> Vec3 {
+		sample_sphere(rng, self.center, self.radius.abs(), origin).0
+	}
+
+	fn light_power(&self) -> f64 {
+		4.0 * PI * self.radius * self.radius * self.mat_ptr.average_emission().luminance()
+	}
+
+	fn material(&self) -> Option<&Arc<dyn Material>> {
+		Some(&self.mat_ptr)
+	}
+
 	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
 		let radius = self.radius.abs();
 		Some(Aabb::new(
@@ -101,3 +183,72 @@ impl Hittable for Sphere {
 		))
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::{Color, Differentials};
+	use crate::object::material::Lambertian;
+
+	#[test]
+	fn test_grazing_hit_on_large_sphere() {
+		let mat = Lambertian::with_color(Color::zero());
+		let center = Point3::new(0.0, -1000.0, 0.0);
+		let mut hits = 0;
+		for i in 1..100 {
+			// nearly horizontal rays coming in from far away, like the camera in the weekend scene
+			// looking at the ground near the horizon
+			let slope = 0.02 + i as f64 * 1e-3;
+			let r = Ray::new(
+				Point3::new(50.0, 2.0, 3.0),
+				Vec3::new(-1.0, -slope, -0.1),
+				0.0,
+				false,
+			);
+			if let Some(rec) = Sphere::hit_implementation(
+				center,
+				1000.0,
+				&mat,
+				r,
+				Interval::new(0.001, f64::INFINITY),
+			) {
+				let error = ((rec.p - center).length() - 1000.0).abs();
+				assert!(error < 1e-9, "hit point is {} away from the surface", error);
+				hits += 1;
+			}
+		}
+		assert!(hits > 0);
+	}
+	#[test]
+	fn test_footprint_across_seam() {
+		let mat = Lambertian::with_color(Color::zero());
+		// u wraps around from 1 to 0 where the sphere crosses -x
+		let r = Ray::new(
+			Point3::new(-5.0, 0.0, 0.0),
+			Vec3::new(1.0, 0.0, 0.0),
+			0.0,
+			false,
+		)
+		.with_differentials(Some(Differentials {
+			dx_direction: Vec3::new(0.0, 0.0, 0.01),
+			dy_direction: Vec3::new(0.0, 0.01, 0.0),
+			..Differentials::default()
+		}));
+		let rec = Sphere::hit_implementation(
+			Point3::zero(),
+			1.0,
+			&mat,
+			r,
+			Interval::new(0.001, f64::INFINITY),
+		)
+		.unwrap();
+		// 0.04 around a circle 2π long, and 0.04 from pole to pole π away
+		let (du, dv) = rec.footprint.duv_dx;
+		assert!((du.abs() - 0.04 / (2.0 * PI)).abs() < 1e-4, "du {}", du);
+		assert!(dv.abs() < 1e-9);
+		let (du, dv) = rec.footprint.duv_dy;
+		assert!(du.abs() < 1e-9);
+		assert!((dv.abs() - 0.04 / PI).abs() < 1e-4, "dv {}", dv);
+		assert!((rec.footprint.world - 0.04).abs() < 1e-9);
+	}
+}
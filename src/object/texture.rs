@@ -1,5 +1,6 @@
 use std::f64::consts::PI;
-use std::fmt::{self, Debug, Formatter};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::{Add, Mul};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -7,16 +8,70 @@ use image::{DynamicImage, ImageResult};
 use once_cell::sync::OnceCell;
 use rand::Rng;
 
-use super::Perlin;
-use crate::common::{Color, Point3};
+use super::{Perlin, Worley};
+use crate::common::{Color, Point3, Vec3};
+
+/// How much of a surface one sample covers, so that textures with hard edges can blend across
+/// them instead of aliasing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Footprint {
+	/// width in world units
+	pub world: f64,
+	/// width in texture coordinates, along v
+	pub uv: f64,
+	/// change in (u, v) from this pixel to the next one to the right and the next one up, from
+	/// ray differentials. zero if the ray didn't have them
+	pub duv_dx: (f64, f64),
+	pub duv_dy: (f64, f64),
+}
+
+impl Footprint {
+	/// width:    in world units
+	/// v_length: distance along the surface from v = 0 to v = 1
+	pub fn new(width: f64, v_length: f64) -> Footprint {
+		Footprint {
+			world: width,
+			uv: width / v_length,
+			..Footprint::default()
+		}
+	}
 
-pub trait Texture: Debug + Sync + Send {
+	/// The footprint of a pixel from ray differentials, which unlike new() gets wider where the
+	/// surface is seen at an angle
+	/// dp_dx, dp_dy: how far the hit point moves to the next pixel to the right and the next one up
+	pub fn from_differentials(
+		(dp_dx, dp_dy): (Vec3, Vec3),
+		duv_dx: (f64, f64),
+		duv_dy: (f64, f64),
+	) -> Footprint {
+		Footprint {
+			world: dp_dx.length().max(dp_dy.length()),
+			uv: duv_dx.1.abs().max(duv_dy.1.abs()),
+			duv_dx,
+			duv_dy,
+		}
+	}
+}
+
+/// Display should give a short description of the texture and what it's made of, for printing
+/// scenes
+pub trait Texture: Debug + Display + Sync + Send {
 	fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+
+	/// Average value over the footprint around a point. Textures without hard edges can just use
+	/// the value at the point
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		let _ = footprint;
+		self.value(u, v, p)
+	}
 }
 
+/// a function applied to the colors of a texture
+pub type ColorFn = Arc<dyn Fn(Color) -> Color + Send + Sync>;
+
 pub trait Mappable: Texture {
 	type Mapped: Texture;
-	fn map(&self, f: &(dyn Fn(Color) -> Color + Send + Sync)) -> Self::Mapped;
+	fn map(&self, f: ColorFn) -> Self::Mapped;
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -30,6 +85,12 @@ impl SolidColor {
 	}
 }
 
+impl Display for SolidColor {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "SolidColor{}", self.color_value)
+	}
+}
+
 impl Texture for SolidColor {
 	fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
 		self.color_value
@@ -39,7 +100,7 @@ impl Texture for SolidColor {
 impl Mappable for SolidColor {
 	type Mapped = SolidColor;
 
-	fn map(&self, f: &(dyn Fn(Color) -> Color + Send + Sync)) -> SolidColor {
+	fn map(&self, f: ColorFn) -> SolidColor {
 		SolidColor {
 			color_value: f(self.color_value),
 		}
@@ -64,6 +125,26 @@ impl CheckerTexture<SolidColor, SolidColor> {
 	}
 }
 
+/// sign(sin(x)) averaged over [x - width / 2, x + width / 2]
+fn filtered_square_wave(x: f64, width: f64) -> f64 {
+	// the integral of sign(sin(x)) is a triangle wave
+	let integral = |x: f64| {
+		let x = x.rem_euclid(2.0 * PI);
+		if x < PI {
+			x
+		} else {
+			2.0 * PI - x
+		}
+	};
+	(integral(x + width / 2.0) - integral(x - width / 2.0)) / width
+}
+
+impl<Odd: Texture, Even: Texture> Display for CheckerTexture<Odd, Even> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Checker({}, {})", self.odd, self.even)
+	}
+}
+
 impl<Odd: Texture, Even: Texture> Texture for CheckerTexture<Odd, Even> {
 	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
 		let sines = f64::sin(10.0 * p.x()) * f64::sin(10.0 * p.y()) * f64::sin(10.0 * p.z());
@@ -73,14 +154,38 @@ impl<Odd: Texture, Even: Texture> Texture for CheckerTexture<Odd, Even> {
 			self.even.value(u, v, p)
 		}
 	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		if footprint.world <= 0.0 {
+			return self.value(u, v, p);
+		}
+		// the sign of the product is the product of the signs, so averaging over a box around p
+		// can be done one axis at a time. 1 means all even, -1 all odd
+		let width = 10.0 * footprint.world;
+		let even = filtered_square_wave(10.0 * p.x(), width)
+			* filtered_square_wave(10.0 * p.y(), width)
+			* filtered_square_wave(10.0 * p.z(), width);
+		let odd_weight = (1.0 - even) / 2.0;
+		let odd = if odd_weight > 0.0 {
+			self.odd.filtered_value(u, v, p, footprint)
+		} else {
+			Color::zero()
+		};
+		let even = if odd_weight < 1.0 {
+			self.even.filtered_value(u, v, p, footprint)
+		} else {
+			Color::zero()
+		};
+		odd * odd_weight + even * (1.0 - odd_weight)
+	}
 }
 
 impl<Odd: Mappable, Even: Mappable> Mappable for CheckerTexture<Odd, Even> {
 	type Mapped = CheckerTexture<Odd::Mapped, Even::Mapped>;
 
-	fn map(&self, f: &(dyn Fn(Color) -> Color + Send + Sync)) -> Self::Mapped {
+	fn map(&self, f: ColorFn) -> Self::Mapped {
 		CheckerTexture {
-			odd: self.odd.map(f),
+			odd: self.odd.map(f.clone()),
 			even: self.even.map(f),
 		}
 	}
@@ -166,24 +271,66 @@ impl<T: Texture> StripeTexture<T> {
 	}
 }
 
-impl<T: Texture> Texture for StripeTexture<T> {
-	fn value(&self, u: f64, mut v: f64, p: Point3) -> Color {
-		v = 1.0 - v;
+impl<T: Texture> StripeTexture<T> {
+	/// position across the stripes, from 0 at the first to 1 at the end of the last
+	fn position(&self, v: f64) -> f64 {
+		let v = 1.0 - v;
 		if self.sphere_adjust {
-			v = (1.0 - f64::cos(PI * v)) / 2.0;
+			(1.0 - f64::cos(PI * v)) / 2.0
+		} else {
+			v
 		}
+	}
+}
+
+/// just the number of stripes, since flags have too many colors to read
+impl<T: Texture> Display for StripeTexture<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Stripe[{}]", self.stripes.len())
+	}
+}
 
+impl<T: Texture> Texture for StripeTexture<T> {
+	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+		let v = self.position(v);
 		let index = ((v * self.stripes.len() as f64) as usize).clamp(0, self.stripes.len() - 1);
 		self.stripes[index].value(u, v, p)
 	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		let position = self.position(v);
+		let mut width = footprint.uv;
+		if self.sphere_adjust {
+			// derivative of position() with respect to v
+			width *= PI / 2.0 * f64::sin(PI * (1.0 - v));
+		}
+
+		// the footprint in units of stripes, clipped to the ends
+		let n = self.stripes.len() as f64;
+		let start = ((position - width / 2.0) * n).max(0.0);
+		let end = ((position + width / 2.0) * n).min(n);
+		if end - start <= 0.0 {
+			return self.value(u, v, p);
+		}
+
+		// weight each stripe by how much of the footprint it covers
+		let mut sum = Color::zero();
+		for i in (start as usize)..(end.ceil() as usize) {
+			let overlap = end.min(i as f64 + 1.0) - start.max(i as f64);
+			if overlap > 0.0 {
+				sum += self.stripes[i].filtered_value(u, position, p, footprint) * overlap;
+			}
+		}
+		sum / (end - start)
+	}
 }
 
 impl<T: Mappable> Mappable for StripeTexture<T> {
 	type Mapped = StripeTexture<T::Mapped>;
 
-	fn map(&self, f: &(dyn Fn(Color) -> Color + Send + Sync)) -> Self::Mapped {
+	fn map(&self, f: ColorFn) -> Self::Mapped {
 		StripeTexture {
-			stripes: Vec::from_iter(self.stripes.iter().map(|s| s.map(f))),
+			stripes: Vec::from_iter(self.stripes.iter().map(|s| s.map(f.clone()))),
 			sphere_adjust: self.sphere_adjust,
 		}
 	}
@@ -216,6 +363,16 @@ impl<Low: Texture, High: Texture> NoiseTexture<Low, High> {
 	}
 }
 
+impl<Low: Texture, High: Texture> Display for NoiseTexture<Low, High> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"NoiseTexture(scale={}, depth={}, low={}, high={})",
+			self.scale, self.depth, self.low, self.high
+		)
+	}
+}
+
 impl<Low: Texture, High: Texture> Texture for NoiseTexture<Low, High> {
 	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
 		let low = self.low.value(u, v, p);
@@ -229,10 +386,10 @@ impl<Low: Texture, High: Texture> Texture for NoiseTexture<Low, High> {
 impl<Low: Mappable, High: Mappable> Mappable for NoiseTexture<Low, High> {
 	type Mapped = NoiseTexture<Low::Mapped, High::Mapped>;
 
-	fn map(&self, f: &(dyn Fn(Color) -> Color + Send + Sync)) -> Self::Mapped {
+	fn map(&self, f: ColorFn) -> Self::Mapped {
 		NoiseTexture {
 			noise: self.noise.clone(),
-			low: self.low.map(f),
+			low: self.low.map(f.clone()),
 			high: self.high.map(f),
 			scale: self.scale,
 			depth: self.depth,
@@ -240,17 +397,83 @@ impl<Low: Mappable, High: Mappable> Mappable for NoiseTexture<Low, High> {
 	}
 }
 
+/// Gray with the brightness of the distance to the nearest of a set of points scattered through
+/// space, which makes a pattern of cells around them, like dimples or scales
+#[derive(Debug, Clone)]
+pub struct WorleyTexture {
+	noise: Worley,
+	/// points per unit of distance along each axis
+	scale: f64,
+}
+
+impl WorleyTexture {
+	pub fn new<R: Rng + ?Sized>(rng: &mut R, scale: f64) -> WorleyTexture {
+		WorleyTexture {
+			noise: Worley::new(rng),
+			scale,
+		}
+	}
+}
+
+impl Display for WorleyTexture {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "WorleyTexture(scale={})", self.scale)
+	}
+}
+
+impl Texture for WorleyTexture {
+	/// in units of the distance between points, so mostly from 0 to 1
+	fn value(&self, _u: f64, _v: f64, p: Point3) -> Color {
+		let distance = self.noise.distance(self.scale * p);
+		Color::new(distance, distance, distance)
+	}
+}
+
+impl Mappable for WorleyTexture {
+	type Mapped = MappedTexture<WorleyTexture>;
+
+	fn map(&self, f: ColorFn) -> Self::Mapped {
+		MappedTexture {
+			inner: Arc::new(self.clone()),
+			f,
+		}
+	}
+}
+
+/// most texels along each side of the footprint that ImageTexture averages, so that distant
+/// surfaces don't take arbitrarily long to shade
+const MAX_IMAGE_TAPS: usize = 8;
+
 #[derive(Debug, Clone)]
 pub struct ImageTexture {
-	image: DynamicImage,
+	// shared so that mapped copies don't duplicate the pixels
+	image: Arc<DynamicImage>,
 }
 
 impl ImageTexture {
 	pub fn new(filename: impl AsRef<Path>) -> ImageResult<ImageTexture> {
 		Ok(ImageTexture {
-			image: image::open(filename)?,
+			image: Arc::new(image::open(filename)?),
 		})
 	}
+
+	/// from the contents of an image file in any format the image crate can guess
+	pub fn from_memory(bytes: &[u8]) -> ImageResult<ImageTexture> {
+		Ok(ImageTexture {
+			image: Arc::new(image::load_from_memory(bytes)?),
+		})
+	}
+}
+
+impl Display for ImageTexture {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"ImageTexture({}x{})",
+			self.image.width(),
+			self.image.height()
+		)
+	}
 }
 
 impl Texture for ImageTexture {
@@ -261,7 +484,7 @@ impl Texture for ImageTexture {
 		let i = ((u * self.image.width() as f64) as u32).clamp(0, self.image.width() - 1);
 		let j = ((v * self.image.height() as f64) as u32).clamp(0, self.image.height() - 1);
 
-		match &self.image {
+		match self.image.as_ref() {
 			DynamicImage::ImageRgb8(im) => {
 				let pix = im.get_pixel(i, j);
 				Color::from_srgb(pix.0[0], pix.0[1], pix.0[2])
@@ -269,6 +492,42 @@ impl Texture for ImageTexture {
 			_ => panic!("bad image type: {:?}", self.image.color()),
 		}
 	}
+
+	/// average of a grid of texels over the parallelogram between the neighboring pixels'
+	/// footprints, with one tap per texel it spans up to MAX_IMAGE_TAPS along each side
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		let size = (self.image.width() as f64, self.image.height() as f64);
+		let taps = |(du, dv): (f64, f64)| {
+			((du * size.0).hypot(dv * size.1).ceil() as usize).clamp(1, MAX_IMAGE_TAPS)
+		};
+		let (nx, ny) = (taps(footprint.duv_dx), taps(footprint.duv_dy));
+		if nx == 1 && ny == 1 {
+			return self.value(u, v, p);
+		}
+
+		let (dx, dy) = (footprint.duv_dx, footprint.duv_dy);
+		let mut sum = Color::zero();
+		for a in 0..nx {
+			for b in 0..ny {
+				// from -0.5 to 0.5 of the way to the next pixel
+				let x = (a as f64 + 0.5) / nx as f64 - 0.5;
+				let y = (b as f64 + 0.5) / ny as f64 - 0.5;
+				sum += self.value(u + x * dx.0 + y * dy.0, v + x * dx.1 + y * dy.1, p);
+			}
+		}
+		sum / (nx * ny) as f64
+	}
+}
+
+impl Mappable for ImageTexture {
+	type Mapped = MappedTexture<ImageTexture>;
+
+	fn map(&self, f: ColorFn) -> Self::Mapped {
+		MappedTexture {
+			inner: Arc::new(self.clone()),
+			f,
+		}
+	}
 }
 
 #[derive(Clone)]
@@ -280,8 +539,356 @@ impl<F: Fn(f64, f64, Point3) -> Color + Send + Sync> Debug for FunctionTexture<F
 	}
 }
 
+/// names the function by where it was defined, since closures don't have names of their own
+impl<F: Fn(f64, f64, Point3) -> Color + Send + Sync> Display for FunctionTexture<F> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let name = std::any::type_name::<F>();
+		// the crate name is the same for every texture
+		let name = name.split_once("::").map_or(name, |(_, rest)| rest);
+		write!(f, "FunctionTexture({})", name)
+	}
+}
+
 impl<F: Fn(f64, f64, Point3) -> Color + Send + Sync> Texture for FunctionTexture<F> {
 	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
 		(self.0)(u, v, p)
 	}
 }
+
+impl<F: Fn(f64, f64, Point3) -> Color + Send + Sync + Clone> Mappable for FunctionTexture<F> {
+	type Mapped = MappedTexture<FunctionTexture<F>>;
+
+	fn map(&self, f: ColorFn) -> Self::Mapped {
+		MappedTexture {
+			inner: Arc::new(self.clone()),
+			f,
+		}
+	}
+}
+
+/// Applies a function to the colors of another texture, for brightness, contrast, or gamma
+/// adjustments to textures that can't be mapped ahead of time
+pub struct MappedTexture<T: Texture + ?Sized> {
+	inner: Arc<T>,
+	f: ColorFn,
+}
+
+impl<T: Texture + ?Sized> MappedTexture<T> {
+	pub fn new(
+		inner: Arc<T>,
+		f: impl Fn(Color) -> Color + Send + Sync + 'static,
+	) -> MappedTexture<T> {
+		MappedTexture {
+			inner,
+			f: Arc::new(f),
+		}
+	}
+}
+
+impl<T: Texture + ?Sized> Debug for MappedTexture<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("MappedTexture").field(&self.inner).finish()
+	}
+}
+
+impl<T: Texture + ?Sized> Display for MappedTexture<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Mapped({})", self.inner)
+	}
+}
+
+impl<T: Texture + ?Sized> Texture for MappedTexture<T> {
+	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+		(self.f)(self.inner.value(u, v, p))
+	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		(self.f)(self.inner.filtered_value(u, v, p, footprint))
+	}
+}
+
+impl<T: Texture + ?Sized> Mappable for MappedTexture<T> {
+	type Mapped = MappedTexture<T>;
+
+	/// applies f after this texture's own function, without another layer of wrapping
+	fn map(&self, f: ColorFn) -> Self::Mapped {
+		let inner_f = self.f.clone();
+		MappedTexture {
+			inner: self.inner.clone(),
+			f: Arc::new(move |c| f(inner_f(c))),
+		}
+	}
+}
+
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+		self.as_ref().value(u, v, p)
+	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		self.as_ref().filtered_value(u, v, p, footprint)
+	}
+}
+
+/// Sum of two textures
+#[derive(Debug, Clone)]
+pub struct AddTexture<A: Texture, B: Texture>(pub A, pub B);
+
+impl<A: Texture, B: Texture> Display for AddTexture<A, B> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "({} + {})", self.0, self.1)
+	}
+}
+
+impl<A: Texture, B: Texture> Texture for AddTexture<A, B> {
+	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+		self.0.value(u, v, p) + self.1.value(u, v, p)
+	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		self.0.filtered_value(u, v, p, footprint) + self.1.filtered_value(u, v, p, footprint)
+	}
+}
+
+/// Component-wise product of two textures
+#[derive(Debug, Clone)]
+pub struct MulTexture<A: Texture, B: Texture>(pub A, pub B);
+
+impl<A: Texture, B: Texture> Display for MulTexture<A, B> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "({} * {})", self.0, self.1)
+	}
+}
+
+impl<A: Texture, B: Texture> Texture for MulTexture<A, B> {
+	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+		self.0.value(u, v, p) * self.1.value(u, v, p)
+	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		self.0.filtered_value(u, v, p, footprint) * self.1.filtered_value(u, v, p, footprint)
+	}
+}
+
+/// Blends from the first texture to the second, separately for each channel, by the value of
+/// the third. factors outside 0 to 1 are clamped
+#[derive(Debug, Clone)]
+pub struct LerpTexture<A: Texture, B: Texture, F: Texture>(pub A, pub B, pub F);
+
+impl<A: Texture, B: Texture, F: Texture> LerpTexture<A, B, F> {
+	fn lerp(a: Color, b: Color, factor: Color) -> Color {
+		let factor = Color::new(
+			factor.x().clamp(0.0, 1.0),
+			factor.y().clamp(0.0, 1.0),
+			factor.z().clamp(0.0, 1.0),
+		);
+		Color::lerp(a, b, factor)
+	}
+}
+
+impl<A: Texture, B: Texture, F: Texture> Display for LerpTexture<A, B, F> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Lerp({}, {}, by={})", self.0, self.1, self.2)
+	}
+}
+
+impl<A: Texture, B: Texture, F: Texture> Texture for LerpTexture<A, B, F> {
+	fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+		Self::lerp(
+			self.0.value(u, v, p),
+			self.1.value(u, v, p),
+			self.2.value(u, v, p),
+		)
+	}
+
+	fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: Footprint) -> Color {
+		Self::lerp(
+			self.0.filtered_value(u, v, p, footprint),
+			self.1.filtered_value(u, v, p, footprint),
+			self.2.filtered_value(u, v, p, footprint),
+		)
+	}
+}
+
+/// lets textures be combined with + and *. the generic parameters of the texture type go in
+/// brackets, each followed by a comma
+macro_rules! texture_ops {
+	($(impl [$($generics: tt)*] $texture: ty;)*) => {$(
+		impl<$($generics)* Rhs: Texture> Add<Rhs> for $texture {
+			type Output = AddTexture<$texture, Rhs>;
+
+			fn add(self, rhs: Rhs) -> Self::Output {
+				AddTexture(self, rhs)
+			}
+		}
+
+		impl<$($generics)* Rhs: Texture> Mul<Rhs> for $texture {
+			type Output = MulTexture<$texture, Rhs>;
+
+			fn mul(self, rhs: Rhs) -> Self::Output {
+				MulTexture(self, rhs)
+			}
+		}
+	)*};
+}
+
+texture_ops! {
+	impl [] SolidColor;
+	impl [Odd: Texture, Even: Texture,] CheckerTexture<Odd, Even>;
+	impl [T: Texture,] StripeTexture<T>;
+	impl [Low: Texture, High: Texture,] NoiseTexture<Low, High>;
+	impl [] ImageTexture;
+	impl [F: Fn(f64, f64, Point3) -> Color + Send + Sync,] FunctionTexture<F>;
+	impl [T: Texture + ?Sized,] MappedTexture<T>;
+	impl [A: Texture, B: Texture,] AddTexture<A, B>;
+	impl [A: Texture, B: Texture,] MulTexture<A, B>;
+	impl [A: Texture, B: Texture, F: Texture,] LerpTexture<A, B, F>;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_display() {
+		let gray = SolidColor::new(Color::new(0.5, 0.5, 0.5));
+		let sum = gray + SolidColor::new(Color::new(0.25, 0.0, 0.0)) * gray;
+		assert_eq!(
+			sum.to_string(),
+			"(SolidColor(0.5, 0.5, 0.5) + (SolidColor(0.25, 0, 0) * SolidColor(0.5, 0.5, 0.5)))"
+		);
+
+		let function = FunctionTexture(|_u, v, _p| Color::new(v, v, v));
+		let name = function.to_string();
+		assert!(
+			name.starts_with("FunctionTexture(object::texture::tests::test_display::{{closure}}"),
+			"{}",
+			name
+		);
+		assert_eq!(
+			MappedTexture::new(Arc::new(gray), |c| c * 2.0).to_string(),
+			"Mapped(SolidColor(0.5, 0.5, 0.5))"
+		);
+	}
+
+	#[test]
+	fn test_filtered_stripes() {
+		let red = Color::new(1.0, 0.0, 0.0);
+		let blue = Color::new(0.0, 0.0, 1.0);
+		let stripes = StripeTexture::with_colors(&[red, blue], false);
+		let p = Point3::zero();
+
+		// a footprint centered on the boundary covers half of each stripe
+		let blended = stripes.filtered_value(0.0, 0.5, p, Footprint::new(0.2, 1.0));
+		assert!((blended - (red + blue) / 2.0).near_zero(), "{}", blended);
+		// one quarter of the footprint covers red
+		let blended = stripes.filtered_value(0.0, 0.45, p, Footprint::new(0.2, 1.0));
+		assert!(
+			(blended - (red * 0.25 + blue * 0.75)).near_zero(),
+			"{}",
+			blended
+		);
+		// entirely within one stripe, or no footprint at all
+		let inside = stripes.filtered_value(0.0, 0.8, p, Footprint::new(0.2, 1.0));
+		assert!((inside - red).near_zero(), "{}", inside);
+		let sharp = stripes.filtered_value(0.0, 0.5, p, Footprint::default());
+		assert!(
+			(sharp - stripes.value(0.0, 0.5, p)).near_zero(),
+			"{}",
+			sharp
+		);
+	}
+
+	#[test]
+	fn test_filtered_checker() {
+		let checker = CheckerTexture::with_colors(Color::zero(), Color::new(1.0, 1.0, 1.0));
+		let p = Point3::new(0.1, 0.2, 0.3);
+
+		// a tiny footprint matches the unfiltered value, and one many cells wide averages to gray
+		let sharp = checker.filtered_value(0.0, 0.0, p, Footprint::new(1e-6, 1.0));
+		assert!(
+			(sharp - checker.value(0.0, 0.0, p)).near_zero(),
+			"{}",
+			sharp
+		);
+		let wide = checker.filtered_value(0.0, 0.0, p, Footprint::new(100.0, 1.0));
+		assert!(
+			(wide - Color::new(0.5, 0.5, 0.5)).length() < 0.01,
+			"{}",
+			wide
+		);
+	}
+
+	#[test]
+	fn test_mapped_textures() {
+		let p = Point3::zero();
+		let gradient = FunctionTexture(|u, _v, _p| Color::new(u, u, u));
+		let brighter = gradient.map(Arc::new(|c| c * 2.0));
+		assert!((brighter.value(0.25, 0.0, p) - Color::new(0.5, 0.5, 0.5)).near_zero());
+
+		// mapping again applies the new function after the existing one
+		let offset = brighter.map(Arc::new(|c| c + Color::new(1.0, 0.0, 0.0)));
+		assert!((offset.value(0.25, 0.0, p) - Color::new(1.5, 0.5, 0.5)).near_zero());
+
+		let inner: Arc<dyn Texture> = Arc::new(SolidColor::new(Color::new(0.25, 0.5, 1.0)));
+		let squared = MappedTexture::new(inner, |c| c * c);
+		assert!((squared.value(0.0, 0.0, p) - Color::new(0.0625, 0.25, 1.0)).near_zero());
+	}
+
+	#[test]
+	fn test_texture_arithmetic() {
+		let p = Point3::zero();
+		let a = SolidColor::new(Color::new(0.5, 1.0, 2.0));
+		let b = SolidColor::new(Color::new(2.0, 3.0, 0.0));
+
+		assert!(((a + b).value(0.0, 0.0, p) - Color::new(2.5, 4.0, 2.0)).near_zero());
+		assert!(((a * b).value(0.0, 0.0, p) - Color::new(1.0, 3.0, 0.0)).near_zero());
+		// combinators can themselves be combined
+		let nested = (a + b) * a;
+		assert!((nested.value(0.0, 0.0, p) - Color::new(1.25, 4.0, 4.0)).near_zero());
+	}
+
+	#[test]
+	fn test_lerp_texture() {
+		let p = Point3::zero();
+		let black = SolidColor::new(Color::zero());
+		let white = SolidColor::new(Color::new(1.0, 1.0, 1.0));
+		let lerp = |factor| LerpTexture(black, white, SolidColor::new(factor)).value(0.0, 0.0, p);
+
+		let mixed = lerp(Color::new(0.0, 0.25, 1.0));
+		assert!(
+			(mixed - Color::new(0.0, 0.25, 1.0)).near_zero(),
+			"{}",
+			mixed
+		);
+		// factors are clamped instead of extrapolating past either end
+		let clamped = lerp(Color::new(-1.0, 2.0, 0.5));
+		assert!(
+			(clamped - Color::new(0.0, 1.0, 0.5)).near_zero(),
+			"{}",
+			clamped
+		);
+	}
+	#[test]
+	fn test_image_filtering() {
+		// black on the left and white on the right
+		let mut pixels = image::RgbImage::new(2, 1);
+		pixels.put_pixel(1, 0, image::Rgb([255, 255, 255]));
+		let texture = ImageTexture {
+			image: Arc::new(DynamicImage::ImageRgb8(pixels)),
+		};
+		let p = Point3::zero();
+		let left = Footprint {
+			duv_dx: (0.1, 0.0),
+			duv_dy: (0.0, 0.1),
+			..Footprint::default()
+		};
+		assert_eq!(texture.filtered_value(0.25, 0.5, p, left).x(), 0.0);
+		// a footprint as wide as the image covers both halves equally
+		let wide = Footprint {
+			duv_dx: (1.0, 0.0),
+			..left
+		};
+		assert!((texture.filtered_value(0.5, 0.5, p, wide).x() - 0.5).abs() < 1e-9);
+	}
+}
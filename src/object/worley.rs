@@ -0,0 +1,50 @@
+use rand::Rng;
+
+use super::Perlin;
+use crate::common::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+/// Cellular noise: one point scattered randomly in each unit cube of space, looked up by a hash
+/// of the cube's position like Perlin's gradients
+#[derive(Debug, Clone)]
+pub struct Worley {
+	/// where in its cube each point is
+	offsets: Vec<Vec3>,
+	perm_x: Vec<usize>,
+	perm_y: Vec<usize>,
+	perm_z: Vec<usize>,
+}
+
+impl Worley {
+	pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Worley {
+		Worley {
+			offsets: (0..POINT_COUNT)
+				.map(|_| Vec3::random_range(rng, 0.0, 1.0))
+				.collect(),
+			perm_x: Perlin::generate_perm(rng),
+			perm_y: Perlin::generate_perm(rng),
+			perm_z: Perlin::generate_perm(rng),
+		}
+	}
+
+	/// Distance from p to the nearest point. Only the 27 cubes around p's are searched, which
+	/// almost always includes the nearest one, like most implementations
+	pub fn distance(&self, p: Point3) -> f64 {
+		let cell = p.to_array().map(|c| c.floor() as isize);
+		let index = |c: isize| c.rem_euclid(POINT_COUNT as isize) as usize;
+		let mut nearest = f64::INFINITY;
+		for di in -1..=1 {
+			for dj in -1..=1 {
+				for dk in -1..=1 {
+					let (i, j, k) = (cell[0] + di, cell[1] + dj, cell[2] + dk);
+					let offset = self.offsets
+						[self.perm_x[index(i)] ^ self.perm_y[index(j)] ^ self.perm_z[index(k)]];
+					let point = Point3::new(i as f64, j as f64, k as f64) + offset;
+					nearest = nearest.min((point - p).length_squared());
+				}
+			}
+		}
+		nearest.sqrt()
+	}
+}
@@ -0,0 +1,152 @@
+use std::io::{self, BufWriter, Write};
+
+use super::ImageWriter;
+use crate::common::color::{Color, Dither};
+
+/// size of BITMAPFILEHEADER and BITMAPINFOHEADER
+const HEADER_SIZE: usize = 14 + 40;
+
+/// Writes a 24-bit uncompressed Windows bitmap. BMP stores the bottom row first, so every row is
+/// kept in memory until end()
+pub struct BmpWriter<W: Write> {
+	dest: BufWriter<W>,
+	width: usize,
+	height: usize,
+	dither: Dither,
+	/// dithered pixels as BGR, top row first
+	pixels: Vec<u8>,
+	ended: bool,
+}
+
+impl<W: Write> BmpWriter<W> {
+	pub fn new(dest: W, (width, height): (usize, usize)) -> Self {
+		Self {
+			dest: BufWriter::new(dest),
+			width,
+			height,
+			dither: Dither::new(8, width),
+			pixels: Vec::with_capacity(width * height * 3),
+			ended: false,
+		}
+	}
+
+	/// bytes in each row in the file, which are padded to a multiple of 4
+	fn row_size(&self) -> usize {
+		(self.width * 3).div_ceil(4) * 4
+	}
+}
+
+impl<W: Write> ImageWriter for BmpWriter<W> {
+	fn write_header(&mut self) -> io::Result<()> {
+		let image_size = self.row_size() * self.height;
+		let dest = &mut self.dest;
+
+		// BITMAPFILEHEADER
+		dest.write_all(b"BM")?;
+		dest.write_all(&((HEADER_SIZE + image_size) as u32).to_le_bytes())?;
+		// reserved
+		dest.write_all(&[0; 4])?;
+		// offset to the pixels
+		dest.write_all(&(HEADER_SIZE as u32).to_le_bytes())?;
+
+		// BITMAPINFOHEADER
+		dest.write_all(&40u32.to_le_bytes())?;
+		dest.write_all(&(self.width as i32).to_le_bytes())?;
+		// positive for bottom-up
+		dest.write_all(&(self.height as i32).to_le_bytes())?;
+		// planes
+		dest.write_all(&1u16.to_le_bytes())?;
+		// bits per pixel
+		dest.write_all(&24u16.to_le_bytes())?;
+		// BI_RGB, meaning no compression
+		dest.write_all(&0u32.to_le_bytes())?;
+		dest.write_all(&(image_size as u32).to_le_bytes())?;
+		// 2835 pixels per meter is 72 DPI
+		dest.write_all(&2835i32.to_le_bytes())?;
+		dest.write_all(&2835i32.to_le_bytes())?;
+		// no palette
+		dest.write_all(&0u32.to_le_bytes())?;
+		dest.write_all(&0u32.to_le_bytes())
+	}
+
+	fn write_pixels(&mut self, pixels: &[Color]) -> io::Result<()> {
+		for &p in pixels {
+			let p = self.dither.dither(p);
+			self.pixels.extend([p.2 as u8, p.1 as u8, p.0 as u8]);
+		}
+		Ok(())
+	}
+
+	fn end(&mut self) -> io::Result<()> {
+		if self.ended {
+			return Ok(());
+		}
+		self.ended = true;
+		let row_size = self.row_size();
+		let padding = row_size - self.width * 3;
+		for row in self.pixels.chunks(self.width * 3).rev() {
+			self.dest.write_all(row)?;
+			self.dest.write_all(&[0; 3][..padding])?;
+		}
+		self.dest.flush()
+	}
+
+	/// fill in the rest of the image with black, since the rows that were written go at the end
+	/// of the file
+	fn abort(&mut self) -> io::Result<()> {
+		self.pixels.resize(self.width * self.height * 3, 0);
+		self.end()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_decode() {
+		// widths with each amount of padding
+		for (width, row_size) in [(1, 4), (2, 8), (3, 12), (4, 12)] {
+			let height = 3;
+			let pixels: Vec<Color> = (0..width * height)
+				.map(|i| match i % 3 {
+					0 => Color::new(1.0, 0.0, 0.0),
+					1 => Color::new(0.0, 1.0, 0.0),
+					_ => Color::new(0.0, 0.0, 1.0),
+				})
+				.collect();
+			let mut bmp = Vec::new();
+			let mut writer = BmpWriter::new(&mut bmp, (width, height));
+			writer.write_header().unwrap();
+			// one row at a time, like the renderer
+			for row in pixels.chunks(width) {
+				writer.write_pixels(row).unwrap();
+			}
+			writer.end().unwrap();
+			drop(writer);
+
+			assert_eq!(bmp.len(), HEADER_SIZE + row_size * height);
+			let decoded = image::load_from_memory(&bmp).unwrap().to_rgb8();
+			assert_eq!(decoded.dimensions(), (width as u32, height as u32));
+			for (pixel, color) in decoded.pixels().zip(&pixels) {
+				let expected = [color.x(), color.y(), color.z()].map(|c| (c * 255.0) as u8);
+				assert_eq!(pixel.0, expected, "width {}", width);
+			}
+		}
+	}
+
+	#[test]
+	fn test_abort() {
+		let white = Color::new(1.0, 1.0, 1.0);
+		let mut bmp = Vec::new();
+		let mut writer = BmpWriter::new(&mut bmp, (2, 2));
+		writer.write_header().unwrap();
+		writer.write_pixels(&[white, white, white]).unwrap();
+		writer.abort().unwrap();
+		drop(writer);
+
+		let decoded = image::load_from_memory(&bmp).unwrap().to_rgb8();
+		let decoded: Vec<[u8; 3]> = decoded.pixels().map(|p| p.0).collect();
+		assert_eq!(decoded, [[255; 3], [255; 3], [255; 3], [0; 3]]);
+	}
+}
@@ -0,0 +1,131 @@
+use std::ops::Range;
+
+use super::font::{draw_text, GLYPH_HEIGHT, GLYPH_WIDTH};
+use super::RenderMetadata;
+use crate::common::color::{ImageStats, HISTOGRAM_BUCKETS};
+use crate::common::{Color, ImageBuffer};
+
+/// space around and between the lines, in font pixels
+const PADDING: usize = 2;
+/// height of the tallest histogram bar, in font pixels
+const HISTOGRAM_HEIGHT: usize = 12;
+/// how much of the image shows through behind the text
+const BACKGROUND: f64 = 0.2;
+
+/// Draw the scene, seeds, samples, and render time into the top left corner of an image, with a
+/// luminance histogram underneath, over a darkened box so that they stay readable. Returns the x
+/// and y ranges covered, which are cut off to fit in the image
+/// exposure: the exposure the image is going to be shown with, so that the text still comes out
+///           white
+pub fn burn_in(
+	image: &mut ImageBuffer<Color>,
+	metadata: &RenderMetadata,
+	stats: &ImageStats,
+	exposure: f64,
+) -> (Range<usize>, Range<usize>) {
+	let (width, height) = image.size();
+	// big enough to read on large images
+	let scale = (height / 360).max(1);
+
+	let lines = [
+		format!("scene {}", metadata.scene),
+		format!(
+			"seeds -r {} -R {}",
+			metadata.world_seed, metadata.sample_seed
+		),
+		format!("{} spp", metadata.samples),
+		format!("{:.3}s", metadata.render_time.as_secs_f64()),
+	];
+	let line_height = (GLYPH_HEIGHT + PADDING) * scale;
+	let text_width = lines.iter().map(|l| l.len()).max().unwrap_or(0) * (GLYPH_WIDTH + 1);
+	let box_width = (text_width.max(2 * HISTOGRAM_BUCKETS) + 2 * PADDING) * scale;
+	let box_height = lines.len() * line_height + (HISTOGRAM_HEIGHT + 2 * PADDING) * scale;
+	let (xs, ys) = (0..box_width.min(width), 0..box_height.min(height));
+
+	for y in ys.clone() {
+		for p in &mut image.row_mut(y)[xs.clone()] {
+			*p *= BACKGROUND;
+		}
+	}
+
+	let white = Color::new(1.0, 1.0, 1.0) / exposure;
+	for (i, line) in lines.iter().enumerate() {
+		draw_text(
+			image,
+			line,
+			(PADDING * scale, PADDING * scale + i * line_height),
+			scale,
+			white,
+		);
+	}
+
+	// each bucket is a bar two font pixels wide, growing up from the bottom of the box
+	let peak = stats.histogram.iter().copied().max().unwrap_or(0).max(1);
+	let bottom = box_height - PADDING * scale;
+	for (i, &n) in stats.histogram.iter().enumerate() {
+		// like ImageStats::histogram_line, any nonempty bucket should show up
+		let bar = if n == 0 {
+			0
+		} else {
+			(n * HISTOGRAM_HEIGHT * scale / peak).max(1)
+		};
+		let left = (PADDING + 2 * i) * scale;
+		for row in image.rows_mut().take(bottom).skip(bottom - bar) {
+			for p in row.iter_mut().skip(left).take(2 * scale) {
+				*p = white;
+			}
+		}
+	}
+
+	(xs, ys)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	fn metadata() -> RenderMetadata {
+		RenderMetadata {
+			software: String::new(),
+			command_line: String::new(),
+			reproduce: String::new(),
+			scene: "cornell".to_string(),
+			world_seed: 1,
+			sample_seed: 2,
+			samples: 100,
+			render_time: Duration::from_millis(1234),
+		}
+	}
+
+	#[test]
+	fn test_burn_in() {
+		let gray = Color::new(0.8, 0.8, 0.8);
+		let mut image = ImageBuffer::new((400, 200), gray);
+		let stats = ImageStats::new(image.pixels());
+		let (xs, ys) = burn_in(&mut image, &metadata(), &stats, 2.0);
+		assert!(xs.end < 400 && ys.end < 200);
+
+		// the text is white once the exposure is applied, and the rest of the box is darkened
+		let covered: Vec<f64> = ys
+			.clone()
+			.flat_map(|y| xs.clone().map(move |x| y * 400 + x))
+			.map(|i| image.pixels()[i].x())
+			.collect();
+		assert!(covered.contains(&0.5));
+		assert!(covered.iter().any(|&x| (x - 0.16).abs() < 1e-9));
+		assert!(covered.iter().all(|&x| x == 0.5 || (x - 0.16).abs() < 1e-9));
+		assert_eq!(image[(xs.end, ys.end)].x(), 0.8);
+	}
+
+	#[test]
+	fn test_burn_in_tiny() {
+		let stats = ImageStats::new(&[Color::zero()]);
+		for (width, height) in [(1, 1), (5, 3), (0, 0)] {
+			let mut image = ImageBuffer::new((width, height), Color::zero());
+			let (xs, ys) = burn_in(&mut image, &metadata(), &stats, 1.0);
+			assert_eq!((xs.end, ys.end), (width, height));
+		}
+	}
+}
@@ -0,0 +1,208 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::scene::BvhNode;
+
+/// One node of a BVH, as written by --dump-bvh
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DumpedNode {
+	/// 0 for the root
+	pub depth: usize,
+	pub min: [f64; 3],
+	pub max: [f64; 3],
+	/// objects in the scene directly under this node, which is only nonzero for leaves
+	pub primitives: usize,
+	/// whether this node has no other nodes under it
+	pub leaf: bool,
+}
+
+/// The boxes of a BVH along with statistics about its shape, for looking at why a scene is slow
+/// to render. The statistics are always of the whole tree, even when nodes below a certain depth
+/// are left out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BvhDump {
+	/// nodes in the whole tree
+	pub node_count: usize,
+	pub leaf_count: usize,
+	/// depth of the deepest leaf
+	pub max_depth: usize,
+	/// number of nodes at each depth, starting at the root
+	pub depth_histogram: Vec<usize>,
+	/// number of leaves holding each number of primitives, starting at 0
+	pub leaf_size_histogram: Vec<usize>,
+	/// total surface area of every node's box
+	pub surface_area: f64,
+	/// surface area of the root's box. surface_area divided by this is roughly how many boxes a
+	/// random ray that hits the scene is tested against
+	pub root_surface_area: f64,
+	/// deepest nodes that were written, if not all of them
+	pub depth_limit: Option<usize>,
+	/// parents before their children
+	pub nodes: Vec<DumpedNode>,
+}
+
+/// count of the entry at index, growing the histogram if it doesn't have one yet
+fn histogram_entry(histogram: &mut Vec<usize>, index: usize) -> &mut usize {
+	if histogram.len() <= index {
+		histogram.resize(index + 1, 0);
+	}
+	&mut histogram[index]
+}
+
+impl BvhDump {
+	/// depth_limit: nodes deeper than this are counted in the statistics but not written
+	pub fn new(root: &BvhNode, depth_limit: Option<usize>) -> BvhDump {
+		let mut dump = BvhDump {
+			node_count: 0,
+			leaf_count: 0,
+			max_depth: 0,
+			depth_histogram: Vec::new(),
+			leaf_size_histogram: Vec::new(),
+			surface_area: 0.0,
+			root_surface_area: root.bbox().surface_area(),
+			depth_limit,
+			nodes: Vec::new(),
+		};
+		root.visit(&mut |node, depth| {
+			let bbox = node.bbox();
+			let primitives = node.primitives();
+			let leaf = node.child_nodes().next().is_none();
+			dump.node_count += 1;
+			dump.max_depth = dump.max_depth.max(depth);
+			dump.surface_area += bbox.surface_area();
+			*histogram_entry(&mut dump.depth_histogram, depth) += 1;
+			if leaf {
+				dump.leaf_count += 1;
+				*histogram_entry(&mut dump.leaf_size_histogram, primitives) += 1;
+			}
+			if depth_limit.is_none_or(|limit| depth <= limit) {
+				let (min, max) = (bbox.min(), bbox.max());
+				dump.nodes.push(DumpedNode {
+					depth,
+					min: min.to_array(),
+					max: max.to_array(),
+					primitives,
+					leaf,
+				});
+			}
+		});
+		dump
+	}
+
+	/// Write each box as a wireframe of 12 lines, grouped by depth so they can be shown one level
+	/// at a time
+	pub fn write_obj<W: Write>(&self, mut dest: W) -> io::Result<()> {
+		writeln!(
+			dest,
+			"# BVH with {} nodes, {} leaves, depth {}",
+			self.node_count, self.leaf_count, self.max_depth
+		)?;
+		writeln!(
+			dest,
+			"# surface area {} (root {})",
+			self.surface_area, self.root_surface_area
+		)?;
+		for (i, node) in self.nodes.iter().enumerate() {
+			writeln!(dest, "g depth{}", node.depth)?;
+			for corner in 0..8 {
+				let pick = |axis: usize| {
+					if corner & (1 << axis) == 0 {
+						node.min[axis]
+					} else {
+						node.max[axis]
+					}
+				};
+				writeln!(dest, "v {} {} {}", pick(0), pick(1), pick(2))?;
+			}
+			// OBJ indices start at 1. each edge joins two corners that differ along one axis
+			let first = i * 8 + 1;
+			for corner in 0..8 {
+				for axis in 0..3 {
+					if corner & (1 << axis) == 0 {
+						writeln!(
+							dest,
+							"l {} {}",
+							first + corner,
+							first + (corner | (1 << axis))
+						)?;
+					}
+				}
+			}
+		}
+		dest.flush()
+	}
+
+	/// write as a Wavefront OBJ file if the path ends in .obj, and as JSON otherwise
+	pub fn save(&self, path: &str) -> io::Result<()> {
+		let file = BufWriter::new(File::create(path)?);
+		let is_obj = Path::new(path)
+			.extension()
+			.is_some_and(|e| e.eq_ignore_ascii_case("obj"));
+		if is_obj {
+			self.write_obj(file)
+		} else {
+			serde_json::to_writer_pretty(file, self).map_err(io::Error::from)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::scene::scenes;
+
+	fn cornell_bvh() -> BvhNode {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let (world, ..) = scenes::cornell_box();
+		BvhNode::new(&mut rng, world.flatten().as_ref(), 0.0, 1.0).unwrap()
+	}
+
+	#[test]
+	fn test_counts() {
+		let bvh = cornell_bvh();
+		let dump = BvhDump::new(&bvh, None);
+		// every node that isn't a leaf has two children
+		assert_eq!(dump.nodes.len(), 2 * dump.leaf_count - 1);
+		assert_eq!(dump.nodes.len(), dump.node_count);
+		assert_eq!(dump.depth_histogram.iter().sum::<usize>(), dump.node_count);
+		assert_eq!(dump.depth_histogram.len(), dump.max_depth + 1);
+		assert_eq!(dump.depth_histogram[0], 1);
+		let primitives: usize = dump.nodes.iter().map(|n| n.primitives).sum();
+		assert_eq!(primitives, scenes::cornell_box().0.flatten().len());
+		let leaves = dump.leaf_size_histogram.iter().sum::<usize>();
+		assert_eq!(leaves, dump.leaf_count);
+		assert!(dump.surface_area > dump.root_surface_area);
+
+		// the statistics are the same when the nodes are cut off
+		let shallow = BvhDump::new(&bvh, Some(1));
+		assert_eq!(shallow.nodes.len(), 3);
+		assert!(shallow.nodes.iter().all(|n| n.depth <= 1));
+		assert_eq!(
+			(shallow.node_count, shallow.surface_area),
+			(dump.node_count, dump.surface_area)
+		);
+	}
+
+	#[test]
+	fn test_obj() {
+		let dump = BvhDump::new(&cornell_bvh(), Some(2));
+		let mut obj = Vec::new();
+		dump.write_obj(&mut obj).unwrap();
+		let obj = String::from_utf8(obj).unwrap();
+		let count = |prefix| obj.lines().filter(|l| l.starts_with(prefix)).count();
+		assert_eq!(count("v "), dump.nodes.len() * 8);
+		assert_eq!(count("l "), dump.nodes.len() * 12);
+		// every line joins two corners of the same box
+		for line in obj.lines().filter(|l| l.starts_with("l ")) {
+			let ends: Vec<usize> = line[2..].split(' ').map(|i| i.parse().unwrap()).collect();
+			assert_eq!((ends[0] - 1) / 8, (ends[1] - 1) / 8);
+			assert_eq!(((ends[0] - 1) ^ (ends[1] - 1)).count_ones(), 1);
+		}
+	}
+}
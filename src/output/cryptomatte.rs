@@ -0,0 +1,144 @@
+use exr::image::AnyChannel;
+
+use super::exr::{plane_from_image, ExrChannels};
+use crate::common::raytracer::{Aov, COVERAGE_RANK};
+use crate::common::ImageBuffer;
+
+/// name of the layer holding object ids, which compositors show as the type of matte
+const LAYER: &str = "CryptoObject";
+
+/// MurmurHash3, the 32-bit x86 version, which Cryptomatte uses to turn names into ids
+fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+	const C1: u32 = 0xcc9e2d51;
+	const C2: u32 = 0x1b873593;
+
+	let mut h = seed;
+	let mut chunks = data.chunks_exact(4);
+	for chunk in &mut chunks {
+		let k = u32::from_le_bytes(chunk.try_into().unwrap());
+		h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+		h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+	}
+	let tail = chunks.remainder();
+	if !tail.is_empty() {
+		let k = tail
+			.iter()
+			.rev()
+			.fold(0u32, |k, &byte| (k << 8) | byte as u32);
+		h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+	}
+
+	h ^= data.len() as u32;
+	h ^= h >> 16;
+	h = h.wrapping_mul(0x85ebca6b);
+	h ^= h >> 13;
+	h = h.wrapping_mul(0xc2b2ae35);
+	h ^ (h >> 16)
+}
+
+/// The id of an object with the given name, as the bits of a float. The exponent is kept away from
+/// 0 and 255 so that the float is never denormal, infinite, or NaN, which compositing software
+/// might not pass through unchanged
+pub fn object_id(name: &str) -> f32 {
+	let hash = murmur3_32(name.as_bytes(), 0);
+	let exponent = ((hash >> 23) & 0xff).clamp(1, 254);
+	f32::from_bits((hash & 0x807fffff) | (exponent << 23))
+}
+
+/// Text attributes describing the id channels: how ids were made, and a manifest from object
+/// names back to their ids so that mattes can be picked by name
+/// names: names of the labeled objects, indexed by label id
+pub fn attributes(names: &[String]) -> Vec<(String, String)> {
+	let key = format!("cryptomatte/{:08x}", murmur3_32(LAYER.as_bytes(), 0))[..19].to_string();
+	let manifest = names
+		.iter()
+		.map(|name| {
+			let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+			format!("\"{}\":\"{:08x}\"", escaped, object_id(name).to_bits())
+		})
+		.collect::<Vec<String>>()
+		.join(",");
+	vec![
+		(format!("{}/name", key), LAYER.to_string()),
+		(format!("{}/hash", key), "MurmurHash3_32".to_string()),
+		(
+			format!("{}/conversion", key),
+			"uint32_to_float32".to_string(),
+		),
+		(format!("{}/manifest", key), format!("{{{}}}", manifest)),
+	]
+}
+
+/// Channels with the id and coverage of the objects that cover most of each pixel, two objects
+/// per RGBA layer. Ids must stay exact, so these are always 32-bit
+pub fn channels(aovs: &ImageBuffer<Aov>, names: &[String]) -> ExrChannels {
+	let ids: Vec<f32> = names.iter().map(|name| object_id(name)).collect();
+	let mut channels = ExrChannels::new();
+	for rank in 0..COVERAGE_RANK {
+		let layer = format!("{}{:02}", LAYER, rank / 2);
+		let (id_channel, coverage_channel) = if rank % 2 == 0 {
+			("R", "G")
+		} else {
+			("B", "A")
+		};
+		channels.push(AnyChannel::new(
+			format!("{}.{}", layer, id_channel).as_str(),
+			plane_from_image(aovs, 32, |a| {
+				let (id, coverage) = a.coverage[rank];
+				if coverage > 0.0 {
+					ids[id as usize] as f64
+				} else {
+					0.0
+				}
+			}),
+		));
+		channels.push(AnyChannel::new(
+			format!("{}.{}", layer, coverage_channel).as_str(),
+			plane_from_image(aovs, 32, |a| a.coverage[rank].1),
+		));
+	}
+	channels
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_murmur3() {
+		assert_eq!(murmur3_32(b"", 0), 0);
+		assert_eq!(murmur3_32(b"", 1), 0x514e28b7);
+		assert_eq!(murmur3_32(b"hello", 0), 0x248bfa47);
+		assert_eq!(
+			murmur3_32(b"The quick brown fox jumps over the lazy dog", 0),
+			0x2e4ff723
+		);
+	}
+
+	#[test]
+	fn test_object_id() {
+		for name in ["", "Sphere 3", "hello", "a much longer object name"] {
+			let id = object_id(name);
+			assert!(id.is_normal(), "{} -> {}", name, id);
+		}
+		// only the exponent is changed, and only when it has to be
+		assert_eq!(object_id("hello").to_bits(), 0x248bfa47);
+	}
+
+	#[test]
+	fn test_manifest() {
+		let names = vec!["Sphere 0".to_string(), "a \"quoted\" name".to_string()];
+		let attributes = attributes(&names);
+		let (key, manifest) = &attributes[3];
+		assert!(key.starts_with("cryptomatte/") && key.ends_with("/manifest"));
+		assert_eq!(key.len(), "cryptomatte/0123456/manifest".len());
+		assert_eq!(
+			manifest,
+			&format!(
+				"{{\"Sphere 0\":\"{:08x}\",\"a \\\"quoted\\\" name\":\"{:08x}\"}}",
+				object_id("Sphere 0").to_bits(),
+				object_id("a \"quoted\" name").to_bits()
+			)
+		);
+	}
+}
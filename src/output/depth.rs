@@ -0,0 +1,67 @@
+use crate::common::raytracer::Aov;
+use crate::common::ImageBuffer;
+
+/// Depths to map to black and white in a normalized depth map: the 1st and 99th percentiles of
+/// the pixels that hit something, so that a few stray pixels don't squash everything else into a
+/// narrow range. None if nothing was hit
+pub fn depth_range(aovs: &ImageBuffer<Aov>) -> Option<(f64, f64)> {
+	let mut depths: Vec<f64> = aovs
+		.pixels()
+		.iter()
+		.map(|aov| aov.depth)
+		.filter(|d| d.is_finite())
+		.collect();
+	if depths.is_empty() {
+		return None;
+	}
+	depths.sort_by(f64::total_cmp);
+	let percentile = |p: f64| depths[((depths.len() - 1) as f64 * p).round() as usize];
+	Some((percentile(0.01), percentile(0.99)))
+}
+
+/// depth from 0 at near to 1 at far, clamped to that range. pixels that hit nothing are at
+/// infinity, so they come out as 1
+pub fn normalize_depth(depth: f64, (near, far): (f64, f64)) -> f64 {
+	if depth >= far {
+		1.0
+	} else if depth <= near {
+		0.0
+	} else {
+		(depth - near) / (far - near)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn aovs(depths: &[f64]) -> ImageBuffer<Aov> {
+		ImageBuffer::from_fn((depths.len(), 1), |x, _| Aov {
+			depth: depths[x],
+			..Aov::default()
+		})
+	}
+
+	#[test]
+	fn test_depth_range() {
+		assert_eq!(depth_range(&aovs(&[f64::INFINITY; 4])), None);
+
+		// one very close and one very far pixel out of 201 are left out
+		let mut depths: Vec<f64> = (0..=200).map(|i| 10.0 + i as f64 / 10.0).collect();
+		depths[0] = 0.001;
+		depths[200] = 1e6;
+		depths.push(f64::INFINITY);
+		let (near, far) = depth_range(&aovs(&depths)).unwrap();
+		assert_eq!((near, far), (10.2, 29.8));
+	}
+
+	#[test]
+	fn test_normalize_depth() {
+		assert_eq!(normalize_depth(2.0, (2.0, 6.0)), 0.0);
+		assert_eq!(normalize_depth(3.0, (2.0, 6.0)), 0.25);
+		assert_eq!(normalize_depth(1.0, (2.0, 6.0)), 0.0);
+		assert_eq!(normalize_depth(f64::INFINITY, (2.0, 6.0)), 1.0);
+		// everything at the same depth doesn't divide by zero
+		assert_eq!(normalize_depth(2.0, (2.0, 2.0)), 1.0);
+	}
+}
@@ -0,0 +1,184 @@
+use std::io::{self, Write};
+
+use exr::image::{write::WritableImage, Image};
+use exr::image::{AnyChannel, AnyChannels, FlatSamples};
+use exr::math::Vec2;
+use exr::meta::attribute::{self, AttributeValue, Text};
+use half::f16;
+use smallvec::SmallVec;
+
+use super::RenderMetadata;
+use crate::common::color::Chromaticities;
+use crate::common::ImageBuffer;
+
+pub type ExrChannels = SmallVec<[AnyChannel<FlatSamples>; 4]>;
+
+/// convert one value of each pixel in an image into a plane of samples for an OpenEXR channel
+pub fn plane_from_image<T>(
+	image: &ImageBuffer<T>,
+	bit_depth: u8,
+	value: impl Fn(&T) -> f64,
+) -> FlatSamples {
+	let pixels = image.pixels().iter();
+	match bit_depth {
+		16 => FlatSamples::F16(pixels.map(|pixel| f16::from_f64(value(pixel))).collect()),
+		32 => FlatSamples::F32(pixels.map(|pixel| value(pixel) as f32).collect()),
+		_ => unreachable!(),
+	}
+}
+
+/// OpenEXR text attributes must be Latin-1, so replace anything that isn't ASCII
+fn text(s: &str) -> Text {
+	let ascii: String = s
+		.chars()
+		.map(|c| if c.is_ascii() { c } else { '?' })
+		.collect();
+	Text::new_or_panic(ascii)
+}
+
+/// Write a single-layer OpenEXR image
+///
+/// channels:       every channel in the image, which will be sorted by name
+/// chromaticities: primaries and white point of the color channels
+/// metadata:       render parameters to store as attributes
+/// attributes:     any other text attributes, by name
+pub fn write_exr<W: Write>(
+	dest: &mut W,
+	(width, height): (usize, usize),
+	channels: ExrChannels,
+	chromaticities: Chromaticities,
+	metadata: Option<&RenderMetadata>,
+	attributes: &[(String, String)],
+) -> io::Result<()> {
+	let mut image = Image::from_channels((width, height), AnyChannels::sort(channels));
+	let vec2 = |(x, y): (f64, f64)| Vec2(x as f32, y as f32);
+	image.attributes.chromaticities = Some(attribute::Chromaticities {
+		red: vec2(chromaticities.red),
+		green: vec2(chromaticities.green),
+		blue: vec2(chromaticities.blue),
+		white: vec2(chromaticities.white),
+	});
+
+	if let Some(m) = metadata {
+		let attributes = &mut image.layer_data.attributes;
+		attributes.software_name = Some(text(&m.software));
+		let other = &mut attributes.other;
+		other.insert(
+			text("commandLine"),
+			AttributeValue::Text(text(&m.command_line)),
+		);
+		other.insert(
+			text("reproduceCommand"),
+			AttributeValue::Text(text(&m.reproduce)),
+		);
+		other.insert(text("scene"), AttributeValue::Text(text(&m.scene)));
+		// seeds are stored as text since there is no 64-bit integer attribute type
+		other.insert(
+			text("worldSeed"),
+			AttributeValue::Text(text(&m.world_seed.to_string())),
+		);
+		other.insert(
+			text("sampleSeed"),
+			AttributeValue::Text(text(&m.sample_seed.to_string())),
+		);
+		other.insert(
+			text("samplesPerPixel"),
+			AttributeValue::I32(m.samples as i32),
+		);
+		other.insert(
+			text("renderTime"),
+			AttributeValue::F64(m.render_time.as_secs_f64()),
+		);
+	}
+
+	for (name, value) in attributes {
+		image
+			.layer_data
+			.attributes
+			.other
+			.insert(text(name), AttributeValue::Text(text(value)));
+	}
+
+	// the exr crate needs a seekable writer
+	let mut image_data = io::Cursor::new(vec![0u8; 0]);
+	image
+		.write()
+		.to_buffered(&mut image_data)
+		.map_err(io::Error::other)?;
+	io::copy(&mut image_data.into_inner().as_slice(), dest)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use exr::prelude::{read, ReadChannels, ReadLayers};
+	use smallvec::smallvec;
+
+	use super::*;
+	use crate::common::color::Primaries;
+
+	#[test]
+	fn test_metadata_attributes() {
+		let metadata = RenderMetadata {
+			software: "raytracing 0.1.0".to_string(),
+			command_line: "raytracing -S cornell -s 4".to_string(),
+			reproduce: "raytracing -S cornell -s 4 -r 1 -R 12".to_string(),
+			scene: "cornell".to_string(),
+			world_seed: u64::MAX,
+			sample_seed: 12,
+			samples: 4,
+			render_time: Duration::from_millis(1500),
+		};
+		let pixels = ImageBuffer::new((2, 2), 0.5f64);
+		let mut written: Vec<u8> = Vec::new();
+		write_exr(
+			&mut written,
+			(2, 2),
+			smallvec![AnyChannel::new("Y", plane_from_image(&pixels, 32, |&p| p))],
+			Primaries::Srgb.chromaticities(),
+			Some(&metadata),
+			&[("extra".to_string(), "value".to_string())],
+		)
+		.unwrap();
+
+		let image = read()
+			.no_deep_data()
+			.largest_resolution_level()
+			.all_channels()
+			.first_valid_layer()
+			.all_attributes()
+			.from_buffered(io::Cursor::new(written))
+			.unwrap();
+		let attributes = &image.layer_data.attributes;
+		let other = &attributes.other;
+		assert_eq!(attributes.software_name, Some(text("raytracing 0.1.0")));
+		assert_eq!(
+			other.get(&text("commandLine")),
+			Some(&AttributeValue::Text(text("raytracing -S cornell -s 4")))
+		);
+		assert_eq!(
+			other.get(&text("reproduceCommand")),
+			Some(&AttributeValue::Text(text(
+				"raytracing -S cornell -s 4 -r 1 -R 12"
+			)))
+		);
+		assert_eq!(
+			other.get(&text("worldSeed")),
+			Some(&AttributeValue::Text(text("18446744073709551615")))
+		);
+		assert_eq!(
+			other.get(&text("samplesPerPixel")),
+			Some(&AttributeValue::I32(4))
+		);
+		assert_eq!(
+			other.get(&text("renderTime")),
+			Some(&AttributeValue::F64(1.5))
+		);
+		assert_eq!(
+			other.get(&text("extra")),
+			Some(&AttributeValue::Text(text("value")))
+		);
+	}
+}
@@ -0,0 +1,260 @@
+use crate::common::{Color, ImageBuffer};
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+
+/// Rows of a character in a 5x7 bitmap font, from the top, with the leftmost column in the highest
+/// bit. Only digits, letters, and some punctuation are included; lowercase letters are drawn as
+/// uppercase and anything else as ?
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+	match c.to_ascii_uppercase() {
+		' ' => [
+			0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+		],
+		'0' => [
+			0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+		],
+		'1' => [
+			0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+		],
+		'2' => [
+			0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+		],
+		'3' => [
+			0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+		],
+		'4' => [
+			0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+		],
+		'5' => [
+			0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+		],
+		'6' => [
+			0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+		],
+		'7' => [
+			0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+		],
+		'8' => [
+			0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+		],
+		'9' => [
+			0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+		],
+		'A' => [
+			0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+		],
+		'B' => [
+			0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+		],
+		'C' => [
+			0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+		],
+		'D' => [
+			0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+		],
+		'E' => [
+			0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+		],
+		'F' => [
+			0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+		],
+		'G' => [
+			0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+		],
+		'H' => [
+			0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+		],
+		'I' => [
+			0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+		],
+		'J' => [
+			0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+		],
+		'K' => [
+			0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+		],
+		'L' => [
+			0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+		],
+		'M' => [
+			0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+		],
+		'N' => [
+			0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+		],
+		'O' => [
+			0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+		],
+		'P' => [
+			0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+		],
+		'Q' => [
+			0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+		],
+		'R' => [
+			0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+		],
+		'S' => [
+			0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+		],
+		'T' => [
+			0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+		],
+		'U' => [
+			0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+		],
+		'V' => [
+			0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+		],
+		'W' => [
+			0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+		],
+		'X' => [
+			0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+		],
+		'Y' => [
+			0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100,
+		],
+		'Z' => [
+			0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+		],
+		'.' => [
+			0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+		],
+		',' => [
+			0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+		],
+		':' => [
+			0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+		],
+		'-' => [
+			0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+		],
+		'+' => [
+			0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+		],
+		'=' => [
+			0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+		],
+		'/' => [
+			0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+		],
+		'_' => [
+			0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+		],
+		'(' => [
+			0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+		],
+		')' => [
+			0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+		],
+		'%' => [
+			0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+		],
+		'?' => [
+			0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+		],
+		_ => glyph('?'),
+	}
+}
+
+/// Which pixels of a line of text are set, as rows from the top. Characters are separated by one
+/// pixel
+pub fn text_mask(text: &str) -> Vec<Vec<bool>> {
+	let chars: Vec<char> = text.chars().collect();
+	let width = (chars.len() * (GLYPH_WIDTH + 1)).saturating_sub(1);
+	let mut mask = vec![vec![false; width]; GLYPH_HEIGHT];
+	for (i, &c) in chars.iter().enumerate() {
+		for (row, bits) in mask.iter_mut().zip(glyph(c)) {
+			for column in 0..GLYPH_WIDTH {
+				row[i * (GLYPH_WIDTH + 1) + column] = bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0;
+			}
+		}
+	}
+	mask
+}
+
+/// Draw a line of text onto an image. Anything that falls outside of the image is cut off
+/// (x, y):  top left corner of the text
+/// scale:   size of each pixel of the font, in pixels of the image
+pub fn draw_text(
+	image: &mut ImageBuffer<Color>,
+	text: &str,
+	(x, y): (usize, usize),
+	scale: usize,
+	color: Color,
+) {
+	for (j, row) in text_mask(text).iter().enumerate() {
+		for (i, _) in row.iter().enumerate().filter(|&(_, &set)| set) {
+			for image_y in (y + j * scale)..(y + (j + 1) * scale) {
+				if image_y >= image.height() {
+					return;
+				}
+				for image_x in (x + i * scale)..(x + (i + 1) * scale) {
+					if let Some(p) = image.get_mut(image_x, image_y) {
+						*p = color;
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// a mask as lines of # and spaces
+	fn picture(mask: &[Vec<bool>]) -> Vec<String> {
+		mask.iter()
+			.map(|row| row.iter().map(|&set| if set { '#' } else { ' ' }).collect())
+			.collect()
+	}
+
+	#[test]
+	fn test_text_mask() {
+		assert_eq!(
+			picture(&text_mask("1x")),
+			[
+				"  #   #   #",
+				" ##   #   #",
+				"  #    # # ",
+				"  #     #  ",
+				"  #    # # ",
+				"  #   #   #",
+				" ###  #   #",
+			]
+		);
+		// unknown characters are drawn as ?
+		assert_eq!(text_mask("~"), text_mask("?"));
+		assert_eq!(text_mask(""), vec![Vec::<bool>::new(); GLYPH_HEIGHT]);
+	}
+
+	#[test]
+	fn test_draw_text_clipped() {
+		let white = Color::new(1.0, 1.0, 1.0);
+		let mut image = ImageBuffer::new((4, 3), Color::zero());
+		draw_text(&mut image, "T", (1, 1), 2, white);
+		// the top bar of the T, doubled in size and cut off by the right and bottom edges
+		let drawn: Vec<String> = image
+			.rows()
+			.map(|row| {
+				row.iter()
+					.map(|p| if p.x() > 0.0 { '#' } else { ' ' })
+					.collect()
+			})
+			.collect();
+		assert_eq!(drawn, ["    ", " ###", " ###"]);
+
+		// entirely outside of the image
+		draw_text(&mut image, "hello", (100, 100), 1, white);
+		draw_text(
+			&mut ImageBuffer::new((0, 0), white),
+			"hello",
+			(0, 0),
+			1,
+			white,
+		);
+	}
+}
@@ -0,0 +1,121 @@
+use std::io::{self, Write};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::ColorType;
+
+use super::ImageWriter;
+use crate::common::color::Color;
+
+/// Writes a baseline JPEG with the image crate's encoder, which needs the whole image at once, so
+/// every pixel is kept in memory until end(). Samples are rounded without dithering, since the
+/// noise from error diffusion only makes the file bigger once JPEG quantizes it
+pub struct JpegWriter<W: Write> {
+	/// None once the file has been written
+	dest: Option<W>,
+	width: usize,
+	height: usize,
+	/// 1 to 100
+	quality: u8,
+	/// pixels as RGB, top row first
+	pixels: Vec<u8>,
+}
+
+impl<W: Write> JpegWriter<W> {
+	pub fn new(dest: W, (width, height): (usize, usize), quality: u8) -> Self {
+		assert!((1..=100).contains(&quality));
+		Self {
+			dest: Some(dest),
+			width,
+			height,
+			quality,
+			pixels: Vec::with_capacity(width * height * 3),
+		}
+	}
+}
+
+impl<W: Write> ImageWriter for JpegWriter<W> {
+	fn write_header(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn write_pixels(&mut self, pixels: &[Color]) -> io::Result<()> {
+		for p in pixels {
+			self.pixels.extend(
+				p.to_array()
+					.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+			);
+		}
+		Ok(())
+	}
+
+	fn end(&mut self) -> io::Result<()> {
+		let Some(mut dest) = self.dest.take() else {
+			return Ok(());
+		};
+		JpegEncoder::new_with_quality(&mut dest, self.quality)
+			.encode(
+				&self.pixels,
+				self.width as u32,
+				self.height as u32,
+				ColorType::Rgb8,
+			)
+			.map_err(io::Error::other)?;
+		dest.flush()
+	}
+
+	/// fill in the rest of the image with black, since nothing has been written yet
+	fn abort(&mut self) -> io::Result<()> {
+		self.pixels.resize(self.width * self.height * 3, 0);
+		self.end()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// peak signal to noise ratio of 8-bit samples, in decibels
+	fn psnr(a: &[u8], b: &[u8]) -> f64 {
+		let mse = a
+			.iter()
+			.zip(b)
+			.map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+			.sum::<f64>()
+			/ a.len() as f64;
+		10.0 * (255.0 * 255.0 / mse).log10()
+	}
+
+	#[test]
+	fn test_psnr() {
+		let (width, height) = (64, 48);
+		let pixels: Vec<Color> = (0..width * height)
+			.map(|i| {
+				let (x, y) = ((i % width) as f64, (i / width) as f64);
+				Color::new(
+					x / width as f64,
+					y / height as f64,
+					0.5 + 0.5 * (x * 0.2).sin() * (y * 0.2).cos(),
+				)
+			})
+			.collect();
+
+		let mut last_size = 0;
+		for (quality, min_psnr) in [(50, 30.0), (90, 38.0), (100, 45.0)] {
+			let mut jpeg = Vec::new();
+			let mut writer = JpegWriter::new(&mut jpeg, (width, height), quality);
+			writer.write_header().unwrap();
+			writer.write_pixels(&pixels).unwrap();
+			writer.end().unwrap();
+			let expected = writer.pixels.clone();
+			drop(writer);
+
+			let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+			assert_eq!(decoded.dimensions(), (width as u32, height as u32));
+			let psnr = psnr(decoded.as_raw(), &expected);
+			assert!(psnr > min_psnr, "quality {}: {:.1} dB", quality, psnr);
+			// better quality takes more space
+			assert!(jpeg.len() > last_size);
+			last_size = jpeg.len();
+		}
+	}
+}
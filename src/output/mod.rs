@@ -1,15 +1,462 @@
+mod bmp;
+pub mod burn_in;
+pub mod bvh_dump;
+pub mod cryptomatte;
+pub mod depth;
+pub mod exr;
+pub mod font;
+mod jpeg;
 pub mod png;
 mod ppm;
+pub mod rawvideo;
+pub mod stats;
 
-pub use png::PngWriter;
+pub use bmp::BmpWriter;
+pub use jpeg::JpegWriter;
+pub use png::{ApngWriter, PngCompression, PngWriter};
 pub use ppm::PpmWriter;
+pub use rawvideo::RawVideoWriter;
 
-use std::io;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::time::Duration;
 
+use time::OffsetDateTime;
+
+use crate::common::color::Primaries;
 use crate::common::Color;
+use png::PngRenderingIntent;
+
+/// Parameters of a render, for formats that can store them alongside the image
+#[derive(Debug, Clone)]
+pub struct RenderMetadata {
+	/// name and version of this program
+	pub software: String,
+	pub command_line: String,
+	/// a canonical command line that renders the same image, which command_line might not if
+	/// the seeds came from the OS
+	pub reproduce: String,
+	pub scene: String,
+	pub world_seed: u64,
+	pub sample_seed: u64,
+	pub samples: usize,
+	pub render_time: Duration,
+}
+
+impl RenderMetadata {
+	/// metadata as human-readable key/value pairs, for formats that only support text
+	pub fn entries(&self) -> Vec<(&'static str, String)> {
+		vec![
+			("Software", self.software.clone()),
+			("Command line", self.command_line.clone()),
+			("Reproduce", self.reproduce.clone()),
+			("Scene", self.scene.clone()),
+			("World seed", self.world_seed.to_string()),
+			("Sample seed", self.sample_seed.to_string()),
+			("Samples per pixel", self.samples.to_string()),
+			(
+				"Render time",
+				format!("{:.3}s", self.render_time.as_secs_f64()),
+			),
+		]
+	}
+}
 
 pub trait ImageWriter {
+	/// store metadata about the render in the image, if the format supports it. must be called
+	/// before write_header
+	fn set_metadata(&mut self, metadata: &RenderMetadata) {
+		let _ = metadata;
+	}
 	fn write_header(&mut self) -> io::Result<()>;
 	fn write_pixels(&mut self, pixels: &[Color]) -> io::Result<()>;
+	/// write pixels along with their alpha (0 to 1, not premultiplied into the color). formats
+	/// without an alpha channel ignore it
+	fn write_pixels_with_alpha(&mut self, pixels: &[Color], alpha: &[f64]) -> io::Result<()> {
+		let _ = alpha;
+		self.write_pixels(pixels)
+	}
 	fn end(&mut self) -> io::Result<()>;
+	/// finish the file early after something went wrong partway through writing it, leaving it
+	/// as readable as the format allows with the pixels written so far. by default this is end(),
+	/// which flushes what's been written
+	fn abort(&mut self) -> io::Result<()> {
+		self.end()
+	}
+}
+
+/// Settings for an image that a writer needs besides its size
+pub struct OutputOptions {
+	pub bit_depth: u8,
+	pub alpha: bool,
+	pub primaries: Primaries,
+	/// when the image was made, for formats that record it
+	pub time: Option<OffsetDateTime>,
+	/// bytes of image data in each PNG IDAT chunk
+	pub png_chunk_size: usize,
+	pub png_compression: PngCompression,
+	/// 1 to 100
+	pub jpeg_quality: u8,
+	/// threads to compress with, for formats that can use more than one
+	pub threads: usize,
+}
+
+/// makes a writer for an image of the given size
+pub type WriterConstructor =
+	for<'a> fn(Box<dyn Write + 'a>, (usize, usize), &OutputOptions) -> Box<dyn ImageWriter + 'a>;
+
+/// An image format that can be chosen with --format or guessed from the output's extension
+pub struct Format {
+	/// what --format accepts, the first of which is the canonical name
+	pub names: &'static [&'static str],
+	/// file extensions without the dot
+	pub extensions: &'static [&'static str],
+	/// the format's usual name, for messages
+	pub title: &'static str,
+	/// bits per channel that the format can store
+	pub bit_depths: &'static [u8],
+	pub default_bit_depth: u8,
+	pub alpha: bool,
+	/// makes a writer for the format, or None for OpenEXR, which has layers that don't fit
+	/// ImageWriter and is written separately
+	pub writer: Option<WriterConstructor>,
+}
+
+impl Format {
+	pub fn name(&self) -> &'static str {
+		self.names[0]
+	}
+
+	/// the bit depths the format allows, for error messages
+	pub fn describe_bit_depths(&self) -> String {
+		let (first, last) = (self.bit_depths[0], *self.bit_depths.last().unwrap());
+		if first == last {
+			first.to_string()
+		} else if self.bit_depths.len() == (last - first) as usize + 1 {
+			format!("between {} and {}", first, last)
+		} else {
+			format!("{} or {}", first, last)
+		}
+	}
+}
+
+impl PartialEq for Format {
+	fn eq(&self, other: &Format) -> bool {
+		self.name() == other.name()
+	}
+}
+
+impl Debug for Format {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "Format({})", self.name())
+	}
+}
+
+pub static PNG: Format = Format {
+	names: &["png"],
+	extensions: &["png"],
+	title: "PNG",
+	bit_depths: &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+	default_bit_depth: 8,
+	alpha: true,
+	writer: Some(|output, size, options| {
+		let writer = PngWriter::new(
+			output,
+			size,
+			options.bit_depth,
+			options.time,
+			if options.primaries == Primaries::Srgb {
+				Some(PngRenderingIntent::Perceptual)
+			} else {
+				None
+			},
+			Some(options.primaries.chromaticities()),
+		);
+		let writer = writer
+			.with_chunk_size(options.png_chunk_size)
+			.with_compression(options.png_compression, options.threads);
+		Box::new(if options.alpha {
+			writer.with_alpha()
+		} else {
+			writer
+		})
+	}),
+};
+
+pub static PPM: Format = Format {
+	names: &["ppm"],
+	extensions: &["ppm"],
+	title: "PPM",
+	bit_depths: &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+	default_bit_depth: 8,
+	alpha: false,
+	writer: Some(|output, size, options| Box::new(PpmWriter::new(output, size, options.bit_depth))),
+};
+
+/// PPM with the samples written out as decimal text, which is only chosen with --format since it
+/// has the same extension
+pub static PPM_ASCII: Format = Format {
+	names: &["ppm-ascii"],
+	extensions: &[],
+	title: "ASCII PPM",
+	bit_depths: &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+	default_bit_depth: 8,
+	alpha: false,
+	writer: Some(|output, size, options| {
+		Box::new(PpmWriter::new(output, size, options.bit_depth).with_ascii())
+	}),
+};
+
+pub static BMP: Format = Format {
+	names: &["bmp"],
+	extensions: &["bmp"],
+	title: "BMP",
+	bit_depths: &[8],
+	default_bit_depth: 8,
+	alpha: false,
+	writer: Some(|output, size, _| Box::new(BmpWriter::new(output, size))),
+};
+
+pub static JPEG: Format = Format {
+	names: &["jpeg", "jpg"],
+	extensions: &["jpg", "jpeg"],
+	title: "JPEG",
+	bit_depths: &[8],
+	default_bit_depth: 8,
+	alpha: false,
+	writer: Some(|output, size, options| {
+		Box::new(JpegWriter::new(output, size, options.jpeg_quality))
+	}),
+};
+
+/// bare RGB frames, one after another, for piping an animation into ffmpeg
+pub static RAWVIDEO: Format = Format {
+	names: &["rawvideo"],
+	extensions: &[],
+	title: "raw video",
+	bit_depths: &[8, 16],
+	default_bit_depth: 8,
+	alpha: false,
+	writer: Some(|output, size, options| {
+		Box::new(RawVideoWriter::new(output, size, options.bit_depth))
+	}),
+};
+
+pub static EXR: Format = Format {
+	names: &["exr"],
+	extensions: &["exr"],
+	title: "OpenEXR",
+	bit_depths: &[16, 32],
+	default_bit_depth: 16,
+	alpha: true,
+	writer: None,
+};
+
+/// every output format, in the order --help lists them
+pub static FORMATS: [&Format; 7] = [&PNG, &PPM, &PPM_ASCII, &BMP, &JPEG, &RAWVIDEO, &EXR];
+
+/// The format with the given name, as passed to --format
+pub fn format_by_name(name: &str) -> Option<&'static Format> {
+	FORMATS
+		.iter()
+		.copied()
+		.find(|format| format.names.contains(&name))
+}
+
+/// The format to write a file in, from its extension
+pub fn format_from_extension(filename: &str) -> Option<&'static Format> {
+	let (_, extension) = filename.rsplit_once('.')?;
+	FORMATS
+		.iter()
+		.copied()
+		.find(|format| format.extensions.contains(&extension))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{mpsc, Arc};
+	use std::time::Duration;
+
+	use ::exr::image::AnyChannel;
+	use smallvec::smallvec;
+
+	use super::exr::{plane_from_image, write_exr};
+	use super::*;
+	use crate::common::color::Primaries;
+	use crate::common::raytracer::{
+		render, Aov, RenderSettings, RngEngine, RngFactory, SceneContext,
+	};
+	use crate::common::tile_queue::TileQueue;
+	use crate::common::watchdog::Heartbeat;
+	use crate::common::ImageBuffer;
+	use crate::scene::scenes;
+
+	/// FNV-1a, to compare outputs without keeping whole files around
+	fn fnv1a(bytes: &[u8]) -> u64 {
+		bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+			(hash ^ b as u64).wrapping_mul(0x100000001b3)
+		})
+	}
+
+	/// render a small square image with a fixed seed
+	/// tiles: order to render the tiles in, or None for scanline order
+	fn render_scene(
+		scene: scenes::Scene,
+		size: usize,
+		tiles: Option<TileQueue>,
+	) -> (ImageBuffer<Color>, ImageBuffer<Aov>) {
+		let (world, cam, background, lights) = scene;
+		let (send, recv) = mpsc::channel();
+		render(
+			send,
+			RngFactory::new(RngEngine::Xoshiro, 7),
+			&SceneContext {
+				background,
+				world: &world,
+				lights: &lights.into(),
+				fog: None,
+			},
+			cam,
+			&RenderSettings {
+				size: (size, size),
+				samples_per_pixel: 2,
+				max_depth: 10,
+				glass_depth_bonus: 0,
+				debug_mode: None,
+			},
+			Arc::new(tiles.unwrap_or_else(|| TileQueue::scanline((size, size)))),
+			Arc::new(Heartbeat::new()),
+		)
+		.unwrap();
+		let mut image = ImageBuffer::new((size, size), Color::zero());
+		let mut aovs = ImageBuffer::new((size, size), Aov::default());
+		for tile in recv.try_iter() {
+			tile.copy_to(&mut image, &mut aovs);
+		}
+		(image, aovs)
+	}
+
+	fn png(image: &ImageBuffer<Color>, aovs: &ImageBuffer<Aov>) -> Vec<u8> {
+		let mut png = Vec::new();
+		let mut writer = PngWriter::new(&mut png, image.size(), 8, None, None, None).with_alpha();
+		writer.write_header().unwrap();
+		let alpha = aovs.map(|a| a.alpha);
+		for (row, alpha_row) in image.rows().zip(alpha.rows()) {
+			writer.write_pixels_with_alpha(row, alpha_row).unwrap();
+		}
+		writer.end().unwrap();
+		drop(writer);
+		png
+	}
+
+	#[test]
+	fn test_ported_scenes_unchanged() {
+		// recorded when the scenes were ported from the old scene module, again when IDAT chunks
+		// became a fixed size, which changed the PNG bytes but not the pixels, again for
+		// refraction when ray differentials started filtering its checkered floor, again when
+		// every sample got its own random numbers, and again when camera rays got streams of
+		// their own
+		for (scene, expected) in [
+			(scenes::figure19(), 8503916444014964973),
+			(scenes::refraction(), 15976292327890142819),
+		] {
+			let (image, aovs) = render_scene(scene, 24, None);
+			assert_eq!(fnv1a(&png(&image, &aovs)), expected);
+		}
+	}
+
+	#[test]
+	fn test_output_unchanged() {
+		let size = 24;
+		let (image, aovs) = render_scene(scenes::cornell_box(), size, None);
+		let png = png(&image, &aovs);
+
+		let mut exr = Vec::new();
+		write_exr(
+			&mut exr,
+			(size, size),
+			smallvec![
+				AnyChannel::new("R", plane_from_image(&image, 16, |p| p.x())),
+				AnyChannel::new("G", plane_from_image(&image, 16, |p| p.y())),
+				AnyChannel::new("B", plane_from_image(&image, 16, |p| p.z())),
+				AnyChannel::new("Z", plane_from_image(&aovs, 32, |a| a.depth)),
+			],
+			Primaries::Srgb.chromaticities(),
+			None,
+			&[],
+		)
+		.unwrap();
+
+		// recorded when every sample got its own random numbers, which changed what every sample
+		// seed renders, and again when camera rays got streams of their own
+		assert_eq!(
+			(fnv1a(&png), fnv1a(&exr)),
+			(16255367258837296725, 13152941725263630150)
+		);
+	}
+
+	#[test]
+	fn test_tile_order_unchanged() {
+		let size = 40;
+		let (scanline, _) = render_scene(scenes::cornell_box(), size, None);
+		// the last tile in scanline order is the most expensive
+		let costs: Vec<Duration> = (0..9).map(Duration::from_millis).collect();
+		let tiles = TileQueue::by_cost((size, size), &costs);
+		assert_eq!(tiles.tiles()[0], (32, 32));
+		let (by_cost, _) = render_scene(scenes::cornell_box(), size, Some(tiles));
+		assert!(scanline
+			.rows()
+			.flatten()
+			.zip(by_cost.rows().flatten())
+			.all(|(a, b)| (a.x(), a.y(), a.z()) == (b.x(), b.y(), b.z())));
+	}
+
+	#[test]
+	fn test_format_registry() {
+		for (i, format) in FORMATS.iter().enumerate() {
+			// names and extensions only belong to one format
+			for other in &FORMATS[i + 1..] {
+				assert!(format.names.iter().all(|n| !other.names.contains(n)));
+				assert!(format
+					.extensions
+					.iter()
+					.all(|e| !other.extensions.contains(e)));
+			}
+			assert!(format.bit_depths.contains(&format.default_bit_depth));
+			for name in format.names {
+				assert_eq!(format_by_name(name), Some(*format));
+			}
+			for extension in format.extensions {
+				let filename = format!("renders/final.{}", extension);
+				assert_eq!(format_from_extension(&filename), Some(*format));
+			}
+
+			let Some(writer) = format.writer else {
+				continue;
+			};
+			let options = OutputOptions {
+				bit_depth: format.default_bit_depth,
+				alpha: format.alpha,
+				primaries: Primaries::Srgb,
+				time: None,
+				png_chunk_size: png::IDAT_SIZE,
+				png_compression: PngCompression::Default,
+				jpeg_quality: 90,
+				threads: 1,
+			};
+			let mut bytes = Vec::new();
+			let mut writer = writer(Box::new(&mut bytes), (2, 1), &options);
+			writer.write_header().unwrap();
+			writer
+				.write_pixels_with_alpha(&[Color::zero(), Color::new(1.0, 1.0, 1.0)], &[1.0, 0.5])
+				.unwrap();
+			writer.end().unwrap();
+			drop(writer);
+			assert!(!bytes.is_empty());
+		}
+		assert_eq!(format_from_extension("final.tga"), None);
+		assert_eq!(format_from_extension("png"), None);
+		assert_eq!(EXR.describe_bit_depths(), "16 or 32");
+		assert_eq!(PNG.describe_bit_depths(), "between 1 and 16");
+	}
 }
@@ -0,0 +1,229 @@
+use std::io::{self, Write};
+
+use super::chunk::PngChunk;
+use super::SIGNATURE;
+
+/// a chunk's tag, its data, and the whole chunk including its length and CRC
+type RawChunk<'a> = ([u8; 4], &'a [u8], &'a [u8]);
+
+/// The chunks of a PNG file, in order
+fn chunks(png: &[u8]) -> io::Result<Vec<RawChunk<'_>>> {
+	let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "frame isn't a valid PNG");
+	let mut rest = png.strip_prefix(&SIGNATURE).ok_or_else(invalid)?;
+	let mut chunks = Vec::new();
+	while !rest.is_empty() {
+		if rest.len() < 12 {
+			return Err(invalid());
+		}
+		let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+		if rest.len() < 12 + length {
+			return Err(invalid());
+		}
+		let tag = rest[4..8].try_into().unwrap();
+		chunks.push((tag, &rest[8..8 + length], &rest[..12 + length]));
+		rest = &rest[12 + length..];
+	}
+	Ok(chunks)
+}
+
+/// Joins PNG files of the same size into an animated PNG, one frame at a time. The first frame is
+/// written as it is with animation chunks added, so viewers that don't know about APNG show it as
+/// a still image
+pub struct ApngWriter<W: Write> {
+	dest: W,
+	frames: u32,
+	/// seconds each frame is shown for, as a numerator and denominator
+	delay: (u16, u16),
+	frames_written: u32,
+	/// shared by every fcTL and fdAT chunk
+	sequence: u32,
+	/// IHDR of the first frame, which the others must match
+	header: Vec<u8>,
+}
+
+impl<W: Write> ApngWriter<W> {
+	/// frames: how many frames will be written, which has to be known before the first one
+	/// fps: frames per second
+	pub fn new(dest: W, frames: u32, fps: u16) -> Self {
+		assert!(frames > 0 && fps > 0);
+		Self {
+			dest,
+			frames,
+			delay: (1, fps),
+			frames_written: 0,
+			sequence: 0,
+			header: Vec::new(),
+		}
+	}
+
+	fn frame_control(&mut self) -> io::Result<()> {
+		let size = |i: usize| u32::from_be_bytes(self.header[i..i + 4].try_into().unwrap());
+		let chunk = PngChunk::Fctl {
+			sequence: self.sequence,
+			width: size(0),
+			height: size(4),
+			delay: self.delay,
+		};
+		self.sequence += 1;
+		chunk.write_to(&mut self.dest)
+	}
+
+	/// add a frame from a whole PNG file
+	pub fn write_frame(&mut self, png: &[u8]) -> io::Result<()> {
+		if self.frames_written == self.frames {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"more frames than the animation was started with",
+			));
+		}
+		let first = self.frames_written == 0;
+		if first {
+			self.dest.write_all(&SIGNATURE)?;
+		}
+		let mut started = false;
+		for (tag, data, chunk) in chunks(png)? {
+			match &tag {
+				b"IHDR" if first => {
+					self.header = data.to_vec();
+					self.dest.write_all(chunk)?;
+					PngChunk::Actl {
+						frames: self.frames,
+						plays: 0,
+					}
+					.write_to(&mut self.dest)?;
+				},
+				b"IHDR" if data != self.header => {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidInput,
+						"frames must have the same size and format",
+					));
+				},
+				b"IDAT" => {
+					if !started {
+						self.frame_control()?;
+						started = true;
+					}
+					if first {
+						self.dest.write_all(chunk)?;
+					} else {
+						PngChunk::Fdat {
+							sequence: self.sequence,
+							data,
+						}
+						.write_to(&mut self.dest)?;
+						self.sequence += 1;
+					}
+				},
+				b"IEND" => break,
+				// the first frame's metadata is kept for the whole file
+				_ if first && !started => self.dest.write_all(chunk)?,
+				_ => {},
+			}
+		}
+		self.frames_written += 1;
+		Ok(())
+	}
+
+	/// finish the file once every frame is written
+	pub fn end(&mut self) -> io::Result<()> {
+		if self.frames_written != self.frames {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"fewer frames than the animation was started with",
+			));
+		}
+		PngChunk::Iend.write_to(&mut self.dest)?;
+		self.dest.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use image::codecs::png::PngDecoder;
+	use image::AnimationDecoder;
+
+	use super::*;
+	use crate::common::color::Color;
+	use crate::output::{ImageWriter, PngWriter};
+
+	fn frame(color: Color, chunk_size: usize) -> Vec<u8> {
+		let mut png = Vec::new();
+		let mut writer =
+			PngWriter::new(&mut png, (3, 2), 8, None, None, None).with_chunk_size(chunk_size);
+		writer.write_header().unwrap();
+		writer.write_pixels(&[color; 6]).unwrap();
+		writer.end().unwrap();
+		drop(writer);
+		png
+	}
+
+	#[test]
+	fn test_apng() {
+		let colors = [
+			Color::new(1.0, 0.0, 0.0),
+			Color::new(0.0, 1.0, 0.0),
+			Color::new(0.0, 0.0, 1.0),
+		];
+		let mut apng = Vec::new();
+		let mut writer = ApngWriter::new(&mut apng, 3, 12);
+		for color in colors {
+			// small chunks so each frame has several
+			writer.write_frame(&frame(color, 4)).unwrap();
+		}
+		writer.end().unwrap();
+
+		let chunks = chunks(&apng).unwrap();
+		let tags: Vec<&[u8; 4]> = chunks.iter().map(|(tag, ..)| tag).collect();
+		let count = |tag| tags.iter().filter(|&&t| t == tag).count();
+		// acTL right after IHDR, and each frame's fcTL before its data
+		assert_eq!(tags[..3], [b"IHDR", b"acTL", b"fcTL"]);
+		assert_eq!(tags.last(), Some(&b"IEND"));
+		assert_eq!(count(b"fcTL"), 3);
+		let first_fdat = tags.iter().position(|&t| t == b"fdAT").unwrap();
+		assert!(tags[3..first_fdat - 1].iter().all(|&t| t == b"IDAT"));
+		assert_eq!(tags[first_fdat - 1], b"fcTL");
+		assert!(count(b"IDAT") > 1);
+
+		// sequence numbers go up by one across fcTL and fdAT
+		let sequences: Vec<u32> = chunks
+			.iter()
+			.filter(|(tag, ..)| tag == b"fcTL" || tag == b"fdAT")
+			.map(|(_, data, _)| u32::from_be_bytes(data[..4].try_into().unwrap()))
+			.collect();
+		assert_eq!(sequences, (0..sequences.len() as u32).collect::<Vec<_>>());
+		let (_, actl, _) = chunks[1];
+		assert_eq!(actl, [0, 0, 0, 3, 0, 0, 0, 0]);
+
+		// without the animation chunks, it's the first frame
+		let decoded = image::load_from_memory(&apng).unwrap().to_rgb8();
+		assert_eq!(decoded.dimensions(), (3, 2));
+		assert!(decoded.pixels().all(|p| p.0 == [255, 0, 0]));
+
+		// and with them, it's every frame
+		let decoder = PngDecoder::new(&apng[..]).unwrap();
+		assert!(decoder.is_apng());
+		let frames = decoder.apng().into_frames().collect_frames().unwrap();
+		assert_eq!(frames.len(), 3);
+		for (frame, color) in frames.iter().zip(colors) {
+			let expected = [color.x(), color.y(), color.z(), 1.0].map(|c| (c * 255.0) as u8);
+			assert!(frame.buffer().pixels().all(|p| p.0 == expected));
+			// 1/12 of a second
+			let (numerator, denominator) = frame.delay().numer_denom_ms();
+			assert_eq!(numerator * 12, denominator * 1000);
+		}
+	}
+
+	#[test]
+	fn test_mismatched_frames() {
+		let mut writer = ApngWriter::new(Vec::new(), 2, 24);
+		writer.write_frame(&frame(Color::zero(), 8192)).unwrap();
+		let mut png = Vec::new();
+		let mut other = PngWriter::new(&mut png, (2, 2), 8, None, None, None);
+		other.write_header().unwrap();
+		other.write_pixels(&[Color::zero(); 4]).unwrap();
+		other.end().unwrap();
+		drop(other);
+		assert!(writer.write_frame(&png).is_err());
+		assert!(writer.end().is_err());
+	}
+}
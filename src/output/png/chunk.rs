@@ -1,8 +1,9 @@
 use std::io::{self, Write};
 
-use once_cell::unsync::Lazy;
 use time::{OffsetDateTime, UtcOffset};
 
+use crate::common::color::Chromaticities;
+
 #[repr(u8)]
 #[derive(Clone, Copy)]
 #[allow(dead_code)]
@@ -25,12 +26,17 @@ pub enum PngChunk<'a> {
 		width: u32,
 		height: u32,
 		bit_depth: u8,
+		alpha: bool,
 	},
 	Idat(&'a [u8]),
 	Iend,
-	Sbit(u8),
+	Sbit {
+		bits: u8,
+		alpha: bool,
+	},
 	Gama(f64),
 	Srgb(PngRenderingIntent),
+	Chrm(Chromaticities),
 	Itxt {
 		keyword: String,
 		language: Option<String>,
@@ -38,6 +44,59 @@ pub enum PngChunk<'a> {
 		text: TextData,
 	},
 	Time(OffsetDateTime),
+	/// APNG animation control: number of frames and times to play them, where 0 loops forever
+	Actl {
+		frames: u32,
+		plays: u32,
+	},
+	/// APNG frame control, before the data of each frame. frames cover the whole image and replace
+	/// the one before them
+	Fctl {
+		sequence: u32,
+		width: u32,
+		height: u32,
+		/// numerator and denominator of how many seconds to show the frame for
+		delay: (u16, u16),
+	},
+	/// image data of an APNG frame after the first
+	Fdat {
+		sequence: u32,
+		data: &'a [u8],
+	},
+}
+
+/// CRC lookup tables for slicing-by-8: TABLES[0] is the usual byte-at-a-time table, and TABLES[k]
+/// gives the CRC of a byte followed by k zero bytes
+static TABLES: [[u32; 256]; 8] = crc_tables();
+
+const fn crc_tables() -> [[u32; 256]; 8] {
+	let mut tables = [[0u32; 256]; 8];
+	let mut i = 0;
+	while i < 256 {
+		let mut c = i as u32;
+		let mut bit = 0;
+		while bit < 8 {
+			c = if c & 1 != 0 {
+				0xedb88320 ^ (c >> 1)
+			} else {
+				c >> 1
+			};
+			bit += 1;
+		}
+		tables[0][i] = c;
+		i += 1;
+	}
+	let mut k = 1;
+	while k < 8 {
+		let mut i = 0;
+		while i < 256 {
+			let c = tables[k - 1][i];
+			tables[k][i] = tables[0][(c & 0xff) as usize] ^ (c >> 8);
+			i += 1;
+		}
+		k += 1;
+	}
+	tables
 }
 
 struct Crc32<W: Write>(u32, W);
@@ -48,24 +107,21 @@ impl<W: Write> Crc32<W> {
 	}
 
 	fn update(&mut self, buf: &[u8]) {
-		let table: Lazy<[u32; 256]> = Lazy::new(|| {
-			let mut table = [0u32; 256];
-			for i in 0..256 {
-				let mut c: u32 = i;
-				for _ in 0..8 {
-					if c & 1 != 0 {
-						c = 0xedb88320 ^ (c >> 1);
-					} else {
-						c = c >> 1;
-					}
-				}
-				table[i as usize] = c;
-			}
-			table
-		});
-
-		for &b in buf {
-			self.0 = table[((self.0 ^ b as u32) & 0xff) as usize] ^ (self.0 >> 8);
+		let t = &TABLES;
+		let mut chunks = buf.chunks_exact(8);
+		for chunk in &mut chunks {
+			let low = self.0 ^ u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+			self.0 = t[7][(low & 0xff) as usize]
+				^ t[6][((low >> 8) & 0xff) as usize]
+				^ t[5][((low >> 16) & 0xff) as usize]
+				^ t[4][(low >> 24) as usize]
+				^ t[3][chunk[4] as usize]
+				^ t[2][chunk[5] as usize]
+				^ t[1][chunk[6] as usize]
+				^ t[0][chunk[7] as usize];
+		}
+		for &b in chunks.remainder() {
+			self.0 = t[0][((self.0 ^ b as u32) & 0xff) as usize] ^ (self.0 >> 8);
 		}
 	}
 
@@ -88,6 +144,13 @@ impl<W: Write> Write for Crc32<W> {
 	}
 }
 
+/// the CRC of data on its own
+pub(super) fn crc(data: &[u8]) -> u32 {
+	let mut crc = Crc32::new(io::sink());
+	crc.update(data);
+	crc.get()
+}
+
 impl<'a> PngChunk<'a> {
 	fn tag(&self) -> &'static [u8; 4] {
 		match self {
@@ -95,12 +158,14 @@ impl<'a> PngChunk<'a> {
 				width: _,
 				height: _,
 				bit_depth: _,
+				alpha: _,
 			} => b"IHDR",
 			PngChunk::Idat(_) => b"IDAT",
 			PngChunk::Iend => b"IEND",
-			PngChunk::Sbit(_) => b"sBIT",
+			PngChunk::Sbit { bits: _, alpha: _ } => b"sBIT",
 			PngChunk::Gama(_) => b"gAMA",
 			PngChunk::Srgb(_) => b"sRGB",
+			PngChunk::Chrm(_) => b"cHRM",
 			PngChunk::Itxt {
 				keyword: _,
 				language: _,
@@ -108,6 +173,9 @@ impl<'a> PngChunk<'a> {
 				text: _,
 			} => b"iTXt",
 			PngChunk::Time(_) => b"tIME",
+			PngChunk::Actl { .. } => b"acTL",
+			PngChunk::Fctl { .. } => b"fcTL",
+			PngChunk::Fdat { .. } => b"fdAT",
 		}
 	}
 
@@ -117,12 +185,20 @@ impl<'a> PngChunk<'a> {
 				width: _,
 				height: _,
 				bit_depth: _,
+				alpha: _,
 			} => 13,
 			PngChunk::Idat(data) => data.len(),
 			PngChunk::Iend => 0,
-			PngChunk::Sbit(_) => 3,
+			&PngChunk::Sbit { bits: _, alpha } => {
+				if alpha {
+					4
+				} else {
+					3
+				}
+			},
 			PngChunk::Gama(_) => 4,
 			PngChunk::Srgb(_) => 1,
+			PngChunk::Chrm(_) => 32,
 			PngChunk::Itxt {
 				keyword,
 				language,
@@ -145,6 +221,9 @@ impl<'a> PngChunk<'a> {
 					}
 			},
 			PngChunk::Time(_) => 7,
+			PngChunk::Actl { .. } => 8,
+			PngChunk::Fctl { .. } => 26,
+			PngChunk::Fdat { data, .. } => 4 + data.len(),
 		}
 	}
 
@@ -158,11 +237,14 @@ impl<'a> PngChunk<'a> {
 				width,
 				height,
 				bit_depth,
+				alpha,
 			} => {
 				crc.write_all(&width.to_be_bytes())?;
 				crc.write_all(&height.to_be_bytes())?;
 				crc.write_all(&[
-					bit_depth, 2, // color type 2 = truecolor
+					bit_depth,
+					// color type 2 = truecolor, 6 = truecolor with alpha
+					if alpha { 6 } else { 2 },
 					0, // compression method 0 = deflate
 					0, // filter method 0 = adaptive with 5 types
 					0, // interlace method 0 = not interlaced
@@ -172,9 +254,12 @@ impl<'a> PngChunk<'a> {
 				crc.write_all(data)?;
 			},
 			PngChunk::Iend => {},
-			&PngChunk::Sbit(bits) => {
-				// write it three times for red, green, and blue
+			&PngChunk::Sbit { bits, alpha } => {
+				// write it once each for red, green, blue, and alpha if present
 				crc.write_all(&[bits, bits, bits])?;
+				if alpha {
+					crc.write_all(&[bits])?;
+				}
 			},
 			&PngChunk::Gama(gamma) => {
 				let integer_gamma = (gamma * 100_000.0) as u32;
@@ -183,6 +268,12 @@ impl<'a> PngChunk<'a> {
 			&PngChunk::Srgb(intent) => {
 				crc.write_all(&[intent as u8])?;
 			},
+			PngChunk::Chrm(c) => {
+				for (x, y) in [c.white, c.red, c.green, c.blue] {
+					crc.write_all(&((x * 100_000.0).round() as u32).to_be_bytes())?;
+					crc.write_all(&((y * 100_000.0).round() as u32).to_be_bytes())?;
+				}
+			},
 			PngChunk::Itxt {
 				keyword,
 				language,
@@ -223,6 +314,32 @@ impl<'a> PngChunk<'a> {
 					utc_time.second(),
 				])?;
 			},
+			&PngChunk::Actl { frames, plays } => {
+				crc.write_all(&frames.to_be_bytes())?;
+				crc.write_all(&plays.to_be_bytes())?;
+			},
+			&PngChunk::Fctl {
+				sequence,
+				width,
+				height,
+				delay: (numerator, denominator),
+			} => {
+				crc.write_all(&sequence.to_be_bytes())?;
+				crc.write_all(&width.to_be_bytes())?;
+				crc.write_all(&height.to_be_bytes())?;
+				// x and y offset
+				crc.write_all(&[0; 8])?;
+				crc.write_all(&numerator.to_be_bytes())?;
+				crc.write_all(&denominator.to_be_bytes())?;
+				crc.write_all(&[
+					0, // dispose op 0 = leave the frame as it is
+					0, // blend op 0 = replace what was there
+				])?;
+			},
+			PngChunk::Fdat { sequence, data } => {
+				crc.write_all(&sequence.to_be_bytes())?;
+				crc.write_all(data)?;
+			},
 		}
 
 		// we write to the CRC here since it's easier than accessing the original stream that is now
@@ -231,3 +348,64 @@ impl<'a> PngChunk<'a> {
 		crc.write_all(&crc.get().to_be_bytes())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::time::Instant;
+
+	use super::*;
+
+	/// the CRC one byte at a time, without the extra tables
+	fn crc_bytewise(data: &[u8]) -> u32 {
+		!data.iter().fold(0xffffffff, |c, &b| {
+			TABLES[0][((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+		})
+	}
+
+	#[test]
+	fn test_crc32() {
+		assert_eq!(crc(b""), 0);
+		assert_eq!(crc(b"123456789"), 0xcbf43926);
+		assert_eq!(
+			crc(b"The quick brown fox jumps over the lazy dog"),
+			0x414fa339
+		);
+		assert_eq!(crc(b"IEND"), 0xae426082);
+
+		let data: Vec<u8> = (0..1000u32).map(|i| ((i * 7919) >> 3) as u8).collect();
+		for len in 0..40 {
+			assert_eq!(crc(&data[..len]), crc_bytewise(&data[..len]));
+		}
+		// the same however the data is split up between writes
+		for split in [1, 3, 8, 13, 500] {
+			let mut crc = Crc32::new(Vec::new());
+			for piece in data.chunks(split) {
+				crc.write_all(piece).unwrap();
+			}
+			assert_eq!(crc.get(), crc_bytewise(&data));
+			// and everything is passed through
+			assert_eq!(crc.1, data);
+		}
+	}
+
+	/// run with `cargo test --release -- --ignored bench_crc32 --nocapture`
+	#[test]
+	#[ignore]
+	fn bench_crc32() {
+		let data = vec![0x5au8; 64 << 20];
+		for (name, f) in [
+			("slicing-by-8", crc as fn(&[u8]) -> u32),
+			("bytewise", crc_bytewise),
+		] {
+			let start = Instant::now();
+			let result = f(&data);
+			let elapsed = start.elapsed();
+			eprintln!(
+				"{:>12}: {:.0} MB/s ({:08x})",
+				name,
+				data.len() as f64 / 1e6 / elapsed.as_secs_f64(),
+				result
+			);
+		}
+	}
+}
@@ -1,4 +1,7 @@
+mod apng;
 mod chunk;
+mod parallel;
+mod verify;
 
 use std::io::{self, BufWriter, Write};
 
@@ -6,32 +9,117 @@ use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use time::OffsetDateTime;
 
-use super::ImageWriter;
-use crate::common::color::{Color, Dither};
-use chunk::PngChunk;
+use super::{ImageWriter, RenderMetadata};
+use crate::common::color::{Chromaticities, Color, Dither};
+use chunk::{PngChunk, TextData};
+use parallel::{ParallelZlibEncoder, GROUP_SIZE};
 
+pub use apng::ApngWriter;
 pub use chunk::PngRenderingIntent;
+pub use verify::verify;
 
-const IDAT_SIZE: usize = 8192;
+/// default size of the data in each IDAT chunk
+pub const IDAT_SIZE: usize = 8192;
 
-/// writes slices to the underlying writer in the form of IDAT chunks
-struct IdatWriter<W: Write>(pub W);
+/// the first 8 bytes of every PNG file
+const SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// writes slices to the underlying writer in the form of IDAT chunks, all of the same size except
+/// for the last one
+struct IdatWriter<W: Write> {
+	dest: W,
+	/// data that hasn't filled up a chunk yet
+	pending: Vec<u8>,
+	chunk_size: usize,
+}
 
 impl<W: Write> IdatWriter<W> {
+	pub fn new(dest: W, chunk_size: usize) -> Self {
+		assert!(chunk_size > 0);
+		Self {
+			dest,
+			pending: Vec::with_capacity(chunk_size),
+			chunk_size,
+		}
+	}
+
+	/// write whatever is left as a last, smaller chunk
 	pub fn finish(mut self) -> io::Result<W> {
-		self.0.flush()?;
-		Ok(self.0)
+		if !self.pending.is_empty() {
+			PngChunk::Idat(&self.pending).write_to(&mut self.dest)?;
+		}
+		self.dest.flush()?;
+		Ok(self.dest)
 	}
 }
 
 impl<W: Write> Write for IdatWriter<W> {
+	/// only flushes the underlying writer, since writing out the pending data would make a short
+	/// chunk in the middle of the stream
+	fn flush(&mut self) -> io::Result<()> {
+		self.dest.flush()
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = buf.len().min(self.chunk_size - self.pending.len());
+		self.pending.extend_from_slice(&buf[..n]);
+		if self.pending.len() == self.chunk_size {
+			PngChunk::Idat(&self.pending).write_to(&mut self.dest)?;
+			self.pending.clear();
+		}
+		Ok(n)
+	}
+}
+
+/// How the image data is compressed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PngCompression {
+	/// zlib's default level on one thread
+	Default,
+	/// zlib's fastest level on one thread, for a bigger file sooner
+	Fast,
+	/// the default level on several threads, in pieces that make the file a little bigger
+	Parallel,
+}
+
+/// the zlib stream that filtered scanlines are compressed into
+enum Compressor<W: Write> {
+	Serial(ZlibEncoder<W>),
+	Parallel(ParallelZlibEncoder<W>),
+}
+
+impl<W: Write> Compressor<W> {
+	fn new(dest: W, compression: PngCompression, threads: usize) -> Self {
+		match compression {
+			PngCompression::Default => Self::Serial(ZlibEncoder::new(dest, Compression::default())),
+			PngCompression::Fast => Self::Serial(ZlibEncoder::new(dest, Compression::fast())),
+			PngCompression::Parallel => {
+				Self::Parallel(ParallelZlibEncoder::new(dest, threads, GROUP_SIZE))
+			},
+		}
+	}
+
+	pub fn finish(self) -> io::Result<W> {
+		match self {
+			Self::Serial(encoder) => encoder.finish(),
+			Self::Parallel(encoder) => encoder.finish(),
+		}
+	}
+}
+
+impl<W: Write> Write for Compressor<W> {
 	fn flush(&mut self) -> io::Result<()> {
-		self.0.flush()
+		match self {
+			Self::Serial(encoder) => encoder.flush(),
+			Self::Parallel(encoder) => encoder.flush(),
+		}
 	}
 
 	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-		PngChunk::Idat(buf).write_to(&mut self.0)?;
-		Ok(buf.len())
+		match self {
+			Self::Serial(encoder) => encoder.write(buf),
+			Self::Parallel(encoder) => encoder.write(buf),
+		}
 	}
 }
 
@@ -89,13 +177,24 @@ impl<W: Write> Write for FilterWriter<W> {
 
 pub struct PngWriter<W: Write> {
 	buf: Option<BufWriter<W>>,
-	pixel_writer: Option<BufWriter<FilterWriter<ZlibEncoder<IdatWriter<BufWriter<W>>>>>>,
+	pixel_writer: Option<BufWriter<FilterWriter<Compressor<IdatWriter<BufWriter<W>>>>>>,
 	width: usize,
 	height: usize,
 	bits: u8,
 	time: Option<OffsetDateTime>,
 	srgb: Option<PngRenderingIntent>,
+	chromaticities: Option<Chromaticities>,
+	metadata: Option<RenderMetadata>,
 	dither: Dither,
+	/// ditherer for the alpha channel, if the image has one
+	alpha_dither: Option<Dither>,
+	header_written: bool,
+	pixels_written: usize,
+	/// size of the data in each IDAT chunk but the last
+	chunk_size: usize,
+	compression: PngCompression,
+	/// threads to use for PngCompression::Parallel
+	threads: usize,
 }
 
 impl<W: Write> PngWriter<W> {
@@ -105,37 +204,127 @@ impl<W: Write> PngWriter<W> {
 		bits: u8,
 		time: Option<OffsetDateTime>,
 		srgb: Option<PngRenderingIntent>,
+		chromaticities: Option<Chromaticities>,
 	) -> Self {
 		assert!(bits > 0 && bits <= 16);
 		Self {
-			buf: Some(BufWriter::with_capacity(IDAT_SIZE + 12, dest)),
+			buf: Some(BufWriter::new(dest)),
 			pixel_writer: None,
 			width,
 			height,
 			bits,
 			time,
 			srgb,
+			chromaticities,
+			metadata: None,
 			dither: Dither::new(bits, width),
+			alpha_dither: None,
+			header_written: false,
+			pixels_written: 0,
+			chunk_size: IDAT_SIZE,
+			compression: PngCompression::Default,
+			threads: 1,
+		}
+	}
+
+	/// split the image data into IDAT chunks of this many bytes, instead of IDAT_SIZE
+	pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+		self.chunk_size = chunk_size;
+		self
+	}
+
+	/// compress the image data differently
+	/// threads: how many threads to use with PngCompression::Parallel
+	pub fn with_compression(mut self, compression: PngCompression, threads: usize) -> Self {
+		self.compression = compression;
+		self.threads = threads;
+		self
+	}
+
+	/// add an alpha channel to the image
+	pub fn with_alpha(mut self) -> Self {
+		self.alpha_dither = Some(Dither::new(self.bits, self.width));
+		self
+	}
+
+	fn channels(&self) -> usize {
+		if self.alpha_dither.is_some() {
+			4
+		} else {
+			3
+		}
+	}
+
+	/// bytes per sample as stored in the file
+	fn sample_size(&self) -> usize {
+		if self.bits <= 8 {
+			1
+		} else {
+			2
+		}
+	}
+
+	/// the compressed stream that pixels are written to, started on the first call
+	fn pixel_writer(
+		&mut self,
+	) -> &mut BufWriter<FilterWriter<Compressor<IdatWriter<BufWriter<W>>>>> {
+		if self.pixel_writer.is_none() {
+			self.pixel_writer = Some(BufWriter::with_capacity(
+				IDAT_SIZE,
+				FilterWriter::new(
+					Compressor::new(
+						IdatWriter::new(self.buf.take().unwrap(), self.chunk_size),
+						self.compression,
+						self.threads,
+					),
+					FilterType::None,
+					self.width * self.channels() * self.sample_size(),
+				),
+			));
+		}
+		self.pixel_writer.as_mut().unwrap()
+	}
+
+	/// scale a dithered sample from the image's bit depth up to 8 or 16 bits
+	fn expand(&self, mut sample: u16) -> u16 {
+		let mut written_bits = if self.bits > 8 { 16 } else { 8 };
+		sample <<= written_bits - self.bits;
+
+		// repeat most significant bits into the lower ones so that the overall sample ranges
+		// from all zeroes to all ones
+		while written_bits > self.bits {
+			sample |= sample >> self.bits;
+			written_bits -= self.bits;
 		}
+		sample
 	}
 }
 
 impl<W: Write> ImageWriter for PngWriter<W> {
+	fn set_metadata(&mut self, metadata: &RenderMetadata) {
+		self.metadata = Some(metadata.clone());
+	}
+
 	fn write_header(&mut self) -> io::Result<()> {
 		let buf = self.buf.as_mut().unwrap();
 
-		let signature = [0x89u8, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
-		buf.write_all(&signature)?;
+		buf.write_all(&SIGNATURE)?;
 
 		let header = PngChunk::Ihdr {
 			width: self.width as u32,
 			height: self.height as u32,
 			bit_depth: if self.bits <= 8 { 8 } else { 16 },
+			alpha: self.alpha_dither.is_some(),
 		};
 		header.write_to(buf)?;
+		self.header_written = true;
 
 		if self.bits != 8 && self.bits != 16 {
-			PngChunk::Sbit(self.bits).write_to(buf)?;
+			PngChunk::Sbit {
+				bits: self.bits,
+				alpha: self.alpha_dither.is_some(),
+			}
+			.write_to(buf)?;
 		}
 		if let Some(time) = self.time {
 			PngChunk::Time(time).write_to(buf)?;
@@ -143,53 +332,51 @@ impl<W: Write> ImageWriter for PngWriter<W> {
 		if let Some(intent) = self.srgb {
 			PngChunk::Gama(1.0 / 2.2).write_to(buf)?;
 			PngChunk::Srgb(intent).write_to(buf)?;
+		} else if let Some(chromaticities) = self.chromaticities {
+			// the sRGB transfer function is still used for other primaries, which is close
+			// enough to a 2.2 gamma
+			PngChunk::Gama(1.0 / 2.2).write_to(buf)?;
+			PngChunk::Chrm(chromaticities).write_to(buf)?;
+		}
+		if let Some(ref metadata) = self.metadata {
+			for (keyword, text) in metadata.entries() {
+				PngChunk::Itxt {
+					keyword: keyword.to_string(),
+					language: None,
+					translated_keyword: None,
+					text: TextData::Uncompressed(text),
+				}
+				.write_to(buf)?;
+			}
 		}
 
 		Ok(())
 	}
 
 	fn write_pixels(&mut self, pixels: &[Color]) -> io::Result<()> {
-		if self.pixel_writer.is_none() {
-			self.pixel_writer = Some(BufWriter::with_capacity(
-				IDAT_SIZE,
-				FilterWriter::new(
-					ZlibEncoder::new(IdatWriter(self.buf.take().unwrap()), Compression::default()),
-					FilterType::None,
-					self.width * 3 * if self.bits <= 8 { 1 } else { 2 },
-				),
-			));
-		}
-		let pw = self.pixel_writer.as_mut().unwrap();
-
-		for p in pixels {
-			let mut p = self.dither.dither(*p);
-
-			let mut written_bits = if self.bits > 8 { 16 } else { 8 };
-			p.0 <<= written_bits - self.bits;
-			p.1 <<= written_bits - self.bits;
-			p.2 <<= written_bits - self.bits;
-
-			// repeat most significant bits into the lower ones so that the overall sample ranges
-			// from all zeroes to all ones
-			while written_bits > self.bits {
-				p.0 |= p.0 >> self.bits;
-				p.1 |= p.1 >> self.bits;
-				p.2 |= p.2 >> self.bits;
-				written_bits -= self.bits;
-			}
+		self.write_pixels_with_alpha(pixels, &vec![1.0; pixels.len()])
+	}
 
-			if self.bits <= 8 {
-				pw.write_all(&[p.0 as u8, p.1 as u8, p.2 as u8])?;
-			} else {
-				pw.write_all(&[
-					(p.0 >> 8) as u8,
-					(p.0 & 0xff) as u8,
-					(p.1 >> 8) as u8,
-					(p.1 & 0xff) as u8,
-					(p.2 >> 8) as u8,
-					(p.2 & 0xff) as u8,
-				])?;
+	fn write_pixels_with_alpha(&mut self, pixels: &[Color], alpha: &[f64]) -> io::Result<()> {
+		let channels = self.channels();
+		for (p, &a) in pixels.iter().zip(alpha) {
+			let p = self.dither.dither(*p);
+			let a = match self.alpha_dither {
+				Some(ref mut alpha_dither) => alpha_dither.dither(Color::new(a, a, a)).0,
+				None => 0,
+			};
+			let samples = [p.0, p.1, p.2, a].map(|sample| self.expand(sample));
+
+			let bits = self.bits;
+			let pw = self.pixel_writer();
+			for &sample in &samples[..channels] {
+				if bits <= 8 {
+					pw.write_all(&[sample as u8])?;
+				} else {
+					pw.write_all(&sample.to_be_bytes())?;
+				}
 			}
+			self.pixels_written += 1;
 		}
 		Ok(())
 	}
@@ -203,11 +390,39 @@ impl<W: Write> ImageWriter for PngWriter<W> {
 		PngChunk::Iend.write_to(&mut buf)?;
 		buf.flush()
 	}
+
+	/// fill in the pixels that weren't written with zeros, which are black or transparent, and
+	/// end the file, so that decoders see a complete image instead of a stream that stops
+	/// partway through
+	fn abort(&mut self) -> io::Result<()> {
+		if !self.header_written {
+			return self.buf.as_mut().map_or(Ok(()), |buf| buf.flush());
+		}
+		if self.buf.is_none() && self.pixel_writer.is_none() {
+			// already ended
+			return Ok(());
+		}
+		let remaining =
+			self.width * self.height - self.pixels_written.min(self.width * self.height);
+		let zeros = vec![0; self.width * self.channels() * self.sample_size()];
+		let mut bytes = remaining * self.channels() * self.sample_size();
+		let pw = self.pixel_writer();
+		while bytes > 0 {
+			let n = bytes.min(zeros.len());
+			pw.write_all(&zeros[..n])?;
+			bytes -= n;
+		}
+		self.pixels_written += remaining;
+		self.end()
+	}
 }
 
 #[cfg(test)]
 mod tests {
+	use std::time::Duration;
+
 	use super::*;
+	use crate::common::args;
 
 	#[test]
 	fn test_idat_writer() {
@@ -222,7 +437,9 @@ mod tests {
 			0x44, 0x32, 0x30, 0x32, 0x00, 0x01, 0x0b, 0x2b, 0x03, 0x00, 0x9a, 0xf3, 0x27, 0x9d,
 		];
 		let mut written: Vec<u8> = Vec::new();
-		assert!(IdatWriter(&mut written).write_all(data).is_ok());
+		let mut writer = IdatWriter::new(&mut written, IDAT_SIZE);
+		assert!(writer.write_all(data).is_ok());
+		assert!(writer.finish().is_ok());
 		assert_eq!(written.len(), data.len() + 12);
 		assert_eq!(
 			&written[..8],
@@ -234,6 +451,289 @@ mod tests {
 		assert_eq!(&written[(data.len() + 8)..], &[0x62, 0x60, 0x9a, 0xcd]);
 	}
 
+	#[test]
+	fn test_chunk_size() {
+		let (width, height) = (40, 30);
+		// noise, so that the compressed data takes up several chunks
+		let mut state = 1u32;
+		let pixels: Vec<Color> = (0..width * height)
+			.map(|_| {
+				let mut next = || {
+					state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+					(state >> 8) as f64 / (1 << 24) as f64
+				};
+				Color::new(next(), next(), next())
+			})
+			.collect();
+		let mut expected = None;
+		for chunk_size in [IDAT_SIZE, 1000, 100, 1] {
+			let mut png = Vec::new();
+			let mut writer = PngWriter::new(&mut png, (width, height), 16, None, None, None)
+				.with_chunk_size(chunk_size);
+			writer.write_header().unwrap();
+			writer.write_pixels(&pixels).unwrap();
+			writer.end().unwrap();
+			drop(writer);
+
+			let verified = verify(&png).unwrap_or_else(|e| panic!("{}", e));
+			let idats: Vec<usize> = verified
+				.chunks
+				.iter()
+				.filter(|chunk| &chunk.tag == b"IDAT")
+				.map(|chunk| chunk.length)
+				.collect();
+			let (last, rest) = idats.split_last().unwrap();
+			assert!(rest.iter().all(|&length| length == chunk_size));
+			assert!(*last > 0 && *last <= chunk_size);
+			assert!(chunk_size == IDAT_SIZE || !rest.is_empty());
+
+			// the chunks only split up the data, so it decodes the same at every size
+			let decoded = image::load_from_memory(&png).unwrap().to_rgb16();
+			assert_eq!(decoded.dimensions(), (width as u32, height as u32));
+			match expected {
+				None => expected = Some(decoded),
+				Some(ref expected) => assert!(decoded == *expected),
+			}
+		}
+	}
+
+	#[test]
+	fn test_parallel_compression() {
+		// more than one group of 16-bit RGBA data
+		let (width, height) = (400, 400);
+		let pixels: Vec<Color> = (0..width * height)
+			.map(|i| {
+				let (x, y) = ((i % width) as f64, (i / width) as f64);
+				Color::new(x / width as f64, y / height as f64, (x * y).sin().abs())
+			})
+			.collect();
+		let alpha: Vec<f64> = (0..width * height).map(|i| (i % 7) as f64 / 6.0).collect();
+		assert!(width * height * 8 > GROUP_SIZE);
+
+		let encode = |compression, threads| {
+			let mut png = Vec::new();
+			let mut writer = PngWriter::new(&mut png, (width, height), 16, None, None, None)
+				.with_alpha()
+				.with_compression(compression, threads);
+			writer.write_header().unwrap();
+			writer.write_pixels_with_alpha(&pixels, &alpha).unwrap();
+			writer.end().unwrap();
+			drop(writer);
+			verify(&png).unwrap_or_else(|e| panic!("{:?}: {}", compression, e));
+			image::load_from_memory(&png).unwrap().to_rgba16()
+		};
+		let expected = encode(PngCompression::Default, 1);
+		assert!(encode(PngCompression::Fast, 1) == expected);
+		for threads in [1, 3] {
+			assert!(encode(PngCompression::Parallel, threads) == expected);
+		}
+	}
+
+	/// run with `cargo test --release -- --ignored bench_compression --nocapture`
+	#[test]
+	#[ignore]
+	fn bench_compression() {
+		// a 45 megapixel 16-bit image, the size of a full frame camera's
+		let (width, height) = (8192, 5461);
+		let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+		let mut state = 1u32;
+		let rows: Vec<Vec<Color>> = (0..16)
+			.map(|y| {
+				(0..width)
+					.map(|x| {
+						// a smooth gradient with some noise, like a rendered image
+						state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+						let noise = (state >> 24) as f64 / 256.0 * 0.02;
+						let (u, v) = (x as f64 / width as f64, y as f64 / 16.0);
+						Color::new(u + noise, v + noise, (u * v).sqrt() + noise)
+					})
+					.collect()
+			})
+			.collect();
+		for (compression, threads) in [
+			(PngCompression::Default, 1),
+			(PngCompression::Fast, 1),
+			(PngCompression::Parallel, threads),
+		] {
+			let start = std::time::Instant::now();
+			let mut length = 0;
+			let mut writer = PngWriter::new(
+				CountingSink(&mut length),
+				(width, height),
+				16,
+				None,
+				None,
+				None,
+			)
+			.with_compression(compression, threads);
+			writer.write_header().unwrap();
+			for y in 0..height {
+				writer.write_pixels(&rows[y % rows.len()]).unwrap();
+			}
+			writer.end().unwrap();
+			drop(writer);
+			eprintln!(
+				"{:?} on {} threads: {:.2}s, {:.1} MB",
+				compression,
+				threads,
+				start.elapsed().as_secs_f64(),
+				length as f64 / 1e6
+			);
+		}
+	}
+
+	/// counts the bytes written to it
+	struct CountingSink<'a>(&'a mut usize);
+
+	impl Write for CountingSink<'_> {
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			*self.0 += buf.len();
+			Ok(buf.len())
+		}
+	}
+
+	/// text of every iTXt chunk in a PNG file, by keyword
+	fn text_chunks(png: &[u8]) -> Vec<(String, String)> {
+		let mut chunks = Vec::new();
+		let mut rest = &png[8..];
+		while rest.len() >= 12 {
+			let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+			let (tag, data) = (&rest[4..8], &rest[8..8 + length]);
+			if tag == b"iTXt" {
+				// keyword, null, compression flag and method, then a null after the empty
+				// language and translated keyword
+				let keyword_end = data.iter().position(|&b| b == 0).unwrap();
+				let keyword = String::from_utf8(data[..keyword_end].to_vec()).unwrap();
+				let text = String::from_utf8(data[keyword_end + 5..].to_vec()).unwrap();
+				chunks.push((keyword, text));
+			}
+			rest = &rest[12 + length..];
+		}
+		chunks
+	}
+
+	/// the options besides the basics that change what a render looks like
+	fn image_options(args: &args::Args) -> String {
+		format!(
+			"{:?}",
+			(
+				(args.scene.name(), args.flake_depth, args.menger_level),
+				(args.debug_mode, args.debug_samples, args.debug_scale),
+				(
+					args.denoise,
+					args.denoise_raw,
+					args.aberration,
+					args.vignette
+				),
+				(
+					args.fog,
+					args.shutter,
+					args.highlights,
+					args.output_primaries
+				),
+				(args.stereo, args.ipd, args.focus_at, args.focus_distance),
+				(
+					args.burn_in,
+					args.alpha,
+					args.auto_expose,
+					args.bake_exposure
+				),
+			)
+		)
+	}
+
+	#[test]
+	fn test_reproduce_metadata() {
+		let commands = [
+			"-S cornell -w 20 -s 3 -r 5 -R 6",
+			"-S sphereflake --flake-depth 2 -w 20 -s 3 -r 5 -R 6 --fog 0.05,#b0b8c8 \
+			 --shutter 0.25,0.75 --highlights preserve-hue --aberration 0.3 --vignette 0.4 \
+			 --stereo anaglyph --ipd 0.1 --focus-distance 3 --burn-in --auto-expose \
+			 --bake-exposure --output-primaries p3 --denoise 0.5 --denoise-raw",
+			"-S menger --menger-level 2 -w 20 -s 3 -r 5 -R 6 -D id --debug-samples 2 \
+			 --debug-scale 2 --focus-at 3,4 --shutter 0.5 -f png --alpha",
+		];
+		for arguments in commands {
+			let args = args::parse_from(arguments.split_whitespace().map(|a| a.into()).collect())
+				.unwrap_or_else(|e| panic!("{}: {}", arguments, e));
+			let metadata = RenderMetadata {
+				software: String::new(),
+				command_line: String::new(),
+				reproduce: args.reproduce_command(),
+				scene: args.scene.name().to_string(),
+				world_seed: 5,
+				sample_seed: 6,
+				samples: 3,
+				render_time: Duration::ZERO,
+			};
+			let mut png = Vec::new();
+			let mut writer = PngWriter::new(&mut png, (1, 1), 8, None, None, None);
+			writer.set_metadata(&metadata);
+			writer.write_header().unwrap();
+			writer.write_pixels(&[Color::zero()]).unwrap();
+			writer.end().unwrap();
+			drop(writer);
+
+			let (_, command) = text_chunks(&png)
+				.into_iter()
+				.find(|(keyword, _)| keyword == "Reproduce")
+				.unwrap();
+			// without the program name
+			let reparsed = args::parse_from(command.split(' ').skip(1).map(|a| a.into()).collect())
+				.unwrap_or_else(|e| panic!("{}: {}", command, e));
+			assert_eq!((reparsed.width, reparsed.samples), (20, args.samples));
+			assert_eq!((reparsed.world_seed, reparsed.sample_seed), (5, 6));
+			assert_eq!(reparsed.depth, args.depth);
+			assert_eq!(reparsed.format, args.format);
+			assert_eq!(reparsed.bit_depth, args.bit_depth);
+			assert_eq!(
+				image_options(&reparsed),
+				image_options(&args),
+				"{}",
+				command
+			);
+			assert_eq!(reparsed.reproduce_command(), command);
+		}
+	}
+
+	#[test]
+	fn test_abort() {
+		let (width, height) = (3, 4);
+		let white = Color::new(1.0, 1.0, 1.0);
+		for (bits, alpha) in [(8, false), (16, true)] {
+			let mut png = Vec::new();
+			let writer = PngWriter::new(&mut png, (width, height), bits, None, None, None);
+			let mut writer = if alpha { writer.with_alpha() } else { writer };
+			writer.write_header().unwrap();
+			// two rows and part of the third
+			for _ in 0..2 * width + 1 {
+				writer.write_pixels_with_alpha(&[white], &[1.0]).unwrap();
+			}
+			writer.abort().unwrap();
+			// aborting again after the file is finished does nothing
+			writer.abort().unwrap();
+			drop(writer);
+
+			verify(&png).unwrap_or_else(|e| panic!("{}", e));
+			let decoded = image::load_from_memory(&png).unwrap().to_rgba16();
+			assert_eq!(decoded.dimensions(), (width as u32, height as u32));
+			for (i, pixel) in decoded.pixels().enumerate() {
+				let expected = if i <= 2 * width {
+					[u16::MAX; 4]
+				} else if alpha {
+					[0; 4]
+				} else {
+					[0, 0, 0, u16::MAX]
+				};
+				assert_eq!(pixel.0, expected, "pixel {}", i);
+			}
+		}
+	}
+
 	#[test]
 	fn test_filter_writer() {
 		let data: &[u8] = &[5, 5, 5, 5, 5, 5, 5, 5];
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use flate2::{Compress, Compression, FlushCompress, Status};
+
+use crate::common::pool::{JobHandle, ThreadPool};
+
+/// how much uncompressed data each thread compresses at a time
+pub const GROUP_SIZE: usize = 1 << 20;
+
+const ADLER_BASE: u32 = 65521;
+
+/// zlib header for a 32K window and the default compression level
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x9c];
+
+fn adler32(data: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+	// the most bytes that can be added up before b might overflow
+	for chunk in data.chunks(5552) {
+		for &byte in chunk {
+			a += byte as u32;
+			b += a;
+		}
+		a %= ADLER_BASE;
+		b %= ADLER_BASE;
+	}
+	b << 16 | a
+}
+
+/// The Adler-32 checksum of two pieces of data one after the other, from their separate checksums
+/// and the length of the second, like zlib's adler32_combine
+fn adler32_combine(first: u32, second: u32, second_len: usize) -> u32 {
+	let base = ADLER_BASE as u64;
+	let rem = second_len as u64 % base;
+	let (a1, b1) = ((first & 0xffff) as u64, (first >> 16) as u64);
+	let (a2, b2) = ((second & 0xffff) as u64, (second >> 16) as u64);
+	let a = (a1 + a2 + base - 1) % base;
+	let b = (rem * a1 + b1 + b2 + base - rem) % base;
+	(b << 16 | a) as u32
+}
+
+/// Compress data into raw deflate blocks that can be followed by more blocks from a different
+/// compressor: everything but the last group ends in an empty stored block from a sync flush
+fn deflate(data: &[u8], last: bool) -> io::Result<Vec<u8>> {
+	let mut compress = Compress::new(Compression::default(), false);
+	let flush = if last {
+		FlushCompress::Finish
+	} else {
+		FlushCompress::Sync
+	};
+	let mut output = Vec::with_capacity(data.len() / 2 + 64);
+	loop {
+		if output.len() == output.capacity() {
+			output.reserve(output.capacity());
+		}
+		let input = &data[compress.total_in() as usize..];
+		let status = compress
+			.compress_vec(input, &mut output, flush)
+			.map_err(io::Error::other)?;
+		let done = if last {
+			status == Status::StreamEnd
+		} else {
+			// the flush is only finished if it didn't run out of room
+			input.is_empty() && output.len() < output.capacity()
+		};
+		if done {
+			return Ok(output);
+		}
+	}
+}
+
+/// compressed data, checksum, and uncompressed length of a group
+type Group = io::Result<(Vec<u8>, u32, usize)>;
+
+/// Writes one zlib stream like ZlibEncoder, but compresses GROUP_SIZE pieces of the data on
+/// separate threads and joins them together the way pigz does. Each piece starts without the
+/// previous 32K as a dictionary, so the output is a little bigger
+pub struct ParallelZlibEncoder<W: Write> {
+	dest: W,
+	pool: ThreadPool,
+	/// how many groups can be compressing at once before waiting for the oldest one
+	max_in_flight: usize,
+	group_size: usize,
+	/// data that hasn't filled up a group yet
+	pending: Vec<u8>,
+	/// groups being compressed, oldest first
+	in_flight: VecDeque<JobHandle<Group>>,
+	/// checksum of the data in the groups written so far
+	adler: u32,
+	header_written: bool,
+}
+
+impl<W: Write> ParallelZlibEncoder<W> {
+	pub fn new(dest: W, threads: usize, group_size: usize) -> Self {
+		assert!(threads > 0 && group_size > 0);
+		Self {
+			dest,
+			pool: ThreadPool::new(threads),
+			// enough that the threads have something to do while the oldest group is written
+			max_in_flight: threads * 2,
+			group_size,
+			pending: Vec::with_capacity(group_size),
+			in_flight: VecDeque::new(),
+			adler: adler32(&[]),
+			header_written: false,
+		}
+	}
+
+	/// start compressing the pending data
+	fn send_group(&mut self, last: bool) -> io::Result<()> {
+		if self.in_flight.len() == self.max_in_flight {
+			self.write_oldest()?;
+		}
+		let data = std::mem::replace(&mut self.pending, Vec::with_capacity(self.group_size));
+		self.in_flight.push_back(self.pool.spawn(move || {
+			let compressed = deflate(&data, last)?;
+			Ok((compressed, adler32(&data), data.len()))
+		}));
+		Ok(())
+	}
+
+	/// wait for the oldest group and write it out
+	fn write_oldest(&mut self) -> io::Result<()> {
+		let Some(group) = self.in_flight.pop_front() else {
+			return Ok(());
+		};
+		let (compressed, adler, len) = group
+			.join()
+			.map_err(|_| io::Error::other("compression thread panicked"))??;
+		if !self.header_written {
+			self.dest.write_all(&ZLIB_HEADER)?;
+			self.header_written = true;
+		}
+		self.dest.write_all(&compressed)?;
+		self.adler = adler32_combine(self.adler, adler, len);
+		Ok(())
+	}
+
+	/// compress what's left as the end of the stream and add the checksum
+	pub fn finish(mut self) -> io::Result<W> {
+		self.send_group(true)?;
+		while !self.in_flight.is_empty() {
+			self.write_oldest()?;
+		}
+		self.dest.write_all(&self.adler.to_be_bytes())?;
+		self.dest.flush()?;
+		Ok(self.dest)
+	}
+}
+
+impl<W: Write> Write for ParallelZlibEncoder<W> {
+	/// only flushes the underlying writer, since compressing the pending data early would make
+	/// the output bigger
+	fn flush(&mut self) -> io::Result<()> {
+		self.dest.flush()
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = buf.len().min(self.group_size - self.pending.len());
+		self.pending.extend_from_slice(&buf[..n]);
+		if self.pending.len() == self.group_size {
+			self.send_group(false)?;
+		}
+		Ok(n)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Read;
+
+	use flate2::read::ZlibDecoder;
+
+	use super::*;
+
+	#[test]
+	fn test_adler32() {
+		assert_eq!(adler32(b""), 1);
+		assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+		let data: Vec<u8> = (0..20000u32).map(|i| ((i * 7919) >> 3) as u8).collect();
+		for split in [0, 1, 5552, 12345, 20000] {
+			let (first, second) = data.split_at(split);
+			assert_eq!(
+				adler32_combine(adler32(first), adler32(second), second.len()),
+				adler32(&data)
+			);
+		}
+	}
+
+	#[test]
+	fn test_parallel_zlib() {
+		let data: Vec<u8> = (0..100_000u32)
+			.map(|i| ((i / 300) as u8).wrapping_add((i.wrapping_mul(i) >> 9) as u8 & 3))
+			.collect();
+		for (threads, group_size) in [(1, 1000), (3, 4096), (2, 100_000), (4, 1 << 20)] {
+			let mut encoder = ParallelZlibEncoder::new(Vec::new(), threads, group_size);
+			// uneven writes, which don't line up with the groups
+			for piece in data.chunks(777) {
+				encoder.write_all(piece).unwrap();
+			}
+			let compressed = encoder.finish().unwrap();
+			assert_eq!(
+				compressed[compressed.len() - 4..],
+				adler32(&data).to_be_bytes()
+			);
+
+			let mut decoded = Vec::new();
+			ZlibDecoder::new(&compressed[..])
+				.read_to_end(&mut decoded)
+				.unwrap();
+			assert!(decoded == data, "{} threads, {} bytes", threads, group_size);
+		}
+
+		// nothing written is still a whole stream
+		let compressed = ParallelZlibEncoder::new(Vec::new(), 2, 10)
+			.finish()
+			.unwrap();
+		let mut decoded = Vec::new();
+		ZlibDecoder::new(&compressed[..])
+			.read_to_end(&mut decoded)
+			.unwrap();
+		assert!(decoded.is_empty());
+	}
+}
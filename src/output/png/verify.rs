@@ -0,0 +1,619 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use super::chunk::crc;
+use super::SIGNATURE;
+use crate::common::{Color, ImageBuffer};
+
+/// chunks that have to come before the image data
+const BEFORE_IDAT: [&[u8; 4]; 5] = [b"sBIT", b"gAMA", b"cHRM", b"sRGB", b"iCCP"];
+
+/// Something wrong with a PNG file, and where it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngError {
+	/// tag of the chunk the problem is in, if it's in one
+	pub chunk: Option<[u8; 4]>,
+	/// from the start of the file, of the chunk if there is one
+	pub offset: usize,
+	pub message: String,
+}
+
+impl Display for PngError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.chunk {
+			Some(tag) => write!(
+				f,
+				"{} chunk at byte {}: {}",
+				String::from_utf8_lossy(&tag),
+				self.offset,
+				self.message
+			),
+			None => write!(f, "byte {}: {}", self.offset, self.message),
+		}
+	}
+}
+
+impl Error for PngError {}
+
+/// One chunk of a PNG file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkInfo {
+	pub tag: [u8; 4],
+	/// of its length, from the start of the file
+	pub offset: usize,
+	/// of its data
+	pub length: usize,
+}
+
+/// A PNG file that was found to be well formed by verify, with its image data decompressed and
+/// unfiltered. Only the kinds of PNG that PngWriter writes are accepted: 8 or 16 bit RGB or RGBA,
+/// without interlacing
+#[derive(Debug, Clone)]
+pub struct VerifiedPng {
+	pub width: usize,
+	pub height: usize,
+	/// 8 or 16
+	pub bit_depth: u8,
+	pub alpha: bool,
+	/// from the sBIT chunk, if there is one
+	pub significant_bits: Option<u8>,
+	pub chunks: Vec<ChunkInfo>,
+	/// every scanline, without the filter bytes
+	data: Vec<u8>,
+}
+
+fn chunk_error(chunk: &ChunkInfo, message: impl Into<String>) -> PngError {
+	PngError {
+		chunk: Some(chunk.tag),
+		offset: chunk.offset,
+		message: message.into(),
+	}
+}
+
+/// Split a PNG file into chunks, checking the signature, that every chunk fits in the file, and
+/// every CRC
+fn split_chunks(png: &[u8]) -> Result<Vec<(ChunkInfo, &[u8])>, PngError> {
+	if !png.starts_with(&SIGNATURE) {
+		return Err(PngError {
+			chunk: None,
+			offset: 0,
+			message: "not a PNG file: the signature is wrong".to_string(),
+		});
+	}
+	let mut chunks = Vec::new();
+	let mut offset = SIGNATURE.len();
+	while offset < png.len() {
+		let rest = &png[offset..];
+		if rest.len() < 12 {
+			return Err(PngError {
+				chunk: None,
+				offset,
+				message: format!("{} bytes left over, too few for a chunk", rest.len()),
+			});
+		}
+		let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+		let chunk = ChunkInfo {
+			tag: rest[4..8].try_into().unwrap(),
+			offset,
+			length,
+		};
+		if !chunk.tag.iter().all(u8::is_ascii_alphabetic) {
+			return Err(chunk_error(&chunk, "the tag isn't four letters"));
+		}
+		if rest.len() - 12 < length {
+			return Err(chunk_error(
+				&chunk,
+				format!("{} bytes long, past the end of the file", length),
+			));
+		}
+		let data = &rest[8..8 + length];
+		let expected = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+		let actual = crc(&rest[4..8 + length]);
+		if actual != expected {
+			return Err(chunk_error(
+				&chunk,
+				format!("CRC is {:08x} but should be {:08x}", expected, actual),
+			));
+		}
+		chunks.push((chunk, data));
+		offset += 12 + length;
+	}
+	Ok(chunks)
+}
+
+/// Reverse a scanline's filter, given the scanline above it, which is all zeros for the first
+fn unfilter(filter: u8, line: &mut [u8], above: &[u8], pixel_size: usize) -> Result<(), String> {
+	for i in 0..line.len() {
+		let left = if i >= pixel_size {
+			line[i - pixel_size]
+		} else {
+			0
+		};
+		let upper_left = if i >= pixel_size {
+			above[i - pixel_size]
+		} else {
+			0
+		};
+		let up = above[i];
+		let predicted = match filter {
+			0 => 0,
+			1 => left,
+			2 => up,
+			3 => ((left as u16 + up as u16) / 2) as u8,
+			4 => {
+				// Paeth: whichever neighbour is closest to left + up - upper left
+				let p = left as i16 + up as i16 - upper_left as i16;
+				let (a, b, c) = (
+					(p - left as i16).abs(),
+					(p - up as i16).abs(),
+					(p - upper_left as i16).abs(),
+				);
+				if a <= b && a <= c {
+					left
+				} else if b <= c {
+					up
+				} else {
+					upper_left
+				}
+			},
+			_ => return Err(format!("unknown filter type {}", filter)),
+		};
+		line[i] = line[i].wrapping_add(predicted);
+	}
+	Ok(())
+}
+
+/// Check that a PNG file is well formed: the signature, every chunk's CRC, the header, the order
+/// of the chunks, and that the image data decompresses to exactly the right number of scanlines
+/// with valid filters. This is for finding mistakes in PngWriter, not a general decoder
+pub fn verify(png: &[u8]) -> Result<VerifiedPng, PngError> {
+	let chunks = split_chunks(png)?;
+	let Some(&(ihdr, header)) = chunks.first().filter(|(c, _)| &c.tag == b"IHDR") else {
+		return Err(PngError {
+			chunk: chunks.first().map(|(c, _)| c.tag),
+			offset: SIGNATURE.len(),
+			message: "the first chunk isn't IHDR".to_string(),
+		});
+	};
+	if header.len() != 13 {
+		return Err(chunk_error(
+			&ihdr,
+			format!("{} bytes long, not 13", header.len()),
+		));
+	}
+	let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+	let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+	let [bit_depth, color_type, compression, filter, interlace] = header[8..].try_into().unwrap();
+	if width == 0 || height == 0 {
+		return Err(chunk_error(&ihdr, format!("size is {}x{}", width, height)));
+	}
+	if bit_depth != 8 && bit_depth != 16 {
+		return Err(chunk_error(&ihdr, format!("bit depth is {}", bit_depth)));
+	}
+	let alpha = match color_type {
+		2 => false,
+		6 => true,
+		_ => {
+			return Err(chunk_error(
+				&ihdr,
+				format!("color type {} isn't RGB or RGBA", color_type),
+			))
+		},
+	};
+	if (compression, filter, interlace) != (0, 0, 0) {
+		return Err(chunk_error(
+			&ihdr,
+			format!(
+				"compression {}, filter {}, and interlace {} methods should all be 0",
+				compression, filter, interlace
+			),
+		));
+	}
+	let channels = if alpha { 4 } else { 3 };
+
+	let mut significant_bits = None;
+	let mut idat = Vec::new();
+	let mut first_idat = None;
+	let mut idat_ended = false;
+	for (i, &(chunk, data)) in chunks.iter().enumerate().skip(1) {
+		let last = i == chunks.len() - 1;
+		match &chunk.tag {
+			b"IHDR" => return Err(chunk_error(&chunk, "there's already an IHDR")),
+			b"IDAT" => {
+				if idat_ended {
+					return Err(chunk_error(
+						&chunk,
+						"IDAT chunks must be next to each other",
+					));
+				}
+				first_idat.get_or_insert(chunk);
+				idat.extend_from_slice(data);
+			},
+			b"IEND" => {
+				if !last {
+					return Err(chunk_error(&chunk, "chunks come after IEND"));
+				}
+				if !data.is_empty() {
+					return Err(chunk_error(&chunk, "IEND should be empty"));
+				}
+			},
+			tag if tag[0].is_ascii_uppercase() => {
+				return Err(chunk_error(&chunk, "unknown critical chunk"))
+			},
+			tag => {
+				if BEFORE_IDAT.contains(&tag) && first_idat.is_some() {
+					return Err(chunk_error(&chunk, "must come before the first IDAT"));
+				}
+				if tag == b"sBIT" {
+					let bits = match *data {
+						[r, g, b] if !alpha && r == g && g == b => r,
+						[r, g, b, a] if alpha && r == g && g == b && b == a => r,
+						_ => {
+							return Err(chunk_error(
+								&chunk,
+								format!("{:?} isn't the same for all {} channels", data, channels),
+							))
+						},
+					};
+					if bits == 0 || bits > bit_depth {
+						return Err(chunk_error(
+							&chunk,
+							format!("{} significant bits of {}", bits, bit_depth),
+						));
+					}
+					significant_bits = Some(bits);
+				}
+			},
+		}
+		if first_idat.is_some() && &chunk.tag != b"IDAT" {
+			idat_ended = true;
+		}
+	}
+	let last = chunks.last().unwrap().0;
+	if &last.tag != b"IEND" {
+		return Err(chunk_error(&last, "the last chunk isn't IEND"));
+	}
+	let Some(first_idat) = first_idat else {
+		return Err(chunk_error(&last, "there's no IDAT"));
+	};
+
+	// a filter byte and then the samples of each scanline
+	let sample_size = bit_depth as usize / 8;
+	let pixel_size = channels * sample_size;
+	let stride = 1 + width * pixel_size;
+	let expected = stride * height;
+	let mut decoder = ZlibDecoder::new(idat.as_slice());
+	let mut filtered = Vec::with_capacity(expected);
+	// one more than expected, so that too much data is noticed without decompressing all of it
+	(&mut decoder)
+		.take(expected as u64 + 1)
+		.read_to_end(&mut filtered)
+		.map_err(|e| chunk_error(&first_idat, format!("the zlib stream is corrupt: {}", e)))?;
+	if filtered.len() != expected {
+		return Err(chunk_error(
+			&first_idat,
+			format!(
+				"image data is {}{} bytes, but {} scanlines of {} bytes are {}",
+				if filtered.len() > expected {
+					"over "
+				} else {
+					""
+				},
+				filtered.len().min(expected),
+				height,
+				stride,
+				expected
+			),
+		));
+	}
+	if decoder.total_in() != idat.len() as u64 {
+		return Err(chunk_error(
+			&first_idat,
+			format!(
+				"{} bytes are left after the end of the zlib stream",
+				idat.len() as u64 - decoder.total_in()
+			),
+		));
+	}
+
+	let mut data = vec![0; width * pixel_size * height];
+	let mut above = vec![0; width * pixel_size];
+	for (y, (scanline, line)) in filtered
+		.chunks_exact(stride)
+		.zip(data.chunks_exact_mut(width * pixel_size))
+		.enumerate()
+	{
+		line.copy_from_slice(&scanline[1..]);
+		unfilter(scanline[0], line, &above, pixel_size)
+			.map_err(|e| chunk_error(&first_idat, format!("scanline {}: {}", y, e)))?;
+		above.copy_from_slice(line);
+	}
+
+	Ok(VerifiedPng {
+		width,
+		height,
+		bit_depth,
+		alpha,
+		significant_bits,
+		chunks: chunks.into_iter().map(|(chunk, _)| chunk).collect(),
+		data,
+	})
+}
+
+impl VerifiedPng {
+	fn channels(&self) -> usize {
+		if self.alpha {
+			4
+		} else {
+			3
+		}
+	}
+
+	/// the first IDAT chunk, which errors in the image data are reported in
+	fn first_idat(&self) -> ChunkInfo {
+		*self.chunks.iter().find(|c| &c.tag == b"IDAT").unwrap()
+	}
+
+	/// channel of the pixel at (x, y), counted from the top left, from 0 to 1
+	pub fn sample(&self, x: usize, y: usize, channel: usize) -> f64 {
+		let sample_size = self.bit_depth as usize / 8;
+		let i = ((y * self.width + x) * self.channels() + channel) * sample_size;
+		match sample_size {
+			1 => self.data[i] as f64 / u8::MAX as f64,
+			_ => u16::from_be_bytes([self.data[i], self.data[i + 1]]) as f64 / u16::MAX as f64,
+		}
+	}
+
+	/// Check that the header matches the image that was written, and that some of its scanlines
+	/// hold the same pixels, give or take the dithering
+	/// image: encoded and clamped, as it was given to PngWriter
+	/// alpha:  the alpha channel, if the image has one
+	/// bits:   bit depth the image was written at, from 1 to 16
+	/// rows:   which scanlines to compare, counted from the top
+	pub fn compare(
+		&self,
+		image: &ImageBuffer<Color>,
+		alpha: Option<&ImageBuffer<f64>>,
+		bits: u8,
+		rows: impl IntoIterator<Item = usize>,
+	) -> Result<(), PngError> {
+		let ihdr = self.chunks[0];
+		if (self.width, self.height) != image.size() {
+			return Err(chunk_error(
+				&ihdr,
+				format!(
+					"size is {}x{}, but the image is {}x{}",
+					self.width,
+					self.height,
+					image.width(),
+					image.height()
+				),
+			));
+		}
+		let bit_depth = if bits <= 8 { 8 } else { 16 };
+		if self.bit_depth != bit_depth {
+			return Err(chunk_error(
+				&ihdr,
+				format!("bit depth is {}, not {}", self.bit_depth, bit_depth),
+			));
+		}
+		if self.alpha != alpha.is_some() {
+			return Err(chunk_error(
+				&ihdr,
+				match self.alpha {
+					true => "there's an alpha channel that wasn't written",
+					false => "the alpha channel is missing",
+				},
+			));
+		}
+		let expected_sbit = (bits != bit_depth).then_some(bits);
+		if self.significant_bits != expected_sbit {
+			return Err(chunk_error(
+				&ihdr,
+				format!(
+					"{:?} significant bits in sBIT, but the image was written with {}",
+					self.significant_bits, bits
+				),
+			));
+		}
+
+		// dithering moves each sample by less than a step at the written bit depth, and scaling
+		// it up to the stored depth rounds it by less than a step of that
+		let tolerance =
+			1.0 / ((1u32 << bits) - 1) as f64 + 1.0 / ((1u32 << bit_depth) - 1) as f64 + 1e-9;
+		for y in rows {
+			for x in 0..self.width {
+				let p = image[(x, y)];
+				let a = alpha.map_or(1.0, |alpha| alpha[(x, y)]);
+				for (channel, expected) in [p.x(), p.y(), p.z(), a].into_iter().enumerate() {
+					if channel == self.channels() {
+						break;
+					}
+					let actual = self.sample(x, y, channel);
+					if (actual - expected.clamp(0.0, 1.0)).abs() > tolerance {
+						return Err(chunk_error(
+							&self.first_idat(),
+							format!(
+								"channel {} of pixel ({}, {}) is {}, but should be {}",
+								channel, x, y, actual, expected
+							),
+						));
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Write;
+
+	use flate2::write::ZlibEncoder;
+	use flate2::Compression;
+
+	use super::*;
+	use crate::output::{ImageWriter, PngWriter};
+
+	/// a small gradient, written at bits
+	fn written(bits: u8, alpha: bool) -> (Vec<u8>, ImageBuffer<Color>, ImageBuffer<f64>) {
+		let size = (7, 5);
+		let image =
+			ImageBuffer::from_fn(size, |x, y| Color::new(x as f64 / 6.0, y as f64 / 4.0, 0.3));
+		let alpha_channel = ImageBuffer::from_fn(size, |x, _| x as f64 / 7.0);
+		let mut png = Vec::new();
+		let writer = PngWriter::new(&mut png, size, bits, None, None, None);
+		let mut writer = if alpha { writer.with_alpha() } else { writer };
+		writer.write_header().unwrap();
+		for (row, alpha_row) in image.rows().zip(alpha_channel.rows()) {
+			writer.write_pixels_with_alpha(row, alpha_row).unwrap();
+		}
+		writer.end().unwrap();
+		drop(writer);
+		(png, image, alpha_channel)
+	}
+
+	/// png with a chunk's data replaced, and its CRC fixed
+	fn replace_chunk(png: &[u8], chunk: &ChunkInfo, data: &[u8]) -> Vec<u8> {
+		let mut replaced = png[..chunk.offset].to_vec();
+		replaced.extend_from_slice(&(data.len() as u32).to_be_bytes());
+		let start = replaced.len();
+		replaced.extend_from_slice(&chunk.tag);
+		replaced.extend_from_slice(data);
+		let crc = crc(&replaced[start..]);
+		replaced.extend_from_slice(&crc.to_be_bytes());
+		replaced.extend_from_slice(&png[chunk.offset + 12 + chunk.length..]);
+		replaced
+	}
+
+	fn find(png: &VerifiedPng, tag: &[u8; 4]) -> ChunkInfo {
+		*png.chunks.iter().find(|c| &c.tag == tag).unwrap()
+	}
+
+	#[test]
+	fn test_written() {
+		for (bits, alpha) in [(8, false), (16, false), (5, true), (12, true), (1, false)] {
+			let (png, image, alpha_channel) = written(bits, alpha);
+			let verified = verify(&png).unwrap_or_else(|e| panic!("{} bits: {}", bits, e));
+			assert_eq!((verified.width, verified.height), (7, 5));
+			let alpha_channel = alpha.then_some(&alpha_channel);
+			verified
+				.compare(&image, alpha_channel, bits, 0..5)
+				.unwrap_or_else(|e| panic!("{} bits: {}", bits, e));
+			// a different image or bit depth doesn't match. at 1 bit, dithering can turn any
+			// pixel black or white
+			if bits > 1 {
+				let other = image.map(|&p| Color::new(1.0, 1.0, 1.0) - p);
+				assert!(verified.compare(&other, alpha_channel, bits, [2]).is_err());
+			}
+			let other_bits = if bits == 8 { 16 } else { 8 };
+			let e = verified
+				.compare(&image, alpha_channel, other_bits, [0])
+				.unwrap_err();
+			assert_eq!(e.chunk, Some(*b"IHDR"));
+		}
+	}
+
+	#[test]
+	fn test_damaged() {
+		let (png, ..) = written(12, false);
+		let verified = verify(&png).unwrap();
+		let idat = find(&verified, b"IDAT");
+		let iend = find(&verified, b"IEND");
+
+		// a flipped bit in the image data is caught by the CRC, and named by where it is
+		let mut damaged = png.clone();
+		damaged[idat.offset + 10] ^= 1;
+		let e = verify(&damaged).unwrap_err();
+		assert_eq!((e.chunk, e.offset), (Some(*b"IDAT"), idat.offset));
+		assert!(e
+			.to_string()
+			.starts_with(&format!("IDAT chunk at byte {}: CRC", idat.offset)));
+
+		// a stream that's cut short, even with the right CRC
+		let data = &png[idat.offset + 8..idat.offset + 8 + idat.length];
+		let e = verify(&replace_chunk(&png, &idat, &data[..data.len() - 6])).unwrap_err();
+		assert_eq!(e.chunk, Some(*b"IDAT"));
+		// the wrong amount of data, as a valid stream
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(&[0; 100]).unwrap();
+		let short = encoder.finish().unwrap();
+		let e = verify(&replace_chunk(&png, &idat, &short)).unwrap_err();
+		assert!(e.message.contains("100 bytes"), "{}", e);
+		// a filter type that doesn't exist
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		let stride = 1 + 7 * 3 * 2;
+		let mut scanlines = vec![0; stride * 5];
+		scanlines[2 * stride] = 7;
+		encoder.write_all(&scanlines).unwrap();
+		let e = verify(&replace_chunk(&png, &idat, &encoder.finish().unwrap())).unwrap_err();
+		assert!(e.message.contains("scanline 2"), "{}", e);
+
+		// sBIT after the image data
+		let sbit = find(&verified, b"sBIT");
+		let sbit_bytes = &png[sbit.offset..sbit.offset + 12 + sbit.length];
+		let mut moved = png[..sbit.offset].to_vec();
+		moved.extend_from_slice(&png[sbit.offset + 12 + sbit.length..iend.offset]);
+		moved.extend_from_slice(sbit_bytes);
+		moved.extend_from_slice(&png[iend.offset..]);
+		let e = verify(&moved).unwrap_err();
+		assert_eq!(e.chunk, Some(*b"sBIT"));
+		assert!(e.message.contains("before the first IDAT"));
+
+		// no IEND, and something after it
+		let e = verify(&png[..iend.offset]).unwrap_err();
+		assert!(e.message.contains("isn't IEND"), "{}", e);
+		let mut extra = png.clone();
+		extra.extend_from_slice(&png[idat.offset..idat.offset + 12 + idat.length]);
+		let e = verify(&extra).unwrap_err();
+		assert_eq!((e.chunk, e.offset), (Some(*b"IEND"), iend.offset));
+		// and a file that's cut off partway through a chunk
+		let e = verify(&png[..iend.offset + 6]).unwrap_err();
+		assert_eq!((e.chunk, e.offset), (None, iend.offset));
+		assert!(verify(&png[1..]).is_err());
+	}
+
+	#[test]
+	fn test_filters() {
+		let above = [10u8, 20, 30, 200, 100, 50];
+		let pixels = [12u8, 25, 31, 180, 120, 40];
+		// the predictors as the PNG specification writes them, to filter the line like an encoder
+		let paeth = |a: u8, b: u8, c: u8| {
+			let p = a as i16 + b as i16 - c as i16;
+			let (pa, pb, pc) = (
+				(p - a as i16).abs(),
+				(p - b as i16).abs(),
+				(p - c as i16).abs(),
+			);
+			if pa <= pb && pa <= pc {
+				a
+			} else if pb <= pc {
+				b
+			} else {
+				c
+			}
+		};
+		for filter in 0..5 {
+			let mut line = [0u8; 6];
+			for i in 0..6 {
+				let left = if i >= 3 { pixels[i - 3] } else { 0 };
+				let upper_left = if i >= 3 { above[i - 3] } else { 0 };
+				let predicted = match filter {
+					0 => 0,
+					1 => left,
+					2 => above[i],
+					3 => ((left as u16 + above[i] as u16) / 2) as u8,
+					_ => paeth(left, above[i], upper_left),
+				};
+				line[i] = pixels[i].wrapping_sub(predicted);
+			}
+			unfilter(filter, &mut line, &above, 3).unwrap();
+			assert_eq!(line, pixels, "filter {}", filter);
+		}
+		assert!(unfilter(5, &mut [0; 6], &above, 3).is_err());
+	}
+}
@@ -1,20 +1,29 @@
 use std::io::{self, BufWriter, Write};
 
-use super::ImageWriter;
+use super::{ImageWriter, RenderMetadata};
 use crate::common::color::{Color, Dither};
 
+/// the longest line allowed in an ASCII (P3) file
+const MAX_LINE_LENGTH: usize = 70;
+
 pub struct PpmWriter<W: Write> {
 	dest: BufWriter<W>,
 	width: usize,
 	height: usize,
 	dither: Dither,
 	max: usize,
+	/// write decimal samples (P3) instead of binary (P6)
+	ascii: bool,
+	metadata: Option<RenderMetadata>,
+	pixels_written: usize,
+	/// characters on the current line of an ASCII file
+	line_length: usize,
 }
 
 impl<W: Write> PpmWriter<W> {
 	pub fn new(dest: W, (width, height): (usize, usize), bits: u8) -> Self {
-		if bits > 8 {
-			panic!("PPM only supports up to 8 bits per channel");
+		if !(1..=16).contains(&bits) {
+			panic!("PPM only supports 1 to 16 bits per channel");
 		}
 		Self {
 			dest: BufWriter::new(dest),
@@ -22,27 +31,149 @@ impl<W: Write> PpmWriter<W> {
 			height,
 			dither: Dither::new(bits, width),
 			max: (1 << bits) - 1,
+			ascii: false,
+			metadata: None,
+			pixels_written: 0,
+			line_length: 0,
+		}
+	}
+
+	/// write the samples as decimal text, with each row of pixels starting a new line
+	pub fn with_ascii(mut self) -> Self {
+		self.ascii = true;
+		self
+	}
+
+	fn write_ascii_sample(&mut self, sample: u16, starts_row: bool) -> io::Result<()> {
+		let text = sample.to_string();
+		if self.line_length > 0 {
+			if starts_row || self.line_length + 1 + text.len() > MAX_LINE_LENGTH {
+				self.dest.write_all(b"\n")?;
+				self.line_length = 0;
+			} else {
+				self.dest.write_all(b" ")?;
+				self.line_length += 1;
+			}
 		}
+		self.dest.write_all(text.as_bytes())?;
+		self.line_length += text.len();
+		Ok(())
 	}
 }
 
 impl<W: Write> ImageWriter for PpmWriter<W> {
+	/// stored as comments after the magic number, one line for each entry
+	fn set_metadata(&mut self, metadata: &RenderMetadata) {
+		self.metadata = Some(metadata.clone());
+	}
+
 	fn write_header(&mut self) -> io::Result<()> {
-		write!(
-			self.dest,
-			"P6\n{} {}\n{}\n",
-			self.width, self.height, self.max
-		)
+		writeln!(self.dest, "{}", if self.ascii { "P3" } else { "P6" })?;
+		if let Some(ref metadata) = self.metadata {
+			for (key, value) in metadata.entries() {
+				// a comment ends at the end of the line
+				writeln!(self.dest, "# {}: {}", key, value.replace(['\r', '\n'], " "))?;
+			}
+		}
+		write!(self.dest, "{} {}\n{}\n", self.width, self.height, self.max)
 	}
 
 	fn write_pixels(&mut self, pixels: &[Color]) -> io::Result<()> {
-		for p in pixels.iter().map(|&p| self.dither.dither(p)) {
-			self.dest.write_all(&[p.0 as u8, p.1 as u8, p.2 as u8])?;
+		for &p in pixels {
+			let p = self.dither.dither(p);
+			if self.ascii {
+				let starts_row = self.pixels_written.is_multiple_of(self.width);
+				self.write_ascii_sample(p.0, starts_row)?;
+				self.write_ascii_sample(p.1, false)?;
+				self.write_ascii_sample(p.2, false)?;
+			} else if self.max > 255 {
+				// two bytes per sample, most significant first
+				for sample in [p.0, p.1, p.2] {
+					self.dest.write_all(&sample.to_be_bytes())?;
+				}
+			} else {
+				self.dest.write_all(&[p.0 as u8, p.1 as u8, p.2 as u8])?;
+			}
+			self.pixels_written += 1;
 		}
 		Ok(())
 	}
 
 	fn end(&mut self) -> io::Result<()> {
+		if self.line_length > 0 {
+			self.dest.write_all(b"\n")?;
+			self.line_length = 0;
+		}
 		self.dest.flush()
 	}
+
+	/// PPM has no trailer, so a file cut short is read as far as it goes by most viewers
+	fn abort(&mut self) -> io::Result<()> {
+		self.end()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	fn metadata() -> RenderMetadata {
+		RenderMetadata {
+			software: "raytracing 0.1.0".to_string(),
+			command_line: "raytracing\n-S cornell".to_string(),
+			reproduce: String::new(),
+			scene: "cornell".to_string(),
+			world_seed: 1,
+			sample_seed: 2,
+			samples: 3,
+			render_time: Duration::ZERO,
+		}
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let (width, height) = (30, 4);
+		let pixels: Vec<Color> = (0..width * height)
+			.map(|i| {
+				let (x, y) = ((i % width) as f64, (i / width) as f64);
+				Color::new(x / width as f64, y / height as f64, 1.0 - x / width as f64)
+			})
+			.collect();
+		for bits in [1, 8, 12, 16] {
+			let write = |ascii| {
+				let mut ppm = Vec::new();
+				let writer = PpmWriter::new(&mut ppm, (width, height), bits);
+				let mut writer = if ascii { writer.with_ascii() } else { writer };
+				writer.set_metadata(&metadata());
+				writer.write_header().unwrap();
+				writer.write_pixels(&pixels).unwrap();
+				writer.end().unwrap();
+				drop(writer);
+				ppm
+			};
+			let (binary, ascii) = (write(false), write(true));
+			assert!(binary.starts_with(b"P6\n# Software: raytracing 0.1.0\n"));
+			assert!(ascii.starts_with(b"P3\n# Software: raytracing 0.1.0\n"));
+			let text = String::from_utf8(ascii.clone()).unwrap();
+			assert!(text.contains("\n# Command line: raytracing -S cornell\n"));
+			assert!(text.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+			// both decode to the same pixels
+			let binary = image::load_from_memory(&binary).unwrap().to_rgb16();
+			let ascii = image::load_from_memory(&ascii).unwrap().to_rgb16();
+			assert_eq!(binary.dimensions(), (width as u32, height as u32));
+			assert!(binary == ascii, "{} bits", bits);
+			// the decoder leaves samples unscaled unless the bit depth is 8 or 16
+			if bits == 8 || bits == 16 {
+				// dithering can move a sample by one step at the written bit depth
+				let step = u16::MAX as f64 / ((1u32 << bits) - 1) as f64;
+				for (pixel, color) in binary.pixels().zip(&pixels) {
+					let expected = color.x() * u16::MAX as f64;
+					assert!((pixel[0] as f64 - expected).abs() <= step, "{} bits", bits);
+				}
+			}
+		}
+	}
 }
@@ -0,0 +1,93 @@
+use std::io::{self, BufWriter, Write};
+
+use super::ImageWriter;
+use crate::common::color::{Color, Dither};
+
+/// The pixel format to tell ffmpeg about for frames of the given bit depth, which is 8 or 16
+pub fn pixel_format(bits: u8) -> &'static str {
+	if bits > 8 {
+		"rgb48be"
+	} else {
+		"rgb24"
+	}
+}
+
+/// Writes packed RGB samples with no header or padding, so frames written one after another to
+/// the same stream can be read by ffmpeg's rawvideo demuxer
+pub struct RawVideoWriter<W: Write> {
+	dest: BufWriter<W>,
+	dither: Dither,
+	/// two bytes per sample, most significant first
+	wide: bool,
+}
+
+impl<W: Write> RawVideoWriter<W> {
+	pub fn new(dest: W, (width, _): (usize, usize), bits: u8) -> Self {
+		if bits != 8 && bits != 16 {
+			panic!("raw video only supports 8 or 16 bits per channel");
+		}
+		Self {
+			dest: BufWriter::new(dest),
+			dither: Dither::new(bits, width),
+			wide: bits == 16,
+		}
+	}
+}
+
+impl<W: Write> ImageWriter for RawVideoWriter<W> {
+	fn write_header(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn write_pixels(&mut self, pixels: &[Color]) -> io::Result<()> {
+		for &p in pixels {
+			let p = self.dither.dither(p);
+			if self.wide {
+				for sample in [p.0, p.1, p.2] {
+					self.dest.write_all(&sample.to_be_bytes())?;
+				}
+			} else {
+				self.dest.write_all(&[p.0 as u8, p.1 as u8, p.2 as u8])?;
+			}
+		}
+		Ok(())
+	}
+
+	fn end(&mut self) -> io::Result<()> {
+		self.dest.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_frames() {
+		let (width, height) = (5, 3);
+		let frames = [Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0)];
+		for (bits, sample_size) in [(8, 1), (16, 2)] {
+			let mut stream = Vec::new();
+			for color in frames {
+				let mut writer = RawVideoWriter::new(&mut stream, (width, height), bits);
+				writer.write_header().unwrap();
+				for _ in 0..height {
+					writer.write_pixels(&[color; 5]).unwrap();
+				}
+				writer.end().unwrap();
+			}
+
+			let frame_size = width * height * 3 * sample_size;
+			assert_eq!(stream.len(), frame_size * frames.len());
+			let max = vec![0xff; sample_size];
+			let zero = vec![0; sample_size];
+			// red, then blue
+			for (frame, channel) in stream.chunks(frame_size).zip([0, 2]) {
+				for (i, sample) in frame.chunks(sample_size).enumerate() {
+					let expected = if i % 3 == channel { &max } else { &zero };
+					assert_eq!(sample, expected, "{} bits", bits);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::raytracer::RenderStats;
+
+/// Bumped whenever a field is removed, renamed, or changes meaning, so that scripts reading the
+/// report can tell. Adding a field doesn't change it
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A summary of a whole run, written as JSON by --stats-out. Times are in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsReport {
+	pub schema_version: u32,
+	/// name and version of this program
+	pub software: String,
+	/// the command line, starting with the program itself
+	pub arguments: Vec<String>,
+	pub scene: String,
+	pub world_seed: u64,
+	pub sample_seed: u64,
+	pub samples: usize,
+	pub width: usize,
+	pub height: usize,
+	/// from starting up to writing the report, including loading the scene and writing the image
+	pub wall_time: f64,
+	pub render_time: f64,
+	pub bvh_build_time: f64,
+	/// rays traced from the camera, one per sample
+	pub camera_rays: u64,
+	/// rays traced by every thread together, including the ones scattered off of surfaces
+	pub rays: u64,
+	/// rays per second of render time
+	pub rays_per_sec: f64,
+	/// the most memory this process has used, if the OS says
+	pub peak_rss_bytes: Option<u64>,
+	pub threads: Vec<ThreadReport>,
+}
+
+/// Work done by one rendering thread. Times are in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThreadReport {
+	pub wall_time: f64,
+	pub busy_time: f64,
+	pub pixels: usize,
+	pub tiles: usize,
+	pub camera_rays: u64,
+	pub rays: u64,
+	pub rays_per_sec: f64,
+}
+
+impl From<&RenderStats> for ThreadReport {
+	fn from(stats: &RenderStats) -> ThreadReport {
+		ThreadReport {
+			wall_time: stats.wall_time.as_secs_f64(),
+			busy_time: stats.busy_time.as_secs_f64(),
+			pixels: stats.pixels,
+			tiles: stats.tiles,
+			camera_rays: stats.samples,
+			rays: stats.rays,
+			rays_per_sec: stats.rays_per_sec(),
+		}
+	}
+}
+
+impl StatsReport {
+	/// fill in the totals from the per-thread stats
+	pub fn with_threads(self, threads: &[RenderStats], render_time: Duration) -> StatsReport {
+		let rays = threads.iter().map(|s| s.rays).sum();
+		let secs = render_time.as_secs_f64();
+		StatsReport {
+			camera_rays: threads.iter().map(|s| s.samples).sum(),
+			rays,
+			rays_per_sec: if secs > 0.0 { rays as f64 / secs } else { 0.0 },
+			render_time: secs,
+			threads: threads.iter().map(ThreadReport::from).collect(),
+			..self
+		}
+	}
+}
+
+/// Peak resident set size, from /proc on Linux. None elsewhere
+pub fn peak_rss() -> Option<u64> {
+	let status = fs::read_to_string("/proc/self/status").ok()?;
+	let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+	// e.g. "VmHWM:     12345 kB"
+	let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+	Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn report() -> StatsReport {
+		let thread = RenderStats {
+			wall_time: Duration::from_millis(1500),
+			busy_time: Duration::from_millis(1000),
+			pixels: 100,
+			tiles: 2,
+			samples: 400,
+			rays: 4000,
+			slowest_tile: None,
+		};
+		StatsReport {
+			schema_version: SCHEMA_VERSION,
+			software: "raytracing 0.1.0".to_string(),
+			arguments: vec![
+				"raytracing".to_string(),
+				"-S".to_string(),
+				"cornell".to_string(),
+			],
+			scene: "cornell".to_string(),
+			world_seed: 1,
+			sample_seed: 2,
+			samples: 4,
+			width: 20,
+			height: 10,
+			wall_time: 3.0,
+			render_time: 0.0,
+			bvh_build_time: 0.25,
+			camera_rays: 0,
+			rays: 0,
+			rays_per_sec: 0.0,
+			peak_rss_bytes: Some(1 << 20),
+			threads: Vec::new(),
+		}
+		.with_threads(&[thread; 2], Duration::from_secs(2))
+	}
+
+	#[test]
+	fn test_totals() {
+		let report = report();
+		assert_eq!((report.camera_rays, report.rays), (800, 8000));
+		assert_eq!(report.rays_per_sec, 4000.0);
+		assert_eq!(report.threads.len(), 2);
+		assert_eq!(report.threads[0].rays_per_sec, 4000.0);
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let report = report();
+		let json = serde_json::to_string_pretty(&report).unwrap();
+		assert_eq!(serde_json::from_str::<StatsReport>(&json).unwrap(), report);
+
+		// the field names are the schema, so changing them should be on purpose
+		let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+		let mut keys: Vec<&str> = value
+			.as_object()
+			.unwrap()
+			.keys()
+			.map(|k| k.as_str())
+			.collect();
+		keys.sort_unstable();
+		assert_eq!(
+			keys,
+			[
+				"arguments",
+				"bvh_build_time",
+				"camera_rays",
+				"height",
+				"peak_rss_bytes",
+				"rays",
+				"rays_per_sec",
+				"render_time",
+				"sample_seed",
+				"samples",
+				"scene",
+				"schema_version",
+				"software",
+				"threads",
+				"wall_time",
+				"width",
+				"world_seed",
+			]
+		);
+		let mut keys: Vec<&str> = value["threads"][0]
+			.as_object()
+			.unwrap()
+			.keys()
+			.map(|k| k.as_str())
+			.collect();
+		keys.sort_unstable();
+		assert_eq!(
+			keys,
+			[
+				"busy_time",
+				"camera_rays",
+				"pixels",
+				"rays",
+				"rays_per_sec",
+				"tiles",
+				"wall_time"
+			]
+		);
+	}
+}
@@ -1,52 +1,197 @@
-use crate::common::{Point3, Ray};
+use crate::common::{Interval, Point3, Ray};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Aabb {
-	minimum: Point3,
-	maximum: Point3,
+	x: Interval,
+	y: Interval,
+	z: Interval,
 }
 
 impl Aabb {
+	/// a box around nothing, which any box can be added to with surrounding_box
+	pub const EMPTY: Aabb = Aabb {
+		x: Interval::EMPTY,
+		y: Interval::EMPTY,
+		z: Interval::EMPTY,
+	};
+	/// how thick flat objects make their boxes, as a fraction of the box's size
+	pub const FLAT_PADDING: f64 = 1e-6;
+	/// how thick pad_relative makes boxes that are a single point at the origin
+	const MIN_PADDING: f64 = 1e-12;
+
 	pub fn new(minimum: Point3, maximum: Point3) -> Aabb {
-		Aabb { minimum, maximum }
+		Aabb {
+			x: Interval::new(minimum.x(), maximum.x()),
+			y: Interval::new(minimum.y(), maximum.y()),
+			z: Interval::new(minimum.z(), maximum.z()),
+		}
 	}
 
 	pub fn min(&self) -> Point3 {
-		self.minimum
+		Point3::new(self.x.min, self.y.min, self.z.min)
 	}
 
 	pub fn max(&self) -> Point3 {
-		self.maximum
+		Point3::new(self.x.max, self.y.max, self.z.max)
+	}
+
+	/// the box's extent along axis 0, 1, or 2
+	pub fn axis(&self, n: usize) -> Interval {
+		match n {
+			0 => self.x,
+			1 => self.y,
+			_ => self.z,
+		}
+	}
+
+	/// whether other is entirely inside this box
+	pub fn contains(&self, other: Aabb) -> bool {
+		(0..3).all(|n| {
+			let (outer, inner) = (self.axis(n), other.axis(n));
+			outer.min <= inner.min && inner.max <= outer.max
+		})
+	}
+
+	/// whether the box has nothing in it, like EMPTY
+	pub fn is_empty(&self) -> bool {
+		self.x.is_empty() || self.y.is_empty() || self.z.is_empty()
+	}
+
+	/// The box made at least fraction of its size thick along every axis, keeping it centered
+	/// where it was, so that rays just beside flat objects still hit their boxes. Its size is
+	/// the longer of its diagonal and its distance from the origin, since thinner padding far
+	/// from the origin would be lost to rounding
+	pub fn pad_relative(self, fraction: f64) -> Aabb {
+		if self.is_empty() {
+			return self;
+		}
+		let (min, max) = (self.min(), self.max());
+		let size = (max - min)
+			.length()
+			.max(min.abs().max(max.abs()).max_component());
+		let thickness = (fraction * size).max(Aabb::MIN_PADDING);
+		let pad = |i: Interval| {
+			if i.size() < thickness {
+				i.expand(thickness - i.size())
+			} else {
+				i
+			}
+		};
+		Aabb {
+			x: pad(self.x),
+			y: pad(self.y),
+			z: pad(self.z),
+		}
+	}
+
+	pub fn volume(&self) -> f64 {
+		if self.is_empty() {
+			return 0.0;
+		}
+		self.x.size() * self.y.size() * self.z.size()
+	}
+
+	pub fn surface_area(&self) -> f64 {
+		if self.is_empty() {
+			return 0.0;
+		}
+		let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+		2.0 * (x * y + y * z + z * x)
 	}
 
-	pub fn hit(&self, r: Ray, mut t_min: f64, mut t_max: f64) -> bool {
+	/// whether r goes through the box anywhere in ray_t
+	pub fn hit(&self, r: Ray, mut ray_t: Interval) -> bool {
 		for a in 0..3 {
+			let axis = self.axis(a);
 			let inv_d = 1.0 / r.direction()[a];
-			let mut t0 = (self.minimum[a] - r.origin()[a]) * inv_d;
-			let mut t1 = (self.maximum[a] - r.origin()[a]) * inv_d;
+			let mut t0 = (axis.min - r.origin()[a]) * inv_d;
+			let mut t1 = (axis.max - r.origin()[a]) * inv_d;
 			if inv_d < 0.0 {
 				std::mem::swap(&mut t0, &mut t1);
 			}
-			t_min = if t0 > t_min { t0 } else { t_min };
-			t_max = if t1 < t_max { t1 } else { t_max };
-			if t_max <= t_min {
+			ray_t.min = if t0 > ray_t.min { t0 } else { ray_t.min };
+			ray_t.max = if t1 < ray_t.max { t1 } else { ray_t.max };
+			if ray_t.max <= ray_t.min {
 				return false;
 			}
 		}
 		true
 	}
 
+	/// the smallest box around both, which is the other one if either is empty
 	pub fn surrounding_box(box0: Aabb, box1: Aabb) -> Aabb {
-		let small = Point3::new(
-			f64::min(box0.min().x(), box1.min().x()),
-			f64::min(box0.min().y(), box1.min().y()),
-			f64::min(box0.min().z(), box1.min().z()),
-		);
-		let big = Point3::new(
-			f64::max(box0.max().x(), box1.max().x()),
-			f64::max(box0.max().y(), box1.max().y()),
-			f64::max(box0.max().z(), box1.max().z()),
-		);
-		Aabb::new(small, big)
+		Aabb {
+			x: Interval::hull(box0.x, box1.x),
+			y: Interval::hull(box0.y, box1.y),
+			z: Interval::hull(box0.z, box1.z),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::Vec3;
+
+	#[test]
+	fn test_pad() {
+		for scale in [1e-3, 1.0, 1e6] {
+			// a flat box, like a rect's, is only hit by rays just beside it once it's padded
+			let flat = Aabb::new(
+				Point3::new(0.0, 0.0, scale),
+				Point3::new(scale, scale, scale),
+			);
+			let padded = flat.pad_relative(Aabb::FLAT_PADDING);
+			let thickness = padded.axis(2).size();
+			// the diagonal is the longest way across the box
+			let expected = 2f64.sqrt() * scale * Aabb::FLAT_PADDING;
+			assert!((thickness / expected - 1.0).abs() < 1e-6, "{}", thickness);
+			assert!(((padded.axis(2).min + padded.axis(2).max) / 2.0 - scale).abs() < 1e-9 * scale);
+			// thick enough axes are left alone
+			assert_eq!(padded.axis(0), flat.axis(0));
+
+			let beside = Ray::new(
+				Point3::new(-scale, 0.5 * scale, scale + thickness / 4.0),
+				Vec3::new(1.0, 0.0, 0.0),
+				0.0,
+				false,
+			);
+			let everywhere = Interval::new(0.0, f64::INFINITY);
+			assert!(!flat.hit(beside, everywhere));
+			assert!(padded.hit(beside, everywhere));
+			// but the ray has to reach it
+			assert!(!padded.hit(beside, Interval::new(0.0, 0.5 * scale)));
+			assert!(padded.hit(beside, Interval::new(0.0, 1.5 * scale)));
+		}
+
+		// far from the origin, the padding isn't lost to rounding
+		let far = Aabb::new(Point3::new(1e12, 0.0, 0.0), Point3::new(1e12, 1.0, 1.0))
+			.pad_relative(Aabb::FLAT_PADDING);
+		assert!(far.axis(0).size() >= 1e12 * Aabb::FLAT_PADDING * 0.99);
+		// a point at the origin still gets some thickness
+		let point = Aabb::new(Point3::zero(), Point3::zero()).pad_relative(Aabb::FLAT_PADDING);
+		assert!(point.volume() > 0.0);
+	}
+
+	#[test]
+	fn test_empty() {
+		let b = Aabb::new(Point3::new(-1.0, 0.0, 2.0), Point3::new(1.0, 3.0, 4.0));
+		assert!(Aabb::EMPTY.is_empty());
+		assert!(!b.is_empty());
+		assert_eq!(Aabb::EMPTY.volume(), 0.0);
+		assert_eq!(Aabb::EMPTY.surface_area(), 0.0);
+		assert!(Aabb::EMPTY.pad_relative(Aabb::FLAT_PADDING).is_empty());
+		let r = Ray::new(Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0, false);
+		assert!(!Aabb::EMPTY.hit(r, Interval::UNIVERSE));
+
+		// an empty box adds nothing to another one
+		for surrounding in [
+			Aabb::surrounding_box(Aabb::EMPTY, b),
+			Aabb::surrounding_box(b, Aabb::EMPTY),
+		] {
+			assert_eq!(surrounding.min().to_array(), b.min().to_array());
+			assert_eq!(surrounding.max().to_array(), b.max().to_array());
+		}
+		assert!(Aabb::surrounding_box(Aabb::EMPTY, Aabb::EMPTY).is_empty());
 	}
 }
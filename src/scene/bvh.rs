@@ -1,15 +1,21 @@
-use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
 use std::sync::Arc;
 
 use rand::{Rng, RngCore};
 
-use crate::common::{Color, Point3, Ray, Vec3};
-use crate::object::{material::ScatterResult, HitRecord, Hittable, Material};
+use crate::common::{Color, Interval, Point3, Ray, Vec3};
+use crate::object::{material::ScatterResult, texture::Footprint, HitRecord, Hittable, Material};
 use crate::scene::Aabb;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct DebugMaterial(pub Color);
 
+impl Display for DebugMaterial {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "DebugMaterial{}", self.0)
+	}
+}
+
 impl Material for DebugMaterial {
 	fn scatter(
 		&self,
@@ -20,9 +26,13 @@ impl Material for DebugMaterial {
 		None
 	}
 
-	fn emitted(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+	fn emitted(&self, _rec: &HitRecord) -> Color {
 		self.0
 	}
+
+	fn is_emissive(&self) -> bool {
+		true
+	}
 }
 
 #[derive(Debug)]
@@ -30,38 +40,24 @@ pub enum BvhConstructionError {
 	NoBoundingBox,
 }
 
-fn box_compare(
-	a: &dyn Hittable,
-	b: &dyn Hittable,
-	axis: usize,
-) -> Result<Ordering, BvhConstructionError> {
-	let box_a = a.bounding_box(0.0, 0.0);
-	let box_b = b.bounding_box(0.0, 0.0);
-	if let (Some(a), Some(b)) = (box_a, box_b) {
-		Ok(f64::total_cmp(&a.min()[axis], &b.min()[axis]))
-	} else {
-		Err(BvhConstructionError::NoBoundingBox)
-	}
-}
-
-fn box_x_compare(a: &dyn Hittable, b: &dyn Hittable) -> Result<Ordering, BvhConstructionError> {
-	box_compare(a, b, 0)
-}
-
-fn box_y_compare(a: &dyn Hittable, b: &dyn Hittable) -> Result<Ordering, BvhConstructionError> {
-	box_compare(a, b, 1)
-}
-
-fn box_z_compare(a: &dyn Hittable, b: &dyn Hittable) -> Result<Ordering, BvhConstructionError> {
-	box_compare(a, b, 2)
+/// an object along with its bounding boxes, which are only computed once while building the tree
+struct Entry {
+	object: Arc<dyn Hittable>,
+	/// box at time 0, used to order objects along an axis
+	sort_box: Aabb,
+	/// box over the whole time interval
+	bbox: Aabb,
 }
 
 #[derive(Debug)]
 pub struct BvhNode {
-	left: Arc<dyn Hittable>,
-	right: Arc<dyn Hittable>,
+	/// left and right, which are the same object in a node holding only one
+	children: [Arc<dyn Hittable>; 2],
 	bbox: Aabb,
 	material: DebugMaterial,
+	/// the time interval that bbox covers
+	time0: f64,
+	time1: f64,
 }
 
 impl BvhNode {
@@ -71,92 +67,212 @@ impl BvhNode {
 		time0: f64,
 		time1: f64,
 	) -> Result<BvhNode, BvhConstructionError> {
-		let (left, right) = match src_objects.len() {
-			1 => (src_objects[0].clone(), src_objects[0].clone()),
-			2 => (src_objects[0].clone(), src_objects[1].clone()),
+		let mut entries = src_objects
+			.iter()
+			.map(|object| {
+				match (
+					object.bounding_box(0.0, 0.0),
+					object.bounding_box(time0, time1),
+				) {
+					(Some(sort_box), Some(bbox)) => Ok(Entry {
+						object: object.clone(),
+						sort_box,
+						bbox,
+					}),
+					_ => Err(BvhConstructionError::NoBoundingBox),
+				}
+			})
+			.collect::<Result<Vec<Entry>, BvhConstructionError>>()?;
+		if entries.is_empty() {
+			return Err(BvhConstructionError::NoBoundingBox);
+		}
+		Ok(BvhNode::build(rng, &mut entries, time0, time1))
+	}
+
+	fn build<R: Rng + ?Sized>(
+		rng: &mut R,
+		entries: &mut [Entry],
+		time0: f64,
+		time1: f64,
+	) -> BvhNode {
+		let ((left, box_left), (right, box_right)) = match entries {
+			[only] => (
+				(only.object.clone(), only.bbox),
+				(only.object.clone(), only.bbox),
+			),
+			[first, second] => (
+				(first.object.clone(), first.bbox),
+				(second.object.clone(), second.bbox),
+			),
 			_ => {
-				// convert objects into mutable array
-				let mut objects: Vec<Arc<dyn Hittable>> =
-					src_objects.iter().map(|p| p.clone()).collect();
-
-				let comparator = [box_x_compare, box_y_compare, box_z_compare][rng.gen_range(0..3)];
-				let mut errored = false;
-				objects.sort_by(|a, b| match comparator(a.as_ref(), b.as_ref()) {
-					Ok(ord) => ord,
-					Err(_) => {
-						errored = true;
-						Ordering::Equal
-					},
+				let axis = rng.gen_range(0..3);
+				entries.sort_by(|a, b| {
+					f64::total_cmp(&a.sort_box.min()[axis], &b.sort_box.min()[axis])
 				});
-				if errored {
-					return Err(BvhConstructionError::NoBoundingBox);
-				}
 
-				let midpoint = src_objects.len() / 2;
+				let midpoint = entries.len() / 2;
+				let (left_entries, right_entries) = entries.split_at_mut(midpoint);
+				let left = BvhNode::build(rng, left_entries, time0, time1);
+				let right = BvhNode::build(rng, right_entries, time0, time1);
+				let (box_left, box_right) = (left.bbox, right.bbox);
 				(
-					Arc::new(BvhNode::new(rng, &objects[..midpoint], time0, time1)?)
-						as Arc<dyn Hittable>,
-					Arc::new(BvhNode::new(rng, &objects[midpoint..], time0, time1)?)
-						as Arc<dyn Hittable>,
+					(Arc::new(left) as Arc<dyn Hittable>, box_left),
+					(Arc::new(right) as Arc<dyn Hittable>, box_right),
 				)
 			},
 		};
 
-		let box_left = left.bounding_box(time0, time1);
-		let box_right = right.bounding_box(time0, time1);
-		if let (Some(box_left), Some(box_right)) = (box_left, box_right) {
-			let bbox = Aabb::surrounding_box(box_left, box_right);
-			Ok(BvhNode {
-				left,
-				right,
-				bbox,
-				material: DebugMaterial(Color::random(rng).saturate()),
-			})
+		BvhNode {
+			children: [left, right],
+			bbox: Aabb::surrounding_box(box_left, box_right),
+			material: DebugMaterial(Color::random(rng).saturate()),
+			time0,
+			time1,
+		}
+	}
+
+	/// The same tree with its boxes recomputed for a different time interval. Objects stay in the
+	/// same places in the tree, so this is much cheaper than building a new one, but the tree may
+	/// be less efficient if objects have moved far
+	pub fn refit(&self, time0: f64, time1: f64) -> Result<BvhNode, BvhConstructionError> {
+		let refit_child = |child: &Arc<dyn Hittable>| match child.as_bvh_node() {
+			Some(node) => {
+				let node = node.refit(time0, time1)?;
+				let bbox = node.bbox;
+				Ok((Arc::new(node) as Arc<dyn Hittable>, bbox))
+			},
+			None => child
+				.bounding_box(time0, time1)
+				.map(|bbox| (child.clone(), bbox))
+				.ok_or(BvhConstructionError::NoBoundingBox),
+		};
+
+		let [old_left, old_right] = &self.children;
+		let (left, box_left) = refit_child(old_left)?;
+		let (right, box_right) = if Arc::ptr_eq(old_left, old_right) {
+			(left.clone(), box_left)
 		} else {
-			Err(BvhConstructionError::NoBoundingBox)
+			refit_child(old_right)?
+		};
+		Ok(BvhNode {
+			children: [left, right],
+			bbox: Aabb::surrounding_box(box_left, box_right),
+			material: self.material,
+			time0,
+			time1,
+		})
+	}
+
+	/// box around everything in the tree, over the time interval it was built for
+	pub fn bbox(&self) -> Aabb {
+		self.bbox
+	}
+
+	/// the children that are nodes of the tree, rather than objects in the scene
+	pub fn child_nodes(&self) -> impl Iterator<Item = &BvhNode> {
+		self.children().iter().filter_map(|c| c.as_bvh_node())
+	}
+
+	/// number of children that are objects in the scene, rather than nodes of the tree
+	pub fn primitives(&self) -> usize {
+		self.children()
+			.iter()
+			.filter(|c| c.as_bvh_node().is_none())
+			.count()
+	}
+
+	/// call f with every node in the tree and its depth, with the root at depth 0, parents before
+	/// their children
+	pub fn visit(&self, f: &mut dyn FnMut(&BvhNode, usize)) {
+		self.visit_at(0, f);
+	}
+
+	fn visit_at(&self, depth: usize, f: &mut dyn FnMut(&BvhNode, usize)) {
+		f(self, depth);
+		for child in self.child_nodes() {
+			child.visit_at(depth + 1, f);
 		}
 	}
 
+	fn covers(&self, time0: f64, time1: f64) -> bool {
+		self.time0 <= time0 && time1 <= self.time1
+	}
+
+	/// Whether every box in the tree, and in any trees inside the objects in it, holds everything
+	/// under it from time0 to time1. Rays are only tested against the boxes, so a ray at a time
+	/// when something has moved out of its box misses it. Trees built for an interval covering
+	/// time0 to time1 always hold, and others do if nothing in them moves far enough
+	pub fn bounds(&self, time0: f64, time1: f64) -> bool {
+		self.children()
+			.iter()
+			.all(|child| match child.as_bvh_node() {
+				Some(node) => node.bounds(time0, time1),
+				None => {
+					(self.covers(time0, time1)
+						|| child
+							.bounding_box(time0, time1)
+							.is_some_and(|bbox| self.bbox.contains(bbox)))
+						&& nested_bounds(child.as_ref(), time0, time1)
+				},
+			})
+	}
+
 	fn child_is_bvh(&self, child: &dyn Hittable) -> bool {
 		std::ptr::metadata(self as &dyn Hittable) == std::ptr::metadata(child)
 	}
 }
 
+/// whether the trees anywhere inside object hold it from time0 to time1, as in BvhNode::bounds
+fn nested_bounds(object: &dyn Hittable, time0: f64, time1: f64) -> bool {
+	object
+		.children()
+		.iter()
+		.all(|child| match child.as_bvh_node() {
+			Some(node) => node.bounds(time0, time1),
+			None => nested_bounds(child.as_ref(), time0, time1),
+		})
+}
+
 impl Hittable for BvhNode {
-	fn hit(&self, rng: &mut dyn RngCore, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-		if !self.bbox.hit(r, t_min, t_max) {
+	fn hit(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord> {
+		let [left, right] = &self.children;
+		if !self.bbox.hit(r, ray_t) {
 			None
 		} else {
 			if r.debug_bvh()
 				&& rng.gen::<f64>() < 0.2
-				&& !(self.child_is_bvh(self.left.as_ref())
-					&& self.child_is_bvh(self.right.as_ref()))
+				&& !(self.child_is_bvh(left.as_ref()) && self.child_is_bvh(right.as_ref()))
 			{
 				return Some(HitRecord {
 					p: Point3::zero(),
 					normal: Vec3::zero(),
 					mat_ptr: &self.material,
-					t: t_min,
+					t: ray_t.min,
 					u: 0.0,
 					v: 0.0,
+					has_edges: false,
 					front_face: true,
+					footprint: Footprint::default(),
+					label: None,
 				});
 			} else {
 				// check if left and right are the same node; if so, we don't need to check them both
-				if Arc::ptr_eq(&self.left, &self.right) {
-					return self.left.hit(rng, r, t_min, t_max);
+				if Arc::ptr_eq(left, right) {
+					return left.hit(rng, r, ray_t);
 				}
 
-				let hit_left = self.left.hit(rng, r, t_min, t_max);
-				let hit_right = self.right.hit(
+				let hit_left = left.hit(rng, r, ray_t);
+				let hit_right = right.hit(
 					rng,
 					r,
-					t_min,
-					if let Some(ref rec) = hit_left {
-						rec.t
-					} else {
-						t_max
-					},
+					Interval::new(
+						ray_t.min,
+						if let Some(ref rec) = hit_left {
+							rec.t
+						} else {
+							ray_t.max
+						},
+					),
 				);
 
 				if hit_right.is_none() {
@@ -168,7 +284,325 @@ impl Hittable for BvhNode {
 		}
 	}
 
-	fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
-		Some(self.bbox)
+	/// stops at the first child that's occluded. shadow rays never show the BVH, so there's no
+	/// debug view here
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		if !self.bbox.hit(r, ray_t) {
+			return false;
+		}
+		let [left, right] = &self.children;
+		left.occluded(rng, r, ray_t) || (!Arc::ptr_eq(left, right) && right.occluded(rng, r, ray_t))
+	}
+
+	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+		if self.covers(time0, time1) {
+			Some(self.bbox)
+		} else {
+			self.refit(time0, time1).ok().map(|node| node.bbox)
+		}
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.children().iter().any(|c| c.is_emissive())
+	}
+
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		let [left, right] = &self.children;
+		if Arc::ptr_eq(left, right) {
+			&self.children[..1]
+		} else {
+			&self.children
+		}
+	}
+
+	fn as_bvh_node(&self) -> Option<&BvhNode> {
+		Some(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use std::time::Instant;
+
+	use super::*;
+	use crate::object::material::Lambertian;
+	use crate::object::{Block, MovingSphere, Sphere, Translate, XYRect, XZRect, YZRect};
+	use crate::scene::scenes;
+	use crate::scene::HittableList;
+
+	/// a material that shadow rays go through
+	#[derive(Debug)]
+	struct Clear;
+
+	impl Display for Clear {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			write!(f, "Clear")
+		}
+	}
+
+	impl Material for Clear {
+		fn scatter(
+			&self,
+			_rng: &mut dyn RngCore,
+			_r_in: &Ray,
+			_rec: &HitRecord,
+		) -> Option<ScatterResult> {
+			None
+		}
+
+		fn casts_shadows(&self) -> bool {
+			false
+		}
+	}
+
+	/// just below the Cornell box's ceiling light
+	fn cornell_light() -> Point3 {
+		Point3::new(278.0, 550.0, 280.0)
+	}
+
+	/// shadow rays from random points in a cube to a point light, with their lengths
+	fn shadow_rays(count: usize, (min, max): (f64, f64), light: Point3) -> Vec<(Ray, f64)> {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+		(0..count)
+			.map(|_| {
+				let p = Point3::new(
+					rng.gen_range(min..max),
+					rng.gen_range(min..max),
+					rng.gen_range(min..max),
+				);
+				let to_light = light - p;
+				(Ray::new(p, to_light, 0.0, false), to_light.length())
+			})
+			.collect()
+	}
+
+	/// a sphere moving from x = 0 at time 0 to x = 10 at time 1, and two that stay still
+	fn objects() -> Vec<Arc<dyn Hittable>> {
+		let mat = Arc::new(Lambertian::with_color(Color::zero()));
+		vec![
+			Arc::new(MovingSphere::new(
+				Point3::zero(),
+				Point3::new(10.0, 0.0, 0.0),
+				0.0,
+				1.0,
+				0.5,
+				mat.clone(),
+			)),
+			Arc::new(Sphere::new(Point3::new(-5.0, 0.0, 0.0), 0.5, mat.clone())),
+			Arc::new(Sphere::new(Point3::new(-3.0, 0.0, 0.0), 0.5, mat)),
+		]
+	}
+
+	/// whether a ray straight down onto where the moving sphere is at t = 0.9 hits anything
+	fn hits_late(world: &dyn Hittable, rng: &mut Xoshiro256PlusPlus) -> bool {
+		let r = Ray::new(
+			Point3::new(9.0, 5.0, 0.0),
+			Vec3::new(0.0, -1.0, 0.0),
+			0.9,
+			false,
+		);
+		world
+			.hit(rng, r, Interval::new(0.001, f64::INFINITY))
+			.is_some()
+	}
+
+	#[test]
+	fn test_moving_outside_build_interval() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		// built for an instant, so the boxes only cover where the sphere is at t = 0
+		let bvh = BvhNode::new(&mut rng, &objects(), 0.0, 0.0).unwrap();
+		assert!(bvh.bbox.max().x() < 1.0);
+		assert!(bvh.bounds(0.0, 0.0));
+		assert!(!bvh.bounds(0.0, 1.0));
+		assert!(!hits_late(&bvh, &mut rng));
+
+		let refitted = bvh.refit(0.0, 1.0).unwrap();
+		assert!(refitted.bbox.max().x() >= 10.5);
+		assert!(refitted.bounds(0.0, 1.0));
+		assert!(hits_late(&refitted, &mut rng));
+
+		// a tree inside another object, as scenes build for groups of objects that get transformed
+		let nested: Arc<dyn Hittable> = Arc::new(Translate::new(Arc::new(bvh), Vec3::zero()));
+		let outer = BvhNode::new(&mut rng, &[nested], 0.0, 1.0).unwrap();
+		assert!(outer.bbox.max().x() >= 10.5);
+		assert!(!outer.bounds(0.0, 1.0));
+		let nested: Arc<dyn Hittable> = Arc::new(Translate::new(Arc::new(refitted), Vec3::zero()));
+		let outer = BvhNode::new(&mut rng, &[nested], 0.0, 1.0).unwrap();
+		assert!(outer.bounds(0.0, 1.0));
+		assert!(hits_late(&outer, &mut rng));
+
+		// objects that stay still are held by boxes built for any interval
+		let still = BvhNode::new(&mut rng, &objects()[1..], 0.0, 1.0).unwrap();
+		assert!(still.bounds(0.0, 2.0));
+	}
+	#[test]
+	fn test_occluded() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let scene = scenes::cornell_box();
+		let bvh = BvhNode::new(&mut rng, scene.0.flatten().as_ref(), 0.0, 1.0).unwrap();
+		let mut blocked = 0;
+		for (r, distance) in shadow_rays(1000, (0.0, 555.0), cornell_light()) {
+			let hit = bvh
+				.hit(&mut rng, r, Interval::new(0.001, distance))
+				.is_some();
+			assert_eq!(
+				bvh.occluded(&mut rng, r, Interval::new(0.001, distance)),
+				hit
+			);
+			blocked += hit as usize;
+		}
+		// some of the points are in the boxes' shadows, or inside them
+		assert!(blocked > 0 && blocked < 1000);
+
+		// shadows pass through materials that don't cast them, but not what's behind them
+		let clear: Arc<dyn Hittable> =
+			Arc::new(XZRect::new(-1.0, 1.0, -1.0, 1.0, 1.0, Arc::new(Clear)));
+		let r = Ray::new(Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0, false);
+		let bvh = BvhNode::new(&mut rng, std::slice::from_ref(&clear), 0.0, 1.0).unwrap();
+		assert!(bvh.hit(&mut rng, r, Interval::new(0.001, 10.0)).is_some());
+		assert!(!bvh.occluded(&mut rng, r, Interval::new(0.001, 10.0)));
+		let mat = Arc::new(Lambertian::with_color(Color::zero()));
+		let behind: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0.0, 3.0, 0.0), 1.0, mat));
+		let bvh = BvhNode::new(&mut rng, &[clear, behind], 0.0, 1.0).unwrap();
+		assert!(bvh.occluded(&mut rng, r, Interval::new(0.001, 10.0)));
+	}
+
+	/// A room the size of scale with flat shelves and a flat block in it, and rays from inside
+	/// it, some of them skimming just over the shelves
+	fn flat_room(scale: f64) -> (Vec<Arc<dyn Hittable>>, Vec<Ray>) {
+		let mat = Arc::new(Lambertian::with_color(Color::zero()));
+		let s = scale;
+		let mut objects: Vec<Arc<dyn Hittable>> = vec![
+			Arc::new(XYRect::new(0.0, s, 0.0, s, 0.0, mat.clone())),
+			Arc::new(XYRect::new(0.0, s, 0.0, s, s, mat.clone())),
+			Arc::new(XZRect::new(0.0, s, 0.0, s, 0.0, mat.clone())),
+			Arc::new(XZRect::new(0.0, s, 0.0, s, s, mat.clone())),
+			Arc::new(YZRect::new(0.0, s, 0.0, s, 0.0, mat.clone())),
+			Arc::new(YZRect::new(0.0, s, 0.0, s, s, mat.clone())),
+			Arc::new(Block::new(
+				Point3::new(0.6 * s, 0.1 * s, 0.6 * s),
+				Point3::new(0.9 * s, 0.1 * s, 0.9 * s),
+				mat.clone(),
+			)),
+		];
+		let shelves = [0.2, 0.4, 0.6, 0.8];
+		for &k in &shelves {
+			objects.push(Arc::new(XZRect::new(
+				0.1 * s,
+				0.5 * s,
+				0.1 * s,
+				0.5 * s,
+				k * s,
+				mat.clone(),
+			)));
+		}
+
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(2);
+		let mut rays: Vec<Ray> = (0..500)
+			.map(|_| {
+				let p = Point3::new(
+					rng.gen_range(0.01..0.99),
+					rng.gen_range(0.01..0.99),
+					rng.gen_range(0.01..0.99),
+				);
+				Ray::new(s * p, Vec3::random_unit_vector(&mut rng), 0.0, false)
+			})
+			.collect();
+		for &k in &shelves {
+			for i in 0..50 {
+				// starting a hair above the shelf and only just sloping down onto it
+				let x = 0.1 + 0.4 * i as f64 / 50.0;
+				let origin = s * Point3::new(x, k + 1e-9, 0.05);
+				let direction = Vec3::new(0.0, -1e-9 / (0.1 + 0.007 * i as f64), 1.0);
+				rays.push(Ray::new(origin, direction, 0.0, false));
+			}
+		}
+		(objects, rays)
+	}
+
+	#[test]
+	fn test_flat_scales() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		for scale in [1e-3, 1.0, 1e6] {
+			let (objects, rays) = flat_room(scale);
+			let list = HittableList::from_iter(objects.iter().cloned());
+			let bvh = BvhNode::new(&mut rng, &objects, 0.0, 1.0).unwrap();
+			for r in rays {
+				let expected = list
+					.hit(&mut rng, r, Interval::new(0.0, f64::INFINITY))
+					.map(|rec| rec.t);
+				let actual = bvh
+					.hit(&mut rng, r, Interval::new(0.0, f64::INFINITY))
+					.map(|rec| rec.t);
+				assert!(expected.is_some(), "{}: rays inside the room hit it", scale);
+				assert_eq!(
+					actual,
+					expected,
+					"{}: {} {}",
+					scale,
+					r.origin(),
+					r.direction()
+				);
+				if r.direction().z() == 1.0 {
+					// the skimming rays land on their shelf, not the far wall
+					assert!(
+						expected.unwrap() < 0.5 * scale,
+						"{}: {} {}",
+						scale,
+						r.origin(),
+						r.direction()
+					);
+				}
+			}
+		}
+	}
+
+	/// run with `cargo test --release -- --ignored bench_occluded --nocapture`
+	#[test]
+	#[ignore]
+	fn bench_occluded() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		for (name, scene, bounds, light) in [
+			(
+				"cornell",
+				scenes::cornell_box(),
+				(0.0, 555.0),
+				cornell_light(),
+			),
+			(
+				"sunny",
+				scenes::sunny(&mut rng),
+				(-10.0, 10.0),
+				Point3::new(0.0, 50.0, 0.0),
+			),
+		] {
+			let bvh = BvhNode::new(&mut rng, scene.0.flatten().as_ref(), 0.0, 1.0).unwrap();
+			let rays = shadow_rays(1_000_000, bounds, light);
+			let start = Instant::now();
+			let hits = rays
+				.iter()
+				.filter(|&&(r, distance)| {
+					bvh.hit(&mut rng, r, Interval::new(0.001, distance))
+						.is_some()
+				})
+				.count();
+			let hit_time = start.elapsed();
+			let start = Instant::now();
+			let occluded = rays
+				.iter()
+				.filter(|&&(r, distance)| bvh.occluded(&mut rng, r, Interval::new(0.001, distance)))
+				.count();
+			let occluded_time = start.elapsed();
+			assert_eq!(hits, occluded);
+			eprintln!(
+				"{:>8}: hit {:.0} ns/ray, occluded {:.0} ns/ray",
+				name,
+				hit_time.as_nanos() as f64 / rays.len() as f64,
+				occluded_time.as_nanos() as f64 / rays.len() as f64
+			);
+		}
 	}
 }
@@ -1,6 +1,45 @@
 use rand::Rng;
 
-use crate::common::{Point3, Ray, Vec3};
+use crate::common::{Differentials, OrthonormalBasis, Point3, Ray, Vec3};
+
+/// Settings of a real camera that decide how bright the image is, for matching exposures across
+/// f-stops. The camera's focal plane stands in for its sensor, so the f-number is the focus
+/// distance over the lens diameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalExposure {
+	pub f_number: f64,
+	/// sensitivity of the sensor, where 200 is twice as bright as 100
+	pub iso: f64,
+	/// seconds the shutter is open for. this only changes brightness, not motion blur, which
+	/// comes from the shutter interval in scene time
+	pub shutter_speed: f64,
+}
+
+impl PhysicalExposure {
+	/// Brightness multiplier for the image. The sunny 16 rule, f/16 at ISO 100 for 1/100 of a
+	/// second, leaves it as bright as it is without physical exposure
+	pub fn scale(&self) -> f64 {
+		(self.iso / 100.0) * (self.shutter_speed * 100.0) * (16.0 / self.f_number).powi(2)
+	}
+}
+
+/// The random numbers that a camera ray is made from, each in [0, 1)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CameraSample {
+	/// where on the lens the ray starts
+	pub lens: [f64; 2],
+	/// when in the shutter interval the ray is
+	pub time: f64,
+}
+
+impl CameraSample {
+	pub fn random<R: Rng + ?Sized>(rng: &mut R) -> CameraSample {
+		CameraSample {
+			lens: [rng.gen(), rng.gen()],
+			time: rng.gen(),
+		}
+	}
+}
 
 #[derive(Clone, Copy)]
 pub struct Camera {
@@ -14,10 +53,15 @@ pub struct Camera {
 	time0: f64,
 	time1: f64,
 	aspect_ratio: f64,
+	/// None for an image that's as bright no matter the aperture
+	exposure: Option<PhysicalExposure>,
 }
 
 impl Camera {
-	/// vfov: vertical field of view in degrees
+	/// vfov:         vertical field of view in degrees
+	/// time0, time1: when the shutter opens and closes. rays are spread over this interval for
+	///               motion blur; pass the same time for both (or use with_static_shutter) for a
+	///               frozen instant
 	pub fn new(
 		look_from: Point3,
 		look_at: Point3,
@@ -34,9 +78,8 @@ impl Camera {
 		let viewport_height = 2.0 * h;
 		let viewport_width = aspect_ratio * viewport_height;
 
-		let w = (look_from - look_at).unit_vector();
-		let u = vup.cross(w).unit_vector();
-		let v = w.cross(u);
+		let OrthonormalBasis { u, v, w } =
+			OrthonormalBasis::with_up((look_from - look_at).unit_vector(), vup);
 
 		let origin = look_from;
 		let horizontal = focus_dist * viewport_width * u;
@@ -54,21 +97,302 @@ impl Camera {
 			time0,
 			time1,
 			aspect_ratio,
+			exposure: None,
 		}
 	}
 
 	pub fn get_ray<R: Rng + ?Sized>(&self, rng: &mut R, s: f64, t: f64, only_bvh: bool) -> Ray {
-		let rd = self.lens_radius * Vec3::random_in_unit_disk(rng);
+		self.sample_ray(s, t, CameraSample::random(rng), only_bvh)
+	}
+
+	/// The ray through s, t on the image, starting from a point on the lens and at a time that
+	/// are chosen by sample, so that they can come from their own random numbers
+	pub fn sample_ray(&self, s: f64, t: f64, sample: CameraSample, only_bvh: bool) -> Ray {
+		let rd = self.lens_radius * Vec3::in_unit_disk(sample.lens);
 		let offset = self.u * rd.x() + self.v * rd.y();
 		Ray::new(
 			self.origin + offset,
 			self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-			rng.gen_range(self.time0..self.time1),
+			if self.time1 > self.time0 {
+				self.time0 + sample.time * (self.time1 - self.time0)
+			} else {
+				self.time0
+			},
 			only_bvh,
 		)
 	}
 
+	/// center of the viewport, on the plane of focus
+	fn center(&self) -> Point3 {
+		self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0
+	}
+
+	/// angle in radians covered by one pixel at the center of an image with the given height
+	pub fn pixel_spread(&self, image_height: usize) -> f64 {
+		self.vertical.length() / image_height as f64 / self.focus_distance()
+	}
+
+	/// How the rays through neighboring pixels of an image with the given size differ, for
+	/// Ray::with_differentials. rays from the same point on the lens only differ in direction
+	pub fn pixel_differentials(&self, (width, height): (usize, usize)) -> Differentials {
+		Differentials {
+			dx_origin: Vec3::zero(),
+			dx_direction: self.horizontal / width as f64,
+			dy_origin: Vec3::zero(),
+			dy_direction: self.vertical / height as f64,
+		}
+	}
+
+	/// distance from the camera to the plane that is in focus
+	pub fn focus_distance(&self) -> f64 {
+		(self.center() - self.origin).length()
+	}
+
+	/// distance from the camera to the plane facing it that goes through p. focusing at this
+	/// distance makes p sharp
+	pub fn distance_to_plane(&self, p: Point3) -> f64 {
+		(p - self.origin).dot((self.center() - self.origin).unit_vector())
+	}
+
 	pub fn aspect_ratio(&self) -> f64 {
 		self.aspect_ratio
 	}
+
+	/// times when the shutter opens and closes
+	pub fn shutter(&self) -> (f64, f64) {
+		(self.time0, self.time1)
+	}
+
+	/// The same camera moved to look from look_from at look_at with a vertical field of view of
+	/// vfov degrees, focused on look_at. It keeps its idea of which way is up, and its lens,
+	/// shutter, aspect ratio and exposure
+	pub fn with_view(&self, look_from: Point3, look_at: Point3, vfov: f64) -> Self {
+		let cam = Camera::new(
+			look_from,
+			look_at,
+			self.v,
+			vfov,
+			self.aspect_ratio,
+			2.0 * self.lens_radius,
+			(look_at - look_from).length(),
+			self.time0,
+			self.time1,
+		);
+		match self.exposure {
+			Some(exposure) => cam.with_physical_exposure(exposure),
+			None => cam,
+		}
+	}
+
+	/// The same camera with the shutter open from time0 to time1
+	pub fn with_shutter(&self, time0: f64, time1: f64) -> Self {
+		Self {
+			time0,
+			time1,
+			..*self
+		}
+	}
+
+	/// The same camera capturing a single instant, so there is no motion blur
+	pub fn with_static_shutter(&self, time: f64) -> Self {
+		self.with_shutter(time, time)
+	}
+
+	/// The same camera with a different aspect ratio, keeping the vertical field of view. Used to
+	/// exactly match the integer dimensions of the output image so that pixels stay square
+	pub fn with_aspect_ratio(&self, aspect_ratio: f64) -> Self {
+		let center = self.center();
+		let horizontal = self.horizontal * (aspect_ratio / self.aspect_ratio);
+		Self {
+			horizontal,
+			lower_left_corner: center - horizontal / 2.0 - self.vertical / 2.0,
+			aspect_ratio,
+			..*self
+		}
+	}
+
+	/// The same camera focused at a different distance, keeping the field of view. with physical
+	/// exposure, the lens is resized to keep the f-number
+	pub fn with_focus_distance(&self, focus_dist: f64) -> Self {
+		let scale = focus_dist / self.focus_distance();
+		let horizontal = self.horizontal * scale;
+		let vertical = self.vertical * scale;
+		let center = self.origin + (self.center() - self.origin) * scale;
+		Self {
+			horizontal,
+			vertical,
+			lower_left_corner: center - horizontal / 2.0 - vertical / 2.0,
+			lens_radius: match self.exposure {
+				Some(exposure) => focus_dist / exposure.f_number / 2.0,
+				None => self.lens_radius,
+			},
+			..*self
+		}
+	}
+
+	/// The same camera moved sideways by offset (to the right if positive), still looking at the
+	/// same part of the plane of focus. Used for the two eyes of a stereo pair, so that objects at
+	/// the focus distance end up in the same place in both
+	pub fn with_eye_offset(&self, offset: f64) -> Self {
+		Self {
+			origin: self.origin + offset * self.u,
+			..*self
+		}
+	}
+
+	/// The same camera with a different lens diameter. 0 keeps everything in focus. with
+	/// physical exposure, this changes the f-number and so the brightness too
+	pub fn with_aperture(&self, aperture: f64) -> Self {
+		Self {
+			lens_radius: aperture / 2.0,
+			exposure: self.exposure.map(|exposure| PhysicalExposure {
+				f_number: self.focus_distance() / aperture,
+				..exposure
+			}),
+			..*self
+		}
+	}
+
+	/// The same camera with a lens sized for exposure's f-number, and an image as bright as a real
+	/// camera's with those settings would be
+	pub fn with_physical_exposure(&self, exposure: PhysicalExposure) -> Self {
+		Self {
+			lens_radius: self.focus_distance() / exposure.f_number / 2.0,
+			exposure: Some(exposure),
+			..*self
+		}
+	}
+
+	/// what to multiply the light reaching the camera by, which is 1 without physical exposure
+	pub fn exposure_scale(&self) -> f64 {
+		self.exposure.map_or(1.0, |exposure| exposure.scale())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+
+	#[test]
+	fn test_static_shutter() {
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.0,
+			1.0,
+			0.25,
+			0.25,
+		);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		assert_eq!(cam.get_ray(&mut rng, 0.5, 0.5, false).time(), 0.25);
+
+		let cam = cam.with_shutter(0.0, 1.0).with_static_shutter(0.75);
+		assert_eq!(cam.get_ray(&mut rng, 0.5, 0.5, false).time(), 0.75);
+	}
+
+	#[test]
+	fn test_focus_distance() {
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.5,
+			1.0,
+			0.0,
+			0.0,
+		)
+		.with_focus_distance(4.0);
+		assert!((cam.focus_distance() - 4.0).abs() < 1e-9);
+		assert_eq!(cam.distance_to_plane(Point3::new(3.0, 1.0, -2.0)), 2.0);
+
+		// rays through the same point on the image start at different places on the lens, but meet
+		// again on the plane of focus
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let focused = |rng: &mut Xoshiro256PlusPlus| {
+			let r = cam.get_ray(rng, 0.25, 0.75, false);
+			r.at(-4.0 / r.direction().z())
+		};
+		let first = focused(&mut rng);
+		for _ in 0..10 {
+			assert!((focused(&mut rng) - first).length() < 1e-9);
+		}
+		// and the field of view doesn't change
+		assert!(
+			(first - Point3::new(-2.0, 2.0, -4.0)).length() < 1e-9,
+			"{}",
+			first
+		);
+	}
+
+	#[test]
+	fn test_eye_offset() {
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.0,
+			2.0,
+			0.0,
+			0.0,
+		);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let left = cam.with_eye_offset(-0.5).get_ray(&mut rng, 0.5, 0.5, false);
+		let right = cam.with_eye_offset(0.5).get_ray(&mut rng, 0.5, 0.5, false);
+		assert_eq!(left.origin().x(), -0.5);
+		assert_eq!(right.origin().x(), 0.5);
+		// both eyes see the center of the image at the same point on the plane of focus
+		assert!((left.at(1.0) - Point3::new(0.0, 0.0, -2.0)).near_zero());
+		assert!((right.at(1.0) - Point3::new(0.0, 0.0, -2.0)).near_zero());
+	}
+
+	#[test]
+	fn test_physical_exposure() {
+		let cam = Camera::new(
+			Point3::zero(),
+			Point3::new(0.0, 0.0, -1.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			90.0,
+			1.0,
+			0.5,
+			2.0,
+			0.0,
+			0.0,
+		);
+		// without physical exposure, a bigger lens only changes the depth of field
+		assert_eq!(cam.with_aperture(0.5 * 2f64.sqrt()).exposure_scale(), 1.0);
+
+		let sunny = PhysicalExposure {
+			f_number: 16.0,
+			iso: 100.0,
+			shutter_speed: 0.01,
+		};
+		assert_eq!(sunny.scale(), 1.0);
+		let cam = cam.with_physical_exposure(sunny);
+		assert!((cam.lens_radius - 2.0 / 16.0 / 2.0).abs() < 1e-12);
+		assert_eq!(cam.exposure_scale(), 1.0);
+		// twice the area lets in twice the light, and so does one stop of ISO or shutter
+		let wider = cam.with_aperture(2.0 * cam.lens_radius * 2f64.sqrt());
+		assert!((wider.exposure_scale() - 2.0).abs() < 1e-9);
+		let faster = PhysicalExposure {
+			iso: 200.0,
+			shutter_speed: 0.005,
+			..sunny
+		};
+		assert!((faster.scale() - 1.0).abs() < 1e-12);
+
+		// refocusing keeps the f-number, so the brightness stays and the lens grows
+		let refocused = wider.with_focus_distance(8.0);
+		assert!((refocused.exposure_scale() - 2.0).abs() < 1e-9);
+		assert!((refocused.lens_radius - 4.0 * wider.lens_radius).abs() < 1e-12);
+	}
 }
@@ -0,0 +1,255 @@
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::common::Point3;
+
+/// Where the camera is at one point along a path
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(from = "RawKeyframe")]
+pub struct Keyframe {
+	pub time: f64,
+	pub from: Point3,
+	pub at: Point3,
+	/// vertical field of view in degrees
+	pub fov: f64,
+}
+
+/// a keyframe as it's written in a camera path file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawKeyframe {
+	time: f64,
+	from: [f64; 3],
+	at: [f64; 3],
+	fov: f64,
+}
+
+impl From<RawKeyframe> for Keyframe {
+	fn from(raw: RawKeyframe) -> Keyframe {
+		Keyframe {
+			time: raw.time,
+			from: Point3::from_array(raw.from),
+			at: Point3::from_array(raw.at),
+			fov: raw.fov,
+		}
+	}
+}
+
+/// A smooth path for the camera through keyframes, for --camera-path. The camera's position and
+/// the point it looks at each follow a centripetal Catmull-Rom spline, which goes through every
+/// keyframe without overshooting or looping back where they're unevenly spaced, and the field of
+/// view changes linearly between keyframes
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+	keyframes: Vec<Keyframe>,
+}
+
+/// Position at time in the middle of the segment from p1 to p2, which are at times time1 and
+/// time2, with p0 and p3 the points before and after them
+fn catmull_rom([p0, p1, p2, p3]: [Point3; 4], (time1, time2): (f64, f64), time: f64) -> Point3 {
+	// the spline's own parameter goes up by the square root of the distance between points.
+	// points in the same place still get a tiny gap, so that nothing is divided by zero
+	let gap = |a: Point3, b: Point3| (a - b).length().sqrt().max(1e-9);
+	let t0 = 0.0;
+	let t1 = t0 + gap(p0, p1);
+	let t2 = t1 + gap(p1, p2);
+	let t3 = t2 + gap(p2, p3);
+	let t = t1 + (t2 - t1) * (time - time1) / (time2 - time1);
+
+	// Barry and Goldman's pyramid of linear interpolations
+	let lerp = |a: Point3, b: Point3, ta: f64, tb: f64| {
+		(tb - t) / (tb - ta) * a + (t - ta) / (tb - ta) * b
+	};
+	let a1 = lerp(p0, p1, t0, t1);
+	let a2 = lerp(p1, p2, t1, t2);
+	let a3 = lerp(p2, p3, t2, t3);
+	let b1 = lerp(a1, a2, t0, t2);
+	let b2 = lerp(a2, a3, t1, t3);
+	lerp(b1, b2, t1, t2)
+}
+
+impl CameraPath {
+	/// keyframes: at least two, in order of increasing time
+	pub fn new(keyframes: Vec<Keyframe>) -> Result<CameraPath, String> {
+		if keyframes.len() < 2 {
+			return Err("a camera path needs at least 2 keyframes".to_string());
+		}
+		if let Some(k) = keyframes.iter().find(|k| !k.time.is_finite()) {
+			return Err(format!("keyframe time {} isn't a number", k.time));
+		}
+		if let Some(i) = keyframes.windows(2).position(|k| k[0].time >= k[1].time) {
+			return Err(format!(
+				"keyframe {} is at time {}, which isn't after the one before it",
+				i + 2,
+				keyframes[i + 1].time
+			));
+		}
+		if let Some(k) = keyframes.iter().find(|k| !(k.fov > 0.0 && k.fov < 180.0)) {
+			return Err(format!("field of view {} isn't between 0 and 180", k.fov));
+		}
+		if let Some(k) = keyframes.iter().find(|k| (k.at - k.from).near_zero()) {
+			return Err(format!("keyframe at time {} looks at where it is", k.time));
+		}
+		Ok(CameraPath { keyframes })
+	}
+
+	/// From a JSON file holding a list of keyframes like
+	/// {"time": 0, "from": [478, 278, -600], "at": [278, 278, 0], "fov": 40}
+	pub fn load(path: &str) -> Result<CameraPath, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+		let keyframes = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+		CameraPath::new(keyframes).map_err(|e| format!("{}: {}", path, e))
+	}
+
+	/// times of the first and last keyframes
+	pub fn times(&self) -> (f64, f64) {
+		(self.keyframes[0].time, self.keyframes.last().unwrap().time)
+	}
+
+	/// Where the camera is at time, which is held at the first or last keyframe outside of them
+	pub fn at(&self, time: f64) -> Keyframe {
+		let (first, last) = self.times();
+		let time = time.clamp(first, last);
+		let keys = &self.keyframes;
+		// the segment that time is in
+		let i = keys
+			.partition_point(|k| k.time <= time)
+			.clamp(1, keys.len() - 1);
+		let (k1, k2) = (keys[i - 1], keys[i]);
+		// past the ends, carry on in the same direction as the first or last segment
+		let before = |get: fn(&Keyframe) -> Point3| match i {
+			1 => 2.0 * get(&k1) - get(&k2),
+			_ => get(&keys[i - 2]),
+		};
+		let after = |get: fn(&Keyframe) -> Point3| match keys.get(i + 1) {
+			Some(k3) => get(k3),
+			None => 2.0 * get(&k2) - get(&k1),
+		};
+		let spline = |get: fn(&Keyframe) -> Point3| {
+			catmull_rom(
+				[before(get), get(&k1), get(&k2), after(get)],
+				(k1.time, k2.time),
+				time,
+			)
+		};
+		let fraction = (time - k1.time) / (k2.time - k1.time);
+		Keyframe {
+			time,
+			from: spline(|k| k.from),
+			at: spline(|k| k.at),
+			fov: k1.fov + fraction * (k2.fov - k1.fov),
+		}
+	}
+
+	/// Where the camera is for each of a number of frames spread evenly along the whole path,
+	/// starting at the first keyframe and ending at the last
+	pub fn frame(&self, frame: u32, frames: u32) -> Keyframe {
+		let (first, last) = self.times();
+		if frames <= 1 {
+			return self.at(first);
+		}
+		self.at(first + (last - first) * frame as f64 / (frames - 1) as f64)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::Vec3;
+
+	fn keyframe(time: f64, from: Point3, fov: f64) -> Keyframe {
+		Keyframe {
+			time,
+			from,
+			at: from + Vec3::new(0.0, 0.0, -1.0),
+			fov,
+		}
+	}
+
+	fn square() -> CameraPath {
+		CameraPath::new(vec![
+			keyframe(0.0, Point3::new(0.0, 0.0, 0.0), 40.0),
+			keyframe(1.0, Point3::new(1.0, 0.0, 0.0), 60.0),
+			keyframe(3.0, Point3::new(1.0, 1.0, 0.0), 30.0),
+			keyframe(4.0, Point3::new(0.0, 1.0, 0.0), 30.0),
+		])
+		.unwrap()
+	}
+
+	#[test]
+	fn test_keyframes() {
+		let path = square();
+		for k in &path.keyframes {
+			let at = path.at(k.time);
+			assert!(
+				(at.from - k.from).length() < 1e-9,
+				"{}: {}",
+				k.time,
+				at.from
+			);
+			assert!((at.at - k.at).length() < 1e-9);
+			assert_eq!(at.fov, k.fov);
+		}
+		// held still before and after
+		assert!((path.at(-1.0).from - Point3::zero()).length() < 1e-9);
+		assert!((path.at(5.0).from - Point3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+		assert_eq!(path.frame(0, 5).time, 0.0);
+		assert_eq!(path.frame(4, 5).time, 4.0);
+		assert_eq!(path.frame(2, 5).time, 2.0);
+	}
+
+	#[test]
+	fn test_midpoints() {
+		let path = square();
+		// the middle segment is symmetric, so halfway along it is halfway between its ends, and
+		// the path bulges outwards from the square
+		let middle = path.at(2.0);
+		assert!((middle.from.y() - 0.5).abs() < 1e-9, "{}", middle.from);
+		assert!(middle.from.x() > 1.0, "{}", middle.from);
+		assert_eq!(middle.fov, 45.0);
+		// the first segment, whose points are evenly spaced so it's the same as a uniform
+		// Catmull-Rom spline, worked out by hand
+		let first = path.at(0.5);
+		assert!(
+			(first.from - Point3::new(0.5625, -0.0625, 0.0)).length() < 1e-9,
+			"{}",
+			first.from
+		);
+		assert!((first.at - first.from - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+
+		// points in a straight line, evenly spaced, stay on it
+		let line = CameraPath::new(
+			(0..4)
+				.map(|i| keyframe(i as f64, Point3::new(i as f64, 0.0, 0.0), 40.0))
+				.collect(),
+		)
+		.unwrap();
+		for time in [0.25, 1.5, 2.9] {
+			let at = line.at(time).from;
+			assert!((at - Point3::new(time, 0.0, 0.0)).length() < 1e-9, "{}", at);
+		}
+	}
+
+	#[test]
+	fn test_validation() {
+		let k = |time| keyframe(time, Point3::zero(), 40.0);
+		assert!(CameraPath::new(vec![k(0.0)]).is_err());
+		assert!(CameraPath::new(vec![k(0.0), k(0.0)]).is_err());
+		assert!(CameraPath::new(vec![k(0.0), k(2.0), k(1.0)]).is_err());
+		assert!(CameraPath::new(vec![k(0.0), k(f64::NAN)]).is_err());
+		let wide = keyframe(1.0, Point3::zero(), 180.0);
+		assert!(CameraPath::new(vec![k(0.0), wide]).is_err());
+
+		let json = r#"[
+			{"time": 0, "from": [0, 0, 0], "at": [0, 0, -1], "fov": 40},
+			{"time": 1.5, "from": [1, 2, 3], "at": [0, 0, 0], "fov": 50}
+		]"#;
+		let keyframes: Vec<Keyframe> = serde_json::from_str(json).unwrap();
+		let path = CameraPath::new(keyframes).unwrap();
+		assert_eq!(path.times(), (0.0, 1.5));
+		assert_eq!(path.keyframes[1].from.z(), 3.0);
+		let typo = r#"[{"time": 0, "form": [0, 0, 0], "at": [0, 0, -1], "fov": 40}]"#;
+		assert!(serde_json::from_str::<Vec<Keyframe>>(typo).is_err());
+	}
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use rand::RngCore;
 
-use crate::common::Ray;
+use crate::common::{Interval, Ray};
 use crate::object::{HitRecord, Hittable};
 use crate::scene::Aabb;
 
@@ -21,15 +21,82 @@ impl HittableList {
 	pub fn add(&mut self, object: Arc<dyn Hittable>) {
 		self.objects.push(object);
 	}
+
+	/// add an object that isn't already in an Arc
+	pub fn add_new<T: Hittable + 'static>(&mut self, object: T) {
+		self.objects.push(Arc::new(object));
+	}
+
+	pub fn len(&self) -> usize {
+		self.objects.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.objects.is_empty()
+	}
+
+	/// Every object that gives off light, including ones inside groups. Each one is wrapped in
+	/// the transformations above it, so it is in the same place as in the world
+	pub fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+		fn collect(object: &Arc<dyn Hittable>, emitters: &mut Vec<Arc<dyn Hittable>>) {
+			if !object.is_emissive() {
+				return;
+			}
+			if object.children().is_empty() {
+				emitters.push(object.clone());
+				return;
+			}
+			let start = emitters.len();
+			for child in object.children() {
+				collect(child, emitters);
+			}
+			for emitter in &mut emitters[start..] {
+				*emitter = object.wrap(emitter.clone());
+			}
+		}
+
+		let mut emitters = Vec::new();
+		for o in &self.objects {
+			collect(o, &mut emitters);
+		}
+		emitters
+	}
+
+	/// The same objects, with any lists inside this one (and lists inside those) replaced by their
+	/// contents, so that a BVH built from the result can split them up
+	pub fn flatten(&self) -> HittableList {
+		let mut flat = HittableList::new();
+		for o in &self.objects {
+			match o.as_hittable_list() {
+				Some(list) => flat.extend(list.flatten().objects),
+				None => flat.add(o.clone()),
+			}
+		}
+		flat
+	}
+}
+
+impl Extend<Arc<dyn Hittable>> for HittableList {
+	fn extend<I: IntoIterator<Item = Arc<dyn Hittable>>>(&mut self, iter: I) {
+		self.objects.extend(iter);
+	}
+}
+
+impl FromIterator<Arc<dyn Hittable>> for HittableList {
+	fn from_iter<I: IntoIterator<Item = Arc<dyn Hittable>>>(iter: I) -> Self {
+		Self {
+			objects: Vec::from_iter(iter),
+		}
+	}
 }
 
 impl Hittable for HittableList {
-	fn hit(&self, rng: &mut dyn RngCore, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+	fn hit(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> Option<HitRecord> {
 		let mut temp_rec: Option<HitRecord> = None;
-		let mut closest_so_far = t_max;
+		let mut closest_so_far = ray_t.max;
 
 		for o in &self.objects {
-			if let Some(rec) = o.hit(rng, r, t_min, closest_so_far) {
+			if let Some(rec) = o.hit(rng, r, Interval::new(ray_t.min, closest_so_far)) {
 				closest_so_far = rec.t;
 				temp_rec = Some(rec);
 			}
@@ -38,25 +105,30 @@ impl Hittable for HittableList {
 		temp_rec
 	}
 
+	fn occluded(&self, rng: &mut dyn RngCore, r: Ray, ray_t: Interval) -> bool {
+		self.objects.iter().any(|o| o.occluded(rng, r, ray_t))
+	}
+
 	fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
-		if self.objects.is_empty() {
+		if self.is_empty() {
 			return None;
 		}
 
-		let mut temp_box: Option<Aabb> = None;
-		for o in &self.objects {
-			if let Some(bb) = o.bounding_box(time0, time1) {
-				temp_box = if let Some(bb2) = temp_box {
-					Some(Aabb::surrounding_box(bb, bb2))
-				} else {
-					Some(bb)
-				};
-			} else {
-				return None;
-			}
-		}
+		self.objects.iter().try_fold(Aabb::EMPTY, |bb, o| {
+			Some(Aabb::surrounding_box(bb, o.bounding_box(time0, time1)?))
+		})
+	}
+
+	fn is_emissive(&self) -> bool {
+		self.objects.iter().any(|o| o.is_emissive())
+	}
 
-		temp_box
+	fn children(&self) -> &[Arc<dyn Hittable>] {
+		&self.objects
+	}
+
+	fn as_hittable_list(&self) -> Option<&HittableList> {
+		Some(self)
 	}
 }
 
@@ -65,3 +137,50 @@ impl AsRef<[Arc<dyn Hittable>]> for HittableList {
 		&self.objects
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::{Color, Point3, Vec3};
+	use crate::object::material::Lambertian;
+	use crate::object::Sphere;
+
+	#[test]
+	fn test_flatten() {
+		let mat = Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5)));
+		let sphere = |x| -> Arc<dyn Hittable> {
+			Arc::new(Sphere::new(Point3::new(x, 0.0, 0.0), 0.4, mat.clone()))
+		};
+
+		let mut inner: HittableList = [sphere(2.0), sphere(3.0)].into_iter().collect();
+		inner.add(Arc::new(HittableList::new()));
+		let mut nested = HittableList::new();
+		nested.add(sphere(0.0));
+		nested.add_new(HittableList::from_iter([sphere(1.0), Arc::new(inner) as _]));
+		assert_eq!(nested.len(), 2);
+
+		let flat = nested.flatten();
+		assert_eq!(flat.len(), 4);
+		assert!(flat.as_ref().iter().all(|o| o.as_hittable_list().is_none()));
+
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		for x in [0.0, 1.0, 2.1, 3.2, 4.0] {
+			let r = Ray::new(
+				Point3::new(x, 5.0, 0.0),
+				Vec3::new(0.0, -1.0, 0.0),
+				0.0,
+				false,
+			);
+			let expected = nested
+				.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+				.map(|rec| rec.t);
+			let actual = flat
+				.hit(&mut rng, r, Interval::new(0.001, f64::INFINITY))
+				.map(|rec| rec.t);
+			assert_eq!(expected, actual, "x = {}", x);
+		}
+	}
+}
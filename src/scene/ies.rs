@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum IesError {
+	Io(io::Error),
+	/// the file is not valid LM-63. line is 1-based
+	Parse {
+		line: usize,
+		message: String,
+	},
+}
+
+impl fmt::Display for IesError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "{}", e),
+			Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+		}
+	}
+}
+
+impl Error for IesError {}
+
+impl From<io::Error> for IesError {
+	fn from(e: io::Error) -> IesError {
+		IesError::Io(e)
+	}
+}
+
+/// Beam profile from an IES LM-63 photometric file, averaged around the light's axis so that it
+/// only depends on the angle between the axis and the emission direction
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+	/// angles from the axis in degrees, increasing
+	angles: Vec<f64>,
+	/// relative intensity at each angle, scaled so that the brightest is 1
+	intensities: Vec<f64>,
+}
+
+/// whitespace or comma separated values along with the line they came from
+struct Tokens<'a> {
+	tokens: Vec<(usize, &'a str)>,
+	next: usize,
+	last_line: usize,
+}
+
+impl<'a> Tokens<'a> {
+	fn new(lines: impl Iterator<Item = (usize, &'a str)>) -> Tokens<'a> {
+		let mut last_line = 0;
+		let mut tokens = Vec::new();
+		for (line, text) in lines {
+			last_line = line;
+			tokens.extend(
+				text.split(|c: char| c.is_whitespace() || c == ',')
+					.filter(|t| !t.is_empty())
+					.map(|t| (line, t)),
+			);
+		}
+		Tokens {
+			tokens,
+			next: 0,
+			last_line,
+		}
+	}
+
+	fn number(&mut self, what: &str) -> Result<(usize, f64), IesError> {
+		let (line, token) = *self.tokens.get(self.next).ok_or_else(|| IesError::Parse {
+			line: self.last_line,
+			message: format!("unexpected end of file, expected {}", what),
+		})?;
+		self.next += 1;
+		token
+			.parse()
+			.map(|n| (line, n))
+			.map_err(|_| IesError::Parse {
+				line,
+				message: format!("expected {}, found \"{}\"", what, token),
+			})
+	}
+
+	fn count(&mut self, what: &str) -> Result<usize, IesError> {
+		let (line, n) = self.number(what)?;
+		if n < 1.0 || n.fract() != 0.0 {
+			return Err(IesError::Parse {
+				line,
+				message: format!("{} must be a positive integer, found {}", what, n),
+			});
+		}
+		Ok(n as usize)
+	}
+
+	fn numbers(&mut self, n: usize, what: &str) -> Result<Vec<(usize, f64)>, IesError> {
+		(0..n).map(|_| self.number(what)).collect()
+	}
+}
+
+impl IesProfile {
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<IesProfile, IesError> {
+		IesProfile::parse(&fs::read_to_string(path)?)
+	}
+
+	/// Parses LM-63-1986, 1991, 1995, and 2002 files. Only type C photometry (angles measured
+	/// from the downward axis, used by nearly all architectural lights) is supported
+	pub fn parse(text: &str) -> Result<IesProfile, IesError> {
+		let mut lines = text
+			.lines()
+			.enumerate()
+			.map(|(i, line)| (i + 1, line.trim()));
+
+		// everything before TILT is the version identifier and [KEYWORD] lines, which don't
+		// affect the light
+		let (tilt_line, tilt) = lines
+			.find_map(|(i, line)| line.strip_prefix("TILT=").map(|tilt| (i, tilt.trim())))
+			.ok_or_else(|| IesError::Parse {
+				line: text.lines().count(),
+				message: "missing TILT= line".into(),
+			})?;
+
+		let mut tokens = Tokens::new(lines);
+		match tilt {
+			"NONE" => {},
+			"INCLUDE" => {
+				// tilt only changes the output of lamps that are mounted at an angle, so the
+				// lamp geometry, angles, and multipliers are skipped
+				tokens.number("lamp to luminaire geometry")?;
+				let n = tokens.count("number of tilt angles")?;
+				tokens.numbers(2 * n, "tilt angle or multiplier")?;
+			},
+			// tilt data stored in another file, which is ignored for the same reason
+			_ if !tilt.is_empty() => {},
+			_ => {
+				return Err(IesError::Parse {
+					line: tilt_line,
+					message: "TILT= must be NONE, INCLUDE, or a file name".into(),
+				})
+			},
+		}
+
+		tokens.count("number of lamps")?;
+		tokens.number("lumens per lamp")?;
+		let (_, multiplier) = tokens.number("candela multiplier")?;
+		let n_vertical = tokens.count("number of vertical angles")?;
+		let n_horizontal = tokens.count("number of horizontal angles")?;
+		let (type_line, photometric_type) = tokens.number("photometric type")?;
+		if photometric_type != 1.0 {
+			return Err(IesError::Parse {
+				line: type_line,
+				message: format!(
+					"only type C photometry (1) is supported, found {}",
+					photometric_type
+				),
+			});
+		}
+		// units, width, length, height, ballast factor, future use / ballast-lamp factor, and
+		// input watts
+		tokens.numbers(7, "luminaire dimensions and factors")?;
+
+		let angles = tokens.numbers(n_vertical, "vertical angle")?;
+		for pair in angles.windows(2) {
+			if pair[1].1 <= pair[0].1 {
+				return Err(IesError::Parse {
+					line: pair[1].0,
+					message: "vertical angles must be increasing".into(),
+				});
+			}
+		}
+		if let Some(&(line, angle)) = angles.iter().find(|(_, a)| !(0.0..=180.0).contains(a)) {
+			return Err(IesError::Parse {
+				line,
+				message: format!("vertical angle {} is outside 0 to 180", angle),
+			});
+		}
+		tokens.numbers(n_horizontal, "horizontal angle")?;
+
+		let mut intensities = vec![0.0; n_vertical];
+		for _ in 0..n_horizontal {
+			for intensity in intensities.iter_mut() {
+				let (line, candela) = tokens.number("candela value")?;
+				if candela < 0.0 {
+					return Err(IesError::Parse {
+						line,
+						message: format!("negative candela value {}", candela),
+					});
+				}
+				*intensity += candela * multiplier / n_horizontal as f64;
+			}
+		}
+
+		if let Some(&(line, token)) = tokens.tokens.get(tokens.next) {
+			return Err(IesError::Parse {
+				line,
+				message: format!("unexpected \"{}\" after candela values", token),
+			});
+		}
+
+		let peak = intensities.iter().cloned().fold(0.0, f64::max);
+		if peak > 0.0 {
+			intensities.iter_mut().for_each(|i| *i /= peak);
+		}
+
+		Ok(IesProfile {
+			angles: angles.into_iter().map(|(_, a)| a).collect(),
+			intensities,
+		})
+	}
+
+	/// relative intensity, from 0 to 1, at an angle in degrees from the axis. zero outside the
+	/// measured angles
+	pub fn intensity(&self, angle: f64) -> f64 {
+		let i = self.angles.partition_point(|&a| a < angle);
+		if i == self.angles.len() {
+			return 0.0;
+		}
+		if self.angles[i] == angle {
+			return self.intensities[i];
+		}
+		if i == 0 {
+			return 0.0;
+		}
+		let t = (angle - self.angles[i - 1]) / (self.angles[i] - self.angles[i - 1]);
+		self.intensities[i - 1] + t * (self.intensities[i] - self.intensities[i - 1])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SPOT: &str = "IESNA:LM-63-1995
+[TEST] narrow spot
+[MANUFAC] nobody
+TILT=NONE
+1 1000 2 3 2 1 2 0.1 0.1 0
+1.0 1.0 50
+0 10 20
+0, 90
+400 200 0
+200 100 0
+";
+
+	#[test]
+	fn test_parse_profile() {
+		let profile = IesProfile::parse(SPOT).unwrap();
+		assert_eq!(profile.intensity(0.0), 1.0);
+		assert_eq!(profile.intensity(5.0), 0.75);
+		assert_eq!(profile.intensity(10.0), 0.5);
+		assert_eq!(profile.intensity(45.0), 0.0);
+
+		// 1986 files have no identifier line, and TILT=INCLUDE has extra data before the
+		// photometry
+		let old = SPOT
+			.replace("IESNA:LM-63-1995\n", "")
+			.replace("TILT=NONE\n", "TILT=INCLUDE\n1\n2\n0 90\n1 1\n");
+		assert_eq!(IesProfile::parse(&old).unwrap().intensity(5.0), 0.75);
+	}
+
+	#[test]
+	fn test_malformed_profile() {
+		let error = |text: &str| match IesProfile::parse(text) {
+			Err(IesError::Parse { line, .. }) => line,
+			_ => panic!("parsed malformed file"),
+		};
+		assert_eq!(error(&SPOT.replace("TILT=NONE", "TILT")), 10);
+		assert_eq!(error(&SPOT.replace("0 10 20", "0 x 20")), 7);
+		assert_eq!(error(&SPOT.replace("0 10 20", "0 20 10")), 7);
+		assert_eq!(error(&SPOT.replace("2 1 2 0.1", "2 2 2 0.1")), 5);
+		assert_eq!(error(&SPOT.replace("200 100 0\n", "200 100\n")), 10);
+		assert_eq!(error(&format!("{}1\n", SPOT)), 11);
+	}
+}
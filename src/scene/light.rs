@@ -0,0 +1,731 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use rand::{Rng, RngCore};
+
+use super::{IesProfile, PhotonMap};
+
+use crate::common::{Color, Point3, Ray, Vec3};
+use crate::object::pdf::{sample_cone, sample_sphere, CosinePdf, EmitterList, Pdf};
+
+/// One sample of the light arriving at a point from a light
+pub struct LightSample {
+	/// unit vector from the point towards the light
+	pub direction: Vec3,
+	/// distance to the light, for checking whether it is blocked
+	pub distance: f64,
+	/// irradiance on a surface facing the light head-on, in W/m². this still needs to be scaled by
+	/// the cosine of the angle to the surface normal
+	pub irradiance: Color,
+}
+
+/// A ray of light leaving a light, for tracing photons from it
+pub struct Emission {
+	pub ray: Ray,
+	/// power carried along the ray (W). this is an estimate of all of the light's power, so it
+	/// still needs to be divided by the number of rays
+	pub power: Color,
+}
+
+/// Lights that can't be hit by rays, and are instead sampled directly when shading surfaces
+pub trait Light: Debug + Sync + Send {
+	fn sample(&self, rng: &mut dyn RngCore, p: Point3) -> LightSample;
+	/// A random ray of the light's, or None for lights that photons can't be traced from, like
+	/// ones infinitely far away
+	/// time: when the ray leaves the light
+	fn emit(&self, rng: &mut dyn RngCore, time: f64) -> Option<Emission> {
+		let _ = (rng, time);
+		None
+	}
+}
+
+/// Everything that lights a scene: lights that are sampled directly, objects giving off light
+/// that scattered rays are aimed at some of the time, and photons from the lights that were
+/// focused by mirrors and glass
+#[derive(Debug, Default)]
+pub struct SceneLights {
+	pub lights: Vec<Arc<dyn Light>>,
+	pub emitters: EmitterList,
+	/// caustics, if --caustics was given
+	pub photons: Option<PhotonMap>,
+}
+
+impl From<Vec<Arc<dyn Light>>> for SceneLights {
+	fn from(lights: Vec<Arc<dyn Light>>) -> SceneLights {
+		SceneLights {
+			lights,
+			emitters: EmitterList::default(),
+			photons: None,
+		}
+	}
+}
+
+/// A light infinitely far away, like the sun, which lights everything from the same direction with
+/// no falloff
+#[derive(Debug)]
+pub struct DirectionalLight {
+	/// unit vector pointing towards the light
+	direction: Vec3,
+	/// tangent of the angular radius, i.e. how far a direction can be from the center of the light
+	/// per unit along it
+	spread: f64,
+	irradiance: Color,
+}
+
+impl DirectionalLight {
+	/// direction:      direction pointing towards the light (not the direction the light travels)
+	/// angular_radius: apparent radius of the light in degrees, which softens shadows. the sun's
+	///                 is about 0.27
+	/// irradiance:     power per area received by a surface facing the light (W/m²). this is the
+	///                 integral of the radiance over the light's disc, not the radiance itself, so
+	///                 that brightness doesn't change with angular_radius
+	pub fn new(direction: Vec3, angular_radius: f64, irradiance: Color) -> DirectionalLight {
+		DirectionalLight {
+			direction: direction.unit_vector(),
+			spread: angular_radius.to_radians().tan(),
+			irradiance,
+		}
+	}
+}
+
+impl Light for DirectionalLight {
+	fn sample(&self, rng: &mut dyn RngCore, _p: Point3) -> LightSample {
+		let direction = if self.spread > 0.0 {
+			// pick a point on the light's disc. the disc is tilted a little compared to the true
+			// cone, which doesn't matter at the small sizes used for directional lights
+			(self.direction + self.spread * Vec3::random_in_unit_sphere(rng)).unit_vector()
+		} else {
+			self.direction
+		};
+		LightSample {
+			direction,
+			distance: f64::INFINITY,
+			irradiance: self.irradiance,
+		}
+	}
+}
+
+/// A light emitting equally in every direction from a point (or a small sphere, for soft shadows)
+#[derive(Debug)]
+pub struct PointLight {
+	position: Point3,
+	radius: f64,
+	intensity: Color,
+}
+
+impl PointLight {
+	/// intensity: power emitted per solid angle (W/sr). the irradiance at distance d is
+	///            intensity / d², so a surface 10 units away receives 1/100 of the intensity
+	/// radius:    size of the sphere that light is emitted from, or 0 for perfectly sharp shadows.
+	///            the light is not visible to camera rays regardless of size. a sphere is sampled
+	///            over the cone of directions it covers, which keeps the noise down up close
+	pub fn new(position: Point3, radius: f64, intensity: Color) -> PointLight {
+		PointLight {
+			position,
+			radius,
+			intensity,
+		}
+	}
+}
+
+impl Light for PointLight {
+	fn sample(&self, rng: &mut dyn RngCore, p: Point3) -> LightSample {
+		if self.radius > 0.0 {
+			let (direction, distance, solid_angle) =
+				sample_sphere(rng, self.position, self.radius, p);
+			// a sphere of radius r that's this bright everywhere has the intensity of the point
+			let radiance = self.intensity / (std::f64::consts::PI * self.radius * self.radius);
+			return LightSample {
+				direction,
+				distance,
+				irradiance: radiance * solid_angle,
+			};
+		}
+		let to_light = self.position - p;
+		let distance = to_light.length();
+		LightSample {
+			direction: to_light / distance,
+			distance,
+			irradiance: self.intensity / (distance * distance),
+		}
+	}
+
+	fn emit(&self, rng: &mut dyn RngCore, time: f64) -> Option<Emission> {
+		// the same intensity in every direction
+		let power = 4.0 * std::f64::consts::PI * self.intensity;
+		let ray = if self.radius > 0.0 {
+			// from the sphere's surface, which gives off light like a diffuse emitter
+			let normal = Vec3::random_unit_vector(rng);
+			let direction = CosinePdf::new(normal).generate(rng);
+			Ray::new(self.position + self.radius * normal, direction, time, false)
+		} else {
+			Ray::new(self.position, Vec3::random_unit_vector(rng), time, false)
+		};
+		Some(Emission { ray, power })
+	}
+}
+
+/// How a spotlight's intensity falls off away from its axis
+#[derive(Debug)]
+enum Beam {
+	/// full intensity within the inner angle, fading smoothly to nothing at the outer angle. both
+	/// are cosines of the angle from the axis
+	Cone { cos_inner: f64, cos_outer: f64 },
+	/// measured profile of a real light
+	Profile(IesProfile),
+}
+
+/// A point light that only shines in some directions, like a stage light or a downlight
+#[derive(Debug)]
+pub struct SpotLight {
+	position: Point3,
+	/// unit vector in the direction the light is pointing
+	axis: Vec3,
+	radius: f64,
+	beam: Beam,
+	intensity: Color,
+}
+
+impl SpotLight {
+	/// axis:        direction the light is pointing
+	/// inner_angle: angle from the axis in degrees within which the light is at full intensity
+	/// outer_angle: angle from the axis in degrees past which there is no light. the penumbra in
+	///              between fades smoothly
+	/// intensity:   power emitted per solid angle (W/sr) along the axis
+	pub fn new(
+		position: Point3,
+		axis: Vec3,
+		inner_angle: f64,
+		outer_angle: f64,
+		intensity: Color,
+	) -> SpotLight {
+		SpotLight {
+			position,
+			axis: axis.unit_vector(),
+			radius: 0.0,
+			beam: Beam::Cone {
+				cos_inner: inner_angle.to_radians().cos(),
+				cos_outer: outer_angle.max(inner_angle).to_radians().cos(),
+			},
+			intensity,
+		}
+	}
+
+	/// intensity: power emitted per solid angle (W/sr) in the brightest direction of the profile
+	pub fn with_profile(
+		position: Point3,
+		axis: Vec3,
+		profile: IesProfile,
+		intensity: Color,
+	) -> SpotLight {
+		SpotLight {
+			position,
+			axis: axis.unit_vector(),
+			radius: 0.0,
+			beam: Beam::Profile(profile),
+			intensity,
+		}
+	}
+
+	/// size of the sphere that light is emitted from, for soft shadows
+	pub fn radius(mut self, radius: f64) -> SpotLight {
+		self.radius = radius;
+		self
+	}
+
+	/// where a ray of light starts, somewhere in the sphere given by radius
+	fn random_position(&self, rng: &mut dyn RngCore) -> Point3 {
+		if self.radius > 0.0 {
+			self.position + self.radius * Vec3::random_in_unit_sphere(rng)
+		} else {
+			self.position
+		}
+	}
+
+	/// fraction of the intensity emitted in a direction (a unit vector away from the light)
+	fn falloff(&self, direction: Vec3) -> f64 {
+		let cosine = direction.dot(self.axis);
+		match &self.beam {
+			Beam::Cone {
+				cos_inner,
+				cos_outer,
+			} => {
+				if cosine >= *cos_inner {
+					1.0
+				} else if cosine <= *cos_outer {
+					0.0
+				} else {
+					let t = (cosine - cos_outer) / (cos_inner - cos_outer);
+					t * t * (3.0 - 2.0 * t)
+				}
+			},
+			Beam::Profile(profile) => {
+				profile.intensity(cosine.clamp(-1.0, 1.0).acos().to_degrees())
+			},
+		}
+	}
+}
+
+impl Light for SpotLight {
+	fn sample(&self, rng: &mut dyn RngCore, p: Point3) -> LightSample {
+		let to_light = self.random_position(rng) - p;
+		let distance = to_light.length();
+		let direction = to_light / distance;
+		LightSample {
+			direction,
+			distance,
+			irradiance: self.intensity * self.falloff(-direction) / (distance * distance),
+		}
+	}
+
+	fn emit(&self, rng: &mut dyn RngCore, time: f64) -> Option<Emission> {
+		let solid_angle = match self.beam {
+			Beam::Cone { cos_outer, .. } => 2.0 * std::f64::consts::PI * (1.0 - cos_outer),
+			// a measured beam could shine anywhere
+			Beam::Profile(_) => 4.0 * std::f64::consts::PI,
+		};
+		let (direction, ..) = sample_cone(rng, self.axis, solid_angle);
+		Some(Emission {
+			ray: Ray::new(self.random_position(rng), direction, time, false),
+			power: self.intensity * self.falloff(direction) * solid_angle,
+		})
+	}
+}
+
+/// A one-sided rectangle that gives off the same radiance everywhere, like a softbox or a ceiling
+/// panel. Like the other lights, it isn't visible to rays, only sampled when shading surfaces.
+/// Samples are spread evenly over the solid angle the rectangle covers as seen from the point
+/// being shaded (Ureña et al. 2013, "An Area-Preserving Parametrization for Spherical
+/// Rectangles"), so unlike picking points evenly over its area, the only noise left is from the
+/// cosine at the surface
+#[derive(Debug)]
+pub struct RectLight {
+	corner: Point3,
+	/// unit vectors along the edges
+	x: Vec3,
+	y: Vec3,
+	/// x cross y, the side that light comes out of
+	normal: Vec3,
+	width: f64,
+	height: f64,
+	radiance: Color,
+}
+
+/// The rectangle as seen from one point, in coordinates centered on that point with x and y along
+/// the edges and z along the normal
+struct SphericalRect {
+	z0: f64,
+	x0: f64,
+	x1: f64,
+	y0: f64,
+	y1: f64,
+	b0: f64,
+	b1: f64,
+	k: f64,
+	solid_angle: f64,
+}
+
+impl SphericalRect {
+	/// offsets along x and y (z is z0) of the point on the rectangle for a pair of uniform random
+	/// numbers. each pair covers the same solid angle
+	fn sample(&self, u: f64, v: f64) -> (f64, f64) {
+		// pick the x coordinate by the area to its left...
+		let au = u * self.solid_angle + self.k;
+		let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+		let cu = (1.0 / (fu * fu + self.b0 * self.b0).sqrt())
+			.copysign(fu)
+			.clamp(-1.0, 1.0);
+		let xu = (-(cu * self.z0) / (1.0 - cu * cu).sqrt()).clamp(self.x0, self.x1);
+		// ...then y evenly along the arc at that x
+		let d = (xu * xu + self.z0 * self.z0).sqrt();
+		let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+		let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+		let hv = h0 + v * (h1 - h0);
+		let yv = if hv * hv < 1.0 - 1e-9 {
+			hv * d / (1.0 - hv * hv).sqrt()
+		} else {
+			self.y1
+		};
+		(xu, yv.clamp(self.y0, self.y1))
+	}
+}
+
+impl RectLight {
+	/// corner:   one corner of the rectangle
+	/// edge_u:   the edge from corner to the next corner
+	/// edge_v:   the other edge from corner, at a right angle to edge_u. light comes out of the
+	///           side that edge_u × edge_v points towards
+	/// radiance: power per area per solid angle (W/m²/sr), the same as DiffuseLight's color
+	pub fn new(corner: Point3, edge_u: Vec3, edge_v: Vec3, radiance: Color) -> RectLight {
+		let (x, y) = (edge_u.unit_vector(), edge_v.unit_vector());
+		RectLight {
+			corner,
+			x,
+			y,
+			normal: x.cross(y),
+			width: edge_u.length(),
+			height: edge_v.length(),
+			radiance,
+		}
+	}
+
+	/// The rectangle as seen from p, or None if p is behind it or so close to its plane that it
+	/// gets no light
+	fn seen_from(&self, p: Point3) -> Option<SphericalRect> {
+		let d = self.corner - p;
+		// z is the normal, so z0 is negative on the lit side
+		let z0 = d.dot(self.normal);
+		if z0 > -1e-9 * (self.width + self.height) {
+			return None;
+		}
+		let (x0, y0) = (d.dot(self.x), d.dot(self.y));
+		let (x1, y1) = (x0 + self.width, y0 + self.height);
+
+		// normals of the planes through p and each edge, and the angles between them
+		let corners = [
+			Vec3::new(x0, y0, z0),
+			Vec3::new(x1, y0, z0),
+			Vec3::new(x1, y1, z0),
+			Vec3::new(x0, y1, z0),
+		];
+		let n: Vec<Vec3> = (0..4)
+			.map(|i| corners[i].cross(corners[(i + 1) % 4]).unit_vector())
+			.collect();
+		let angle = |i: usize| (-n[i].dot(n[(i + 1) % 4])).clamp(-1.0, 1.0).acos();
+		let k = 2.0 * std::f64::consts::PI - angle(2) - angle(3);
+		let solid_angle = angle(0) + angle(1) - k;
+		if solid_angle.is_nan() || solid_angle <= 0.0 {
+			return None;
+		}
+		Some(SphericalRect {
+			z0,
+			x0,
+			x1,
+			y0,
+			y1,
+			b0: n[0].z(),
+			b1: n[2].z(),
+			k,
+			solid_angle,
+		})
+	}
+}
+
+impl Light for RectLight {
+	fn sample(&self, rng: &mut dyn RngCore, p: Point3) -> LightSample {
+		let sampled = self.seen_from(p).and_then(|rect| {
+			let (x, y) = rect.sample(rng.gen(), rng.gen());
+			let to_light = x * self.x + y * self.y + rect.z0 * self.normal;
+			let distance = to_light.length();
+			let direction = to_light / distance;
+			direction.x().is_finite().then(|| LightSample {
+				direction,
+				distance,
+				// spread evenly over the solid angle, so each sample stands for all of it
+				irradiance: self.radiance * rect.solid_angle,
+			})
+		});
+		sampled.unwrap_or_else(|| {
+			let to_center =
+				self.corner + self.width / 2.0 * self.x + self.height / 2.0 * self.y - p;
+			let distance = to_center.length();
+			LightSample {
+				direction: to_center / distance,
+				distance,
+				irradiance: Color::zero(),
+			}
+		})
+	}
+
+	fn emit(&self, rng: &mut dyn RngCore, time: f64) -> Option<Emission> {
+		let origin = self.corner
+			+ rng.gen::<f64>() * self.width * self.x
+			+ rng.gen::<f64>() * self.height * self.y;
+		// radiance over the area, integrated over the cosine-weighted hemisphere
+		let power = self.radiance * std::f64::consts::PI * self.width * self.height;
+		Some(Emission {
+			ray: Ray::new(
+				origin,
+				CosinePdf::new(self.normal).generate(rng),
+				time,
+				false,
+			),
+			power,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+
+	#[test]
+	fn test_sphere_light_pdf() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let (center, radius) = (Point3::new(1.0, 2.0, 3.0), 0.5);
+		let p = Point3::new(1.3, 0.8, 3.1);
+		let to_center = center - p;
+
+		// the pdf is one over the cone's solid angle inside of it, so integrating it over the
+		// sphere of directions gives 1
+		let (_, _, solid_angle) = sample_sphere(&mut rng, center, radius, p);
+		let n = 400_000;
+		let hits = (0..n)
+			.filter(|_| {
+				let dir = Vec3::random_unit_vector(&mut rng);
+				let along = dir.dot(to_center);
+				along > 0.0 && (to_center - along * dir).length() < radius
+			})
+			.count();
+		let integral = 4.0 * std::f64::consts::PI * hits as f64 / n as f64 / solid_angle;
+		assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+
+		// samples land on the side of the sphere facing p
+		for _ in 0..1000 {
+			let (direction, distance, _) = sample_sphere(&mut rng, center, radius, p);
+			let q = p + distance * direction;
+			assert!(((q - center).length() - radius).abs() < 1e-9);
+			assert!((q - center).dot(p - q) >= -1e-9);
+		}
+
+		// from inside, every direction is used, and ends on the surface
+		let inside = center + Vec3::new(0.2, -0.1, 0.3);
+		for _ in 0..1000 {
+			let (direction, distance, solid_angle) =
+				sample_sphere(&mut rng, center, radius, inside);
+			assert_eq!(solid_angle, 4.0 * std::f64::consts::PI);
+			let q = inside + distance * direction;
+			assert!(distance > 0.0 && ((q - center).length() - radius).abs() < 1e-9);
+		}
+	}
+
+	#[test]
+	fn test_sphere_light_noise() {
+		// one of the week scene's glowing spheres, lighting a surface nearby in the cluster,
+		// compared with picking points evenly over its surface
+		let (center, radius) = (Point3::zero(), 10.0);
+		let intensity = 50.0 * std::f64::consts::PI * radius * radius;
+		let light = PointLight::new(center, radius, Color::new(intensity, intensity, intensity));
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+
+		for (p, normal) in [
+			(Point3::new(0.0, -15.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+			(Point3::new(12.0, -12.0, 5.0), Vec3::new(0.0, 1.0, 0.0)),
+			(Point3::new(0.0, 0.0, 25.0), Vec3::new(0.6, 0.0, -0.8)),
+		] {
+			// the whole sphere is above the surface's horizon, so it's lit as if by a point
+			let to_center = center - p;
+			let exact =
+				intensity * to_center.unit_vector().dot(normal) / to_center.length_squared();
+			let (mean, variance) = irradiance_stats(
+				|| {
+					let sample = light.sample(&mut rng, p);
+					sample.irradiance.x() * sample.direction.dot(normal).max(0.0)
+				},
+				20_000,
+			);
+			let area = 4.0 * std::f64::consts::PI * radius * radius;
+			let (area_mean, area_variance) = irradiance_stats(
+				|| {
+					let q_normal = Vec3::random_unit_vector(&mut rng);
+					let to_light = center + radius * q_normal - p;
+					let distance = to_light.length();
+					let direction = to_light / distance;
+					// the far side of the sphere is hidden behind the near side
+					let cos_light = (-direction.dot(q_normal)).max(0.0);
+					50.0 * area * cos_light * direction.dot(normal).max(0.0) / (distance * distance)
+				},
+				20_000,
+			);
+			assert!(
+				(mean - exact).abs() < 0.01 * exact,
+				"{} {} at {}",
+				mean,
+				exact,
+				p
+			);
+			assert!(
+				(area_mean - exact).abs() < 0.05 * exact,
+				"{} {} at {}",
+				area_mean,
+				exact,
+				p
+			);
+			assert!(
+				variance < area_variance / 10.0,
+				"{} {} at {}",
+				variance,
+				area_variance,
+				p
+			);
+		}
+	}
+
+	/// a unit square in the x,y plane centered on the origin, shining towards +z
+	fn square() -> RectLight {
+		RectLight::new(
+			Point3::new(-0.5, -0.5, 0.0),
+			Vec3::new(1.0, 0.0, 0.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			Color::new(1.0, 1.0, 1.0),
+		)
+	}
+
+	#[test]
+	fn test_rect_light_pdf() {
+		let light = square();
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+
+		// solid angle of a square of side a centered at distance d
+		let solid_angle = light
+			.seen_from(Point3::new(0.0, 0.0, 2.0))
+			.unwrap()
+			.solid_angle;
+		assert!((solid_angle - 4.0 * (1.0 / 17f64).asin()).abs() < 1e-12);
+
+		// the pdf is one over the solid angle wherever a direction hits the rectangle, so
+		// integrating it over the sphere of directions gives 1
+		let p = Point3::new(0.2, -0.3, 0.8);
+		let rect = light.seen_from(p).unwrap();
+		let n = 400_000;
+		let hits = (0..n)
+			.filter(|_| {
+				let dir = Vec3::random_unit_vector(&mut rng);
+				let t = -p.z() / dir.z();
+				let q = p + t * dir;
+				t > 0.0 && q.x().abs() <= 0.5 && q.y().abs() <= 0.5
+			})
+			.count();
+		let integral = 4.0 * std::f64::consts::PI * hits as f64 / n as f64 / rect.solid_angle;
+		assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+
+		// and samples land on the rectangle spread evenly by solid angle: the left half gets its
+		// share of the samples
+		let left = RectLight::new(
+			Point3::new(-0.5, -0.5, 0.0),
+			Vec3::new(0.5, 0.0, 0.0),
+			Vec3::new(0.0, 1.0, 0.0),
+			Color::zero(),
+		);
+		let expected = left.seen_from(p).unwrap().solid_angle / rect.solid_angle;
+		let n = 100_000;
+		let mut on_left = 0;
+		for _ in 0..n {
+			let sample = light.sample(&mut rng, p);
+			let q = p + sample.distance * sample.direction;
+			assert!(q.z().abs() < 1e-9 && q.x().abs() <= 0.5 + 1e-9 && q.y().abs() <= 0.5 + 1e-9);
+			assert_eq!(sample.irradiance.x(), rect.solid_angle);
+			if q.x() < 0.0 {
+				on_left += 1;
+			}
+		}
+		let fraction = on_left as f64 / n as f64;
+		assert!(
+			(fraction - expected).abs() < 0.01,
+			"{} {}",
+			fraction,
+			expected
+		);
+	}
+
+	#[test]
+	fn test_rect_light_edge_on() {
+		let light = square();
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		// behind, in the plane, and barely above it, from inside and outside of the rectangle
+		for p in [
+			Point3::new(0.0, 0.0, -1.0),
+			Point3::new(2.0, 0.0, 0.0),
+			Point3::new(0.25, 0.0, 0.0),
+			Point3::new(2.0, 0.3, 1e-12),
+			Point3::new(0.25, 0.0, 1e-12),
+			Point3::new(2.0, 0.3, 1e-7),
+			Point3::new(0.25, 0.1, 1e-7),
+		] {
+			for _ in 0..100 {
+				let sample = light.sample(&mut rng, p);
+				let finite = |v: Vec3| v.x().is_finite() && v.y().is_finite() && v.z().is_finite();
+				assert!(
+					finite(sample.direction) && finite(sample.irradiance),
+					"{}",
+					p
+				);
+				assert!(
+					sample.irradiance.x() >= 0.0 && sample.distance.is_finite(),
+					"{}",
+					p
+				);
+				if p.z() <= 0.0 {
+					assert_eq!(sample.irradiance.x(), 0.0);
+				}
+			}
+		}
+		// just above the middle, it covers the whole hemisphere
+		let rect = light.seen_from(Point3::new(0.0, 0.0, 1e-7)).unwrap();
+		assert!((rect.solid_angle - 2.0 * std::f64::consts::PI).abs() < 1e-5);
+	}
+
+	/// mean and variance of n estimates
+	fn irradiance_stats(mut estimate: impl FnMut() -> f64, n: usize) -> (f64, f64) {
+		let samples: Vec<f64> = (0..n).map(|_| estimate()).collect();
+		let mean = samples.iter().sum::<f64>() / n as f64;
+		let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64;
+		(mean, variance)
+	}
+
+	#[test]
+	fn test_rect_light_noise() {
+		// the Cornell box's ceiling light, compared with picking points evenly over its area
+		let (corner, edge_u, edge_v) = (
+			Point3::new(213.0, 554.99, 227.0),
+			Vec3::new(130.0, 0.0, 0.0),
+			Vec3::new(0.0, 0.0, 105.0),
+		);
+		let light = RectLight::new(corner, edge_u, edge_v, Color::new(15.0, 15.0, 15.0));
+		let area = edge_u.length() * edge_v.length();
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+
+		// the middle of the floor, and the back and side walls just below the light
+		for (p, normal) in [
+			(Point3::new(278.0, 0.0, 278.0), Vec3::new(0.0, 1.0, 0.0)),
+			(Point3::new(278.0, 500.0, 554.0), Vec3::new(0.0, 0.0, -1.0)),
+			(Point3::new(0.0, 540.0, 278.0), Vec3::new(1.0, 0.0, 0.0)),
+		] {
+			let (mean, variance) = irradiance_stats(
+				|| {
+					let sample = light.sample(&mut rng, p);
+					sample.irradiance.x() * sample.direction.dot(normal).max(0.0)
+				},
+				20_000,
+			);
+			let (area_mean, area_variance) = irradiance_stats(
+				|| {
+					let q = corner + rng.gen::<f64>() * edge_u + rng.gen::<f64>() * edge_v;
+					let to_light = q - p;
+					let distance = to_light.length();
+					let direction = to_light / distance;
+					let cos_light = direction.y();
+					15.0 * area * cos_light * direction.dot(normal).max(0.0) / (distance * distance)
+				},
+				20_000,
+			);
+			assert!(
+				(mean - area_mean).abs() < 0.02 * area_mean,
+				"{} {} at {}",
+				mean,
+				area_mean,
+				p
+			);
+			assert!(
+				variance < area_variance / 10.0,
+				"{} {} at {}",
+				variance,
+				area_variance,
+				p
+			);
+		}
+	}
+}
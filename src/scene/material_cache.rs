@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::object::Material;
+
+/// Hands out one shared copy of each distinct material, so that scenes with many objects don't
+/// allocate a copy of the same material for each of them. Materials are told apart by their
+/// Display descriptions, so this is only for materials that Display describes completely, like
+/// ones made of solid colors. Dielectrics with priorities are told apart by their addresses, so
+/// they shouldn't be shared either
+#[derive(Default)]
+pub struct MaterialCache {
+	materials: HashMap<String, Arc<dyn Material>>,
+}
+
+impl MaterialCache {
+	pub fn new() -> MaterialCache {
+		MaterialCache::default()
+	}
+
+	/// the copy of material that was handed out before, or material itself if it is new
+	pub fn get(&mut self, material: impl Material + 'static) -> Arc<dyn Material> {
+		self.materials
+			.entry(material.to_string())
+			.or_insert_with(|| Arc::new(material))
+			.clone()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::Color;
+	use crate::object::material::{Dielectric, Lambertian, Metal};
+
+	#[test]
+	fn test_shared() {
+		let mut cache = MaterialCache::new();
+		let red = cache.get(Lambertian::with_color(Color::new(0.8, 0.1, 0.1)));
+		assert!(Arc::ptr_eq(
+			&red,
+			&cache.get(Lambertian::with_color(Color::new(0.8, 0.1, 0.1)))
+		));
+		// the same color, but a different material or a slightly different shade
+		let metal = cache.get(Metal::with_color(Color::new(0.8, 0.1, 0.1), 0.0));
+		assert!(!Arc::ptr_eq(&red, &metal));
+		let redder = cache.get(Lambertian::with_color(Color::new(0.8 + 1e-15, 0.1, 0.1)));
+		assert!(!Arc::ptr_eq(&red, &redder));
+		assert!(Arc::ptr_eq(
+			&cache.get(Dielectric::new(1.5)),
+			&cache.get(Dielectric::new(1.5))
+		));
+		assert_eq!(cache.materials.len(), 4);
+	}
+}
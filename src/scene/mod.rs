@@ -1,10 +1,22 @@
 mod aabb;
 mod bvh;
 mod camera;
+mod camera_path;
 mod hittable_list;
+mod ies;
+mod light;
+mod material_cache;
+mod photon_map;
 pub mod scenes;
+mod stats;
 
 pub use aabb::Aabb;
 pub use bvh::BvhNode;
-pub use camera::Camera;
+pub use camera::{Camera, CameraSample, PhysicalExposure};
+pub use camera_path::CameraPath;
 pub use hittable_list::HittableList;
+pub use ies::{IesError, IesProfile};
+pub use light::{DirectionalLight, Light, PointLight, RectLight, SceneLights, SpotLight};
+pub use material_cache::MaterialCache;
+pub use photon_map::{Photon, PhotonMap};
+pub use stats::SceneStats;
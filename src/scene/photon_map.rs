@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use crate::common::{Color, Point3, Vec3};
+
+/// Light that arrived at a diffuse surface after bouncing off of mirrors or through glass
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+	pub position: Point3,
+	/// unit vector in the direction the light was travelling
+	pub direction: Vec3,
+	/// power (W)
+	pub power: Color,
+}
+
+/// Photons sorted into a grid of cubes twice as wide as the radius they're gathered from, so that
+/// finding the ones near a point only means looking in the 8 cubes nearest to it. Only cubes with
+/// photons in them are stored, so the grid can cover any size of scene
+#[derive(Debug, Clone)]
+pub struct PhotonMap {
+	radius: f64,
+	cells: HashMap<(i64, i64, i64), Vec<Photon>, BuildHasherDefault<CellHasher>>,
+	len: usize,
+}
+
+/// Hashes the coordinates of cells. Every diffuse surface a ray hits looks up 8 of them, and
+/// they're never chosen by anyone trying to make them collide, so this is much faster than the
+/// default hasher with nothing lost
+#[derive(Default)]
+struct CellHasher(u64);
+
+impl Hasher for CellHasher {
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.write_u64(byte as u64);
+		}
+	}
+
+	fn write_u64(&mut self, i: u64) {
+		// the same mixing as rustc's FxHasher
+		self.0 = (self.0.rotate_left(5) ^ i).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+	}
+
+	fn write_i64(&mut self, i: i64) {
+		self.write_u64(i as u64);
+	}
+
+	fn finish(&self) -> u64 {
+		self.0
+	}
+}
+
+impl PhotonMap {
+	/// radius: how far from a point photons are gathered from. photons are spread over this much
+	///         of the surface, so a larger radius is less noisy but blurs the caustics more
+	pub fn new(radius: f64) -> PhotonMap {
+		assert!(radius > 0.0 && radius.is_finite());
+		PhotonMap {
+			radius,
+			cells: HashMap::default(),
+			len: 0,
+		}
+	}
+
+	/// p in units of the width of a cell
+	fn scaled(&self, p: Point3) -> [f64; 3] {
+		p.to_array().map(|c| c / (2.0 * self.radius))
+	}
+
+	fn cell(&self, p: Point3) -> (i64, i64, i64) {
+		let [x, y, z] = self.scaled(p).map(|c| c.floor() as i64);
+		(x, y, z)
+	}
+
+	pub fn add(&mut self, photon: Photon) {
+		let cell = self.cell(photon.position);
+		self.cells.entry(cell).or_default().push(photon);
+		self.len += 1;
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Every photon within the radius of p
+	pub fn near(&self, p: Point3) -> impl Iterator<Item = &Photon> + '_ {
+		let scaled = self.scaled(p);
+		let cell = scaled.map(|c| c.floor() as i64);
+		// cells are as wide as the circle around p, so other than p's own cell, the photons can
+		// only be in the next cell over on the side that p is nearer to along each axis
+		let step = scaled.map(|c| if c - c.floor() < 0.5 { -1 } else { 1 });
+		let radius2 = self.radius * self.radius;
+		(0..8)
+			.filter_map(move |i| {
+				let [x, y, z] = [0, 1, 2].map(|a| cell[a] + step[a] * (i >> a & 1));
+				self.cells.get(&(x, y, z))
+			})
+			.flatten()
+			.filter(move |photon| (photon.position - p).length_squared() <= radius2)
+	}
+
+	/// Power per area arriving at p from the side of the surface that normal points out of,
+	/// spread evenly over the disc of photons around it
+	pub fn irradiance(&self, p: Point3, normal: Vec3) -> Color {
+		let mut total = Color::zero();
+		for photon in self.near(p) {
+			if photon.direction.dot(normal) < 0.0 {
+				total += photon.power;
+			}
+		}
+		total / (std::f64::consts::PI * self.radius * self.radius)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+
+	fn photon(position: Point3) -> Photon {
+		Photon {
+			position,
+			direction: Vec3::new(0.0, -1.0, 0.0),
+			power: Color::new(1.0, 1.0, 1.0),
+		}
+	}
+
+	#[test]
+	fn test_near() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut map = PhotonMap::new(0.3);
+		// on both sides of 0, where rounding towards zero would put two rows of cells together
+		let photons: Vec<Photon> = (0..2000)
+			.map(|_| photon(Vec3::random_range(&mut rng, -2.0, 2.0)))
+			.collect();
+		for &p in &photons {
+			map.add(p);
+		}
+		assert_eq!(map.len(), photons.len());
+
+		for _ in 0..200 {
+			let p = Vec3::random_range(&mut rng, -2.5, 2.5);
+			let mut found: Vec<f64> = map.near(p).map(|photon| photon.position.x()).collect();
+			let mut expected: Vec<f64> = photons
+				.iter()
+				.map(|photon| photon.position)
+				.filter(|&q| (q - p).length() <= 0.3)
+				.map(|q| q.x())
+				.collect();
+			found.sort_by(f64::total_cmp);
+			expected.sort_by(f64::total_cmp);
+			assert_eq!(found, expected);
+		}
+
+		// right up against the edges of cells
+		let mut map = PhotonMap::new(1.0);
+		map.add(photon(Point3::new(0.999, 0.0, 0.0)));
+		map.add(photon(Point3::new(-0.999, 0.0, 0.0)));
+		map.add(photon(Point3::new(2.0, 0.0, 0.0)));
+		assert_eq!(map.near(Point3::new(0.0, 0.0, 0.0)).count(), 2);
+		assert_eq!(map.near(Point3::new(1.0, 0.0, 0.0)).count(), 2);
+		assert_eq!(map.near(Point3::new(-1.5, 0.0, 0.0)).count(), 1);
+		assert_eq!(map.near(Point3::new(0.0, 1.0, 1.0)).count(), 0);
+	}
+
+	#[test]
+	fn test_irradiance() {
+		// photons falling evenly onto the floor, 1 W per square unit
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut map = PhotonMap::new(0.5);
+		let (size, count) = (10.0, 200_000);
+		for _ in 0..count {
+			let position = Point3::new(
+				rng.gen_range(-size / 2.0..size / 2.0),
+				0.0,
+				rng.gen_range(-size / 2.0..size / 2.0),
+			);
+			map.add(Photon {
+				power: Color::new(1.0, 1.0, 1.0) * size * size / count as f64,
+				..photon(position)
+			});
+		}
+		let up = Vec3::new(0.0, 1.0, 0.0);
+		let irradiance = map.irradiance(Point3::new(0.3, 0.0, -1.2), up);
+		assert!((irradiance.x() - 1.0).abs() < 0.05, "{}", irradiance);
+		// the floor's underside gets none of them, and neither does anywhere far from it
+		assert!(map.irradiance(Point3::zero(), -up).near_zero());
+		assert!(map.irradiance(Point3::new(0.0, 1.0, 0.0), up).near_zero());
+	}
+}
@@ -6,18 +6,24 @@ use rand::Rng;
 use super::BvhNode;
 use super::Camera;
 use super::HittableList;
+use super::MaterialCache;
+use super::{DirectionalLight, IesError, IesProfile, Light, PointLight, RectLight, SpotLight};
+use crate::common::assets::{Asset, AssetDirs};
 use crate::common::{Color, Point3, Vec3};
-use crate::object::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::object::material::{
+	Dielectric, DiffuseLight, Lambertian, Material, Metal, ShadowCatcher,
+};
 use crate::object::texture::{
-	CheckerTexture, FunctionTexture, ImageTexture, Mappable, NoiseTexture, SolidColor,
-	StripeTexture, Texture,
+	CheckerTexture, FunctionTexture, ImageTexture, LerpTexture, Mappable, MappedTexture,
+	NoiseTexture, SolidColor, StripeTexture, Texture, WorleyTexture,
 };
 use crate::object::{
-	Block, ConstantMedium, Hittable, MovingSphere, RotateY, Sphere, Translate, XYRect, XZRect,
-	YZRect,
+	AnimatedRotateY, AnimatedTranslate, Block, ConstantMedium, DisplacedSphere, FlipFace, Hittable,
+	MovingSphere, RotateY, Sphere, Translate, Visibility, VisibleTo, XYRect, XZRect, YZRect,
 };
 
-pub type Scene = (HittableList, Camera, Color);
+/// the objects in the scene, the camera, the background color, and lights that are sampled directly
+pub type Scene = (HittableList, Camera, Color, Vec<Arc<dyn Light>>);
 
 fn sky() -> Color {
 	Color::new(0.7, 0.8, 1.0)
@@ -79,12 +85,15 @@ pub fn random_scene<R: Rng + ?Sized>(rng: &mut R, next_week: bool, gay: bool) ->
 			1 => GayMaterial::Sphere(Arc::new(Metal::new(texture, 0.2))),
 			2 => GayMaterial::Glass(texture),
 			3 => GayMaterial::Sphere(Arc::new(DiffuseLight::new(Arc::new(
-				texture.map(&|c| 10.0 * (c.saturate() / 2.0 + c / 2.0)),
+				texture.map(Arc::new(|c| 10.0 * (c.saturate() / 2.0 + c / 2.0))),
 			)))),
 			4.. => unreachable!(),
 		};
 	});
 
+	// the glass spheres are all the same, and so are any small spheres that happen to get the same
+	// color
+	let mut materials = MaterialCache::new();
 	for a in -11..11 {
 		for b in -11..11 {
 			let choose_mat = rng.gen::<f64>();
@@ -104,24 +113,24 @@ pub fn random_scene<R: Rng + ?Sized>(rng: &mut R, next_week: bool, gay: bool) ->
 							let sphere = Arc::new(Sphere::new(
 								center,
 								0.2,
-								Arc::new(Dielectric { ir: 1.5 }),
+								materials.get(Dielectric::new(1.5)),
 							));
 							world.add(sphere.clone());
 							world.add(Arc::new(ConstantMedium::new(sphere, 50.0, texture.clone())));
 						},
 					}
 				} else {
-					let sphere_material: Arc<dyn Material> = if choose_mat < 0.8 {
-						Arc::new(Lambertian::with_color(
+					let sphere_material = if choose_mat < 0.8 {
+						materials.get(Lambertian::with_color(
 							Color::random(rng) * Color::random(rng),
 						))
 					} else if choose_mat < 0.95 {
-						Arc::new(Metal::with_color(
+						materials.get(Metal::with_color(
 							Color::random_range(rng, 0.5, 1.0),
 							rng.gen_range(0.0..0.5),
 						))
 					} else {
-						Arc::new(Dielectric { ir: 1.5 })
+						materials.get(Dielectric::new(1.5))
 					};
 
 					if next_week && choose_mat < 0.8 {
@@ -142,7 +151,7 @@ pub fn random_scene<R: Rng + ?Sized>(rng: &mut R, next_week: bool, gay: bool) ->
 		}
 	}
 
-	let material1 = Arc::new(Dielectric { ir: 1.5 });
+	let material1 = materials.get(Dielectric::new(1.5));
 	world.add(Arc::new(Sphere::new(
 		Point3::new(0.0, 1.0, 0.0),
 		1.0,
@@ -166,14 +175,18 @@ pub fn random_scene<R: Rng + ?Sized>(rng: &mut R, next_week: bool, gay: bool) ->
 	)));
 
 	if gay {
-		world.add(Arc::new(ConstantMedium::with_color(
-			Arc::new(Sphere::new(
-				Point3::zero(),
-				25.0,
-				Arc::new(Dielectric { ir: 0.0 }),
+		// only scatters light around the scene, so the camera sees through it
+		world.add(Arc::new(VisibleTo::new(
+			Visibility::SECONDARY,
+			Arc::new(ConstantMedium::with_color(
+				Arc::new(Sphere::new(
+					Point3::zero(),
+					25.0,
+					Arc::new(Dielectric::new_unchecked(0.0)),
+				)),
+				0.05,
+				Color::new(0.6, 0.9, 1.0) / 2.0,
 			)),
-			0.05,
-			Color::new(0.6, 0.9, 1.0) / 2.0,
 		)));
 	}
 
@@ -181,23 +194,248 @@ pub fn random_scene<R: Rng + ?Sized>(rng: &mut R, next_week: bool, gay: bool) ->
 		world,
 		standard_camera(),
 		if gay { Color::zero() } else { sky() },
+		Vec::new(),
 	)
 }
 
-pub fn perlin_spheres<R: Rng + ?Sized>(rng: &mut R) -> Scene {
+/// the random spheres scene on a sunny day, lit mostly by a directional light so that shadows are
+/// sharp
+pub fn sunny<R: Rng + ?Sized>(rng: &mut R) -> Scene {
+	let (world, cam, background, mut lights) = random_scene(rng, false, false);
+	lights.push(Arc::new(DirectionalLight::new(
+		Vec3::new(-1.0, 2.0, 0.5),
+		0.27,
+		Color::new(3.0, 2.8, 2.5),
+	)));
+	// a warm lamp hovering between the three big spheres
+	lights.push(Arc::new(PointLight::new(
+		Point3::new(2.0, 2.5, 1.5),
+		0.1,
+		Color::new(2.0, 1.2, 0.5),
+	)));
+	(world, cam, background * 0.3, lights)
+}
+
+/// a glass and a diffuse sphere standing on a shadow catcher, for compositing onto a photo with
+/// --alpha
+pub fn shadow_catcher() -> Scene {
+	let mut world = HittableList::new();
+	world.add(Arc::new(XZRect::new(
+		-100.0,
+		100.0,
+		-100.0,
+		100.0,
+		0.0,
+		Arc::new(ShadowCatcher),
+	)));
+	world.add(Arc::new(Sphere::new(
+		Point3::new(0.0, 1.0, 0.0),
+		1.0,
+		Arc::new(Lambertian::with_color(Color::new(0.7, 0.2, 0.1))),
+	)));
+	world.add(Arc::new(Sphere::new(
+		Point3::new(-1.0, 0.7, 2.0),
+		0.7,
+		Arc::new(Dielectric::new(1.5)),
+	)));
+
+	let lights: Vec<Arc<dyn Light>> = vec![Arc::new(DirectionalLight::new(
+		Vec3::new(1.0, 1.5, -1.0),
+		2.0,
+		Color::new(2.0, 2.0, 2.0),
+	))];
+	(world, standard_camera(), sky() * 0.5, lights)
+}
+
+/// a glass of water with air bubbles in it, to show nested dielectrics
+pub fn water() -> Scene {
 	let mut world = HittableList::new();
+	world.add(Arc::new(XZRect::new(
+		-100.0,
+		100.0,
+		-100.0,
+		100.0,
+		0.0,
+		Arc::new(Lambertian::new(Arc::new(CheckerTexture::with_colors(
+			Color::new(0.2, 0.3, 0.1),
+			Color::new(0.9, 0.9, 0.9),
+		)))),
+	)));
+
+	// the bottom and four walls of the glass, which the water overlaps so that there are no gaps
+	// of air between them
+	let glass = Arc::new(Dielectric::new(1.5).with_priority(2));
+	for (p0, p1) in [
+		((-1.0, 0.0, -1.0), (1.0, 0.2, 1.0)),
+		((-1.0, 0.2, -1.0), (-0.9, 3.0, 1.0)),
+		((0.9, 0.2, -1.0), (1.0, 3.0, 1.0)),
+		((-0.9, 0.2, -1.0), (0.9, 3.0, -0.9)),
+		((-0.9, 0.2, 0.9), (0.9, 3.0, 1.0)),
+	] {
+		world.add_new(Block::new(
+			Point3::new(p0.0, p0.1, p0.2),
+			Point3::new(p1.0, p1.1, p1.2),
+			glass.clone(),
+		));
+	}
+	world.add_new(Block::new(
+		Point3::new(-0.95, 0.1, -0.95),
+		Point3::new(0.95, 2.2, 0.95),
+		Arc::new(Dielectric::new(1.33).with_priority(1)),
+	));
+	let air = Arc::new(Dielectric::new(1.0).with_priority(3));
+	world.add_new(Sphere::new(Point3::new(0.2, 1.3, 0.1), 0.35, air.clone()));
+	world.add_new(Sphere::new(Point3::new(-0.4, 0.7, -0.3), 0.15, air));
+
+	let lights: Vec<Arc<dyn Light>> = vec![Arc::new(DirectionalLight::new(
+		Vec3::new(1.0, 2.0, 0.5),
+		1.0,
+		Color::new(1.5, 1.5, 1.5),
+	))];
+	let from = Point3::new(6.0, 4.0, 8.0);
+	let at = Point3::new(0.0, 1.3, 0.0);
+	(
+		world,
+		Camera::new(
+			from,
+			at,
+			Vec3::new(0.0, 1.0, 0.0),
+			22.0,
+			1.5,
+			0.0,
+			(at - from).length(),
+			0.0,
+			1.0,
+		),
+		sky() * 0.5,
+		lights,
+	)
+}
+
+/// A sphereflake: a sphere with nine spheres a third of its size around it, each of which has
+/// nine of its own, and so on. There are (9^(depth + 1) - 1) / 8 spheres, so it's mostly useful for
+/// benchmarking the BVH
+/// depth: how many levels of children to add, so 0 is a single sphere
+pub fn sphereflake(depth: u32) -> Scene {
+	let mut world = HittableList::new();
+	world.add(Arc::new(XZRect::new(
+		-100.0,
+		100.0,
+		-100.0,
+		100.0,
+		0.0,
+		Arc::new(Lambertian::with_color(Color::new(0.5, 0.5, 0.5))),
+	)));
+
+	let materials: [Arc<dyn Material>; 4] = [
+		Arc::new(Metal::with_color(Color::new(0.8, 0.8, 0.85), 0.02)),
+		Arc::new(Lambertian::with_color(Color::new(0.8, 0.3, 0.2))),
+		Arc::new(Metal::with_color(Color::new(0.9, 0.7, 0.3), 0.2)),
+		Arc::new(Lambertian::with_color(Color::new(0.2, 0.4, 0.8))),
+	];
+	// where the children go around a parent that points straight up: six around its equator,
+	// and three around its top in between them
+	let children: [Vec3; 9] = std::array::from_fn(|i| {
+		let (elevation, azimuth) = if i < 6 {
+			(0.0, i as f64 * 60.0)
+		} else {
+			(60.0_f64, (i - 6) as f64 * 120.0 + 30.0)
+		};
+		let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+		Vec3::new(
+			elevation.cos() * azimuth.cos(),
+			elevation.sin(),
+			elevation.cos() * azimuth.sin(),
+		)
+	});
+
+	// (center, radius, direction away from the parent, level). a stack instead of recursion so
+	// that deep flakes can't overflow. the children never reach further than the parent's
+	// diameter, so nothing goes through the floor
+	let mut stack = vec![(Point3::new(0.0, 2.0, 0.0), 1.0, Vec3::new(0.0, 1.0, 0.0), 0)];
+	while let Some((center, radius, up, level)) = stack.pop() {
+		world.add_new(Sphere::new(
+			center,
+			radius,
+			materials[level as usize % materials.len()].clone(),
+		));
+		if level == depth {
+			continue;
+		}
+		// any two directions perpendicular to up and each other
+		let side = if up.x().abs() > 0.9 {
+			Vec3::new(0.0, 1.0, 0.0)
+		} else {
+			Vec3::new(1.0, 0.0, 0.0)
+		}
+		.cross(up)
+		.unit_vector();
+		let front = up.cross(side);
+		let child_radius = radius / 3.0;
+		for c in children {
+			let direction = c.x() * side + c.y() * up + c.z() * front;
+			stack.push((
+				center + (radius + child_radius) * direction,
+				child_radius,
+				direction,
+				level + 1,
+			));
+		}
+	}
+
+	let lights: Vec<Arc<dyn Light>> = vec![Arc::new(DirectionalLight::new(
+		Vec3::new(1.0, 2.0, 0.5),
+		1.0,
+		Color::new(2.0, 2.0, 2.0),
+	))];
+	let from = Point3::new(6.0, 5.0, 8.0);
+	let at = Point3::new(0.0, 2.2, 0.0);
+	(
+		world,
+		Camera::new(
+			from,
+			at,
+			Vec3::new(0.0, 1.0, 0.0),
+			26.0,
+			1.5,
+			0.0,
+			(at - from).length(),
+			0.0,
+			1.0,
+		),
+		sky() * 0.5,
+		lights,
+	)
+}
+
+/// black and white turbulence, used on the ground in the perlin scene
+pub fn noise_texture<R: Rng + ?Sized>(rng: &mut R) -> Arc<dyn Texture> {
 	let black = SolidColor::new(Color::zero());
 	let white = SolidColor::new(Color::new(1.0, 1.0, 1.0));
-	let perlin1 = Arc::new(NoiseTexture::new(rng, black, white, 4.0, 7));
-
-	let noise = NoiseTexture::new(rng, black, white, 10.0, 50);
-	let perlin2 = Arc::new(FunctionTexture(move |u, v, p| {
-		let v = noise.value(u, v, p).x();
-		let blue = Color::new(0.0, 0.1, 0.15);
-		let white = Color::new(1.0, 1.0, 1.0);
-		// interpolate, but darken a lot using the exponent
-		blue + (white - blue) * v.powi(15)
-	}));
+	Arc::new(NoiseTexture::new(rng, black, white, 4.0, 7))
+}
+
+/// white with thin dark blue veins, used on the sphere in the perlin scene
+pub fn marble_texture<R: Rng + ?Sized>(rng: &mut R) -> Arc<dyn Texture> {
+	let black = SolidColor::new(Color::zero());
+	let white = SolidColor::new(Color::new(1.0, 1.0, 1.0));
+	let noise = Arc::new(NoiseTexture::new(rng, black, white, 10.0, 50));
+	// interpolate, but darken a lot using the exponent
+	let factor = MappedTexture::new(noise, |c| {
+		let v = c.x().powi(15);
+		Color::new(v, v, v)
+	});
+	Arc::new(LerpTexture(
+		SolidColor::new(Color::new(0.0, 0.1, 0.15)),
+		white,
+		factor,
+	))
+}
+
+pub fn perlin_spheres<R: Rng + ?Sized>(rng: &mut R) -> Scene {
+	let mut world = HittableList::new();
+	let perlin1 = noise_texture(rng);
+	let perlin2 = marble_texture(rng);
 
 	let material1 = Arc::new(Metal::new(perlin1, 0.3));
 	let material2 = Arc::new(Lambertian::new(perlin2));
@@ -226,12 +464,21 @@ pub fn perlin_spheres<R: Rng + ?Sized>(rng: &mut R) -> Scene {
 			1.0,
 		),
 		sky(),
+		Vec::new(),
 	)
 }
 
-pub fn earth() -> ImageResult<Scene> {
+/// the map of the earth used by the earth and week scenes
+pub fn earth_texture(assets: &AssetDirs) -> ImageResult<ImageTexture> {
+	match assets.find("textures/earthmap.jpg")? {
+		Asset::File(path) => ImageTexture::new(path),
+		Asset::Embedded(bytes) => ImageTexture::from_memory(bytes),
+	}
+}
+
+pub fn earth(assets: &AssetDirs) -> ImageResult<Scene> {
 	let mut world = HittableList::new();
-	let earth_texture = Arc::new(ImageTexture::new("textures/earthmap.jpg")?);
+	let earth_texture = Arc::new(earth_texture(assets)?);
 	let earth_mat = Arc::new(Lambertian::new(earth_texture));
 	let globe = Arc::new(Sphere::new(Point3::zero(), 2.0, earth_mat));
 	world.add(globe);
@@ -249,22 +496,21 @@ pub fn earth() -> ImageResult<Scene> {
 			1.0,
 		),
 		sky(),
+		Vec::new(),
 	))
 }
 
-pub fn cornell_box() -> Scene {
+/// the Cornell box without its light
+/// the walls, floor, and ceiling of the Cornell box, and the white material they use
+fn cornell_walls() -> (HittableList, Arc<dyn Material>) {
 	let mut world = HittableList::new();
 
 	let red = Arc::new(Lambertian::with_color(Color::new(0.65, 0.05, 0.05)));
 	let white = Arc::new(Lambertian::with_color(Color::new(0.73, 0.73, 0.73)));
 	let green = Arc::new(Lambertian::with_color(Color::new(0.12, 0.45, 0.15)));
-	let light = Arc::new(DiffuseLight::with_color(Color::new(15.0, 15.0, 15.0)));
 
 	world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
 	world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
-	world.add(Arc::new(XZRect::new(
-		213.0, 343.0, 227.0, 332.0, 554.99, light,
-	)));
 	world.add(Arc::new(XZRect::new(
 		0.0,
 		555.0,
@@ -289,6 +535,11 @@ pub fn cornell_box() -> Scene {
 		555.0,
 		white.clone(),
 	)));
+	(world, white)
+}
+
+fn empty_cornell_box() -> Scene {
+	let (mut world, white) = cornell_walls();
 
 	let mut block1: Arc<dyn Hittable> = Arc::new(Block::new(
 		Point3::zero(),
@@ -308,46 +559,220 @@ pub fn cornell_box() -> Scene {
 	block2 = Arc::new(Translate::new(block2, Vec3::new(130.0, 0.0, 65.0)));
 	world.add(block2);
 
+	(world, cornell_camera(), Color::zero(), Vec::new())
+}
+
+/// the camera for every scene in the Cornell box, with its shutter open from time 0 to 1
+fn cornell_camera() -> Camera {
 	let from = Point3::new(278.0, 278.0, -800.0);
 	let to = Point3::new(278.0, 278.0, 0.0);
+	Camera::new(
+		from,
+		to,
+		Vec3::new(0.0, 1.0, 0.0),
+		40.0,
+		1.0,
+		0.1,
+		(to - from).length(),
+		0.0,
+		1.0,
+	)
+}
+
+pub fn cornell_box() -> Scene {
+	let (mut world, cam, background, lights) = empty_cornell_box();
+	// only shines downwards, like a real ceiling light
+	let light = Arc::new(DiffuseLight::with_color(Color::new(15.0, 15.0, 15.0)).one_sided());
+	world.add_new(FlipFace::new(Arc::new(XZRect::new(
+		213.0, 343.0, 227.0, 332.0, 554.99, light,
+	))));
+	(world, cam, background, lights)
+}
+
+/// The Cornell box with its ceiling light sampled directly instead of found by scattered rays.
+/// It's just as bright but much less noisy, although the light itself can't be seen
+pub fn softbox() -> Scene {
+	let (world, cam, background, mut lights) = empty_cornell_box();
+	lights.push(Arc::new(RectLight::new(
+		Point3::new(213.0, 554.99, 227.0),
+		Vec3::new(130.0, 0.0, 0.0),
+		Vec3::new(0.0, 0.0, 105.0),
+		Color::new(15.0, 15.0, 15.0),
+	)));
+	(world, cam, background, lights)
+}
+
+/// The softbox scene with a glass ball in front of the tall block, which focuses the light into a
+/// bright spot on the floor. Rays can't hit the light, so the spot only shows up with --caustics
+pub fn glass() -> Scene {
+	let (mut world, cam, background, lights) = softbox();
+	world.add_new(Sphere::new(
+		Point3::new(420.0, 75.0, 150.0),
+		75.0,
+		Arc::new(Dielectric::new(1.5)),
+	));
+	(world, cam, background, lights)
+}
+
+/// The Cornell box with the tall block turning a sixth of the way around its middle and sliding
+/// back while the shutter is open, so it's blurred the most at its outside edges
+pub fn spin() -> Scene {
+	let (mut world, white) = cornell_walls();
+	let light = Arc::new(DiffuseLight::with_color(Color::new(15.0, 15.0, 15.0)).one_sided());
+	world.add_new(FlipFace::new(Arc::new(XZRect::new(
+		213.0, 343.0, 227.0, 332.0, 554.99, light,
+	))));
+
+	// centered on the y axis, so that it turns in place
+	let mut tall: Arc<dyn Hittable> = Arc::new(Block::new(
+		Point3::new(-82.5, 0.0, -82.5),
+		Point3::new(82.5, 320.0, 82.5),
+		white.clone(),
+	));
+	tall = Arc::new(AnimatedRotateY::new(tall, 15.0, 75.0, 0.0, 1.0));
+	tall = Arc::new(AnimatedTranslate::new(
+		tall,
+		Vec3::new(366.0, 0.0, 353.0),
+		Vec3::new(366.0, 0.0, 383.0),
+		0.0,
+		1.0,
+	));
+	world.add(tall);
+
+	let mut short: Arc<dyn Hittable> = Arc::new(Block::new(
+		Point3::zero(),
+		Point3::new(165.0, 165.0, 165.0),
+		white,
+	));
+	short = Arc::new(RotateY::new(short, -18.0));
+	short = Arc::new(Translate::new(short, Vec3::new(130.0, 0.0, 65.0)));
+	world.add(short);
+
+	(world, cornell_camera(), Color::zero(), Vec::new())
+}
+
+/// the Cornell box lit by a downlight with a measured beam profile in front of the tall block and
+/// a spotlight with a soft edge aimed at the short block
+pub fn cornell_spot(assets: &AssetDirs) -> Result<Scene, IesError> {
+	let (world, cam, background, mut lights) = empty_cornell_box();
+
+	let profile = match assets.find("lights/spot.ies")? {
+		Asset::File(path) => IesProfile::open(path)?,
+		Asset::Embedded(bytes) => IesProfile::parse(&String::from_utf8_lossy(bytes))?,
+	};
+	lights.push(Arc::new(
+		SpotLight::with_profile(
+			Point3::new(420.0, 554.0, 150.0),
+			Vec3::new(0.0, -1.0, 0.0),
+			profile,
+			Color::new(1.2e6, 1.1e6, 0.95e6),
+		)
+		.radius(5.0),
+	));
+
+	let position = Point3::new(60.0, 450.0, 40.0);
+	lights.push(Arc::new(SpotLight::new(
+		position,
+		Point3::new(210.0, 165.0, 150.0) - position,
+		8.0,
+		14.0,
+		Color::new(1.2e5, 1.5e5, 2.0e5),
+	)));
+
+	Ok((world, cam, background, lights))
+}
 
+/// A Menger sponge in the Cornell box: a cube split into 27 smaller cubes with the center and
+/// the middle of each face taken out, and the same done again to each of the remaining 20. There
+/// are 20^level blocks, so it's mostly useful for benchmarking the BVH
+/// level: how many times to split the cubes, so 0 is a single block
+pub fn menger(level: u32) -> Scene {
+	let (mut world, _) = cornell_walls();
+	let light = Arc::new(DiffuseLight::with_color(Color::new(15.0, 15.0, 15.0)).one_sided());
+	world.add_new(FlipFace::new(Arc::new(XZRect::new(
+		213.0, 343.0, 227.0, 332.0, 554.99, light,
+	))));
+
+	// (minimum corner, side length) of each cube. every cube shares the one material
+	let mut cubes = vec![(Point3::new(152.5, 0.0, 152.5), 250.0)];
+	for _ in 0..level {
+		cubes = cubes
+			.into_iter()
+			.flat_map(|(corner, size)| {
+				let size = size / 3.0;
+				(0..27).filter_map(move |i| {
+					let (x, y, z) = (i % 3, i / 3 % 3, i / 9);
+					// holes are where the cube is in the middle along at least two axes
+					if [x, y, z].iter().filter(|&&n| n == 1).count() >= 2 {
+						None
+					} else {
+						Some((
+							corner + Vec3::new(x as f64, y as f64, z as f64) * size,
+							size,
+						))
+					}
+				})
+			})
+			.collect();
+	}
+	let material = Arc::new(Lambertian::with_color(Color::new(0.8, 0.6, 0.3)));
+	for (corner, size) in cubes {
+		world.add_new(Block::new(
+			corner,
+			corner + Vec3::new(size, size, size),
+			material.clone(),
+		));
+	}
+
+	// off to the side and above, so that the holes go all the way through
+	let from = Point3::new(378.0, 450.0, -500.0);
+	let at = Point3::new(278.0, 150.0, 278.0);
 	(
 		world,
 		Camera::new(
 			from,
-			to,
+			at,
 			Vec3::new(0.0, 1.0, 0.0),
 			40.0,
 			1.0,
-			0.1,
-			(to - from).length(),
+			0.0,
+			(at - from).length(),
 			0.0,
 			1.0,
 		),
 		Color::zero(),
+		Vec::new(),
 	)
 }
 
-pub fn bisexual_lighting() -> Scene {
-	let (mut world, cam, background) = cornell_box();
+pub fn bisexual_lighting<R: Rng + ?Sized>(rng: &mut R) -> Scene {
+	let (mut world, cam, background, lights) = cornell_box();
 
-	world.add(Arc::new(XZRect::new(
+	world.add_new(XZRect::new(
 		0.0,
 		555.0,
 		0.0,
 		555.0,
 		554.9,
 		Arc::new(DiffuseLight::new(Arc::new(
-			StripeTexture::<SolidColor>::bi().map(&|c| 2.0 * c),
+			StripeTexture::<SolidColor>::bi().map(Arc::new(|c| 2.0 * c)),
 		))),
-	)));
-	world.add(Arc::new(Sphere::new(
+	));
+	world.add_new(Sphere::new(
 		Point3::new(400.0, 80.0, 100.0),
 		50.0,
-		Arc::new(Dielectric { ir: 1.5 }),
-	)));
+		Arc::new(Dielectric::new(1.5)),
+	));
 
-	let mist = Arc::new(SolidColor::new(Color::new(0.0, 0.0, 0.5)));
+	let noise = NoiseTexture::new(
+		rng,
+		SolidColor::new(Color::new(0.0, 0.0, 0.2)),
+		SolidColor::new(Color::new(0.1, 0.2, 0.8)),
+		1.0,
+		7,
+	);
+	// the noise is much too fine-grained at the scale of the Cornell box
+	let mist = Arc::new(FunctionTexture(move |u, v, p| noise.value(u, v, p / 50.0)));
 	let bogus = Arc::new(Lambertian::with_color(Color::zero()));
 	world.add(Arc::new(ConstantMedium::new(
 		Arc::new(Block::new(
@@ -363,16 +788,17 @@ pub fn bisexual_lighting() -> Scene {
 		Arc::new(Block::new(
 			Point3::new(150.0, 50.0, 0.0),
 			Point3::new(250.0, 300.0, 10.0),
-			Arc::new(Dielectric { ir: 1.1 }),
+			Arc::new(Dielectric::new(1.1).with_depth_bonus(4)),
 		)),
 		30.0,
 	)));
 
-	(world, cam, background)
+	(world, cam, background, lights)
 }
 
-pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
+pub fn week<R: Rng + ?Sized>(rng: &mut R, assets: &AssetDirs) -> ImageResult<Scene> {
 	let mut world = HittableList::new();
+	let mut materials = MaterialCache::new();
 	let ground = Arc::new(Lambertian::with_color(Color::new(0.48, 0.83, 0.53)));
 
 	for i in 0..20 {
@@ -384,18 +810,18 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 			let x1 = x0 + w;
 			let y1 = rng.gen_range(1.0..101.0);
 			let z1 = z0 + w;
-			world.add(Arc::new(Block::new(
+			world.add_new(Block::new(
 				Point3::new(x0, y0, z0),
 				Point3::new(x1, y1, z1),
 				ground.clone(),
-			)));
+			));
 		}
 	}
 
-	let light = Arc::new(DiffuseLight::with_color(Color::new(7.0, 7.0, 7.0)));
-	world.add(Arc::new(XZRect::new(
+	let light = Arc::new(DiffuseLight::with_color(Color::new(7.0, 7.0, 7.0)).one_sided());
+	world.add_new(FlipFace::new(Arc::new(XZRect::new(
 		123.0, 423.0, 147.0, 412.0, 554.0, light,
-	)));
+	))));
 
 	let center1 = Point3::new(400.0, 400.0, 200.0);
 	let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
@@ -412,7 +838,7 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 	world.add(Arc::new(Sphere::new(
 		Point3::new(260.0, 150.0, 45.0),
 		50.0,
-		Arc::new(Dielectric { ir: 1.5 }),
+		materials.get(Dielectric::new(1.5)),
 	)));
 	world.add(Arc::new(Sphere::new(
 		Point3::new(0.0, 150.0, 145.0),
@@ -423,7 +849,7 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 	let boundary = Arc::new(Sphere::new(
 		Point3::new(360.0, 150.0, 145.0),
 		70.0,
-		Arc::new(Dielectric { ir: 1.5 }),
+		materials.get(Dielectric::new(1.5)),
 	));
 	world.add(boundary.clone());
 	world.add(Arc::new(ConstantMedium::with_color(
@@ -434,7 +860,7 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 	let boundary2 = Arc::new(Sphere::new(
 		Point3::zero(),
 		5000.0,
-		Arc::new(Dielectric { ir: f64::NAN }),
+		Arc::new(Dielectric::new_unchecked(f64::NAN)),
 	));
 	world.add(Arc::new(ConstantMedium::with_color(
 		boundary2,
@@ -442,9 +868,7 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 		Color::new(1.0, 1.0, 1.0),
 	)));
 
-	let emat = Arc::new(Lambertian::new(Arc::new(ImageTexture::new(
-		"textures/earthmap.jpg",
-	)?)));
+	let emat = Arc::new(Lambertian::new(Arc::new(earth_texture(assets)?)));
 	world.add(Arc::new(Sphere::new(
 		Point3::new(400.0, 200.0, 400.0),
 		100.0,
@@ -463,21 +887,21 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 		Arc::new(Lambertian::new(pertext)),
 	)));
 
-	let mut spheres = HittableList::new();
-	let white = Arc::new(Lambertian::with_color(Color::new(0.73, 0.73, 0.73)));
-	for i in 0..1000 {
-		spheres.add(Arc::new(Sphere::new(
-			Point3::random_range(rng, 0.0, 165.0),
-			10.0,
-			if i < 50 {
-				Arc::new(DiffuseLight::with_color(Color::random_range(
-					rng, 0.0, 50.0,
-				)))
-			} else {
-				white.clone()
-			},
-		)));
-	}
+	let spheres: HittableList = (0..1000)
+		.map(|i| -> Arc<dyn Hittable> {
+			Arc::new(Sphere::new(
+				Point3::random_range(rng, 0.0, 165.0),
+				10.0,
+				if i < 50 {
+					materials.get(DiffuseLight::with_color(Color::random_range(
+						rng, 0.0, 50.0,
+					)))
+				} else {
+					materials.get(Lambertian::with_color(Color::new(0.73, 0.73, 0.73)))
+				},
+			))
+		})
+		.collect();
 	world.add(Arc::new(Translate::new(
 		Arc::new(RotateY::new(
 			Arc::new(
@@ -505,5 +929,274 @@ pub fn week<R: Rng + ?Sized>(rng: &mut R) -> ImageResult<Scene> {
 			1.0,
 		),
 		Color::zero(),
+		Vec::new(),
 	))
 }
+
+pub fn prism() -> Scene {
+	let mut world = HittableList::new();
+
+	let white = Arc::new(Lambertian::with_color(Color::new(0.73, 0.73, 0.73)));
+	world.add(Arc::new(XZRect::new(
+		-1000.0, 1000.0, -1000.0, 1000.0, 0.0, white,
+	)));
+
+	// a block rotated so that light enters and leaves through faces at 90° to each other
+	let glass = Arc::new(Dielectric::with_dispersion(1.6, 20.0));
+	let mut block: Arc<dyn Hittable> = Arc::new(Block::new(
+		Point3::new(-1.0, 0.0, -1.0),
+		Point3::new(1.0, 1.5, 1.0),
+		glass,
+	));
+	block = Arc::new(RotateY::new(block, 45.0));
+	world.add(block);
+
+	// a bright light off to the side, shining through a narrow slit in a wall
+	let black = Arc::new(Lambertian::with_color(Color::zero()));
+	let light = Arc::new(DiffuseLight::with_color(Color::new(15.0, 15.0, 15.0)));
+	world.add(Arc::new(YZRect::new(0.0, 4.0, -4.0, 4.0, -8.0, light)));
+	world.add(Arc::new(YZRect::new(
+		0.0,
+		4.0,
+		-8.0,
+		-0.15,
+		-4.0,
+		black.clone(),
+	)));
+	world.add(Arc::new(YZRect::new(
+		0.0,
+		4.0,
+		0.15,
+		8.0,
+		-4.0,
+		black.clone(),
+	)));
+	world.add(Arc::new(XZRect::new(-8.0, -4.0, -8.0, 8.0, 4.0, black)));
+
+	let from = Point3::new(0.0, 12.0, 6.0);
+	let at = Point3::new(1.0, 0.0, 0.0);
+	(
+		world,
+		Camera::new(
+			from,
+			at,
+			Vec3::new(0.0, 1.0, 0.0),
+			40.0,
+			1.5,
+			0.0,
+			(at - from).length(),
+			0.0,
+			1.0,
+		),
+		Color::zero(),
+		Vec::new(),
+	)
+}
+
+/// Figure 19 from Ray Tracing in One Weekend, looking at three spheres from above: diffuse blue in
+/// the middle, a hollow glass sphere on the left, and gold metal on the right
+pub fn figure19() -> Scene {
+	let mut world = HittableList::new();
+
+	let ground = Arc::new(Lambertian::with_color(Color::new(0.8, 0.8, 0.0)));
+	let center = Arc::new(Lambertian::with_color(Color::new(0.1, 0.2, 0.5)));
+	let left = Arc::new(Dielectric::new(1.5));
+	let right = Arc::new(Metal::with_color(Color::new(0.8, 0.6, 0.2), 0.0));
+
+	world.add_new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, ground));
+	world.add_new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, center));
+	world.add_new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, left.clone()));
+	// a negative radius flips the normals, making the inside of the glass sphere hollow
+	world.add_new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), -0.45, left));
+	world.add_new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, right));
+
+	let from = Point3::new(-2.0, 2.0, 1.0);
+	let at = Point3::new(0.0, 0.0, -1.0);
+	(
+		world,
+		Camera::new(
+			from,
+			at,
+			Vec3::new(0.0, 1.0, 0.0),
+			20.0,
+			16.0 / 9.0,
+			0.0,
+			(at - from).length(),
+			0.0,
+			1.0,
+		),
+		sky(),
+		Vec::new(),
+	)
+}
+
+/// A row of spheres, each lower than the last, sinking into a wide slab of tinted glass over a
+/// checkered floor
+pub fn refraction() -> Scene {
+	let mut world = HittableList::new();
+
+	world.add(Arc::new(XZRect::new(
+		-100.0,
+		100.0,
+		-100.0,
+		100.0,
+		-2.0,
+		Arc::new(Lambertian::new(Arc::new(CheckerTexture::with_colors(
+			Color::new(0.2, 0.3, 0.1),
+			Color::new(0.9, 0.9, 0.9),
+		)))),
+	)));
+	world.add_new(Block::new(
+		// reaching below the floor, so that the floor is inside of the glass
+		Point3::new(-50.0, -3.0, -50.0),
+		Point3::new(50.0, 0.0, 4.0),
+		Arc::new(Dielectric::new(1.33).with_tint(Color::new(0.85, 0.95, 1.0))),
+	));
+
+	let colors = [
+		Color::new(0.8, 0.1, 0.1),
+		Color::new(0.8, 0.5, 0.1),
+		Color::new(0.7, 0.7, 0.1),
+		Color::new(0.1, 0.6, 0.2),
+		Color::new(0.1, 0.3, 0.8),
+	];
+	for (i, color) in colors.into_iter().enumerate() {
+		// from sitting on top of the glass to all the way under its surface
+		let x = -4.0 + 2.0 * i as f64;
+		let y = 0.6 - 0.5 * i as f64;
+		world.add_new(Sphere::new(
+			Point3::new(x, y, 0.0),
+			0.6,
+			Arc::new(Lambertian::with_color(color)),
+		));
+	}
+
+	let from = Point3::new(0.0, 9.0, 10.0);
+	let at = Point3::new(0.0, -1.0, 0.0);
+	(
+		world,
+		Camera::new(
+			from,
+			at,
+			Vec3::new(0.0, 1.0, 0.0),
+			35.0,
+			1.5,
+			0.0,
+			(at - from).length(),
+			0.0,
+			1.0,
+		),
+		sky(),
+		Vec::new(),
+	)
+}
+
+/// A golf ball on the grass in the sun, with dimples pressed into it by a texture. The dimples are
+/// cells of Worley noise shaped like the caps of spheres
+pub fn golf<R: Rng + ?Sized>(rng: &mut R) -> Scene {
+	let mut world = HittableList::new();
+	world.add_new(Sphere::new(
+		Point3::new(0.0, -1000.0, 0.0),
+		1000.0,
+		Arc::new(Lambertian::with_color(Color::new(0.2, 0.45, 0.15))),
+	));
+
+	// a few hundred dimples over the ball, each taking up most of its cell
+	let dimples = WorleyTexture::new(rng, 5.0).map(Arc::new(|c: Color| {
+		let d = (c.x() / 0.45).min(1.0);
+		let depth = (1.0 - d * d).sqrt();
+		Color::new(depth, depth, depth)
+	}));
+	world.add_new(DisplacedSphere::new(
+		Point3::new(0.0, 1.0, 0.0),
+		1.0,
+		Arc::new(dimples),
+		-0.04,
+		64,
+		Arc::new(Lambertian::with_color(Color::new(0.85, 0.85, 0.85))),
+	));
+
+	let from = Point3::new(4.0, 2.5, 5.0);
+	let at = Point3::new(0.0, 0.9, 0.0);
+	(
+		world,
+		Camera::new(
+			from,
+			at,
+			Vec3::new(0.0, 1.0, 0.0),
+			22.0,
+			1.5,
+			0.0,
+			(at - from).length(),
+			0.0,
+			1.0,
+		),
+		sky(),
+		vec![Arc::new(DirectionalLight::new(
+			Vec3::new(-1.0, 1.5, 0.8),
+			0.27,
+			Color::new(3.0, 2.8, 2.5),
+		))],
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::scene::Aabb;
+
+	#[test]
+	fn test_cornell_emitters() {
+		let (world, ..) = cornell_box();
+		let emitters = world.emitters();
+		assert_eq!(emitters.len(), 1);
+		let bbox = emitters[0].bounding_box(0.0, 1.0).unwrap();
+		assert!((bbox.min().y() - 554.99).abs() < 0.01);
+	}
+
+	#[test]
+	fn test_week_emitters() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let (world, ..) = week(&mut rng, &AssetDirs::default()).unwrap();
+		let emitters = world.emitters();
+		// the ceiling light and the glowing spheres in the rotated and translated group
+		assert_eq!(emitters.len(), 51);
+
+		// the group is moved up by 270, so the spheres are only in the right place if they kept
+		// the transformations
+		let raised = emitters
+			.iter()
+			.filter(|e| e.bounding_box(0.0, 1.0).unwrap().min().y() > 250.0)
+			.count();
+		assert_eq!(raised, 51);
+	}
+
+	#[test]
+	fn test_sphereflake() {
+		for depth in 0..4 {
+			let (world, ..) = sphereflake(depth);
+			// the floor and every sphere
+			assert_eq!(world.len(), 1 + (9usize.pow(depth + 1) - 1) / 8);
+			// nothing sticks out below the thin box around the floor
+			let floor = Aabb::new(
+				Point3::new(-100.0, 0.0, -100.0),
+				Point3::new(100.0, 0.0, 100.0),
+			)
+			.pad_relative(Aabb::FLAT_PADDING);
+			let bbox = world.bounding_box(0.0, 1.0).unwrap();
+			assert_eq!(bbox.min().y(), floor.min().y());
+			assert!(bbox.max().y() <= 4.0);
+		}
+	}
+
+	#[test]
+	fn test_menger() {
+		// the light and the five sides of the box, and then the blocks
+		for (level, blocks) in [(0, 1), (1, 20), (2, 400)] {
+			assert_eq!(menger(level).0.len(), 6 + blocks);
+		}
+	}
+}
@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+use super::HittableList;
+use crate::object::Hittable;
+
+/// Number of objects of each type in a scene, counting everything inside groups and
+/// transformations, along with the total volume of their bounding boxes and how many of their
+/// materials are shared
+#[derive(Debug, Default)]
+pub struct SceneStats {
+	/// type name -> (count, total bounding box volume)
+	pub types: BTreeMap<&'static str, (usize, f64)>,
+	/// total volume of the objects that aren't made of other objects, in their own coordinates
+	pub leaf_volume: f64,
+	/// objects with materials of their own
+	pub materials: usize,
+	/// distinct materials among them, counting shared copies once
+	pub unique_materials: usize,
+	/// memory used by the unique materials, not counting their textures
+	pub material_bytes: usize,
+}
+
+impl SceneStats {
+	pub fn new(world: &HittableList, time0: f64, time1: f64) -> SceneStats {
+		let mut stats = SceneStats::default();
+		let mut seen = HashSet::new();
+		world.visit(&mut |object: &dyn Hittable| {
+			if let Some(material) = object.material() {
+				stats.materials += 1;
+				if seen.insert(Arc::as_ptr(material) as *const ()) {
+					stats.unique_materials += 1;
+					// the material, and the reference counts stored in front of it
+					stats.material_bytes +=
+						std::mem::size_of_val(material.as_ref()) + 2 * std::mem::size_of::<usize>();
+				}
+			}
+			let volume = object
+				.bounding_box(time0, time1)
+				.map(|bbox| bbox.volume())
+				.unwrap_or(0.0);
+			let entry = stats.types.entry(object.type_name()).or_insert((0, 0.0));
+			entry.0 += 1;
+			entry.1 += volume;
+			if object.children().is_empty() {
+				stats.leaf_volume += volume;
+			}
+		});
+		stats
+	}
+}
+
+impl Display for SceneStats {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let total: usize = self.types.values().map(|&(count, _)| count).sum();
+		writeln!(f, "scene:      {} objects", total)?;
+		for (name, (count, volume)) in &self.types {
+			writeln!(f, "  {:<16}{:>8}  volume {:.4e}", name, count, volume)?;
+		}
+		writeln!(f, "  leaf volume:    {:.4e}", self.leaf_volume)?;
+		write!(
+			f,
+			"  materials:      {} unique of {} ({} bytes)",
+			self.unique_materials, self.materials, self.material_bytes
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_xoshiro::Xoshiro256PlusPlus;
+
+	use super::*;
+	use crate::common::assets::AssetDirs;
+	use crate::scene::scenes;
+
+	impl SceneStats {
+		fn count(&self, type_name: &str) -> usize {
+			self.types
+				.get(type_name)
+				.map(|&(count, _)| count)
+				.unwrap_or(0)
+		}
+	}
+
+	#[test]
+	fn test_cornell_stats() {
+		let (world, ..) = scenes::cornell_box();
+		let stats = SceneStats::new(&world, 0.0, 1.0);
+		// two blocks, each rotated and then translated, with six sides each
+		assert_eq!(stats.count("Block"), 2);
+		assert_eq!(stats.count("RotateY"), 2);
+		assert_eq!(stats.count("Translate"), 2);
+		// five walls and the light, which is flipped to face down
+		assert_eq!(stats.count("FlipFace"), 1);
+		assert_eq!(
+			stats.count("XYRect") + stats.count("XZRect") + stats.count("YZRect"),
+			18
+		);
+	}
+
+	#[test]
+	fn test_week_stats() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let (world, ..) = scenes::week(&mut rng, &AssetDirs::default()).unwrap();
+		let stats = SceneStats::new(&world, 0.0, 1.0);
+		assert_eq!(stats.count("Block"), 400);
+		// the box of spheres, the five other spheres, and the media's boundaries, one of which is
+		// also in the world on its own
+		assert_eq!(stats.count("Sphere"), 1007);
+		assert_eq!(stats.count("ConstantMedium"), 2);
+		assert_eq!(stats.count("MovingSphere"), 1);
+		// the spheres in the box are put in a BVH by the scene itself
+		assert!(stats.count("BvhNode") > 0);
+
+		// the sides of the blocks, the light, the spheres, the media, and the moving sphere
+		assert_eq!(stats.materials, 400 * 6 + 1 + 1007 + 2 + 1);
+		// but the white spheres in the box share one material, and so do the two glass spheres
+		assert!(stats.unique_materials < 100, "{}", stats.unique_materials);
+	}
+
+	#[test]
+	fn test_random_scene_materials() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let (world, ..) = scenes::random_scene(&mut rng, false, false);
+		let stats = SceneStats::new(&world, 0.0, 1.0);
+		let glass = world
+			.as_ref()
+			.iter()
+			.filter_map(|o| o.material())
+			.filter(|m| m.to_string() == "Dielectric(ir=1.5)")
+			.collect::<Vec<_>>();
+		assert!(glass.len() > 2);
+		assert!(glass.iter().all(|m| Arc::ptr_eq(m, glass[0])));
+		assert_eq!(
+			stats.unique_materials,
+			stats.materials - (glass.len() - 1),
+			"only the glass is the same"
+		);
+	}
+}
@@ -0,0 +1,306 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// An empty directory to run in, so that the scene assets and any config file aren't found
+fn empty_dir(name: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("raytracing-cli-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// Run the renderer on a tiny image with fixed seeds
+/// dir: working directory, or None for the repository with its textures and light profiles. the
+///      program is copied there and run from the copy, since assets are also looked for near the
+///      executable, which is inside of the repository
+fn run(dir: Option<&PathBuf>, arguments: &[&str]) -> Output {
+	let exe = match dir {
+		Some(dir) => {
+			let exe = dir.join("raytracing");
+			fs::copy(env!("CARGO_BIN_EXE_raytracing"), &exe).unwrap();
+			exe
+		},
+		None => PathBuf::from(env!("CARGO_BIN_EXE_raytracing")),
+	};
+	let mut command = Command::new(exe);
+	command
+		.args(["--no-config", "-r", "1", "-R", "1", "-w", "8", "-s", "1"])
+		.args(arguments);
+	if let Some(dir) = dir {
+		command.current_dir(dir);
+	}
+	command.output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_bad_arguments() {
+	let output = run(None, &["--samples", "lots"]);
+	assert_eq!(output.status.code(), Some(2));
+	let output = run(None, &["--pick", "100,100", "-o", "/dev/null"]);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_missing_asset() {
+	let dir = empty_dir("asset");
+	for scene in ["earth", "week", "spot"] {
+		let output = run(Some(&dir), &["-S", scene, "--dry-run"]);
+		if cfg!(feature = "embedded-assets") {
+			// the scene still loads, from the copies built into the program
+			assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+			assert!(stderr(&output).contains("using the copy built into the program"));
+		} else {
+			assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+			assert!(stderr(&output).contains("--texture-dir"));
+		}
+	}
+
+	// the files are used when told where the repository is
+	let repository = env!("CARGO_MANIFEST_DIR");
+	let arguments = ["-S", "earth", "--texture-dir", repository, "--dry-run"];
+	let output = run(Some(&dir), &arguments);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(!stderr(&output).contains("built into the program"));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_unwritable_output() {
+	let output = run(None, &["-o", "/nonexistent/out.png"]);
+	assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+}
+
+#[test]
+fn test_dry_run() {
+	let dir = empty_dir("dry-run");
+	let output_path = dir.join("out.png");
+	let output = run(
+		None,
+		&[
+			"-S",
+			"cornell",
+			"--dry-run",
+			"-o",
+			output_path.to_str().unwrap(),
+		],
+	);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(stderr(&output).contains("size:       8x8"));
+	assert!(output.stdout.is_empty());
+	// the output is only checked, not created
+	assert!(!output_path.exists());
+
+	// the same failures are found without rendering
+	if !cfg!(feature = "embedded-assets") {
+		let output = run(Some(&dir), &["-S", "earth", "--dry-run"]);
+		assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	}
+	let output = run(None, &["--dry-run", "-o", "/nonexistent/out.png"]);
+	assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_rawvideo() {
+	let output = run(None, &["-S", "cornell", "-f", "rawvideo", "--frames", "2"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	// nothing but the two 8x8 frames goes to stdout
+	assert_eq!(output.stdout.len(), 2 * 8 * 8 * 3);
+	assert!(stderr(&output).contains("ffmpeg -f rawvideo -pix_fmt rgb24 -s 8x8 -r 24 -i -"));
+
+	let output = run(None, &["-S", "cornell", "-f", "rawvideo"]);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_temporal_seed() {
+	let frames = |arguments: &[&str]| {
+		let mut all = vec!["-S", "cornell", "-f", "rawvideo", "--frames", "3"];
+		all.extend(arguments);
+		let output = run(None, &all);
+		assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+		output
+			.stdout
+			.chunks(8 * 8 * 3)
+			.map(<[u8]>::to_vec)
+			.collect::<Vec<_>>()
+	};
+	// the cornell box doesn't move, so with the same seed every frame is the same
+	let fixed = frames(&["--temporal-seed", "fixed"]);
+	assert_eq!(fixed.len(), 3);
+	assert!(fixed.iter().all(|frame| *frame == fixed[0]));
+	let varying = frames(&["--temporal-seed", "varying"]);
+	assert_eq!(varying[0], fixed[0]);
+	assert!(varying[1] != varying[0] && varying[2] != varying[1]);
+	// accumulating starts from the same first frame
+	let accumulated = frames(&["--accumulate-frames"]);
+	assert_eq!(accumulated[0], fixed[0]);
+	assert!(accumulated[1] != varying[1]);
+
+	let output = run(None, &["--accumulate-frames"]);
+	assert_eq!(output.status.code(), Some(2));
+	let arguments = [
+		"--frames",
+		"2",
+		"--accumulate-frames",
+		"--temporal-seed",
+		"fixed",
+	];
+	let output = run(None, &arguments);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_verify_output() {
+	for bits in ["8", "5", "16"] {
+		let arguments = [
+			"-S",
+			"cornell",
+			"-f",
+			"png",
+			"-b",
+			bits,
+			"--alpha",
+			"--verify-output",
+			"-v",
+		];
+		let output = run(None, &arguments);
+		assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+		assert!(stderr(&output).contains("verified:"), "{}", stderr(&output));
+	}
+	// the format can come from the output's extension instead of -f
+	let dir = empty_dir("verify-output");
+	let output_path = dir.join("out.png");
+	let arguments = [
+		"-S",
+		"cornell",
+		"--verify-output",
+		"-o",
+		output_path.to_str().unwrap(),
+	];
+	let output = run(None, &arguments);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(output_path.exists());
+	fs::remove_dir_all(dir).unwrap();
+
+	let output = run(None, &["-f", "ppm", "--verify-output"]);
+	assert_eq!(output.status.code(), Some(2));
+	let output = run(None, &["-o", "out.ppm", "--verify-output"]);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_background_intensity() {
+	let image = |arguments: &[&str]| {
+		let mut all = vec!["-S", "sphereflake", "-f", "rawvideo", "--frames", "1"];
+		all.extend(arguments);
+		let output = run(None, &all);
+		assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+		output.stdout
+	};
+	let brightness = |image: &[u8]| image.iter().map(|&b| b as u32).sum::<u32>();
+	let normal = image(&[]);
+	assert_eq!(image(&["--background-intensity", "1"]), normal);
+	let dim = image(&["--background-intensity", "0.25"]);
+	let dark = image(&["--background-intensity", "0"]);
+	assert!(brightness(&dark) < brightness(&dim) && brightness(&dim) < brightness(&normal));
+	// debug views don't show the background's light
+	let depth = ["-D", "depth", "--background-intensity", "0"];
+	assert_eq!(image(&depth), image(&["-D", "depth"]));
+
+	let output = run(None, &["--background-intensity", "-1"]);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_split_lighting() {
+	let dir = empty_dir("split-lighting");
+	let output_path = dir.join("out.exr");
+	let arguments = [
+		"-S",
+		"cornell",
+		"-f",
+		"exr",
+		"-b",
+		"32",
+		"--split-lighting",
+		"-o",
+		output_path.to_str().unwrap(),
+	];
+	let output = run(None, &arguments);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	let read = |name: &str| {
+		exr::prelude::read_first_rgba_layer_from_file(
+			dir.join(name),
+			|resolution, _| vec![[0.0f32; 3]; resolution.width() * resolution.height()],
+			|pixels: &mut Vec<[f32; 3]>, position, (r, g, b, _a): (f32, f32, f32, f32)| {
+				pixels[position.y() * 8 + position.x()] = [r, g, b]
+			},
+		)
+		.unwrap()
+		.layer_data
+		.channel_data
+		.pixels
+	};
+	let (image, direct, indirect) = (
+		read("out.exr"),
+		read("out.direct.exr"),
+		read("out.indirect.exr"),
+	);
+	assert_eq!(image.len(), 8 * 8);
+	for ((pixel, direct), indirect) in image.iter().zip(&direct).zip(&indirect) {
+		for c in 0..3 {
+			let sum = direct[c] + indirect[c];
+			assert!(
+				(pixel[c] - sum).abs() <= 1e-4 * pixel[c].max(1.0),
+				"{:?} {:?} {:?}",
+				pixel,
+				direct,
+				indirect
+			);
+		}
+	}
+	// the walls of the box light each other
+	assert!(indirect.iter().any(|p| p.iter().any(|&c| c > 0.0)));
+	assert!(direct.iter().any(|p| p.iter().any(|&c| c > 0.0)));
+	fs::remove_dir_all(dir).unwrap();
+
+	let output = run(None, &["--split-lighting"]);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_failed_render_keeps_output() {
+	let dir = empty_dir("keep-output");
+	let output_path = dir.join("out.png");
+	let partial_path = dir.join("out.png.partial");
+	fs::write(&output_path, b"a good image").unwrap();
+	// the camera path fails to load after the output is opened
+	let arguments = [
+		"--frames",
+		"2",
+		"--camera-path",
+		"/nonexistent/path.json",
+		"-o",
+		output_path.to_str().unwrap(),
+	];
+	let output = run(None, &arguments);
+	assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	assert_eq!(fs::read(&output_path).unwrap(), b"a good image");
+	assert!(!partial_path.exists());
+
+	let output = run(
+		None,
+		&["-S", "cornell", "-o", output_path.to_str().unwrap()],
+	);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(fs::read(&output_path).unwrap().starts_with(b"\x89PNG"));
+	assert!(!partial_path.exists());
+	fs::remove_dir_all(dir).unwrap();
+}